@@ -1,6 +1,11 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs;
 use std::io;
+use std::time::SystemTime;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context as _, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
 use regex_lite::Regex;
 use reqwest::blocking::{Request, Response};
 use reqwest::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
@@ -9,13 +14,19 @@ use reqwest::StatusCode;
 use crate::cli::AuthType;
 use crate::middleware::{Context, Middleware};
 use crate::netrc;
-use crate::utils::clone_request;
+use crate::ntlm;
+use crate::utils::{clone_request, expand_tilde, test_mode};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Auth {
     Bearer(String),
     Basic(String, Option<String>),
     Digest(String, String),
+    Ntlm(String, String),
+    /// NTLM, wrapped in the Negotiate scheme.
+    Negotiate(String, String),
+    /// An access token from --auth-type=oauth2, and when it expires.
+    OAuth2(String, Option<SystemTime>),
 }
 
 impl Auth {
@@ -32,7 +43,17 @@ impl Auth {
                     password.unwrap_or_else(|| "".into()),
                 ))
             }
+            AuthType::Ntlm | AuthType::Negotiate => {
+                let (username, password) = parse_auth(auth, host)?;
+                let password = password.unwrap_or_default();
+                Ok(match auth_type {
+                    AuthType::Ntlm => Auth::Ntlm(username, password),
+                    _ => Auth::Negotiate(username, password),
+                })
+            }
             AuthType::Bearer => Ok(Auth::Bearer(auth.into())),
+            AuthType::Oauth2 => Err(anyhow!("--auth-type=oauth2 fetches its own token")),
+            AuthType::Jwt => Err(anyhow!("--auth-type=jwt makes its own token")),
         }
     }
 
@@ -41,6 +62,60 @@ impl Auth {
             AuthType::Basic => Some(Auth::Basic(entry.login?, Some(entry.password))),
             AuthType::Bearer => Some(Auth::Bearer(entry.password)),
             AuthType::Digest => Some(Auth::Digest(entry.login?, entry.password)),
+            AuthType::Ntlm => Some(Auth::Ntlm(entry.login?, entry.password)),
+            AuthType::Negotiate => Some(Auth::Negotiate(entry.login?, entry.password)),
+            AuthType::Oauth2 | AuthType::Jwt => None,
+        }
+    }
+}
+
+/// Where the credentials for --auth come from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthSource<'a> {
+    Inline(&'a str),
+    /// `@FILE`
+    File(&'a str),
+    /// `env:VAR`
+    Env(&'a str),
+}
+
+impl<'a> AuthSource<'a> {
+    /// The credentials in --auth are used as given, while --auth-from refers
+    /// to a file or an environment variable.
+    pub fn from_args(auth: Option<&'a str>, auth_from: Option<&'a str>) -> Option<Self> {
+        match (auth, auth_from) {
+            (Some(auth), _) => Some(AuthSource::Inline(auth)),
+            (None, Some(source)) => Some(AuthSource::parse(source)),
+            (None, None) => None,
+        }
+    }
+
+    /// Tell "@FILE" and "env:VAR" apart from a value that's given directly.
+    pub fn parse(auth: &'a str) -> Self {
+        if let Some(path) = auth.strip_prefix('@') {
+            AuthSource::File(path)
+        } else if let Some(var) = auth.strip_prefix("env:") {
+            AuthSource::Env(var)
+        } else {
+            AuthSource::Inline(auth)
+        }
+    }
+
+    /// Read the credentials. A file's trailing newline is ignored.
+    pub fn read(&self) -> Result<Cow<'a, str>> {
+        match *self {
+            AuthSource::Inline(auth) => Ok(Cow::Borrowed(auth)),
+            AuthSource::File(path) => {
+                let content = fs::read_to_string(expand_tilde(path))
+                    .with_context(|| format!("Unable to read the credentials in {path}"))?;
+                Ok(Cow::Owned(
+                    content.trim_end_matches(['\r', '\n']).to_string(),
+                ))
+            }
+            AuthSource::Env(var) => match env::var(var) {
+                Ok(auth) => Ok(Cow::Owned(auth)),
+                Err(_) => Err(anyhow!("${var} isn't set")),
+            },
         }
     }
 }
@@ -55,11 +130,21 @@ pub fn parse_auth(auth: &str, host: &str) -> io::Result<(String, Option<String>)
     } else {
         let username = auth.to_string();
         let prompt = format!("http: password for {}@{}: ", username, host);
-        let password = rpassword::prompt_password(prompt)?;
+        let password = prompt_secret(&prompt)?;
         Ok((username, Some(password)))
     }
 }
 
+/// Ask for a password or a token without echoing it.
+pub fn prompt_secret(prompt: &str) -> io::Result<String> {
+    if test_mode() {
+        // Tests have no terminal to answer on
+        eprint!("{prompt}");
+        return rpassword::read_password_from_bufread(&mut io::stdin().lock());
+    }
+    rpassword::prompt_password(prompt)
+}
+
 pub struct DigestAuthMiddleware<'a> {
     username: &'a str,
     password: &'a str,
@@ -97,10 +182,176 @@ impl Middleware for DigestAuthMiddleware<'_> {
     }
 }
 
+/// Runs the NTLM handshake: the request is sent with a negotiate message,
+/// and sent again with the answer to the challenge that the server replies
+/// with.
+///
+/// NTLM authenticates the connection rather than the request, so the body of
+/// the challenge is read to the end to keep the connection alive for the
+/// last leg.
+pub struct NtlmAuthMiddleware<'a> {
+    scheme: &'static str,
+    username: &'a str,
+    password: &'a str,
+}
+
+impl<'a> NtlmAuthMiddleware<'a> {
+    /// The middleware for NTLM or Negotiate credentials.
+    pub fn for_auth(auth: &'a Auth) -> Option<Self> {
+        let (scheme, username, password) = match auth {
+            Auth::Ntlm(username, password) => ("NTLM", username, password),
+            Auth::Negotiate(username, password) => ("Negotiate", username, password),
+            _ => return None,
+        };
+        Some(NtlmAuthMiddleware {
+            scheme,
+            username,
+            password,
+        })
+    }
+
+    fn header(&self, message: &[u8]) -> Result<HeaderValue> {
+        let mut header = HeaderValue::from_str(&format!(
+            "{} {}",
+            self.scheme,
+            BASE64_STANDARD.encode(message)
+        ))?;
+        header.set_sensitive(true);
+        Ok(header)
+    }
+
+    /// The challenge in a `WWW-Authenticate: NTLM <base64>` header.
+    fn challenge(&self, response: &Response) -> Option<Vec<u8>> {
+        response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok()?.split_once(' '))
+            .find(|(scheme, _)| scheme.eq_ignore_ascii_case(self.scheme))
+            .and_then(|(_, token)| BASE64_STANDARD.decode(token.trim()).ok())
+    }
+}
+
+impl Middleware for NtlmAuthMiddleware<'_> {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        let negotiate = self.header(&ntlm::negotiate_message())?;
+        request.headers_mut().insert(AUTHORIZATION, negotiate);
+        let mut response = self.next(&mut ctx, clone_request(&mut request)?)?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let Some(challenge) = self.challenge(&response) else {
+            return Ok(response);
+        };
+        let answer = ntlm::authenticate_message(&challenge, self.username, self.password)?;
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, self.header(&answer)?);
+        self.print(&mut ctx, &mut response, &mut request)?;
+        io::copy(&mut response, &mut io::sink())?;
+        drop(response);
+        self.next(&mut ctx, request)
+    }
+}
+
+/// Asks for new credentials once if the ones from the session are rejected
+/// with a 401, and retries the request with them.
+///
+/// Only requests that carry the credentials are retried, so nothing is sent
+/// to hosts that a redirect leads to. Only basic and bearer auth are supported.
+pub struct ReauthMiddleware<'a> {
+    auth: &'a Auth,
+    host: String,
+    renewed: &'a mut Option<Auth>,
+    tried: bool,
+}
+
+impl<'a> ReauthMiddleware<'a> {
+    /// If the retry succeeds, the new credentials are put in `renewed`.
+    pub fn new(auth: &'a Auth, host: &str, renewed: &'a mut Option<Auth>) -> Self {
+        ReauthMiddleware {
+            auth,
+            host: host.to_string(),
+            renewed,
+            tried: false,
+        }
+    }
+
+    fn ask(&self) -> Result<Option<(Auth, HeaderValue)>> {
+        let (auth, header) = match self.auth {
+            Auth::Bearer(_) => {
+                let token = prompt_secret(&format!("http: new token for {}: ", self.host))?;
+                let header = format!("Bearer {token}");
+                (Auth::Bearer(token), header)
+            }
+            Auth::Basic(username, _) => {
+                let prompt = format!("http: password for {}@{}: ", username, self.host);
+                let password = prompt_secret(&prompt)?;
+                let header = format!(
+                    "Basic {}",
+                    BASE64_STANDARD.encode(format!("{username}:{password}"))
+                );
+                (Auth::Basic(username.clone(), Some(password)), header)
+            }
+            Auth::Digest(..) | Auth::Ntlm(..) | Auth::Negotiate(..) | Auth::OAuth2(..) => {
+                return Ok(None)
+            }
+        };
+        let mut header = HeaderValue::from_str(&header)?;
+        header.set_sensitive(true);
+        Ok(Some((auth, header)))
+    }
+}
+
+impl Middleware for ReauthMiddleware<'_> {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        let mut response = self.next(&mut ctx, clone_request(&mut request)?)?;
+        if response.status() != StatusCode::UNAUTHORIZED
+            || self.tried
+            || !request.headers().contains_key(AUTHORIZATION)
+        {
+            return Ok(response);
+        }
+        self.tried = true;
+        let Some((auth, header)) = self.ask()? else {
+            return Ok(response);
+        };
+        request.headers_mut().insert(AUTHORIZATION, header);
+        self.print(&mut ctx, &mut response, &mut request)?;
+        let response = self.next(&mut ctx, request)?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            *self.renewed = Some(auth);
+        }
+        Ok(response)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn auth_sources() {
+        assert_eq!(
+            AuthSource::parse("user:pass"),
+            AuthSource::Inline("user:pass")
+        );
+        assert_eq!(
+            AuthSource::parse("@token.txt"),
+            AuthSource::File("token.txt")
+        );
+        assert_eq!(
+            AuthSource::parse("env:API_TOKEN"),
+            AuthSource::Env("API_TOKEN")
+        );
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), "secret-token\n").unwrap();
+        let path = file.path().to_str().unwrap();
+        assert_eq!(AuthSource::File(path).read().unwrap(), "secret-token");
+        assert!(AuthSource::Env("XH_TEST_SURELY_UNSET").read().is_err());
+    }
+
     #[test]
     fn parsing() {
         let expected = vec![
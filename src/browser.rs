@@ -0,0 +1,79 @@
+//! --open, which shows the response in a web browser.
+//!
+//! An HTML page is saved to a temporary file first, with a <base> element so
+//! its relative links and stylesheets still point to the server. The file is
+//! left behind because the browser may load it after we exit.
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::Response;
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+
+use crate::utils::HeaderValueExt;
+
+/// Open the target of a redirect, or an HTML body.
+pub fn open_response(response: &Response, body: &[u8]) -> Result<()> {
+    if response.status().is_redirection() {
+        if let Some(location) = response.headers().get(LOCATION) {
+            let url = response.url().join(location.to_utf8_str()?)?;
+            return open(OsStr::new(url.as_str()));
+        }
+    }
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("html"));
+    if !is_html {
+        return Err(anyhow!("it's not an HTML page or a redirect"));
+    }
+    let random = RandomState::new().build_hasher().finish();
+    let path = env::temp_dir().join(format!("xh-response-{random:016x}.html"));
+    let mut page = format!(
+        "<base href=\"{}\">\n",
+        response.url().as_str().replace('"', "%22")
+    )
+    .into_bytes();
+    page.extend_from_slice(body);
+    fs::write(&path, page).with_context(|| format!("Unable to write {}", path.display()))?;
+    open(path.as_os_str())
+}
+
+/// Open a URL or a file with $BROWSER, or else the system's default
+/// application.
+fn open(target: &OsStr) -> Result<()> {
+    let browser = env::var_os("BROWSER").filter(|browser| !browser.is_empty());
+    let mut command = match &browser {
+        Some(browser) => {
+            // It may come with arguments, and may be a list of browsers to try
+            let browser = browser.to_string_lossy();
+            let browser = browser.split(':').next().unwrap_or_default();
+            let mut args = browser.split_whitespace();
+            let mut command = Command::new(args.next().unwrap_or_default());
+            command.args(args);
+            command
+        }
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None if cfg!(windows) => {
+            let mut command = Command::new("rundll32");
+            command.arg("url.dll,FileProtocolHandler");
+            command
+        }
+        None => Command::new("xdg-open"),
+    };
+    let status = command
+        .arg(target)
+        .status()
+        .with_context(|| format!("couldn't run {:?}", command.get_program()))?;
+    if !status.success() {
+        return Err(anyhow!("{:?} failed ({status})", command.get_program()));
+    }
+    Ok(())
+}
@@ -11,6 +11,10 @@
 //! when possible, but on Windows we often need a BufferedStandardStream
 //! instead to use the terminal APIs.
 //!
+//! The platform-specific part is wrapped to keep track of colors, so that
+//! output that's cut short by an error or a panic doesn't leave the terminal
+//! colored.
+//!
 //! Most of this code is boilerplate.
 
 use std::{
@@ -19,20 +23,20 @@ use std::{
     path::Path,
 };
 
+use termcolor::{ColorSpec, WriteColor};
+
 use crate::{
     cli::Pretty,
     utils::{test_default_color, test_pretend_term},
 };
 
-pub use imp::Buffer;
-
 #[cfg(not(windows))]
 mod imp {
     use std::io::{BufWriter, Write};
 
     use termcolor::{Ansi, WriteColor};
 
-    pub struct Buffer {
+    pub struct Output {
         inner: Ansi<BufWriter<Inner>>,
         terminal: bool,
         redirect: bool,
@@ -44,7 +48,7 @@ mod imp {
         Stderr(std::io::Stderr),
     }
 
-    impl Buffer {
+    impl Output {
         pub fn stdout() -> Self {
             Self {
                 inner: Ansi::new(BufWriter::new(Inner::Stdout(std::io::stdout()))),
@@ -69,6 +73,14 @@ mod imp {
             }
         }
 
+        pub fn redirect_stderr() -> Self {
+            Self {
+                inner: Ansi::new(BufWriter::new(Inner::Stderr(std::io::stderr()))),
+                terminal: crate::test_pretend_term(),
+                redirect: true,
+            }
+        }
+
         pub fn file(file: std::fs::File) -> Self {
             Self {
                 inner: Ansi::new(BufWriter::new(Inner::File(file))),
@@ -127,7 +139,7 @@ mod imp {
         }
     }
 
-    impl Write for Buffer {
+    impl Write for Output {
         fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
             self.inner.write(buf)
         }
@@ -143,7 +155,7 @@ mod imp {
         }
     }
 
-    impl WriteColor for Buffer {
+    impl WriteColor for Output {
         fn supports_color(&self) -> bool {
             true
         }
@@ -166,126 +178,308 @@ mod imp {
 
     use crate::utils::test_default_color;
 
-    pub enum Buffer {
+    pub enum Output {
         // Only escape codes make sense when the output isn't going directly
         // to a terminal, so we use Ansi for some cases.
         File(Ansi<BufWriter<std::fs::File>>),
         Redirect(Ansi<BufWriter<std::io::Stdout>>),
-        Stdout(BufferedStandardStream),
-        Stderr(BufferedStandardStream),
+        RedirectStderr(Ansi<BufWriter<std::io::Stderr>>),
+        Stdout(Console),
+        Stderr(Console),
+    }
+
+    /// Turn on escape code processing in the console, so colors don't need
+    /// the legacy console API and hyperlinks work outside of Windows Terminal.
+    ///
+    /// The code page is left alone: the standard library writes to the console
+    /// as UTF-16 no matter what, and changing it would outlast our process.
+    pub fn setup_console() {
+        type Handle = *mut std::ffi::c_void;
+        const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+        const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+        const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetStdHandle(std_handle: u32) -> Handle;
+            fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+            fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+        }
+
+        for std_handle in [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE] {
+            // Failure just means this isn't a console, or an old one
+            unsafe {
+                let handle = GetStdHandle(std_handle);
+                let mut mode = 0;
+                if GetConsoleMode(handle, &mut mode) != 0 {
+                    SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING);
+                }
+            }
+        }
+    }
+
+    /// The console is UTF-16 underneath, and the standard library fails the
+    /// whole write if it gets invalid UTF-8. So replace invalid sequences
+    /// with U+FFFD first, holding back a character that's cut off at the end
+    /// until the rest of it arrives.
+    pub struct Console {
+        inner: BufferedStandardStream,
+        pending: Vec<u8>,
+    }
+
+    impl Console {
+        fn new(inner: BufferedStandardStream) -> Self {
+            Console {
+                inner,
+                pending: Vec::new(),
+            }
+        }
+    }
+
+    impl Write for Console {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.write_all(buf)?;
+            Ok(buf.len())
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+            if self.pending.is_empty() && std::str::from_utf8(buf).is_ok() {
+                return self.inner.write_all(buf);
+            }
+            self.pending.extend_from_slice(buf);
+            let mut sanitized = Vec::with_capacity(self.pending.len());
+            let mut rest = &self.pending[..];
+            while let Err(err) = std::str::from_utf8(rest) {
+                let (valid, after) = rest.split_at(err.valid_up_to());
+                sanitized.extend_from_slice(valid);
+                rest = after;
+                match err.error_len() {
+                    Some(len) => {
+                        sanitized.extend_from_slice("\u{FFFD}".as_bytes());
+                        rest = &rest[len..];
+                    }
+                    None => break,
+                }
+            }
+            if std::str::from_utf8(rest).is_ok() {
+                sanitized.extend_from_slice(rest);
+                rest = &[];
+            }
+            self.pending = rest.to_vec();
+            self.inner.write_all(&sanitized)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
     }
 
-    impl Buffer {
+    impl WriteColor for Console {
+        fn supports_color(&self) -> bool {
+            self.inner.supports_color()
+        }
+
+        fn set_color(&mut self, spec: &termcolor::ColorSpec) -> std::io::Result<()> {
+            self.inner.set_color(spec)
+        }
+
+        fn reset(&mut self) -> std::io::Result<()> {
+            self.inner.reset()
+        }
+
+        fn is_synchronous(&self) -> bool {
+            self.inner.is_synchronous()
+        }
+    }
+
+    impl Output {
         pub fn stdout() -> Self {
-            Buffer::Stdout(BufferedStandardStream::stdout(if test_default_color() {
-                ColorChoice::AlwaysAnsi
-            } else {
-                ColorChoice::Always
-            }))
+            Output::Stdout(Console::new(BufferedStandardStream::stdout(
+                if test_default_color() {
+                    ColorChoice::AlwaysAnsi
+                } else {
+                    ColorChoice::Always
+                },
+            )))
         }
 
         pub fn stderr() -> Self {
-            Buffer::Stderr(BufferedStandardStream::stderr(if test_default_color() {
-                ColorChoice::AlwaysAnsi
-            } else {
-                ColorChoice::Always
-            }))
+            Output::Stderr(Console::new(BufferedStandardStream::stderr(
+                if test_default_color() {
+                    ColorChoice::AlwaysAnsi
+                } else {
+                    ColorChoice::Always
+                },
+            )))
         }
 
         pub fn redirect() -> Self {
-            Buffer::Redirect(Ansi::new(BufWriter::new(std::io::stdout())))
+            Output::Redirect(Ansi::new(BufWriter::new(std::io::stdout())))
+        }
+
+        pub fn redirect_stderr() -> Self {
+            Output::RedirectStderr(Ansi::new(BufWriter::new(std::io::stderr())))
         }
 
         pub fn file(file: std::fs::File) -> Self {
-            Buffer::File(Ansi::new(BufWriter::new(file)))
+            Output::File(Ansi::new(BufWriter::new(file)))
         }
 
         pub fn is_terminal(&self) -> bool {
-            matches!(self, Buffer::Stdout(_) | Buffer::Stderr(_))
+            matches!(self, Output::Stdout(_) | Output::Stderr(_))
         }
 
         pub fn is_redirect(&self) -> bool {
-            matches!(self, Buffer::Redirect(_))
+            matches!(self, Output::Redirect(_) | Output::RedirectStderr(_))
         }
 
         #[cfg(test)]
         pub fn is_stdout(&self) -> bool {
-            matches!(self, Buffer::Stdout(_))
+            matches!(self, Output::Stdout(_))
         }
 
         #[cfg(test)]
         pub fn is_stderr(&self) -> bool {
-            matches!(self, Buffer::Stderr(_))
+            matches!(self, Output::Stderr(_) | Output::RedirectStderr(_))
         }
 
         #[cfg(test)]
         pub fn is_file(&self) -> bool {
-            matches!(self, Buffer::File(_))
+            matches!(self, Output::File(_))
         }
     }
 
-    impl Write for Buffer {
+    impl Write for Output {
         fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
             match self {
-                Buffer::File(w) => w.write(buf),
-                Buffer::Redirect(w) => w.write(buf),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.write(buf),
+                Output::File(w) => w.write(buf),
+                Output::Redirect(w) => w.write(buf),
+                Output::RedirectStderr(w) => w.write(buf),
+                Output::Stdout(w) | Output::Stderr(w) => w.write(buf),
             }
         }
 
         fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
             match self {
-                Buffer::File(w) => w.get_mut().write_all(buf),
-                Buffer::Redirect(w) => w.get_mut().write_all(buf),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.write_all(buf),
+                Output::File(w) => w.get_mut().write_all(buf),
+                Output::Redirect(w) => w.get_mut().write_all(buf),
+                Output::RedirectStderr(w) => w.get_mut().write_all(buf),
+                Output::Stdout(w) | Output::Stderr(w) => w.write_all(buf),
             }
         }
 
         fn flush(&mut self) -> std::io::Result<()> {
             match self {
-                Buffer::File(w) => w.flush(),
-                Buffer::Redirect(w) => w.flush(),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.flush(),
+                Output::File(w) => w.flush(),
+                Output::Redirect(w) => w.flush(),
+                Output::RedirectStderr(w) => w.flush(),
+                Output::Stdout(w) | Output::Stderr(w) => w.flush(),
             }
         }
     }
 
-    impl WriteColor for Buffer {
+    impl WriteColor for Output {
         fn supports_color(&self) -> bool {
             match self {
-                Buffer::File(w) => w.supports_color(),
-                Buffer::Redirect(w) => w.supports_color(),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.supports_color(),
+                Output::File(w) => w.supports_color(),
+                Output::Redirect(w) => w.supports_color(),
+                Output::RedirectStderr(w) => w.supports_color(),
+                Output::Stdout(w) | Output::Stderr(w) => w.supports_color(),
             }
         }
 
         fn set_color(&mut self, spec: &termcolor::ColorSpec) -> std::io::Result<()> {
             match self {
-                Buffer::File(w) => w.set_color(spec),
-                Buffer::Redirect(w) => w.set_color(spec),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.set_color(spec),
+                Output::File(w) => w.set_color(spec),
+                Output::Redirect(w) => w.set_color(spec),
+                Output::RedirectStderr(w) => w.set_color(spec),
+                Output::Stdout(w) | Output::Stderr(w) => w.set_color(spec),
             }
         }
 
         fn reset(&mut self) -> std::io::Result<()> {
             match self {
-                Buffer::File(w) => w.reset(),
-                Buffer::Redirect(w) => w.reset(),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.reset(),
+                Output::File(w) => w.reset(),
+                Output::Redirect(w) => w.reset(),
+                Output::RedirectStderr(w) => w.reset(),
+                Output::Stdout(w) | Output::Stderr(w) => w.reset(),
             }
         }
 
         fn is_synchronous(&self) -> bool {
             match self {
-                Buffer::File(w) => w.is_synchronous(),
-                Buffer::Redirect(w) => w.is_synchronous(),
-                Buffer::Stdout(w) | Buffer::Stderr(w) => w.is_synchronous(),
+                Output::File(w) => w.is_synchronous(),
+                Output::Redirect(w) => w.is_synchronous(),
+                Output::RedirectStderr(w) => w.is_synchronous(),
+                Output::Stdout(w) | Output::Stderr(w) => w.is_synchronous(),
             }
         }
     }
 }
 
+#[cfg(windows)]
+pub use imp::setup_console;
+
+/// The output, wrapped to keep track of colors so they can be reset if
+/// printing is cut short.
+pub struct Buffer {
+    inner: imp::Output,
+    colored: bool,
+    at_line_start: bool,
+}
+
 impl Buffer {
+    fn wrap(inner: imp::Output) -> Self {
+        Buffer {
+            inner,
+            colored: false,
+            at_line_start: true,
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::wrap(imp::Output::stdout())
+    }
+
+    pub fn stderr() -> Self {
+        Self::wrap(imp::Output::stderr())
+    }
+
+    pub fn redirect() -> Self {
+        Self::wrap(imp::Output::redirect())
+    }
+
+    pub fn redirect_stderr() -> Self {
+        Self::wrap(imp::Output::redirect_stderr())
+    }
+
+    pub fn file(file: std::fs::File) -> Self {
+        Self::wrap(imp::Output::file(file))
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.inner.is_terminal()
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        self.inner.is_redirect()
+    }
+
+    #[cfg(test)]
+    pub fn is_stdout(&self) -> bool {
+        self.inner.is_stdout()
+    }
+
+    #[cfg(test)]
+    pub fn is_stderr(&self) -> bool {
+        self.inner.is_stderr()
+    }
+
+    #[cfg(test)]
+    pub fn is_file(&self) -> bool {
+        self.inner.is_file()
+    }
+
     pub fn new(download: bool, output: Option<&Path>, is_stdout_tty: bool) -> io::Result<Self> {
         log::trace!("is_stdout_tty: {is_stdout_tty}");
         Ok(if download {
@@ -301,10 +495,25 @@ impl Buffer {
         })
     }
 
+    /// A buffer for parts of the output that --print-to sends to stderr.
+    pub fn for_stderr(is_stderr_tty: bool) -> Self {
+        if is_stderr_tty {
+            Buffer::stderr()
+        } else {
+            Buffer::redirect_stderr()
+        }
+    }
+
     pub fn print(&mut self, s: &str) -> io::Result<()> {
         self.write_all(s.as_bytes())
     }
 
+    /// Whether nothing has been written yet or the last thing written ended
+    /// with a newline.
+    pub fn at_line_start(&self) -> bool {
+        self.at_line_start
+    }
+
     pub fn guess_pretty(&self) -> Pretty {
         if test_default_color() {
             Pretty::All
@@ -329,3 +538,90 @@ impl Buffer {
         }
     }
 }
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(&last) = buf[..written].last() {
+            self.at_line_start = last == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)?;
+        if let Some(&last) = buf.last() {
+            self.at_line_start = last == b'\n';
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl WriteColor for Buffer {
+    fn supports_color(&self) -> bool {
+        self.inner.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        self.colored = true;
+        self.inner.set_color(spec)
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.colored = false;
+        self.inner.reset()
+    }
+
+    fn is_synchronous(&self) -> bool {
+        self.inner.is_synchronous()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        // An error or a panic can stop us in the middle of colored output,
+        // which would then carry over into whatever the terminal shows next.
+        // Best effort, there's nothing to be done if this fails.
+        if self.colored {
+            let _ = self.inner.reset();
+            let _ = self.inner.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use termcolor::Color;
+
+    use super::*;
+
+    #[test]
+    fn reset_color_on_drop() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut buffer = Buffer::file(file.reopen().unwrap());
+        buffer
+            .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
+            .unwrap();
+        buffer.print("cut short").unwrap();
+        drop(buffer);
+        let written = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(written, "\x1b[0m\x1b[31mcut short\x1b[0m");
+    }
+
+    #[test]
+    fn tracks_line_start() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut buffer = Buffer::file(file.reopen().unwrap());
+        assert!(buffer.at_line_start());
+        buffer.print("a").unwrap();
+        assert!(!buffer.at_line_start());
+        buffer.print("b\n").unwrap();
+        assert!(buffer.at_line_start());
+        buffer.print("").unwrap();
+        assert!(buffer.at_line_start());
+    }
+}
@@ -1,26 +1,38 @@
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
 use std::convert::TryFrom;
 use std::env;
 use std::ffi::OsString;
 use std::fmt;
 use std::fs;
+use std::hash::{BuildHasher, Hasher};
 use std::io::Write;
 use std::mem;
-use std::net::{IpAddr, Ipv6Addr};
-use std::path::PathBuf;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, Context};
-use clap::{self, ArgAction, FromArgMatches, ValueEnum};
+use clap::{self, parser::ValueSource, ArgAction, FromArgMatches, ValueEnum};
 use encoding_rs::Encoding;
 use regex_lite::Regex;
+use reqwest::header::{HeaderName, HeaderValue};
 use reqwest::{tls, Method, Url};
-use serde::Deserialize;
 
+use crate::auth::AuthSource;
 use crate::buffer::Buffer;
+use crate::config::{default_cli_args, ConfigCommand};
+use crate::formatting::background::Background;
+use crate::generators::Generators;
+use crate::output_template::OutputTemplate;
+use crate::presets::Preset;
+use crate::presign::Presign;
 use crate::redacted::SecretString;
-use crate::request_items::RequestItems;
-use crate::utils::config_dir;
+use crate::request_items::{read_headers_file, read_query_file, RequestItem, RequestItems};
+use crate::session::SessionCommand;
+use crate::template::{render, Variables};
+use crate::utils::{expand_tilde, is_local_url};
 
 // Some doc comments were copy-pasted from HTTPie
 
@@ -63,7 +75,18 @@ pub struct Cli {
     #[clap(long, conflicts_with = "raw", overrides_with_all = &["json", "form"])]
     pub multipart: bool,
 
+    /// Use a fixed boundary for multipart bodies instead of a random one.
+    ///
+    /// Together with the parts being sent in the order they're given this
+    /// makes the body byte-for-byte reproducible, e.g. for servers that check
+    /// a signature over it. The boundary must be 1 to 70 characters from the
+    /// set allowed by RFC 2046.
+    #[clap(long, value_name = "STRING", value_parser = parse_multipart_boundary)]
+    pub multipart_boundary: Option<String>,
+
     /// Pass raw request data without extra processing.
+    ///
+    /// Use --raw=@clipboard to send what's on the system clipboard.
     #[clap(long, value_name = "RAW")]
     pub raw: Option<String>,
 
@@ -94,12 +117,17 @@ Set output formatting options. Supported option are:
     json.indent:<NUM>
     json.format:<true|false>
     headers.sort:<true|false>
+    headers.merge:<true|false>
 
 Example: --format-options=json.indent:2,headers.sort:false"
     )]
     pub format_options: Vec<FormatOptions>,
 
     /// Output coloring style.
+    ///
+    /// "auto" uses the terminal's own colors, in a variant that suits its
+    /// background. To skip detecting the background, set "background" to
+    /// "light" or "dark" in the config file.
     #[clap(short = 's', long, value_enum, value_name = "THEME")]
     pub style: Option<Theme>,
 
@@ -115,6 +143,12 @@ Example: --format-options=json.indent:2,headers.sort:false"
     #[clap(long, value_name = "MIME_TYPE")]
     pub response_mime: Option<String>,
 
+    /// Show Set-Cookie response headers as a table.
+    ///
+    /// Each cookie gets a row with its name, value, domain, path, expiry and flags.
+    #[clap(long)]
+    pub show_cookies: bool,
+
     /// String specifying what the output should contain
     #[clap(
         short = 'p',
@@ -133,6 +167,23 @@ Example: --print=Hb"
     )]
     pub print: Option<Print>,
 
+    #[clap(
+        long,
+        value_name = "ROUTES",
+        conflicts_with = "download",
+        long_help = "\
+Choose where each part of the output goes, stdout or stderr
+
+Takes a comma-separated list of TARGET:FORMAT pairs, where FORMAT uses the
+same letters as --print. Parts that aren't listed go to stdout as usual.
+This only decides where parts go, not which parts are printed.
+
+'stdout' is the regular output, so it's the --output file if one is given.
+
+Example: --print-to=stderr:Hhm,stdout:b"
+    )]
+    pub print_to: Option<PrintTo>,
+
     /// Print only the response headers. Shortcut for --print=h.
     #[clap(short = 'h', long)]
     pub headers: bool,
@@ -145,6 +196,17 @@ Example: --print=Hb"
     #[clap(short = 'm', long)]
     pub meta: bool,
 
+    /// Stop after the response headers, without downloading the body.
+    ///
+    /// The connection is closed as soon as the headers have arrived, whatever
+    /// the method, which makes it cheap to probe huge resources. Unless --print
+    /// says otherwise, only the response headers are printed.
+    #[clap(
+        long,
+        conflicts_with_all = &["download", "split_parts", "template", "pipe"]
+    )]
+    pub no_body_read: bool,
+
     /// Print the whole request as well as the response.
     ///
     /// Additionally, this enables --all for printing intermediary
@@ -164,6 +226,14 @@ Example: --print=Hb"
     #[clap(long)]
     pub debug: bool,
 
+    /// Format of log messages, including those from --debug.
+    ///
+    /// With json, each message is a JSON object on its own line with the
+    /// fields ts, level, target, message and request_id. The request_id is
+    /// random and shared by all messages from one invocation.
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+
     /// Show any intermediary requests/responses while following redirects with --follow.
     #[clap(long)]
     pub all: bool,
@@ -185,17 +255,169 @@ Example: --print=Hb"
     #[clap(skip)]
     pub stream: Option<bool>,
 
+    /// Truncate response bodies shown in the terminal after SIZE bytes.
+    ///
+    /// A notice is printed after the truncated body. Bodies written to a file
+    /// or a pipe, including with --output and --download, are never truncated.
+    ///
+    /// Example: --max-display-bytes=1M
+    #[clap(long, value_name = "SIZE")]
+    pub max_display_bytes: Option<ByteSize>,
+
+    /// Print only the first SIZE bytes of request and response bodies.
+    ///
+    /// The rest of the body is still read, and summarized with a line like
+    /// "… (12,482 more bytes)". Unlike --max-display-bytes this also applies
+    /// when the output goes to a file or a pipe. Useful with --verbose.
+    ///
+    /// Example: --summarize-bodies=512
+    #[clap(
+        long,
+        value_name = "SIZE",
+        conflicts_with_all = &["download", "pipe", "template"]
+    )]
+    pub summarize_bodies: Option<ByteSize>,
+
+    /// Give up on a compressed response body once it decodes to more than SIZE bytes.
+    ///
+    /// This protects against small responses that decompress to enough data to
+    /// fill up memory. Defaults to 1G for printed bodies, and to no limit for
+    /// bodies saved with --output, --download, --split-parts or --mirror.
+    ///
+    /// Example: --max-decode-size=10G
+    #[clap(long, value_name = "SIZE")]
+    pub max_decode_size: Option<ByteSize>,
+
+    /// Decode string values in a JSON response body that look like base64.
+    ///
+    /// Values that decode to text are shown as that text, and long values
+    /// that decode to binary data are shown as a short hex preview. This is
+    /// a heuristic meant for reading responses, e.g. from secret stores, and
+    /// only affects how the body is printed.
+    #[clap(long)]
+    pub decode_body_base64: bool,
+
+    /// Decode JWTs found in the body and in Authorization and Set-Cookie headers.
+    ///
+    /// The header and claims of each token are printed below it, along with
+    /// its expiry time, which is highlighted once it has passed. Signatures
+    /// are not verified.
+    #[clap(long)]
+    pub decode_jwt: bool,
+
+    /// Print a line rendered from TEMPLATE instead of the response.
+    ///
+    /// Fields between double braces are replaced by parts of the response:
+    /// {{status}}, {{reason}}, {{version}}, {{method}}, {{url}}, {{elapsed}},
+    /// {{headers[NAME]}}, {{body}}, and {{json}} or {{json.PATH}} for a value
+    /// from a JSON body (e.g. {{json.items.0.id}}). Use "\{{" for a literal "{{".
+    ///
+    /// Nothing else is printed by default, but --print and --verbose still apply.
+    ///
+    /// Example: --template='{{status}} {{headers[content-type]}} {{json.data.id}}'
+    #[clap(long, value_name = "TEMPLATE", conflicts_with_all = &["download", "split_parts"])]
+    pub template: Option<OutputTemplate>,
+
+    /// Pipe the response body through a shell command and print its output.
+    ///
+    /// The command gets the body as it was received, after decompression,
+    /// on stdin. Headers and metadata are printed around its output as usual.
+    ///
+    /// Example: --pipe='jq .data'
+    #[clap(long, value_name = "COMMAND", conflicts_with_all = &["download", "split_parts", "template"])]
+    pub pipe: Option<String>,
+
+    /// Copy the response body to the system clipboard.
+    ///
+    /// The whole body is copied after decompression, even if less of it is
+    /// printed. This uses pbcopy on macOS, PowerShell on Windows, and
+    /// wl-copy, xclip or xsel on other systems.
+    #[clap(long, conflicts_with_all = &["download", "split_parts", "template"])]
+    pub copy: bool,
+
+    /// Open the response in a web browser.
+    ///
+    /// An HTML response is saved to a temporary file that's opened in the
+    /// default browser, or in $BROWSER if it's set. For a redirect the URL it
+    /// points to is opened instead. This is handy for HTML error pages and for
+    /// links to web consoles.
+    #[clap(long, conflicts_with_all = &["download", "split_parts", "template"])]
+    pub open: bool,
+
     /// Save output to FILE instead of stdout.
     #[clap(short = 'o', long, value_name = "FILE")]
     pub output: Option<PathBuf>,
 
+    /// Also write the response headers to FILE.
+    ///
+    /// The headers of every response are written, including those of redirects
+    /// when using --follow, regardless of what is printed with --print.
+    #[clap(short = 'D', long, value_name = "FILE")]
+    pub dump_header: Option<PathBuf>,
+
+    /// Append metrics about each response to FILE, as one line of JSON per response.
+    ///
+    /// Each line holds the method, URL, status, timings in seconds and body
+    /// sizes in bytes. Redirects followed with --follow get their own lines.
+    /// The response body size is taken from the Content-Length header and is
+    /// null if there isn't one.
+    #[clap(long, value_name = "FILE")]
+    pub metrics_file: Option<PathBuf>,
+
     /// Download the body to a file instead of printing it.
     ///
     /// The Accept-Encoding header is set to identify and any redirects will be followed.
+    ///
+    /// ftp:// and ftps:// URLs can be downloaded too. If the URL ends with a
+    /// slash the directory is listed instead.
     #[clap(short = 'd', long)]
     pub download: bool,
 
+    /// Print a single line for scripts when a download finishes.
+    ///
+    /// The line is "OK <bytes> <path>" on success or "ERR <exit status> <url>" on
+    /// failure. Nothing else is printed to stdout, and the body is always saved
+    /// to a file, even if stdout is redirected.
+    #[clap(long, requires = "download")]
+    pub porcelain: bool,
+
+    /// Name a downloaded file after the URL it was redirected to.
+    ///
+    /// By default the file is named after the URL that was requested, unless the
+    /// server suggests a name in the Content-Disposition header. Like wget's option
+    /// of the same name.
+    #[clap(long, requires = "download")]
+    pub trust_server_names: bool,
+
+    /// Download everything below URL by following the links in its directory listings.
+    ///
+    /// Listings are read from the index pages that nginx, Apache and similar servers
+    /// generate, or with WebDAV if there's no such page. Files are saved under --output,
+    /// or under a directory named after the URL. Interrupted files are resumed and files
+    /// that haven't changed since an earlier run are skipped.
+    #[clap(long, requires = "download", conflicts_with = "continue")]
+    pub mirror: bool,
+
+    /// How many levels of subdirectories --mirror descends into. Defaults to 5.
+    #[clap(long, value_name = "NUM", requires = "mirror")]
+    pub mirror_depth: Option<usize>,
+
+    /// Stop --mirror once this many bytes have been downloaded.
+    #[clap(long, value_name = "SIZE", requires = "mirror")]
+    pub mirror_max_size: Option<ByteSize>,
+
+    /// Save each part of a multipart response to its own file in DIR.
+    ///
+    /// Files are named after the filename in each part's Content-Disposition header,
+    /// or after the part's position in the response if it doesn't have one.
+    #[clap(long, value_name = "DIR", conflicts_with = "download")]
+    pub split_parts: Option<PathBuf>,
+
     /// Resume an interrupted download. Requires --download and --output.
+    ///
+    /// Downloads are written to a .part file next to the output and only renamed
+    /// once they're complete, so this picks up from that file. If there's no
+    /// .part file, the output itself is resumed.
     #[clap(
         short = 'c',
         long = "continue",
@@ -205,6 +427,22 @@ Example: --print=Hb"
     )]
     pub resume: bool,
 
+    /// Flush a download to disk before reporting success.
+    ///
+    /// This makes sure the file survives a crash or power loss right after xh
+    /// exits, at the cost of waiting for the disk.
+    #[clap(long, requires = "download")]
+    pub sync: bool,
+
+    /// Save the URL and ETag of a download in the file's extended attributes.
+    ///
+    /// They're stored as user.xdg.origin.url and user.etag, like wget and curl
+    /// do, on filesystems that support them. With --continue the ETag is sent
+    /// in an If-Range header, so a file that changed on the server is
+    /// downloaded again from the start instead of being resumed.
+    #[clap(long, requires = "download")]
+    pub xattr: bool,
+
     /// Create, or reuse and update a session.
     ///
     /// Within a session, custom headers, auth credentials, as well as any cookies sent
@@ -223,19 +461,136 @@ Example: --print=Hb"
     #[clap(short = 'A', long, value_enum)]
     pub auth_type: Option<AuthType>,
 
-    /// Authenticate as USER with PASS (-A basic|digest) or with TOKEN (-A bearer).
+    /// Authenticate as USER with PASS (-A basic|digest|ntlm|negotiate) or with TOKEN (-A bearer).
     ///
     /// PASS will be prompted if missing. Use a trailing colon (i.e. "USER:")
     /// to authenticate with just a username.
     ///
+    /// For NTLM, USER can include a domain, as in "DOMAIN\USER". Negotiate
+    /// uses NTLM too, since Kerberos isn't supported.
+    ///
     /// TOKEN is expected if --auth-type=bearer.
+    ///
+    /// With --auth-type=oauth2 this is the client secret, if there is one.
+    ///
+    /// With --auth-type=jwt this is "[KEY_ID:]KEY", where KEY is a PEM private
+    /// key (usually "@FILE") or a shared secret for HS256. KEY_ID becomes the
+    /// token's "kid".
+    ///
+    /// To keep the credentials out of the process list, use --auth-from.
     #[clap(short = 'a', long, value_name = "USER[:PASS] | TOKEN")]
     pub auth: Option<SecretString>,
 
+    /// Read what would go in --auth from a file or an environment variable.
+    ///
+    /// SOURCE is "@FILE" to read a file, whose trailing newline is ignored, or
+    /// "env:VAR" to take an environment variable. This keeps the credentials
+    /// out of the process list.
+    ///
+    /// Example: --auth-type=bearer --auth-from=env:API_TOKEN
+    #[clap(
+        long,
+        value_name = "SOURCE",
+        value_parser = parse_auth_from,
+        conflicts_with_all = &["auth", "bearer"]
+    )]
+    pub auth_from: Option<String>,
+
     /// Authenticate with a bearer token.
     #[clap(long, value_name = "TOKEN", hide = true)]
     pub bearer: Option<SecretString>,
 
+    /// The token endpoint to get an access token from with --auth-type=oauth2.
+    ///
+    /// With a client secret in --auth, the token is requested with the client
+    /// credentials grant. Otherwise --oauth-device-url is needed to sign in with
+    /// the device authorization grant, which asks you to approve the request in
+    /// a browser.
+    ///
+    /// Tokens are saved in the session with their expiry and reused until they
+    /// expire.
+    #[clap(long, value_name = "URL")]
+    pub oauth_token_url: Option<Url>,
+
+    /// The client ID to get an access token for with --auth-type=oauth2.
+    #[clap(long, value_name = "ID")]
+    pub oauth_client_id: Option<String>,
+
+    /// The scope to request with --auth-type=oauth2, e.g. "read write".
+    #[clap(long, value_name = "SCOPE")]
+    pub oauth_scope: Option<String>,
+
+    /// The device authorization endpoint, to sign in with --auth-type=oauth2
+    /// without a client secret.
+    #[clap(long, value_name = "URL")]
+    pub oauth_device_url: Option<Url>,
+
+    /// Add a claim to the token made with --auth-type=jwt. Can be repeated.
+    ///
+    /// "NAME=VALUE" adds a string and "NAME:=JSON" adds any JSON value, like
+    /// request items do. The token has "iat" and "exp" claims for five minutes
+    /// by default, which can be overridden.
+    #[clap(long = "jwt-claim", value_name = "NAME=VALUE", value_parser = parse_jwt_claim)]
+    pub jwt_claims: Vec<(String, serde_json::Value)>,
+
+    /// Ask for new credentials if the ones saved in the session are rejected.
+    ///
+    /// When a request with the session's auth gets a 401, xh prompts for a new
+    /// token (-A bearer) or password (-A basic) once and retries. If that
+    /// works, the session is updated with the new credentials.
+    #[clap(long, requires = "session")]
+    pub reauth: bool,
+
+    /// Don't warn about sending credentials over plain HTTP.
+    ///
+    /// By default xh warns when a request with an Authorization header or
+    /// cookies goes to an http:// URL, unless the host is the local machine.
+    #[clap(long)]
+    pub allow_insecure_auth: bool,
+
+    /// Use a preset for a well-known API, e.g. "github" or "gitlab".
+    ///
+    /// A preset supplies a base URL for URLs that start with a "/", default
+    /// headers, and a token read from an environment variable such as
+    /// $GITHUB_TOKEN or $GITLAB_TOKEN.
+    ///
+    /// More presets can be defined in presets.json in the config directory.
+    ///
+    /// Example: xh --preset=github /repos/ducaale/xh/issues
+    #[clap(long, value_name = "NAME")]
+    pub preset: Option<String>,
+
+    /// Load variables from a JSON file for use in the URL and request items.
+    ///
+    /// The file should contain a single object. Variables are referenced with
+    /// "{{name}}". Use "\{{" for a literal "{{".
+    ///
+    /// Example: xh --env=staging.env.json '{{base_url}}/users' 'Authorization:Bearer {{token}}'
+    #[clap(long, value_name = "FILE")]
+    pub env: Option<PathBuf>,
+
+    /// Add querystring parameters from a file.
+    ///
+    /// Each line holds a name=value pair, as in a .env file. Blank lines and
+    /// lines starting with "#" are ignored. The parameters are added before any
+    /// name==value request items.
+    ///
+    /// Example: --query-file=signature.env
+    #[clap(long, value_name = "FILE")]
+    pub query_file: Option<PathBuf>,
+
+    /// Add headers from a file.
+    ///
+    /// Each line holds a header in the "Name: value" format. Blank lines and
+    /// lines starting with "#" are ignored. As with request items, "Name:"
+    /// unsets a header and "Name;" sends it with an empty value. Headers
+    /// given as request items are added after the ones from the file, and
+    /// "Name:" unsets a header from the file.
+    ///
+    /// Example: --headers-file=headers.txt
+    #[clap(long, value_name = "FILE")]
+    pub headers_file: Option<PathBuf>,
+
     /// Do not use credentials from .netrc
     #[clap(long)]
     pub ignore_netrc: bool,
@@ -244,19 +599,189 @@ Example: --print=Hb"
     #[clap(long)]
     pub offline: bool,
 
+    /// Send the request headers exactly as given, for request smuggling tests.
+    ///
+    /// The request is written to a plain TCP or TLS connection instead of going
+    /// through the HTTP client, so duplicate and conflicting framing headers
+    /// (e.g. both Content-Length and Transfer-Encoding) are sent unmodified.
+    /// The connection only supports rustls, so --native-tls and client
+    /// certificates can't be used.
+    ///
+    /// Such requests can put servers and proxies in a bad state, so this
+    /// requires --yes-i-know.
+    #[clap(
+        long,
+        requires = "yes_i_know",
+        conflicts_with_all = &["download", "follow", "proxy", "http_version", "split_parts", "no_body_read", "resolve", "route_to", "hosts_file", "dns_servers", "dns_url", "interface", "lb", "ipv4", "ipv6", "native_tls", "cert", "cert_key"]
+    )]
+    pub raw_headers: bool,
+
+    /// Confirm the use of options that send deliberately malformed requests.
+    #[clap(long)]
+    pub yes_i_know: bool,
+
+    /// Send a complete HTTP/1.1 request from a file, byte for byte.
+    ///
+    /// The file holds the request line, the headers and the body, and is written
+    /// to the connection without any changes. The URL only decides which host
+    /// to connect to and whether to use TLS. Use "-" to read the request from
+    /// stdin. An "http" or "all" proxy given with --proxy is used through a
+    /// CONNECT tunnel. Like with --raw-headers, --native-tls and client
+    /// certificates can't be used.
+    ///
+    /// This is useful for reproducing bugs with requests that the HTTP client
+    /// would normalize or reject.
+    ///
+    /// Example: xh --http-raw=request.txt https://example.com
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["raw_headers", "download", "follow", "http_version", "split_parts", "no_body_read", "body_from", "resolve", "route_to", "hosts_file", "dns_servers", "dns_url", "interface", "lb", "ipv4", "ipv6", "native_tls", "cert", "cert_key"]
+    )]
+    pub http_raw: Option<PathBuf>,
+
+    /// Ask the server with "Expect: 100-continue" before sending the body.
+    ///
+    /// The body is held back until the server answers with "100 Continue", so a
+    /// large upload that would be rejected (e.g. for being too large or for
+    /// missing credentials) isn't sent for nothing. If the server doesn't answer
+    /// within a second the body is sent anyway. Use --all to see the interim
+    /// response.
+    ///
+    /// The request is written to the connection by hand, like --raw-headers.
+    /// The body is streamed, so large files aren't read into memory first.
+    #[clap(
+        long,
+        conflicts_with_all = &["raw_headers", "http_raw", "download", "follow", "http_version", "split_parts", "no_body_read", "resolve", "route_to", "hosts_file", "dns_servers", "dns_url", "interface", "lb", "ipv4", "ipv6", "native_tls", "cert", "cert_key"]
+    )]
+    pub expect_continue: bool,
+
+    /// Send a trailer field after a chunked request body.
+    ///
+    /// The body is sent with "Transfer-Encoding: chunked", followed by the
+    /// trailer fields. Their names are announced in a "Trailer" header. Can be
+    /// given several times.
+    ///
+    /// The request is written to the connection by hand, like --raw-headers.
+    /// Any trailers in the response are printed in their own block after the
+    /// body, along with the response headers.
+    ///
+    /// Example: --trailer=x-checksum:8a3f0c
+    #[clap(
+        long = "trailer",
+        value_name = "NAME:VALUE",
+        conflicts_with_all = &["raw_headers", "http_raw", "download", "follow", "http_version", "split_parts", "no_body_read", "resolve", "route_to", "hosts_file", "dns_servers", "dns_url", "interface", "lb", "ipv4", "ipv6", "native_tls", "cert", "cert_key"]
+    )]
+    pub trailers: Vec<Trailer>,
+
+    /// Use TLS for "xh rawsend".
+    ///
+    /// "xh rawsend HOST:PORT [DATA]" opens a TCP connection, sends DATA verbatim and
+    /// prints whatever comes back, for debugging protocols below HTTP. DATA is
+    /// either text or "@" followed by a file name, and defaults to stdin. The
+    /// reply is shown as text if it's printable and as a hex dump otherwise,
+    /// unless stdout is redirected.
+    ///
+    /// --verify and --timeout apply as usual.
+    ///
+    /// Example: xh rawsend example.com:443 --tls @request.bin
+    #[clap(long)]
+    pub tls: bool,
+
+    /// The target of "xh rawsend".
+    #[clap(skip)]
+    pub rawsend: Option<RawSend>,
+
+    /// What to do for "xh config".
+    #[clap(skip)]
+    pub config_command: Option<ConfigCommand>,
+
+    /// Whether to run "xh init".
+    #[clap(skip)]
+    pub init: bool,
+
+    /// The format for "xh session export". Defaults to httpie.
+    ///
+    /// "httpie" is a session file that HTTPie can use, "har" is a HAR file with
+    /// a single request that has the session's headers, cookies and auth, and
+    /// "netscape" is a cookies.txt file as used by curl and browsers, which
+    /// only has the cookies.
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    pub session_format: Option<SessionFormat>,
+
+    /// Make "xh session prune" remove the sessions that weren't used for this
+    /// long, e.g. "30d".
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub session_older_than: Option<Duration>,
+
+    /// What to do for "xh session".
+    #[clap(skip)]
+    pub session_command: Option<SessionCommand>,
+
+    /// How many links away from the starting page "xh crawl" goes. Defaults to 2.
+    ///
+    /// "xh crawl URL" requests URL and every link on it, and searches the pages on
+    /// the same host for more links, then reports the links that are broken: those
+    /// that end in an error status, after any redirects, or that can't be fetched.
+    /// The exit status is 1 if any are found.
+    ///
+    /// Example: xh crawl example.com --depth=3 --same-host
+    #[clap(long, value_name = "NUM")]
+    pub depth: Option<usize>,
+
+    /// Make "xh crawl" skip links to other hosts instead of checking them.
+    #[clap(long)]
+    pub same_host: bool,
+
+    /// Print the broken links found by "xh crawl" as a table or as JSON.
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    pub report_format: Option<ReportFormat>,
+
+    /// Don't follow robots.txt in "xh crawl" and --mirror.
+    ///
+    /// By default the paths that a site's robots.txt disallows are skipped, and
+    /// its Crawl-delay is waited out between requests.
+    #[clap(long)]
+    pub ignore_robots: bool,
+
+    /// Whether to crawl from the URL instead of making a single request.
+    #[clap(skip)]
+    pub crawl: bool,
+
     /// (default) Exit with an error status code if the server replies with an error.
     ///
     /// The exit code will be 4 on 4xx (Client Error), 5 on 5xx (Server Error),
     /// or 3 on 3xx (Redirect) if --follow isn't set.
     ///
     /// If stdout is redirected then a warning is written to stderr.
-    #[clap(long = "check-status", name = "check-status")]
-    pub check_status_raw: bool,
+    ///
+    /// With --check-status=all the intermediary responses of --follow are
+    /// checked as well, and the first one that fails ends the request with
+    /// its exit code.
+    #[clap(
+        long = "check-status",
+        name = "check-status",
+        value_name = "all",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "final",
+        hide_possible_values = true
+    )]
+    pub check_status_raw: Option<CheckStatus>,
 
     #[clap(skip)]
     pub check_status: Option<bool>,
 
+    #[clap(skip)]
+    pub check_status_all: bool,
+
     /// Do follow redirects.
+    ///
+    /// If a redirect fails because of a network error, such as a refused
+    /// connection or a timeout, that step is tried again up to two times
+    /// without starting over from the first URL. Non-idempotent requests
+    /// (e.g. a POST kept by a 307 redirect) are only tried again with
+    /// --retry-all-methods.
     #[clap(short = 'F', long)]
     pub follow: bool,
 
@@ -264,12 +789,60 @@ Example: --print=Hb"
     #[clap(long, value_name = "NUM")]
     pub max_redirects: Option<usize>,
 
+    /// Also retry non-idempotent requests like POST when the connection is closed.
+    ///
+    /// By default a request is retried once if the server closes the connection
+    /// before responding, but only if its method is idempotent, since the server
+    /// may have already processed it. The same goes for redirects that are
+    /// tried again by --follow.
+    #[clap(long)]
+    pub retry_all_methods: bool,
+
+    /// Show a desktop notification with the status and elapsed time when the
+    /// response has been received, or downloaded with --download.
+    ///
+    /// This uses notify-send on Linux, osascript on macOS and PowerShell on Windows.
+    #[clap(long)]
+    pub notify: bool,
+
+    /// Check the response's HTTP message signature (RFC 9421) with a public key.
+    ///
+    /// The key is a PEM file, as in "@public_key.pem", with an Ed25519, ECDSA
+    /// (P-256 or P-384) or RSA key. The result is shown with --meta, and if no
+    /// signature on the response matches, xh warns and exits with status 13.
+    /// A status code that already causes a non-zero exit takes precedence.
+    #[clap(long, value_name = "@KEY_FILE")]
+    pub verify_signature: Option<String>,
+
+    /// Exit with status 11 if the response took longer than DURATION.
+    ///
+    /// Unlike --timeout the request is completed. The time is the one shown by
+    /// --meta: from sending the request until the body has been received.
+    /// A status code that already causes a non-zero exit takes precedence.
+    ///
+    /// Example: --max-time-assert=500ms
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_time_assert: Option<Duration>,
+
     /// Connection timeout of the request.
     ///
     /// The default value is "0", i.e., there is no timeout limit.
     #[clap(long, value_name = "SEC")]
     pub timeout: Option<Timeout>,
 
+    /// Send a Date header with the request.
+    ///
+    /// DATE is either "now" or an HTTP date such as "Sun, 06 Nov 1994 08:49:37 GMT".
+    #[clap(long, value_name = "DATE")]
+    pub date_header: Option<DateHeader>,
+
+    /// Shift the time in the Date header, e.g. "30s", "-5m" or "1h".
+    ///
+    /// Useful for testing servers that validate request timestamps.
+    /// Implies --date-header=now if --date-header is not used.
+    #[clap(long, value_name = "DURATION", allow_hyphen_values = true)]
+    pub clock_skew: Option<ClockSkew>,
+
     /// Use a proxy for a protocol. For example: --proxy https:http://proxy.host:8080.
     ///
     /// PROTOCOL can be "http", "https" or "all".
@@ -287,6 +860,11 @@ Example: --print=Hb"
     /// If "no", skip SSL verification. If a file path, use it as a CA bundle.
     ///
     /// Specifying a CA bundle will disable the system's built-in root certificates.
+    /// Put a "+" in front of the path (e.g. --verify=+corp.pem) to trust the
+    /// bundle in addition to them instead.
+    ///
+    /// The path can also be a directory, in which case every .pem, .crt and .cer
+    /// file in it is loaded.
     ///
     /// "false" instead of "no" also works. The default is "yes" ("true").
     #[clap(long, value_name = "VERIFY", value_parser = VerifyParser)]
@@ -302,6 +880,35 @@ Example: --print=Hb"
     #[clap(long, value_name = "FILE")]
     pub cert_key: Option<PathBuf>,
 
+    /// The format of the --cert file.
+    ///
+    /// "pem" is a PEM certificate, optionally with its key appended, and "p12"
+    /// is a PKCS#12 bundle (.p12 or .pfx) holding both the certificate chain
+    /// and the key.
+    #[clap(long, value_enum, value_name = "FORMAT", requires = "cert")]
+    pub cert_format: Option<CertFormat>,
+
+    /// The password of a --cert-format=p12 bundle.
+    ///
+    /// If it's missing and the bundle is encrypted, you're asked for it.
+    #[clap(long, value_name = "PASSWORD", requires = "cert")]
+    pub cert_password: Option<SecretString>,
+
+    /// Read what would go in --cert-password from a file or an environment
+    /// variable.
+    ///
+    /// SOURCE is "@FILE" or "env:VAR", like for --auth-from.
+    ///
+    /// Example: --cert-password-from=env:CERT_PASSWORD
+    #[clap(
+        long,
+        value_name = "SOURCE",
+        value_parser = parse_auth_from,
+        requires = "cert",
+        conflicts_with = "cert_password"
+    )]
+    pub cert_password_from: Option<String>,
+
     /// Force a particular TLS version.
     ///
     /// "auto" gives the default behavior of negotiating a version
@@ -321,24 +928,87 @@ Example: --print=Hb"
     #[clap(long)]
     pub https: bool,
 
+    /// Try HTTPS first for URLs without a scheme, and fall back to HTTP with a
+    /// warning if the server can't be reached over HTTPS.
+    ///
+    /// URLs that point to the local machine still default to HTTP. To do this
+    /// for every request, add it to "default_options" in the config file.
+    #[clap(long)]
+    pub https_first: bool,
+
+    /// Set when --https-first changed the scheme, so HTTP is still an option.
+    #[clap(skip)]
+    pub https_fallback: bool,
+
     /// HTTP version to use
     #[clap(long, value_name = "VERSION", value_parser)]
     pub http_version: Option<HttpVersion>,
 
     /// Override DNS resolution for specific domain to a custom IP.
     ///
-    /// You can override multiple domains by repeating this option.
+    /// You can override multiple domains by repeating this option. Give several
+    /// addresses separated by commas to fall back on (or balance over, with --lb).
     ///
     /// Example: --resolve=example.com:127.0.0.1
     #[clap(long, value_name = "HOST:ADDRESS")]
     pub resolve: Vec<Resolve>,
 
+    /// Connect to a different host and port, while keeping the URL, the Host
+    /// header and the TLS server name (SNI) as they are.
+    ///
+    /// This is useful to try a request against a staging server or a single
+    /// backend before switching DNS over. Only the host in the URL is routed,
+    /// redirects to other hosts are followed as usual. This takes precedence
+    /// over --resolve and --hosts-file.
+    ///
+    /// Example: --route-to=staging.internal:443
+    #[clap(long, value_name = "HOST:PORT")]
+    pub route_to: Option<RouteTo>,
+
+    /// Override DNS resolution using a file in the format of /etc/hosts.
+    ///
+    /// Each line contains an IP address followed by one or more hostnames. When a
+    /// hostname occurs more than once the first entry wins. Entries from --resolve
+    /// take precedence over the file.
+    ///
+    /// Example: --hosts-file=staging.hosts
+    #[clap(long, value_name = "FILE")]
+    pub hosts_file: Option<PathBuf>,
+
+    /// Resolve hostnames by querying these DNS servers instead of using the system resolver.
+    ///
+    /// Servers are tried in order until one answers. The port defaults to 53.
+    /// Entries from --resolve and --hosts-file take precedence. With --meta the
+    /// server that answered and the addresses it gave are printed.
+    ///
+    /// Example: --dns-servers=10.0.0.2:53,1.1.1.1
+    #[clap(long, value_name = "ADDRESS", value_delimiter = ',')]
+    pub dns_servers: Vec<DnsServer>,
+
+    /// Resolve hostnames with DNS-over-HTTPS, by sending queries to this URL.
+    ///
+    /// The server's own hostname is looked up with the system resolver. Entries
+    /// from --resolve and --hosts-file take precedence. With --meta the URL and
+    /// the addresses it gave are printed.
+    ///
+    /// Example: --dns-url=https://1.1.1.1/dns-query
+    #[clap(long, value_name = "URL", conflicts_with = "dns_servers", value_parser = parse_dns_url)]
+    pub dns_url: Option<Url>,
+
     /// Bind to a network interface or local IP address.
     ///
     /// Example: --interface=eth0 --interface=192.168.0.2
     #[clap(long, value_name = "NAME")]
     pub interface: Option<String>,
 
+    /// Spread new connections over all the addresses a host resolves to.
+    ///
+    /// "round-robin" moves on to the next address for every new connection, "random"
+    /// starts at a random one. The other addresses are still tried if connecting
+    /// fails. The address that got used is shown with --meta.
+    #[clap(long, value_name = "STRATEGY", value_enum)]
+    pub lb: Option<LoadBalance>,
+
     /// Resolve hostname to ipv4 addresses only.
     #[clap(short = '4', long)]
     pub ipv4: bool,
@@ -347,6 +1017,22 @@ Example: --print=Hb"
     #[clap(short = '6', long)]
     pub ipv6: bool,
 
+    /// (default) Send small packets immediately instead of batching them (TCP_NODELAY).
+    #[clap(long = "tcp-nodelay", name = "tcp-nodelay")]
+    pub tcp_nodelay_raw: bool,
+
+    #[clap(skip)]
+    pub tcp_nodelay: Option<bool>,
+
+    /// Send TCP keepalive probes after a connection has been idle for DURATION.
+    ///
+    /// This keeps long-lived connections, such as event streams and slow downloads,
+    /// from being dropped by NAT gateways and firewalls.
+    ///
+    /// Example: --tcp-keepalive=30s
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub tcp_keepalive: Option<Duration>,
+
     /// Do not attempt to read stdin.
     ///
     /// This disables the default behaviour of reading the request body from stdin
@@ -357,6 +1043,80 @@ Example: --print=Hb"
     #[clap(short = 'I', long)]
     pub ignore_stdin: bool,
 
+    /// Where to take the request body from, instead of detecting it.
+    ///
+    /// "stdin" always reads the body from stdin, even if it's a terminal.
+    /// "file" requires the body to come from a single @file request item and
+    /// never looks at stdin.
+    ///
+    /// Headers and querystring parameters can be combined with either, but
+    /// request data (key=value) can't.
+    #[clap(long, value_enum, value_name = "SOURCE", conflicts_with_all = &["raw", "ignore_stdin"])]
+    pub body_from: Option<BodySource>,
+
+    /// Compose the request body in an editor before sending it.
+    ///
+    /// The editor starts out with the body from the other arguments, or an
+    /// empty JSON object. JSON is checked when you're done, and the editor
+    /// reopens if it's invalid. Saving an empty body cancels the request.
+    ///
+    /// The editor is the one from the config file, or else $VISUAL or $EDITOR.
+    /// Stdin is only read with --body-from=stdin.
+    #[clap(long, conflicts_with = "multipart")]
+    pub edit: bool,
+
+    /// Show the request and ask for confirmation before sending it.
+    ///
+    /// To always do this for some methods, list them under "confirm_methods"
+    /// in the config file, e.g. "xh config set confirm_methods '[\"DELETE\"]'".
+    #[clap(long)]
+    pub confirm: bool,
+
+    /// Send a GraphQL query.
+    ///
+    /// The query is taken from the "query" field (e.g. query=@query.graphql)
+    /// or from stdin, and the other fields become the variables. An
+    /// "operationName" field is passed along as well. Errors in the response
+    /// are listed after it.
+    #[clap(long, conflicts_with_all = &["form", "multipart", "raw"])]
+    pub graphql: bool,
+
+    /// Send the request body as a protobuf message, and decode protobuf
+    /// responses to JSON.
+    ///
+    /// The message types are those of the method that the URL path names in
+    /// this .proto file, e.g. /package.Service/Method. The body is sent as
+    /// gRPC-web (application/grpc-web+proto) unless another Content-Type header
+    /// is given: application/grpc-web-text is base64 encoded, and
+    /// application/x-protobuf sends a bare message. gRPC errors in the
+    /// response are listed after it.
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["form", "multipart", "graphql"],
+        hide = cfg!(not(feature = "protobuf"))
+    )]
+    pub proto: Option<PathBuf>,
+
+    /// Convert a request body from this format to JSON before sending it.
+    ///
+    /// This applies to a body from a @file request item or from stdin. Data
+    /// items (key=value) are merged into a converted @file body as usual.
+    #[clap(long, value_enum, value_name = "FORMAT", conflicts_with_all = &["form", "multipart"])]
+    pub input_format: Option<InputFormat>,
+
+    /// (default) Guess the Content-Type of a @file request body.
+    ///
+    /// The type is taken from the file's extension or, if that doesn't help,
+    /// from the first few bytes of the file. Otherwise, and with
+    /// --no-content-type-guess, application/json is used. An explicit
+    /// ";type=" always takes precedence.
+    #[clap(long = "content-type-guess", name = "content-type-guess")]
+    pub content_type_guess_raw: bool,
+
+    #[clap(skip)]
+    pub content_type_guess: Option<bool>,
+
     /// Print a translation to a curl command.
     ///
     /// For translating the other way, try https://curl2httpie.online/.
@@ -367,6 +1127,48 @@ Example: --print=Hb"
     #[clap(long)]
     pub curl_long: bool,
 
+    /// Print a presigned URL for the request instead of sending it.
+    ///
+    /// The only STYLE is "hmac", which adds "expires" and "signature" querystring
+    /// parameters. The signature is the hex-encoded HMAC-SHA256 of the method,
+    /// the path with the query and the expiry time as a Unix timestamp, separated
+    /// by newlines.
+    ///
+    /// Example: --presign=hmac:$SIGNING_SECRET
+    #[clap(long, value_name = "STYLE:SECRET")]
+    pub presign: Option<Presign>,
+
+    /// How long a presigned URL stays valid. [default: 15m]
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration, requires = "presign")]
+    pub presign_expiry: Option<Duration>,
+
+    /// Replace generators like "id:=#uuid" in request items with made-up values.
+    ///
+    /// Without this flag, values that start with "#" are sent as they are.
+    /// See REQUEST_ITEM for the list of generators.
+    #[clap(long)]
+    pub generate: bool,
+
+    /// Seed for the random values of --generate.
+    ///
+    /// The same seed produces the same values.
+    #[clap(long, value_name = "NUMBER", requires = "generate")]
+    pub seed: Option<u64>,
+
+    /// Print the equivalent xh command and exit without sending a request.
+    ///
+    /// Defaults from the config file are included, and --env, --preset,
+    /// --headers-file and --query-file are resolved into the URL and request
+    /// items. Credentials, including passwords in URLs such as those of
+    /// --proxy, are replaced by a placeholder. Sessions and .netrc are not
+    /// expanded.
+    #[clap(long)]
+    pub print_command: bool,
+
+    /// The options that were passed, for --print-command.
+    #[clap(skip)]
+    pub given_options: Vec<OsString>,
+
     /// Print help.
     #[clap(long, action = ArgAction::HelpShort)]
     pub help: Option<bool>,
@@ -381,6 +1183,25 @@ Example: --print=Hb"
     ///
     /// A leading colon works as shorthand for localhost. ":8000" is equivalent
     /// to "localhost:8000", and ":/path" is equivalent to "localhost/path".
+    ///
+    /// A "ws://" or "wss://" URL opens a WebSocket. Each line of stdin is sent
+    /// as a text message and incoming messages are printed as they arrive. A
+    /// request body is sent as the first message.
+    ///
+    /// "rawsend" instead of a URL sends raw bytes, see --tls. "crawl" followed
+    /// by a URL checks the links on a site, see --depth. "config" manages the
+    /// config file: "xh config list", "xh config get KEY", "xh config set KEY
+    /// VALUE" with a JSON VALUE, or "xh config edit". "init" asks some questions
+    /// and writes a new config file. "session" moves sessions between machines
+    /// and tools: "xh session export SESSION --session-format=har" prints one, and "xh
+    /// session import FILE [SESSION]" reads a session, HAR or cookies.txt file.
+    /// SESSION is a name, "HOST/NAME" if several hosts have a session with that
+    /// name, or a path ending in .json. "xh session list" shows the sessions and
+    /// which hold credentials, and "xh session prune --session-older-than=30d" removes
+    /// the ones that weren't used lately.
+    ///
+    /// To send a request to a host with one of these names, include the scheme
+    /// (e.g. "http://config") or a method.
     #[clap(value_name = "[METHOD] URL")]
     raw_method_or_url: String,
 
@@ -411,6 +1232,9 @@ Example: --print=Hb"
     ///     @filename
     ///         Use a file as the request body.
     ///
+    ///         In JSON mode, other data items are merged into the
+    ///         file's JSON, e.g. "@base.json nested[override]=x".
+    ///
     ///     header:value
     ///         Add a header, e.g. "user-agent:foobar"
     ///
@@ -421,11 +1245,20 @@ Example: --print=Hb"
     ///         Add a header with an empty value.
     ///
     /// An "@" prefix can be used to read a value from a file. For example: "x-api-key:@api-key.txt".
+    /// Files read by "=@" must be UTF-8 text, unless ";base64" is added to send them
+    /// base64-encoded, e.g. "avatar=@photo.jpg;base64". A ";b64" suffix does the
+    /// same for a "=" value, e.g. "token=secret;b64".
+    ///
+    /// With --generate, a "#" prefix generates a value for "=", ":=" and "==" items,
+    /// e.g. "id:=#uuid". The generators are uuid, int, int(MIN,MAX), float, bool, name,
+    /// email, word and now, now(unix) or now(unix_ms). Use "\#" to send one of these
+    /// literally.
     ///
     /// A backslash can be used to escape special characters, e.g. "weird\:key=value".
     ///
     /// To construct a complex JSON object, the REQUEST_ITEM's key can be set to a JSON path instead of a field name.
     /// For more information on this syntax, refer to https://httpie.io/docs/cli/nested-json.
+    /// Query parameter names accept the same syntax, e.g. "filter[tags][]==a".
     #[clap(value_name = "REQUEST_ITEM", verbatim_doc_comment)]
     raw_rest_args: Vec<String>,
 
@@ -437,6 +1270,11 @@ Example: --print=Hb"
     #[clap(skip = ("http://placeholder".parse::<Url>().unwrap()))]
     pub url: Url,
 
+    /// The zone ID of a link-local IPv6 address in the URL, either an
+    /// interface name or its index.
+    #[clap(skip)]
+    pub zone_id: Option<String>,
+
     /// Optional key-value pairs to be included in the request.
     #[clap(skip)]
     pub request_items: RequestItems,
@@ -480,7 +1318,75 @@ impl Cli {
         let matches = app.try_get_matches_from_mut(iter)?;
         let mut cli = Self::from_arg_matches(&matches)?;
 
+        app.get_bin_name()
+            .and_then(|name| name.split('.').next())
+            .unwrap_or("xh")
+            .clone_into(&mut cli.bin_name);
+
+        if cli.raw_method_or_url == "crawl" {
+            if cli.raw_rest_args.is_empty() {
+                return Err(app.error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "Missing <URL>",
+                ));
+            }
+            cli.crawl = true;
+            cli.raw_method_or_url = cli.raw_rest_args.remove(0);
+        } else if cli.depth.is_some() || cli.same_host || cli.report_format.is_some() {
+            return Err(app.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--depth, --same-host and --report-format can only be used with \"xh crawl\"",
+            ));
+        }
+        if cli.ignore_robots && !cli.crawl && !cli.mirror {
+            return Err(app.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--ignore-robots can only be used with \"xh crawl\" or --mirror",
+            ));
+        }
+
+        if (cli.session_format.is_some() || cli.session_older_than.is_some())
+            && cli.raw_method_or_url != "session"
+        {
+            return Err(app.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--session-format and --session-older-than can only be used with \"xh session\"",
+            ));
+        }
+
         match cli.raw_method_or_url.as_str() {
+            "rawsend" => {
+                let rawsend = RawSend::from_args(mem::take(&mut cli.raw_rest_args))
+                    .map_err(|err| app.error(clap::error::ErrorKind::ValueValidation, err))?;
+                cli.rawsend = Some(rawsend);
+                return Ok(cli);
+            }
+            "config" => {
+                let command = ConfigCommand::from_args(mem::take(&mut cli.raw_rest_args))
+                    .map_err(|err| app.error(clap::error::ErrorKind::ValueValidation, err))?;
+                cli.config_command = Some(command);
+                return Ok(cli);
+            }
+            "session" => {
+                let command = SessionCommand::from_args(
+                    mem::take(&mut cli.raw_rest_args),
+                    cli.session_format,
+                    cli.session_older_than,
+                )
+                .map_err(|err| app.error(clap::error::ErrorKind::ValueValidation, err))?;
+                cli.session_command = Some(command);
+                return Ok(cli);
+            }
+            "init" => {
+                if let Some(extra) = cli.raw_rest_args.first() {
+                    return Err(app.error(
+                        clap::error::ErrorKind::UnknownArgument,
+                        format!("Unexpected argument {extra:?}"),
+                    ));
+                }
+                cli.init = true;
+                return Ok(cli);
+            }
             "help" => {
                 // opt-out of clap's auto-generated possible values help for --pretty
                 // as we already list them in the long_help
@@ -494,7 +1400,7 @@ impl Cli {
             _ => {}
         }
         let mut rest_args = mem::take(&mut cli.raw_rest_args).into_iter();
-        let raw_url = match parse_method(&cli.raw_method_or_url) {
+        let mut raw_url = match parse_method(&cli.raw_method_or_url) {
             Some(method) => {
                 cli.method = Some(method);
                 rest_args.next().ok_or_else(|| {
@@ -509,6 +1415,33 @@ impl Cli {
                 mem::take(&mut cli.raw_method_or_url)
             }
         };
+        let variables = match &cli.env {
+            Some(path) => Some(Variables::load(path).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            })?),
+            None => None,
+        };
+        let mut substitute = |text: String| match &variables {
+            Some(variables) => render(&text, |name| variables.get(name)).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            }),
+            None => Ok(text),
+        };
+        raw_url = substitute(raw_url)?;
+        let rest_args = rest_args.map(substitute).collect::<Result<Vec<_>, _>>()?;
+        let rest_args = if cli.generate {
+            let mut generators = Generators::new(cli.seed);
+            rest_args
+                .into_iter()
+                .map(|arg| {
+                    generators.expand(arg).map_err(|err| {
+                        app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            rest_args
+        };
         for request_item in rest_args {
             cli.request_items.items.push(
                 request_item
@@ -517,11 +1450,6 @@ impl Cli {
             );
         }
 
-        app.get_bin_name()
-            .and_then(|name| name.split('.').next())
-            .unwrap_or("xh")
-            .clone_into(&mut cli.bin_name);
-
         if matches!(cli.bin_name.as_str(), "https" | "xhs" | "xhttps") {
             cli.https = true;
         }
@@ -533,17 +1461,69 @@ impl Cli {
 
         cli.process_relations(&matches)?;
 
+        if let Some(path) = &cli.headers_file {
+            let headers = read_headers_file(path).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            })?;
+            cli.request_items.items.splice(0..0, headers);
+        }
+
+        if let Some(name) = cli.preset.clone() {
+            let preset = Preset::load(&name).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            })?;
+            raw_url = preset.expand_url(&raw_url);
+            cli.apply_preset(&preset).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            })?;
+        }
+
+        if let Some(path) = &cli.hosts_file {
+            let mut entries = read_hosts_file(path).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            })?;
+            // Later calls to ClientBuilder::resolve() override earlier ones
+            entries.append(&mut cli.resolve);
+            cli.resolve = entries;
+        }
+
+        if let Some(path) = &cli.query_file {
+            let params = read_query_file(path).map_err(|err| {
+                app.error(clap::error::ErrorKind::ValueValidation, format!("{err:#}"))
+            })?;
+            cli.request_items.items.splice(0..0, params);
+        }
+
+        // The url crate can't parse a zone ID, so it's kept on the side
+        let (raw_url, zone_id) = split_zone_id(&raw_url);
+        cli.zone_id = zone_id;
+
         cli.url = construct_url(&raw_url, cli.default_scheme.as_deref()).map_err(|err| {
             app.error(
                 clap::error::ErrorKind::ValueValidation,
                 format!("Invalid <URL>: {}", err),
             )
         })?;
+        if cli.https_first
+            && cli.default_scheme.is_none()
+            && cli.url.scheme() == "http"
+            && !has_scheme(&raw_url)
+            && !is_local_url(&cli.url)
+        {
+            cli.url
+                .set_scheme("https")
+                .expect("http URLs can become https URLs");
+            cli.https_fallback = true;
+        }
 
         if cfg!(not(feature = "rustls")) {
             cli.native_tls = true;
         }
 
+        if cli.print_command {
+            cli.given_options = given_options(&app, &matches);
+        }
+
         Ok(cli)
     }
 
@@ -563,12 +1543,28 @@ impl Cli {
             self.auth = self.bearer.take();
         }
         self.check_status = match (self.check_status_raw, matches.get_flag("no-check-status")) {
+            (Some(_), true) => unreachable!(),
+            (Some(_), false) => Some(true),
+            (None, true) => Some(false),
+            (None, false) => None,
+        };
+        self.check_status_all = self.check_status_raw == Some(CheckStatus::All);
+        self.stream = match (self.stream_raw, matches.get_flag("no-stream")) {
             (true, true) => unreachable!(),
             (true, false) => Some(true),
             (false, true) => Some(false),
             (false, false) => None,
         };
-        self.stream = match (self.stream_raw, matches.get_flag("no-stream")) {
+        self.tcp_nodelay = match (self.tcp_nodelay_raw, matches.get_flag("no-tcp-nodelay")) {
+            (true, true) => unreachable!(),
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        };
+        self.content_type_guess = match (
+            self.content_type_guess_raw,
+            matches.get_flag("no-content-type-guess"),
+        ) {
             (true, true) => unreachable!(),
             (true, false) => Some(true),
             (false, true) => Some(false),
@@ -578,6 +1574,9 @@ impl Cli {
             self.follow = true;
             self.check_status = Some(true);
         }
+        if self.porcelain {
+            self.quiet = self.quiet.max(1);
+        }
         // `overrides_with_all` ensures that only one of these is true
         if self.json {
             self.request_items.body_type = BodyType::Json;
@@ -586,12 +1585,30 @@ impl Cli {
         } else if self.multipart {
             self.request_items.body_type = BodyType::Multipart;
         }
+        self.request_items.multipart_boundary = self.multipart_boundary.clone();
+        self.request_items.input_format = self.input_format.unwrap_or_default();
+        self.request_items.content_type_guess = self.content_type_guess.unwrap_or(true);
         if self.raw.is_some() && !self.request_items.is_body_empty() {
             return Err(Self::into_app().error(
                 clap::error::ErrorKind::ValueValidation,
                 "Request body (from --raw) and request data (key=value) cannot be mixed.",
             ));
         }
+        match self.body_from {
+            Some(BodySource::Stdin) if !self.request_items.is_body_empty() => {
+                return Err(Self::into_app().error(
+                    clap::error::ErrorKind::ValueValidation,
+                    "Request body (from stdin) and request data (key=value) cannot be mixed.",
+                ));
+            }
+            Some(BodySource::File) if !self.request_items.has_body_file() => {
+                return Err(Self::into_app().error(
+                    clap::error::ErrorKind::ValueValidation,
+                    "--body-from=file requires a request item like @body.json",
+                ));
+            }
+            _ => {}
+        }
         if self.session_read_only.is_some() {
             self.is_session_read_only = true;
             self.session = mem::take(&mut self.session_read_only);
@@ -599,6 +1616,33 @@ impl Cli {
         Ok(())
     }
 
+    /// Add a preset's headers and credentials, unless they were already given.
+    fn apply_preset(&mut self, preset: &Preset) -> anyhow::Result<()> {
+        let is_overridden = |name: &str| {
+            self.request_items.items.iter().any(|item| match item {
+                RequestItem::HttpHeader(key, _)
+                | RequestItem::HttpHeaderFromFile(key, _)
+                | RequestItem::HttpHeaderToUnset(key) => key.eq_ignore_ascii_case(name),
+                _ => false,
+            })
+        };
+        let headers: Vec<_> = preset
+            .headers
+            .iter()
+            .filter(|(name, _)| !is_overridden(name))
+            .map(|(name, value)| RequestItem::HttpHeader(name.clone(), value.clone()))
+            .collect();
+        self.request_items.items.splice(0..0, headers);
+
+        if self.auth.is_none() && self.auth_from.is_none() {
+            if let Some(token) = preset.auth() {
+                self.auth = Some(SecretString::from_str(&token)?);
+                self.auth_type = self.auth_type.or(preset.auth_type()?);
+            }
+        }
+        Ok(())
+    }
+
     pub fn into_app() -> clap::Command {
         let app = <Self as clap::CommandFactory>::command();
 
@@ -631,7 +1675,7 @@ impl Cli {
     }
 
     pub fn logger_config(&self) -> env_logger::Builder {
-        if self.debug || std::env::var_os("RUST_LOG").is_some() {
+        let mut builder = if self.debug || std::env::var_os("RUST_LOG").is_some() {
             let env = env_logger::Env::default().default_filter_or("debug");
             let mut builder = env_logger::Builder::from_env(env);
 
@@ -672,41 +1716,23 @@ impl Cli {
             });
 
             builder
-        }
-    }
-}
-
-#[derive(Deserialize)]
-struct Config {
-    default_options: Vec<String>,
-}
+        };
 
-fn default_cli_args() -> Option<Vec<String>> {
-    let content = match fs::read_to_string(config_dir()?.join("config.json")) {
-        Ok(file) => Some(file),
-        Err(err) => {
-            if err.kind() != std::io::ErrorKind::NotFound {
-                // Can't use log::warn!() because logging isn't initialized yet
-                eprintln!(
-                    "\n{}: warning: Unable to read config file: {}\n",
-                    env!("CARGO_PKG_NAME"),
-                    err
-                );
-            }
-            None
+        if self.log_format == Some(LogFormat::Json) {
+            let request_id = format!("{:016x}", RandomState::new().build_hasher().finish());
+            builder.format(move |buf, record| {
+                let event = serde_json::json!({
+                    "ts": buf.timestamp_micros().to_string(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                    "request_id": request_id,
+                });
+                writeln!(buf, "{event}")
+            });
         }
-    }?;
 
-    match serde_json::from_str::<Config>(&content) {
-        Ok(config) => Some(config.default_options),
-        Err(err) => {
-            eprintln!(
-                "\n{}: warning: Unable to parse config file: {}\n",
-                env!("CARGO_PKG_NAME"),
-                err
-            );
-            None
-        }
+        builder
     }
 }
 
@@ -721,6 +1747,27 @@ fn parse_method(method: &str) -> Option<Method> {
     }
 }
 
+/// Remove the zone ID from a URL with an IPv6 host like `http://[fe80::1%eth0]/`.
+///
+/// Both the raw form and the percent-encoded form from RFC 6874 (`%25eth0`)
+/// are accepted.
+fn split_zone_id(url: &str) -> (Cow<'_, str>, Option<String>) {
+    let re = Regex::new(r"\[([0-9A-Fa-f:.]+)%(?:25)?([^\]/]+)\]").unwrap();
+    match re.captures(url) {
+        Some(caps) => {
+            let zone_id = caps[2].to_string();
+            let url = re.replace(url, "[$1]");
+            (url, Some(zone_id))
+        }
+        None => (Cow::Borrowed(url), None),
+    }
+}
+
+/// Whether `construct_url` takes the scheme from the URL itself.
+fn has_scheme(url: &str) -> bool {
+    !url.starts_with(':') && Regex::new("[a-zA-Z0-9]://.+").unwrap().is_match(url)
+}
+
 fn construct_url(
     url: &str,
     default_scheme: Option<&str>,
@@ -736,7 +1783,7 @@ fn construct_url(
         format!("{}{}", default_scheme, url).parse()?
     } else if url.starts_with(':') {
         format!("{}{}{}", default_scheme, "localhost", url).parse()?
-    } else if !Regex::new("[a-zA-Z0-9]://.+").unwrap().is_match(url) {
+    } else if !has_scheme(url) {
         format!("{}{}", default_scheme, url).parse()?
     } else {
         url.parse()?
@@ -953,6 +2000,10 @@ pub enum AuthType {
     Basic,
     Bearer,
     Digest,
+    Ntlm,
+    Negotiate,
+    Oauth2,
+    Jwt,
 }
 
 #[derive(ValueEnum, Debug, Clone)]
@@ -1009,6 +2060,7 @@ pub struct FormatOptions {
     pub json_indent: Option<usize>,
     pub json_format: Option<bool>,
     pub headers_sort: Option<bool>,
+    pub headers_merge: Option<bool>,
 }
 
 impl FormatOptions {
@@ -1016,6 +2068,7 @@ impl FormatOptions {
         self.json_indent = other.json_indent.or(self.json_indent);
         self.json_format = other.json_format.or(self.json_format);
         self.headers_sort = other.headers_sort.or(self.headers_sort);
+        self.headers_merge = other.headers_merge.or(self.headers_merge);
         self
     }
 }
@@ -1042,6 +2095,9 @@ impl FromStr for FormatOptions {
                 "headers.sort" => {
                     format_options.headers_sort = Some(value.parse().with_context(value_error)?);
                 }
+                "headers.merge" => {
+                    format_options.headers_merge = Some(value.parse().with_context(value_error)?);
+                }
                 "json.sort_keys" | "xml.format" | "xml.indent" => {
                     return Err(anyhow!("Unsupported option '{key}'"));
                 }
@@ -1066,7 +2122,10 @@ pub enum Theme {
 impl Theme {
     pub fn as_str(&self) -> &'static str {
         match self {
-            Theme::Auto => "ansi",
+            Theme::Auto => match crate::formatting::background::get() {
+                Background::Light => "ansi-light",
+                Background::Dark => "ansi",
+            },
             Theme::Solarized => "solarized",
             Theme::Monokai => "monokai",
             Theme::Fruity => "fruity",
@@ -1188,6 +2247,45 @@ impl FromStr for Print {
     }
 }
 
+/// The parts of the output that --print-to sends to stderr.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintTo {
+    pub stderr: Print,
+}
+
+impl FromStr for PrintTo {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<PrintTo> {
+        let mut stderr = String::new();
+        let mut stdout = String::new();
+
+        for route in s.split(',') {
+            let (target, format) = route
+                .split_once(':')
+                .ok_or_else(|| anyhow!("{:?} is not of the form TARGET:FORMAT", route))?;
+            // Validate the letters
+            Print::from_str(format)?;
+            match target {
+                "stderr" => stderr.push_str(format),
+                "stdout" => stdout.push_str(format),
+                _ => {
+                    return Err(anyhow!(
+                        "{:?} is not a valid target, expected stdout or stderr",
+                        target
+                    ))
+                }
+            }
+        }
+
+        if let Some(char) = stderr.chars().find(|&char| stdout.contains(char)) {
+            return Err(anyhow!("{:?} can't go to both stdout and stderr", char));
+        }
+        Ok(PrintTo {
+            stderr: Print::from_str(&stderr)?,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Timeout(Duration);
 
@@ -1216,6 +2314,158 @@ impl FromStr for Timeout {
     }
 }
 
+/// A number of bytes, optionally with a binary unit suffix such as `K`, `MiB` or `G`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(size: &str) -> anyhow::Result<ByteSize> {
+        let size = size.trim();
+        let split_at = size
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(size.len());
+        let (number, unit) = size.split_at(split_at);
+        let number: u64 = number
+            .parse()
+            .with_context(|| format!("Invalid size '{size}'"))?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" | "kib" => 1 << 10,
+            "m" | "mb" | "mib" => 1 << 20,
+            "g" | "gb" | "gib" => 1 << 30,
+            "t" | "tb" | "tib" => 1 << 40,
+            _ => return Err(anyhow!("Invalid size unit '{unit}' in '{size}'")),
+        };
+        number
+            .checked_mul(multiplier)
+            .map(ByteSize)
+            .with_context(|| format!("Size '{size}' is too big"))
+    }
+}
+
+/// Parse a duration like "500ms", "30s", "15m", "2h" or "7d".
+///
+/// A number without a unit is interpreted as seconds.
+pub fn parse_duration(duration: &str) -> anyhow::Result<Duration> {
+    let duration = duration.trim();
+    let split_at = duration
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split_at);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{duration}'"))?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 60.0 * 60.0,
+        "d" => number * 60.0 * 60.0 * 24.0,
+        unit => return Err(anyhow!("Invalid duration unit '{unit}' in '{duration}'")),
+    };
+    Duration::try_from_secs_f64(seconds).with_context(|| format!("Invalid duration '{duration}'"))
+}
+
+fn parse_auth_from(source: &str) -> anyhow::Result<String> {
+    match AuthSource::parse(source) {
+        AuthSource::Inline(_) => Err(anyhow!("Expected \"@FILE\" or \"env:VAR\"")),
+        AuthSource::File(_) | AuthSource::Env(_) => Ok(source.to_string()),
+    }
+}
+
+fn parse_jwt_claim(claim: &str) -> anyhow::Result<(String, serde_json::Value)> {
+    let (name, value) = claim
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected NAME=VALUE or NAME:=JSON"))?;
+    match name.strip_suffix(':') {
+        Some(name) => {
+            let value = serde_json::from_str(value)
+                .with_context(|| format!("Invalid JSON in the {name:?} claim"))?;
+            Ok((name.to_string(), value))
+        }
+        None => Ok((name.to_string(), value.into())),
+    }
+}
+
+fn parse_dns_url(url: &str) -> anyhow::Result<Url> {
+    let url: Url = url.parse()?;
+    if !matches!(url.scheme(), "https" | "http") {
+        return Err(anyhow!("DNS-over-HTTPS URLs must start with https://"));
+    }
+    Ok(url)
+}
+
+/// Check a multipart boundary against the rules in RFC 2046 section 5.1.1.
+fn parse_multipart_boundary(boundary: &str) -> anyhow::Result<String> {
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(anyhow!("Boundary must be between 1 and 70 characters long"));
+    }
+    if let Some(c) = boundary
+        .chars()
+        .find(|&c| !c.is_ascii_alphanumeric() && !"'()+_,-./:=? ".contains(c))
+    {
+        return Err(anyhow!("Invalid character {c:?} in boundary"));
+    }
+    if boundary.ends_with(' ') {
+        return Err(anyhow!("Boundary can't end with a space"));
+    }
+    Ok(boundary.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub negative: bool,
+    pub duration: Duration,
+}
+
+impl ClockSkew {
+    pub fn apply(&self, time: SystemTime) -> Option<SystemTime> {
+        if self.negative {
+            time.checked_sub(self.duration)
+        } else {
+            time.checked_add(self.duration)
+        }
+    }
+}
+
+impl FromStr for ClockSkew {
+    type Err = anyhow::Error;
+
+    fn from_str(skew: &str) -> anyhow::Result<ClockSkew> {
+        let skew = skew.trim();
+        let (negative, duration) = match skew.strip_prefix('-') {
+            Some(duration) => (true, duration),
+            None => (false, skew.strip_prefix('+').unwrap_or(skew)),
+        };
+        Ok(ClockSkew {
+            negative,
+            duration: parse_duration(duration)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateHeader {
+    Now,
+    At(SystemTime),
+}
+
+impl FromStr for DateHeader {
+    type Err = anyhow::Error;
+
+    fn from_str(date: &str) -> anyhow::Result<DateHeader> {
+        if date.eq_ignore_ascii_case("now") {
+            Ok(DateHeader::Now)
+        } else {
+            httpdate::parse_http_date(date)
+                .map(DateHeader::At)
+                .with_context(|| format!("Invalid HTTP date '{date}'"))
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Proxy {
     Http(Url),
@@ -1253,10 +2503,39 @@ impl FromStr for Proxy {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Trailer {
+    pub name: HeaderName,
+    pub value: HeaderValue,
+}
+
+impl FromStr for Trailer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (name, value) = s
+            .split_once(':')
+            .context("Value should be formatted as <NAME>:<VALUE>")?;
+        Ok(Trailer {
+            name: HeaderName::from_str(name.trim())
+                .with_context(|| format!("Invalid trailer name {name:?}"))?,
+            value: HeaderValue::from_str(value.trim())
+                .with_context(|| format!("Invalid trailer value {value:?}"))?,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Resolve {
     pub domain: String,
+    pub addrs: Vec<ResolveAddr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolveAddr {
     pub addr: IpAddr,
+    /// The zone ID (interface) of a link-local IPv6 address.
+    pub zone_id: Option<String>,
 }
 
 impl FromStr for Resolve {
@@ -1271,30 +2550,253 @@ impl FromStr for Resolve {
             ));
         }
 
-        let (domain, raw_addr) = s
+        let (domain, raw_addrs) = s
             .split_once(':')
             .context("Value should be formatted as <HOST>:<ADDRESS>")?;
 
-        let addr = if raw_addr.starts_with('[') && raw_addr.ends_with(']') {
-            // Support IPv6 addresses enclosed in square brackets e.g. [::1]
-            Ipv6Addr::from_str(&raw_addr[1..raw_addr.len() - 1]).map(IpAddr::V6)
+        Ok(Resolve {
+            domain: domain.to_string(),
+            addrs: raw_addrs
+                .split(',')
+                .map(ResolveAddr::from_str)
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+}
+
+impl FromStr for ResolveAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(raw_addr: &str) -> anyhow::Result<Self> {
+        // Support IPv6 addresses enclosed in square brackets e.g. [::1]
+        let bracketed = raw_addr
+            .strip_prefix('[')
+            .and_then(|addr| addr.strip_suffix(']'));
+        // Support zone IDs of link-local addresses e.g. fe80::1%eth0 or fe80::1%25eth0
+        let (unzoned, zone_id) = match bracketed.unwrap_or(raw_addr).split_once('%') {
+            Some((addr, zone_id)) => {
+                let zone_id = zone_id
+                    .strip_prefix("25")
+                    .filter(|zone_id| !zone_id.is_empty())
+                    .unwrap_or(zone_id);
+                (addr, Some(zone_id.to_string()))
+            }
+            None => (bracketed.unwrap_or(raw_addr), None),
+        };
+
+        let addr = if bracketed.is_some() || zone_id.is_some() {
+            Ipv6Addr::from_str(unzoned).map(IpAddr::V6)
         } else {
-            raw_addr.parse()
+            unzoned.parse()
         }
         .with_context(|| format!("Invalid address '{raw_addr}'"))?;
 
-        Ok(Resolve {
-            domain: domain.to_string(),
-            addr,
+        Ok(ResolveAddr { addr, zone_id })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteTo {
+    /// A hostname or IP address, IPv6 addresses without brackets.
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for RouteTo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (host, port) = s
+            .rsplit_once(':')
+            .context("Value should be formatted as <HOST>:<PORT>")?;
+        let host = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            Some(addr) => Ipv6Addr::from_str(addr)
+                .with_context(|| format!("Invalid IPv6 address '{addr}'"))?
+                .to_string(),
+            None if host.contains(':') => {
+                return Err(anyhow!(
+                    "IPv6 addresses should be enclosed in square brackets, e.g. [::1]:443"
+                ))
+            }
+            None if host.is_empty() => return Err(anyhow!("Missing host in '{s}'")),
+            None => host.to_string(),
+        };
+        let port = match port.parse() {
+            Ok(0) | Err(_) => return Err(anyhow!("Invalid port '{port}'")),
+            Ok(port) => port,
+        };
+        Ok(RouteTo { host, port })
+    }
+}
+
+impl fmt::Display for RouteTo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsServer(pub SocketAddr);
+
+impl FromStr for DnsServer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if let Ok(addr) = s.parse() {
+            return Ok(DnsServer(addr));
+        }
+        let addr: IpAddr = s
+            .strip_prefix('[')
+            .and_then(|addr| addr.strip_suffix(']'))
+            .unwrap_or(s)
+            .parse()
+            .with_context(|| format!("Invalid DNS server '{s}'"))?;
+        Ok(DnsServer(SocketAddr::new(addr, 53)))
+    }
+}
+
+#[derive(Debug)]
+pub struct RawSend {
+    pub host: String,
+    pub port: u16,
+    pub data: RawSendData,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RawSendData {
+    Text(String),
+    File(PathBuf),
+    Stdin,
+}
+
+impl RawSend {
+    fn from_args(args: Vec<String>) -> anyhow::Result<Self> {
+        let mut args = args.into_iter();
+        let target = args
+            .next()
+            .context("Usage: xh rawsend <HOST:PORT> [DATA]")?;
+        let (host, port) = target
+            .rsplit_once(':')
+            .filter(|(host, _)| !host.is_empty())
+            .context("Target should be formatted as <HOST>:<PORT>")?;
+        let port = port
+            .parse()
+            .with_context(|| format!("Invalid port {port:?}"))?;
+        let data = match args.next() {
+            Some(data) => match data.strip_prefix('@') {
+                Some(path) => RawSendData::File(expand_tilde(path)),
+                None => RawSendData::Text(data),
+            },
+            None => RawSendData::Stdin,
+        };
+        if let Some(extra) = args.next() {
+            return Err(anyhow!("Unexpected argument {extra:?}"));
+        }
+        Ok(RawSend {
+            host: host.to_string(),
+            port,
+            data,
         })
     }
 }
 
+/// Reconstruct the options that were passed, including those from the config
+/// file.
+///
+/// Options that have already been resolved into the URL and request items are
+/// left out, and so are credentials, which need to be redacted.
+fn given_options(app: &clap::Command, matches: &clap::ArgMatches) -> Vec<OsString> {
+    const RESOLVED: &[&str] = &[
+        "env",
+        "preset",
+        "headers_file",
+        "query_file",
+        "auth",
+        "auth_type",
+        "bearer",
+        "presign",
+        "cert_password",
+        "print_command",
+    ];
+    let mut options = Vec::new();
+    for arg in app.get_arguments() {
+        let id = arg.get_id().as_str();
+        if arg.is_positional()
+            || RESOLVED.contains(&id)
+            || matches.value_source(id) != Some(ValueSource::CommandLine)
+        {
+            continue;
+        }
+        let long = format!("--{}", arg.get_long().expect("long option"));
+        match arg.get_action() {
+            ArgAction::SetTrue => options.push(long.into()),
+            ArgAction::Count => {
+                for _ in 0..matches.get_count(id) {
+                    options.push(long.clone().into());
+                }
+            }
+            _ => {
+                for value in matches.get_raw(id).into_iter().flatten() {
+                    let mut option = OsString::from(format!("{long}="));
+                    option.push(value);
+                    options.push(option);
+                }
+            }
+        }
+    }
+    options
+}
+
+/// Read a file in the format of /etc/hosts.
+fn read_hosts_file(path: &Path) -> anyhow::Result<Vec<Resolve>> {
+    let path = expand_tilde(path);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read hosts file {}", path.display()))?;
+    parse_hosts(&content).with_context(|| format!("Invalid hosts file {}", path.display()))
+}
+
+fn parse_hosts(content: &str) -> anyhow::Result<Vec<Resolve>> {
+    let mut entries: Vec<Resolve> = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let Some(raw_addr) = fields.next() else {
+            continue;
+        };
+        let addr: IpAddr = raw_addr
+            .parse()
+            .with_context(|| format!("Invalid address '{raw_addr}' on line {}", line_number + 1))?;
+        let mut has_hostname = false;
+        for domain in fields {
+            has_hostname = true;
+            if !entries.iter().any(|entry| entry.domain == domain) {
+                entries.push(Resolve {
+                    domain: domain.to_string(),
+                    addrs: vec![ResolveAddr {
+                        addr,
+                        zone_id: None,
+                    }],
+                });
+            }
+        }
+        if !has_hostname {
+            return Err(anyhow!("Missing hostname on line {}", line_number + 1));
+        }
+    }
+    Ok(entries)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Verify {
     Yes,
     No,
     CustomCaBundle(PathBuf),
+    /// Trusted along with the built-in root certificates.
+    ExtraCaBundle(PathBuf),
 }
 
 impl clap::builder::ValueParserFactory for Verify {
@@ -1318,7 +2820,10 @@ impl clap::builder::TypedValueParser for VerifyParser {
         Ok(match value.to_ascii_lowercase().to_str() {
             Some("no") | Some("false") => Verify::No,
             Some("yes") | Some("true") => Verify::Yes,
-            _ => Verify::CustomCaBundle(PathBuf::from(value)),
+            _ => match value.to_str().and_then(|value| value.strip_prefix('+')) {
+                Some(path) => Verify::ExtraCaBundle(PathBuf::from(path)),
+                None => Verify::CustomCaBundle(PathBuf::from(value)),
+            },
         })
     }
 }
@@ -1329,6 +2834,7 @@ impl fmt::Display for Verify {
             Verify::No => write!(f, "no"),
             Verify::Yes => write!(f, "yes"),
             Verify::CustomCaBundle(path) => write!(f, "custom ca bundle: {}", path.display()),
+            Verify::ExtraCaBundle(path) => write!(f, "extra ca bundle: {}", path.display()),
         }
     }
 }
@@ -1341,6 +2847,70 @@ pub enum BodyType {
     Multipart,
 }
 
+#[derive(ValueEnum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CheckStatus {
+    /// Only the final response
+    Final,
+    /// Intermediary responses too
+    All,
+}
+
+#[derive(ValueEnum, Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InputFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BodySource {
+    Stdin,
+    File,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CertFormat {
+    #[default]
+    Pem,
+    P12,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionFormat {
+    #[default]
+    Httpie,
+    Har,
+    Netscape,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadBalance {
+    RoundRobin,
+    Random,
+}
+
+impl fmt::Display for LoadBalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LoadBalance::RoundRobin => "round-robin",
+            LoadBalance::Random => "random",
+        })
+    }
+}
+
 #[derive(ValueEnum, Debug, Clone)]
 pub enum HttpVersion {
     #[clap(name = "1.0", alias = "1")]
@@ -1369,7 +2939,7 @@ pub enum HttpVersion {
 /// BE instead.
 fn parse_encoding(encoding: &str) -> anyhow::Result<&'static Encoding> {
     let normalized_encoding = encoding.to_lowercase().replace(
-        |c: char| (!c.is_alphanumeric() && c != '_' && c != '-' && c != ':'),
+        |c: char| !c.is_alphanumeric() && c != '_' && c != '-' && c != ':',
         "",
     );
 
@@ -1758,6 +3328,33 @@ mod tests {
 
         let cli = parse(["--no-check-status", "--check-status", ":"]).unwrap();
         assert_eq!(cli.check_status, Some(true));
+        assert!(!cli.check_status_all);
+
+        let cli = parse(["--check-status=all", ":"]).unwrap();
+        assert_eq!(cli.check_status, Some(true));
+        assert!(cli.check_status_all);
+
+        let cli = parse(["--check-status=all", "--no-check-status", ":"]).unwrap();
+        assert_eq!(cli.check_status, Some(false));
+        assert!(!cli.check_status_all);
+
+        assert!(parse(["--check-status=some", ":"]).is_err());
+    }
+
+    #[test]
+    fn tcp_options() {
+        let cli = parse([":"]).unwrap();
+        assert_eq!(cli.tcp_nodelay, None);
+        assert_eq!(cli.tcp_keepalive, None);
+
+        let cli = parse(["--no-tcp-nodelay", "--tcp-keepalive=30s", ":"]).unwrap();
+        assert_eq!(cli.tcp_nodelay, Some(false));
+        assert_eq!(cli.tcp_keepalive, Some(Duration::from_secs(30)));
+
+        let cli = parse(["--no-tcp-nodelay", "--tcp-nodelay", ":"]).unwrap();
+        assert_eq!(cli.tcp_nodelay, Some(true));
+
+        parse(["--tcp-keepalive=soon", ":"]).unwrap_err();
     }
 
     #[test]
@@ -1836,7 +3433,8 @@ mod tests {
 
     #[test]
     fn merge_format_options() {
-        let format_option_one = FormatOptions::from_str("json.indent:2").unwrap();
+        let format_option_one =
+            FormatOptions::from_str("json.indent:2,headers.merge:true").unwrap();
         let format_option_two =
             FormatOptions::from_str("headers.sort:true,headers.sort:false").unwrap();
         assert_eq!(
@@ -1844,11 +3442,175 @@ mod tests {
             FormatOptions {
                 json_indent: Some(2),
                 headers_sort: Some(false),
+                headers_merge: Some(true),
                 json_format: None
             }
         )
     }
 
+    #[test]
+    fn preset_base_url_and_headers() {
+        let cli = parse([
+            "--preset",
+            "github",
+            "/repos/ducaale/xh",
+            "accept:text/plain",
+        ])
+        .unwrap();
+        assert_eq!(cli.url.as_str(), "https://api.github.com/repos/ducaale/xh");
+        assert_eq!(
+            cli.request_items.items,
+            vec![
+                RequestItem::HttpHeader("x-github-api-version".into(), "2022-11-28".into()),
+                RequestItem::HttpHeader("accept".into(), "text/plain".into()),
+            ]
+        );
+
+        parse(["--preset", "nonexistent", "/"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(15 * 60));
+        assert_eq!(
+            parse_duration("2h").unwrap(),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86400)
+        );
+        parse_duration("").unwrap_err();
+        parse_duration("-1s").unwrap_err();
+        parse_duration("10 fortnights").unwrap_err();
+
+        let skew: ClockSkew = "-30s".parse().unwrap();
+        assert_eq!(
+            skew.apply(SystemTime::UNIX_EPOCH + Duration::from_secs(100)),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(70))
+        );
+    }
+
+    #[test]
+    fn parse_byte_size() {
+        assert_eq!("0".parse::<ByteSize>().unwrap(), ByteSize(0));
+        assert_eq!("512".parse::<ByteSize>().unwrap(), ByteSize(512));
+        assert_eq!("2k".parse::<ByteSize>().unwrap(), ByteSize(2048));
+        assert_eq!("1M".parse::<ByteSize>().unwrap(), ByteSize(1024 * 1024));
+        assert_eq!("3 GiB".parse::<ByteSize>().unwrap(), ByteSize(3 << 30));
+        assert!("".parse::<ByteSize>().is_err());
+        assert!("1.5M".parse::<ByteSize>().is_err());
+        assert!("10 parsecs".parse::<ByteSize>().is_err());
+        assert!("99999999999999999999T".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn parse_print_to() {
+        let print_to = "stderr:Hhm,stdout:b".parse::<PrintTo>().unwrap();
+        assert!(print_to.stderr.request_headers);
+        assert!(print_to.stderr.response_headers);
+        assert!(print_to.stderr.response_meta);
+        assert!(!print_to.stderr.request_body);
+        assert!(!print_to.stderr.response_body);
+
+        assert!(
+            "stderr:hm,stderr:B"
+                .parse::<PrintTo>()
+                .unwrap()
+                .stderr
+                .request_body
+        );
+        assert!("stderr:h,stdout:h".parse::<PrintTo>().is_err());
+        assert!("stderr:x".parse::<PrintTo>().is_err());
+        assert!("file:h".parse::<PrintTo>().is_err());
+        assert!("hm".parse::<PrintTo>().is_err());
+    }
+
+    #[test]
+    fn parse_hosts_file() {
+        let entries = parse_hosts(indoc::indoc! {"
+            # staging
+            10.0.0.1  api.example.com www.example.com
+            ::1       localhost6  # loopback
+
+            10.0.0.2  api.example.com
+        "})
+        .unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.domain.as_str(), entry.addrs[0].addr.to_string()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("api.example.com", "10.0.0.1".to_string()),
+                ("www.example.com", "10.0.0.1".to_string()),
+                ("localhost6", "::1".to_string()),
+            ]
+        );
+
+        parse_hosts("example.com 10.0.0.1").unwrap_err();
+        parse_hosts("10.0.0.1").unwrap_err();
+    }
+
+    #[test]
+    fn https_first() {
+        let cli = parse(["--https-first", "example.org"]).unwrap();
+        assert_eq!(cli.url.as_str(), "https://example.org/");
+        assert!(cli.https_fallback);
+
+        for url in ["http://example.org", ":8000", "localhost:8000", "127.0.0.1"] {
+            let cli = parse(["--https-first", url]).unwrap();
+            assert_eq!(cli.url.scheme(), "http", "{url}");
+            assert!(!cli.https_fallback);
+        }
+    }
+
+    #[test]
+    fn parse_verify() {
+        let verify = |arg: &str| parse([arg, "example.org"]).unwrap().verify.unwrap();
+        assert_eq!(verify("--verify=no"), Verify::No);
+        assert_eq!(
+            verify("--verify=corp.pem"),
+            Verify::CustomCaBundle(PathBuf::from("corp.pem"))
+        );
+        assert_eq!(
+            verify("--verify=+corp.pem"),
+            Verify::ExtraCaBundle(PathBuf::from("corp.pem"))
+        );
+    }
+
+    #[test]
+    fn parse_dns_server() {
+        let parse = |s: &str| s.parse::<DnsServer>().map(|server| server.0.to_string());
+        assert_eq!(parse("10.0.0.2").unwrap(), "10.0.0.2:53");
+        assert_eq!(parse("10.0.0.2:5353").unwrap(), "10.0.0.2:5353");
+        assert_eq!(parse("::1").unwrap(), "[::1]:53");
+        assert_eq!(parse("[::1]").unwrap(), "[::1]:53");
+        assert_eq!(parse("[::1]:5353").unwrap(), "[::1]:5353");
+        parse("dns.example.com").unwrap_err();
+    }
+
+    #[test]
+    fn parse_route_to() {
+        let parse = |s: &str| RouteTo::from_str(s).map(|route| route.to_string());
+        assert_eq!(
+            parse("staging.internal:443").unwrap(),
+            "staging.internal:443"
+        );
+        assert_eq!(parse("10.0.0.5:8080").unwrap(), "10.0.0.5:8080");
+        assert_eq!(parse("[::1]:8443").unwrap(), "[::1]:8443");
+        assert_eq!(RouteTo::from_str("[::1]:8443").unwrap().host, "::1");
+        parse("staging.internal").unwrap_err();
+        parse("staging.internal:0").unwrap_err();
+        parse("staging.internal:https").unwrap_err();
+        parse("::1:8443").unwrap_err();
+        parse(":443").unwrap_err();
+    }
+
     #[test]
     fn parse_resolve() {
         let invalid_test_cases = [
@@ -1857,6 +3619,7 @@ mod tests {
             "example.com::::1",
             "example.com:1",
             "example.com:example.com",
+            "example.com:10.0.0.1,example.com",
             "http://example.com:127.0.0.1",
             "http://example.com:[::1]",
             "http://example.com:80:[::1]",
@@ -1869,5 +3632,33 @@ mod tests {
         assert!(Resolve::from_str("example.com:127.0.0.1").is_ok());
         assert!(Resolve::from_str("example.com:::1").is_ok());
         assert!(Resolve::from_str("example.com:[::1]").is_ok());
+
+        let resolve = Resolve::from_str("example.com:fe80::1%25eth0").unwrap();
+        assert_eq!(resolve.addrs[0].addr, "fe80::1".parse::<IpAddr>().unwrap());
+        assert_eq!(resolve.addrs[0].zone_id.as_deref(), Some("eth0"));
+        let resolve = Resolve::from_str("example.com:[fe80::1%2]").unwrap();
+        assert_eq!(resolve.addrs[0].zone_id.as_deref(), Some("2"));
+        assert!(Resolve::from_str("example.com:127.0.0.1%eth0").is_err());
+
+        let resolve = Resolve::from_str("example.com:10.0.0.1,::1,[fe80::1%eth0]").unwrap();
+        let addrs: Vec<_> = resolve.addrs.iter().map(|a| a.addr.to_string()).collect();
+        assert_eq!(addrs, ["10.0.0.1", "::1", "fe80::1"]);
+        assert!(Resolve::from_str("example.com:10.0.0.1,").is_err());
+    }
+
+    #[test]
+    fn url_with_zone_id() {
+        let cli = parse(["http://[fe80::1%25eth0]:8080/path"]).unwrap();
+        assert_eq!(cli.url.to_string(), "http://[fe80::1]:8080/path");
+        assert_eq!(cli.zone_id.as_deref(), Some("eth0"));
+        assert_eq!(cli.interface, None);
+
+        let cli = parse(["--interface=lo", "[fe80::1%eth0]/path"]).unwrap();
+        assert_eq!(cli.url.to_string(), "http://[fe80::1]/path");
+        assert_eq!(cli.zone_id.as_deref(), Some("eth0"));
+        assert_eq!(cli.interface.as_deref(), Some("lo"));
+
+        let cli = parse(["http://[fe80::1%2]/"]).unwrap();
+        assert_eq!(cli.zone_id.as_deref(), Some("2"));
     }
 }
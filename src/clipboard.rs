@@ -0,0 +1,131 @@
+//! The system clipboard, for --copy and --raw=@clipboard.
+//!
+//! There's no portable API for this, so we run the tool that each platform
+//! comes with: pbcopy and pbpaste on macOS, PowerShell on Windows,
+//! and wl-clipboard, xclip or xsel elsewhere.
+
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+
+/// The value of --raw that reads the body from the clipboard.
+pub const RAW_ARG: &str = "@clipboard";
+
+pub fn copy(data: &[u8]) -> Result<()> {
+    let mut last_err = None;
+    for tool in copy_tools() {
+        match run(tool, Some(data)) {
+            Ok(_) => return Ok(()),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err
+        .unwrap_or_else(|| anyhow!("not supported on this platform"))
+        .context("Unable to copy to the clipboard"))
+}
+
+pub fn paste() -> Result<Vec<u8>> {
+    let mut last_err = None;
+    for tool in paste_tools() {
+        match run(tool, None) {
+            Ok(output) => return Ok(output),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err
+        .unwrap_or_else(|| anyhow!("not supported on this platform"))
+        .context("Unable to read the clipboard"))
+}
+
+/// Run a clipboard tool, with `input` on its stdin, and return its output.
+fn run(tool: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>> {
+    let (program, args) = tool.split_first().expect("tool has a program");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(if input.is_some() {
+            Stdio::null()
+        } else {
+            Stdio::piped()
+        })
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("couldn't run {program}"))?;
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin.write_all(input)?;
+    }
+    let mut output = Vec::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_end(&mut output)?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("{program} failed ({status})"));
+    }
+    Ok(output)
+}
+
+#[cfg(target_os = "macos")]
+fn copy_tools() -> Vec<&'static [&'static str]> {
+    vec![&["pbcopy"]]
+}
+
+#[cfg(target_os = "macos")]
+fn paste_tools() -> Vec<&'static [&'static str]> {
+    vec![&["pbpaste"]]
+}
+
+#[cfg(windows)]
+fn copy_tools() -> Vec<&'static [&'static str]> {
+    vec![&[
+        "powershell",
+        "-NoProfile",
+        "-Command",
+        "$input | Set-Clipboard",
+    ]]
+}
+
+#[cfg(windows)]
+fn paste_tools() -> Vec<&'static [&'static str]> {
+    vec![&["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"]]
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn copy_tools() -> Vec<&'static [&'static str]> {
+    let mut tools: Vec<&[&str]> = vec![
+        &["xclip", "-selection", "clipboard", "-in"],
+        &["xsel", "--clipboard", "--input"],
+    ];
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.insert(0, &["wl-copy"]);
+    }
+    tools
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn paste_tools() -> Vec<&'static [&'static str]> {
+    let mut tools: Vec<&[&str]> = vec![
+        &["xclip", "-selection", "clipboard", "-out"],
+        &["xsel", "--clipboard", "--output"],
+    ];
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.insert(0, &["wl-paste", "--no-newline"]);
+    }
+    tools
+}
+
+#[cfg(not(any(unix, windows)))]
+fn copy_tools() -> Vec<&'static [&'static str]> {
+    Vec::new()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn paste_tools() -> Vec<&'static [&'static str]> {
+    Vec::new()
+}
@@ -0,0 +1,532 @@
+//! The config file, `config.json` in the config directory, and "xh config"
+//! for managing it:
+//!
+//! ```json
+//! {
+//!     "default_options": ["--follow", "--timeout=30"]
+//! }
+//! ```
+//!
+//! `//` and `/* */` comments are allowed, as written by "xh init".
+//!
+//! "xh config" checks keys and values before it writes anything, and never
+//! leaves a half-written file behind. Keys it doesn't know about and comments
+//! are kept.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::formatting::background::Background;
+use crate::utils::config_dir;
+
+/// The keys that config.json can have, with a description of their value.
+const KEYS: &[(&str, &str)] = &[
+    (
+        "default_options",
+        "a list of options to add before the ones on the command line",
+    ),
+    (
+        "editor",
+        "the editor for --edit and \"xh config edit\", instead of $VISUAL or $EDITOR",
+    ),
+    (
+        "confirm_methods",
+        "a list of methods, like \"DELETE\", to ask for confirmation before sending",
+    ),
+    (
+        "background",
+        "\"light\" or \"dark\", for --style=auto instead of asking the terminal",
+    ),
+];
+
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    default_options: Vec<String>,
+    #[serde(default)]
+    editor: Option<String>,
+    #[serde(default)]
+    confirm_methods: Vec<String>,
+    #[serde(default)]
+    background: Option<Background>,
+}
+
+pub fn path() -> Result<PathBuf> {
+    Ok(config_dir()
+        .context("Couldn't find the config directory")?
+        .join("config.json"))
+}
+
+pub fn default_cli_args() -> Option<Vec<String>> {
+    let content = match fs::read_to_string(config_dir()?.join("config.json")) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                // Can't use log::warn!() because logging isn't initialized yet
+                eprintln!(
+                    "\n{}: warning: Unable to read config file: {}\n",
+                    env!("CARGO_PKG_NAME"),
+                    err
+                );
+            }
+            None
+        }
+    }?;
+
+    match serde_json::from_str::<Config>(&strip_comments(&content)) {
+        Ok(config) => Some(config.default_options),
+        Err(err) => {
+            eprintln!(
+                "\n{}: warning: Unable to parse config file: {}\n",
+                env!("CARGO_PKG_NAME"),
+                err
+            );
+            None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigCommand {
+    List,
+    Get(String),
+    Set(String, Value),
+    Edit,
+}
+
+impl ConfigCommand {
+    pub fn from_args(args: Vec<String>) -> Result<Self> {
+        const USAGE: &str = "Usage: xh config <list | get KEY | set KEY VALUE | edit>";
+        let mut args = args.into_iter();
+        let command = match args.next().as_deref() {
+            Some("list") => ConfigCommand::List,
+            Some("get") => ConfigCommand::Get(check_key(args.next().context(USAGE)?)?),
+            Some("set") => {
+                let key = check_key(args.next().context(USAGE)?)?;
+                let value = args.next().context(USAGE)?;
+                let value = serde_json::from_str(&value).with_context(|| {
+                    format!("The value for {key} should be JSON, e.g. '[\"--follow\"]'")
+                })?;
+                ConfigCommand::Set(key, value)
+            }
+            Some("edit") => ConfigCommand::Edit,
+            _ => return Err(anyhow!(USAGE)),
+        };
+        if let Some(extra) = args.next() {
+            return Err(anyhow!("Unexpected argument {extra:?}"));
+        }
+        Ok(command)
+    }
+
+    /// Returns the exit status, which is 1 for "get" on a key that isn't set.
+    pub fn run(&self) -> Result<i32> {
+        let path = path()?;
+        match self {
+            ConfigCommand::List => {
+                for (key, value) in read(&path)? {
+                    println!("{key} = {value}");
+                }
+            }
+            ConfigCommand::Get(key) => match read(&path)?.get(key) {
+                Some(value) => println!("{value}"),
+                None => return Ok(1),
+            },
+            ConfigCommand::Set(key, value) => {
+                let mut config = read(&path)?;
+                config.insert(key.clone(), value.clone());
+                serde_json::from_value::<Config>(Value::Object(config))
+                    .with_context(|| format!("Invalid value for {key}"))?;
+                let content = match fs::read_to_string(&path) {
+                    Ok(content) => content,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => "{}".to_string(),
+                    Err(err) => {
+                        return Err(err)
+                            .with_context(|| format!("Unable to read {}", path.display()))
+                    }
+                };
+                save(&path, &set_value(&content, key, value)?)?;
+            }
+            ConfigCommand::Edit => edit(&path)?,
+        }
+        Ok(0)
+    }
+}
+
+fn check_key(key: String) -> Result<String> {
+    if KEYS.iter().any(|(name, _)| *name == key) {
+        return Ok(key);
+    }
+    let known: Vec<String> = KEYS
+        .iter()
+        .map(|(name, description)| format!("{name} ({description})"))
+        .collect();
+    Err(anyhow!(
+        "Unknown config key {key:?}, expected one of: {}",
+        known.join(", ")
+    ))
+}
+
+fn read(path: &Path) -> Result<Map<String, Value>> {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            parse(&content).with_context(|| format!("Unable to parse {}", path.display()))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Map::new()),
+        Err(err) => Err(err).with_context(|| format!("Unable to read {}", path.display())),
+    }
+}
+
+fn parse(content: &str) -> Result<Map<String, Value>> {
+    match serde_json::from_str(&strip_comments(content))? {
+        Value::Object(config) => Ok(config),
+        _ => Err(anyhow!("The config should be a JSON object")),
+    }
+}
+
+/// Check a whole config, as opposed to a single value.
+pub fn validate(content: &str) -> Result<()> {
+    let config = parse(content)?;
+    for key in config.keys() {
+        check_key(key.clone())?;
+    }
+    serde_json::from_value::<Config>(Value::Object(config))?;
+    Ok(())
+}
+
+/// Write to a temporary file first so that the config is never left half
+/// written.
+pub fn save(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Unable to create {}", dir.display()))?;
+    }
+    let temp_path = sibling(path, ".tmp");
+    let mut content = content.to_string();
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    fs::write(&temp_path, content)
+        .with_context(|| format!("Unable to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Open a copy of the config in an editor, and put it in place if it's
+/// still valid afterwards.
+fn edit(path: &Path) -> Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            "{\n    \"default_options\": []\n}\n".to_string()
+        }
+        Err(err) => return Err(err).with_context(|| format!("Unable to read {}", path.display())),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Unable to create {}", dir.display()))?;
+    }
+    let draft_path = sibling(path, ".edit");
+    fs::write(&draft_path, content)
+        .with_context(|| format!("Unable to write {}", draft_path.display()))?;
+
+    if let Err(err) = open_editor(&draft_path) {
+        fs::remove_file(&draft_path).ok();
+        return Err(err.context("The config is unchanged"));
+    }
+
+    let content = fs::read_to_string(&draft_path)?;
+    if let Err(err) = validate(&content) {
+        return Err(err.context(format!(
+            "Not saving the config, your changes are in {}",
+            draft_path.display()
+        )));
+    }
+    fs::rename(&draft_path, path).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Let the user edit a file, with the editor from the config or else $VISUAL
+/// or $EDITOR.
+pub fn open_editor(path: &Path) -> Result<()> {
+    let configured = load().and_then(|config| config.editor);
+    let editor = configured
+        .map(OsString::from)
+        .or_else(|| env::var_os("VISUAL"))
+        .or_else(|| env::var_os("EDITOR"))
+        .filter(|editor| !editor.is_empty())
+        .unwrap_or_else(|| OsString::from(if cfg!(windows) { "notepad" } else { "vi" }));
+    let editor = editor.to_string_lossy();
+    // It may come with arguments, like "code --wait"
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args.next().unwrap_or_default();
+    let status = Command::new(program)
+        .args(editor_args)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Unable to run {editor}"))?;
+    if !status.success() {
+        return Err(anyhow!("{editor} failed ({status})"));
+    }
+    Ok(())
+}
+
+/// The methods that --confirm is implied for.
+pub fn confirm_methods() -> Vec<String> {
+    load()
+        .map(|config| config.confirm_methods)
+        .unwrap_or_default()
+}
+
+/// The terminal background to assume for --style=auto.
+pub fn background() -> Option<Background> {
+    load().and_then(|config| config.background)
+}
+
+/// Read the config, quietly giving up if there isn't a valid one. Only
+/// [`default_cli_args`] reports problems, since it always runs first.
+fn load() -> Option<Config> {
+    let content = fs::read_to_string(path().ok()?).ok()?;
+    serde_json::from_str(&strip_comments(&content)).ok()
+}
+
+/// Blank out comments. Everything else stays at the same byte offset, so
+/// positions in the result can be used on the original.
+pub fn strip_comments(content: &str) -> String {
+    fn blank(out: &mut String, c: char) {
+        if c == '\n' {
+            out.push('\n');
+        } else {
+            out.extend(std::iter::repeat(' ').take(c.len_utf8()));
+        }
+    }
+
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                blank(&mut out, c);
+                while let Some(c) = chars.next_if(|&c| c != '\n') {
+                    blank(&mut out, c);
+                }
+            }
+            ('/', Some('*')) => {
+                blank(&mut out, c);
+                blank(&mut out, chars.next().unwrap_or_default());
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    blank(&mut out, c);
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Replace or add a top-level value, leaving the rest of the text (like
+/// comments and formatting) as it is. `content` must be a valid config.
+fn set_value(content: &str, key: &str, value: &Value) -> Result<String> {
+    let stripped = strip_comments(content);
+    let (values, close) = top_level_values(&stripped).context("Unable to parse the config")?;
+    let value = serde_json::to_string(value)?;
+    let mut content = content.to_string();
+    // Like serde_json, the last occurrence of a key wins
+    match values.iter().rev().find(|(name, _)| name == key) {
+        Some((_, range)) => content.replace_range(range.clone(), &value),
+        None => {
+            let key = serde_json::to_string(key)?;
+            match values.last() {
+                Some((_, range)) => {
+                    content.insert_str(range.end, &format!(",\n    {key}: {value}"));
+                }
+                None => content.insert_str(close, &format!("\n    {key}: {value}\n")),
+            }
+        }
+    }
+    Ok(content)
+}
+
+/// Keys with the byte range of their value.
+type ValueRanges = Vec<(String, Range<usize>)>;
+
+/// Find the keys of a JSON object without comments, with the byte range of
+/// each value, and the position of the closing brace.
+fn top_level_values(json: &str) -> Option<(ValueRanges, usize)> {
+    fn skip_whitespace(json: &str, pos: usize) -> usize {
+        json.len() - json[pos..].trim_start().len()
+    }
+    fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+        let mut pos = start + 1;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\\' => pos += 2,
+                b'"' => return Some(pos + 1),
+                _ => pos += 1,
+            }
+        }
+        None
+    }
+
+    let bytes = json.as_bytes();
+    let mut values = Vec::new();
+    let mut pos = json.find('{')? + 1;
+    loop {
+        pos = skip_whitespace(json, pos);
+        match *bytes.get(pos)? {
+            b'}' => return Some((values, pos)),
+            b',' => {
+                pos += 1;
+                continue;
+            }
+            b'"' => {}
+            _ => return None,
+        }
+        let key_end = string_end(bytes, pos)?;
+        let key: String = serde_json::from_str(&json[pos..key_end]).ok()?;
+        pos = skip_whitespace(json, key_end);
+        if bytes.get(pos) != Some(&b':') {
+            return None;
+        }
+        let start = skip_whitespace(json, pos + 1);
+        let mut depth = 0;
+        pos = start;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'"' => {
+                    pos = string_end(bytes, pos)?;
+                    continue;
+                }
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth == 0 => break,
+                b'}' | b']' => depth -= 1,
+                b',' if depth == 0 => break,
+                _ => {}
+            }
+            pos += 1;
+        }
+        let end = start + json[start..pos].trim_end().len();
+        values.push((key, start..end));
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<ConfigCommand> {
+        ConfigCommand::from_args(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn command_parsing() {
+        assert_eq!(parse_args(&["list"]).unwrap(), ConfigCommand::List);
+        assert_eq!(parse_args(&["edit"]).unwrap(), ConfigCommand::Edit);
+        assert_eq!(
+            parse_args(&["get", "default_options"]).unwrap(),
+            ConfigCommand::Get("default_options".into())
+        );
+        assert_eq!(
+            parse_args(&["set", "default_options", r#"["--follow"]"#]).unwrap(),
+            ConfigCommand::Set("default_options".into(), serde_json::json!(["--follow"]))
+        );
+
+        assert!(parse_args(&[])
+            .unwrap_err()
+            .to_string()
+            .starts_with("Usage:"));
+        assert!(parse_args(&["get"]).is_err());
+        assert!(parse_args(&["list", "extra"]).is_err());
+        assert!(parse_args(&["set", "default_options", "--follow"])
+            .unwrap_err()
+            .to_string()
+            .contains("should be JSON"));
+        assert!(parse_args(&["get", "default_option"])
+            .unwrap_err()
+            .to_string()
+            .starts_with(
+                "Unknown config key \"default_option\", expected one of: default_options"
+            ));
+    }
+
+    #[test]
+    fn comments() {
+        let content = r#"// Top
+{
+    /* "a": 1, */
+    "url": "http://x//y", // not a comment in the string
+    "escaped": "\"//"
+}"#;
+        let stripped = strip_comments(content);
+        assert_eq!(stripped.len(), content.len());
+        assert_eq!(
+            serde_json::from_str::<Value>(&stripped).unwrap(),
+            serde_json::json!({"url": "http://x//y", "escaped": "\"//"})
+        );
+    }
+
+    #[test]
+    fn setting_values() {
+        let content = r#"// Keep me
+{
+    "default_options": ["--follow"], // And me
+    "other": {"a": [1, "]"]}
+}
+"#;
+        let value = serde_json::json!(["--form"]);
+        assert_eq!(
+            set_value(content, "default_options", &value).unwrap(),
+            r#"// Keep me
+{
+    "default_options": ["--form"], // And me
+    "other": {"a": [1, "]"]}
+}
+"#
+        );
+        assert_eq!(
+            set_value(content, "editor", &"nano".into()).unwrap(),
+            r#"// Keep me
+{
+    "default_options": ["--follow"], // And me
+    "other": {"a": [1, "]"]},
+    "editor": "nano"
+}
+"#
+        );
+        assert_eq!(
+            set_value("{}", "editor", &"nano".into()).unwrap(),
+            "{\n    \"editor\": \"nano\"\n}"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! --confirm, and the "confirm_methods" config key: show the request and ask
+//! before sending it, so that a DELETE to the wrong host can still be caught.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Method;
+
+use crate::config;
+use crate::utils::test_mode;
+
+/// Whether to ask before sending a request with `method`.
+pub fn is_needed(method: &Method, confirm: bool) -> bool {
+    confirm
+        || config::confirm_methods()
+            .iter()
+            .any(|configured| configured.eq_ignore_ascii_case(method.as_str()))
+}
+
+/// Ask whether to send the request. The answer is read from the terminal
+/// even if stdin is redirected, since stdin may hold the request body.
+pub fn ask() -> Result<bool> {
+    eprint!("Send this request? (y/N) ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    let read = if io::stdin().is_terminal() || test_mode() {
+        io::stdin().lock().read_line(&mut answer)?
+    } else {
+        let terminal = File::open(if cfg!(windows) { "CONIN$" } else { "/dev/tty" })
+            .context("Unable to ask for confirmation without a terminal")?;
+        BufReader::new(terminal).read_line(&mut answer)?
+    };
+    if read == 0 {
+        eprintln!();
+    }
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(true),
+        "" | "n" | "no" => Ok(false),
+        other => Err(anyhow!(
+            "Unexpected answer {other:?}, the request wasn't sent"
+        )),
+    }
+}
@@ -0,0 +1,176 @@
+//! File names for downloads, from the Content-Disposition header or the URL.
+//!
+//! These names come from the server, so they're reduced to a single plain
+//! file name that's safe on every platform before we create anything.
+
+use mime2ext::mime2ext;
+use regex_lite::Regex;
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+
+use crate::utils::HeaderValueExt;
+
+pub fn file_name(headers: &HeaderMap) -> Option<String> {
+    let quoted = Regex::new("filename=\"([^\"]*)\"").unwrap();
+    // Alternative form:
+    let unquoted = Regex::new("filename=([^;=\"]*)").unwrap();
+    // TODO: support "filename*" version
+
+    let header = headers.get(CONTENT_DISPOSITION)?.to_utf8_str().ok()?;
+    let caps = quoted
+        .captures(header)
+        .or_else(|| unquoted.captures(header))?;
+    Some(caps[1].to_string())
+}
+
+/// Make a file name safe to create in the current directory, and add an
+/// extension based on the Content-Type if there isn't one yet.
+///
+/// Directories are stripped (with either kind of slash, on any platform),
+/// as are control characters and leading dots. Characters and names that
+/// Windows reserves are replaced, and long names are shortened. The result
+/// may be empty.
+pub fn sanitize_file_name(file_name: &str, headers: &HeaderMap) -> String {
+    let base_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    if base_name.len() != file_name.len() {
+        log::warn!("Ignoring the directories in the file name {file_name:?}");
+    }
+
+    let file_name: String = base_name
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            // A colon would also make "C:foo" a path relative to drive C
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces by itself
+    let mut file_name = file_name
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' '])
+        .to_string();
+    if file_name.is_empty() {
+        return file_name;
+    }
+
+    let stem = file_name.split('.').next().unwrap_or_default();
+    if is_reserved_on_windows(stem.trim_end()) {
+        file_name.insert(0, '_');
+    }
+
+    if !file_name.contains('.') {
+        if let Some(extension) = headers
+            .get(CONTENT_TYPE)
+            .and_then(|mimetype| mimetype.to_str().ok())
+            .and_then(mime2ext)
+        {
+            file_name.push('.');
+            file_name.push_str(extension);
+        }
+    }
+
+    truncate_file_name(file_name)
+}
+
+/// Most file systems allow 255 bytes (or UTF-16 units on Windows) per name.
+/// Keep some room for the suffixes that downloads add, like "-1".
+const MAX_FILE_NAME_LEN: usize = 240;
+
+/// Shorten a long name, keeping its extension if that's short enough.
+fn truncate_file_name(file_name: String) -> String {
+    if file_name.len() <= MAX_FILE_NAME_LEN {
+        return file_name;
+    }
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.len() <= 16 => {
+            (stem, &file_name[stem.len()..])
+        }
+        _ => (file_name.as_str(), ""),
+    };
+    let mut end = MAX_FILE_NAME_LEN - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    log::warn!("Shortening the file name {file_name:?}");
+    let stem = stem[..end].trim_end_matches(['.', ' ']);
+    format!("{stem}{extension}")
+}
+
+/// Device names like "NUL" can't be used as file names on Windows, even with
+/// an extension.
+fn is_reserved_on_windows(stem: &str) -> bool {
+    let stem = stem.to_ascii_uppercase();
+    matches!(
+        stem.as_bytes(),
+        b"CON"
+            | b"PRN"
+            | b"AUX"
+            | b"NUL"
+            | [b'C', b'O', b'M', b'1'..=b'9']
+            | [b'L', b'P', b'T', b'1'..=b'9']
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn file_names_from_header() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_DISPOSITION, HeaderValue::from_static(value));
+            headers
+        };
+        assert_eq!(
+            file_name(&headers(r#"attachment; filename="foo bar.txt""#)).as_deref(),
+            Some("foo bar.txt")
+        );
+        assert_eq!(
+            file_name(&headers("attachment; filename=foo.txt; size=3")).as_deref(),
+            Some("foo.txt")
+        );
+        assert_eq!(file_name(&headers("inline")), None);
+    }
+
+    #[test]
+    fn adversarial_file_names() {
+        let headers = HeaderMap::new();
+        let sanitize = |file_name| sanitize_file_name(file_name, &headers);
+        assert_eq!(sanitize("../../.bashrc"), "bashrc");
+        assert_eq!(sanitize("/etc/passwd"), "passwd");
+        assert_eq!(sanitize(r"..\..\Windows\win.ini"), "win.ini");
+        assert_eq!(sanitize(r"\\server\share\x.exe"), "x.exe");
+        assert_eq!(sanitize("C:autoexec.bat"), "C_autoexec.bat");
+        assert_eq!(sanitize(".."), "");
+        assert_eq!(sanitize("foo/"), "");
+        assert_eq!(sanitize("a\0b\r\nc\x1b[31m.txt"), "abc[31m.txt");
+        assert_eq!(sanitize("what?<*>|\".txt"), "what______.txt");
+        assert_eq!(sanitize("report.txt. . "), "report.txt");
+        assert_eq!(sanitize("NUL"), "_NUL");
+        assert_eq!(sanitize("com1.tar.gz"), "_com1.tar.gz");
+        assert_eq!(sanitize("lpt9 .txt"), "_lpt9 .txt");
+        assert_eq!(sanitize("COM10.txt"), "COM10.txt");
+        assert_eq!(sanitize("console.log"), "console.log");
+        assert_eq!(sanitize("😀.bar"), "😀.bar");
+
+        let long = sanitize_file_name(&format!("{}.tar.gz", "a".repeat(300)), &headers);
+        assert_eq!(long.len(), MAX_FILE_NAME_LEN);
+        assert!(long.ends_with("aaa.gz"));
+        let long = sanitize_file_name(&"😀".repeat(100), &headers);
+        assert!(long.len() <= MAX_FILE_NAME_LEN);
+        assert_eq!(long.chars().count(), MAX_FILE_NAME_LEN / 4);
+    }
+
+    #[test]
+    fn extension_from_content_type() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        assert_eq!(sanitize_file_name("data", &headers), "data.json");
+        assert_eq!(sanitize_file_name("data.txt", &headers), "data.txt");
+        assert_eq!(sanitize_file_name("../", &headers), "");
+    }
+}
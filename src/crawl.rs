@@ -0,0 +1,545 @@
+//! A link checker, for "xh crawl", and robots.txt handling for it and
+//! --mirror.
+//!
+//! Starting from one page, every link is requested and the pages on the same
+//! site are searched for more links, up to a depth. Links that end in an
+//! error status or can't be fetched at all are reported, along with the
+//! redirects that led there.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex_lite::Regex;
+use reqwest::blocking::{Client, Request, Response};
+use reqwest::header::{HeaderMap, CONTENT_TYPE, USER_AGENT};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+use url::Url;
+
+use crate::cli::ReportFormat;
+use crate::middleware::ClientWithMiddleware;
+use crate::redirect::RedirectFollower;
+use crate::retry::RetryStaleConnection;
+
+const DEFAULT_MAX_DEPTH: usize = 2;
+
+pub struct Crawler<'a> {
+    client: &'a Client,
+    start: Url,
+    headers: HeaderMap,
+    max_depth: usize,
+    same_host: bool,
+    max_redirects: usize,
+    respect_robots: bool,
+}
+
+#[derive(Serialize)]
+pub struct BrokenLink {
+    pub url: String,
+    /// The page that links here, or None for the starting page.
+    pub found_on: Option<String>,
+    /// The final status, if there was a response.
+    pub status: Option<u16>,
+    pub redirects: Vec<Redirect>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Redirect {
+    pub status: u16,
+    pub url: String,
+}
+
+pub struct Report {
+    pub checked: usize,
+    pub broken: Vec<BrokenLink>,
+    /// Links that robots.txt asked us not to visit.
+    pub disallowed: usize,
+}
+
+impl<'a> Crawler<'a> {
+    /// Crawl from the URL of `start`, sending its headers with every request.
+    pub fn new(client: &'a Client, start: &Request) -> Self {
+        Crawler {
+            client,
+            start: start.url().clone(),
+            headers: start.headers().clone(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            same_host: false,
+            max_redirects: 10,
+            respect_robots: true,
+        }
+    }
+
+    /// How many links away from the starting page to go.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        self
+    }
+
+    /// Don't check links to other hosts at all.
+    pub fn with_same_host(mut self, same_host: bool) -> Self {
+        self.same_host = same_host;
+        self
+    }
+
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Follow the rules in robots.txt files.
+    pub fn with_respect_robots(mut self, respect_robots: bool) -> Self {
+        self.respect_robots = respect_robots;
+        self
+    }
+
+    pub fn run(&self) -> Result<Report> {
+        let mut queue = VecDeque::from([(self.start.clone(), 0, None)]);
+        let mut seen = HashSet::from([self.start.clone()]);
+        let mut robots = self.respect_robots.then(|| Robots::new(&self.headers));
+        let mut report = Report {
+            checked: 0,
+            broken: Vec::new(),
+            disallowed: 0,
+        };
+        while let Some((url, depth, found_on)) = queue.pop_front() {
+            if let Some(robots) = &mut robots {
+                if !robots.admit(&url, |url| self.fetch(&url, &mut Vec::new())) {
+                    log::debug!("Skipping {url}, robots.txt disallows it");
+                    report.disallowed += 1;
+                    continue;
+                }
+            }
+            report.checked += 1;
+            let mut redirects = Vec::new();
+            let response = self.fetch(&url, &mut redirects);
+            let broken = |status, error| BrokenLink {
+                url: url.to_string(),
+                found_on: found_on.as_ref().map(Url::to_string),
+                status,
+                redirects,
+                error,
+            };
+            let response = match response {
+                Ok(response) if response.status().is_success() => response,
+                Ok(response) => {
+                    log::debug!("{url}: {}", response.status());
+                    report
+                        .broken
+                        .push(broken(Some(response.status().as_u16()), None));
+                    continue;
+                }
+                Err(err) => {
+                    let error = err.root_cause().to_string();
+                    report.broken.push(broken(None, Some(error)));
+                    continue;
+                }
+            };
+
+            // Only pages on the starting site are searched for more links
+            if depth >= self.max_depth || response.url().host_str() != self.start.host_str() {
+                continue;
+            }
+            let page = response.url().clone();
+            let is_xml = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.contains("xml"));
+            let body = match response.text() {
+                Ok(body) => body,
+                Err(err) => {
+                    log::warn!("Couldn't read {page}: {err}");
+                    continue;
+                }
+            };
+            let links = if is_xml {
+                find_sitemap_links(&body)
+            } else {
+                find_html_links(&body)
+            };
+            for link in links {
+                let Ok(mut link) = page.join(&link) else {
+                    continue;
+                };
+                link.set_fragment(None);
+                if !matches!(link.scheme(), "http" | "https")
+                    || (self.same_host && link.host_str() != self.start.host_str())
+                {
+                    continue;
+                }
+                if seen.insert(link.clone()) {
+                    queue.push_back((link, depth + 1, Some(page.clone())));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// GET a URL, following redirects and collecting them in `redirects`.
+    fn fetch(&self, url: &Url, redirects: &mut Vec<Redirect>) -> Result<Response> {
+        let mut request = Request::new(Method::GET, url.clone());
+        *request.headers_mut() = self.headers.clone();
+        let mut client = ClientWithMiddleware::new(self.client)
+            .with_printer(|prev_response: &mut Response, _: &mut Request| {
+                redirects.push(Redirect {
+                    status: prev_response.status().as_u16(),
+                    url: prev_response.url().to_string(),
+                });
+                Ok(())
+            })
+            .with(RedirectFollower::new(self.max_redirects, false))
+            .with(RetryStaleConnection::new(false));
+        client.execute(request)
+    }
+}
+
+impl Report {
+    pub fn write(&self, out: &mut impl Write, format: ReportFormat) -> Result<()> {
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, &self.broken)?;
+                writeln!(out)?;
+            }
+            ReportFormat::Table => {
+                if self.broken.is_empty() {
+                    return Ok(());
+                }
+                let rows: Vec<[String; 4]> = self
+                    .broken
+                    .iter()
+                    .map(|link| {
+                        let mut status: Vec<String> = link
+                            .redirects
+                            .iter()
+                            .map(|redirect| redirect.status.to_string())
+                            .collect();
+                        status.push(match link.status {
+                            Some(status) => status.to_string(),
+                            None => "ERR".to_string(),
+                        });
+                        let reason = match (&link.error, link.status) {
+                            (Some(error), _) => error.clone(),
+                            (None, status) => status
+                                .and_then(|status| StatusCode::from_u16(status).ok())
+                                .and_then(|status| status.canonical_reason())
+                                .unwrap_or_default()
+                                .to_string(),
+                        };
+                        [
+                            status.join(" → "),
+                            link.url.clone(),
+                            link.found_on.clone().unwrap_or_else(|| "-".to_string()),
+                            reason,
+                        ]
+                    })
+                    .collect();
+                let header = ["STATUS", "URL", "FOUND ON", "REASON"].map(String::from);
+                let mut widths = [0; 3];
+                for row in std::iter::once(&header).chain(&rows) {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.width());
+                    }
+                }
+                for row in std::iter::once(&header).chain(&rows) {
+                    for (width, cell) in widths.iter().zip(row) {
+                        write!(out, "{cell}{}  ", " ".repeat(width - cell.width()))?;
+                    }
+                    writeln!(out, "{}", row[3])?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Links in `href` and `src` attributes.
+fn find_html_links(html: &str) -> Vec<String> {
+    static LINK: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"(?i)\s(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+    LINK.captures_iter(html)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        .map(|link| link.as_str().trim().replace("&amp;", "&"))
+        .filter(|link| !link.is_empty())
+        .collect()
+}
+
+/// The pages listed in a sitemap, or the sitemaps in a sitemap index.
+fn find_sitemap_links(xml: &str) -> Vec<String> {
+    static LOC: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<loc>\s*([^<]*?)\s*</loc>").unwrap());
+    LOC.captures_iter(xml)
+        .map(|caps| caps[1].replace("&amp;", "&"))
+        .collect()
+}
+
+/// The robots.txt rules of each site we visit, fetched on first use.
+pub struct Robots {
+    /// Our name in User-agent lines.
+    agent: String,
+    sites: HashMap<String, Site>,
+}
+
+struct Site {
+    rules: Rules,
+    last_visit: Option<Instant>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Rules {
+    /// Allow (true) or Disallow (false) rules with their path patterns.
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Rules are looked up for the product in the User-Agent header, e.g.
+    /// "xh" for "xh/0.23.1".
+    pub fn new(headers: &HeaderMap) -> Self {
+        let agent = headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(['/', ' ']).next())
+            .filter(|agent| !agent.is_empty())
+            .unwrap_or("xh")
+            .to_lowercase();
+        Robots {
+            agent,
+            sites: HashMap::new(),
+        }
+    }
+
+    /// Whether we may visit `url`. If so, first wait out the site's crawl
+    /// delay. `send` is used to request robots.txt.
+    pub fn admit(&mut self, url: &Url, send: impl FnOnce(Url) -> Result<Response>) -> bool {
+        let origin = url.origin().ascii_serialization();
+        let site = self.sites.entry(origin).or_insert_with(|| Site {
+            rules: fetch_rules(url, &self.agent, send),
+            last_visit: None,
+        });
+
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+        if !site.rules.is_allowed(&path) {
+            return false;
+        }
+        if let (Some(delay), Some(last_visit)) = (site.rules.crawl_delay, site.last_visit) {
+            let wait = delay.saturating_sub(last_visit.elapsed());
+            if !wait.is_zero() {
+                log::debug!(
+                    "Waiting {wait:?} for the crawl delay of {}",
+                    url.origin().ascii_serialization()
+                );
+                thread::sleep(wait);
+            }
+        }
+        site.last_visit = Some(Instant::now());
+        true
+    }
+}
+
+fn fetch_rules(url: &Url, agent: &str, send: impl FnOnce(Url) -> Result<Response>) -> Rules {
+    let Ok(robots_url) = url.join("/robots.txt") else {
+        return Rules::default();
+    };
+    // See RFC 9309, section 2.3.1: no file means no rules, but if the
+    // server is failing we can't tell what it wants. If the site can't be
+    // reached at all the request itself will fail, which a link checker
+    // should report rather than skip.
+    let unreachable = |reason: String| {
+        log::warn!("{robots_url} is unavailable ({reason}), so not visiting the site. Use --ignore-robots to visit it anyway.");
+        Rules {
+            rules: vec![(false, "/".to_string())],
+            crawl_delay: None,
+        }
+    };
+    match send(robots_url.clone()) {
+        Ok(response) if response.status().is_success() => match response.text() {
+            Ok(text) => Rules::parse(&text, agent),
+            Err(err) => unreachable(err.to_string()),
+        },
+        Ok(response) if response.status().is_client_error() => Rules::default(),
+        Ok(response) => unreachable(response.status().to_string()),
+        Err(_) => Rules::default(),
+    }
+}
+
+impl Rules {
+    /// Get the rules for `agent`, or the ones for everyone if there are none
+    /// specifically for us.
+    fn parse(text: &str, agent: &str) -> Rules {
+        let mut ours = Rules::default();
+        let mut everyone = Rules::default();
+        let mut found_ours = false;
+        // Which of the two the current group applies to. A group starts
+        // with one or more User-agent lines.
+        let (mut for_us, mut for_everyone) = (false, false);
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        (for_us, for_everyone) = (false, false);
+                        in_agents = true;
+                    }
+                    if value.eq_ignore_ascii_case(agent) {
+                        for_us = true;
+                        found_ours = true;
+                    } else if value == "*" {
+                        for_everyone = true;
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // An empty Disallow allows everything, so it's not a rule
+                    if value.is_empty() {
+                        continue;
+                    }
+                    for (applies, rules) in [(for_us, &mut ours), (for_everyone, &mut everyone)] {
+                        if applies {
+                            rules.rules.push((key == "allow", value.to_string()));
+                        }
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    let delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                        .map(Duration::from_secs_f64);
+                    for (applies, rules) in [(for_us, &mut ours), (for_everyone, &mut everyone)] {
+                        if applies {
+                            rules.crawl_delay = delay;
+                        }
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+        if found_ours {
+            ours
+        } else {
+            everyone
+        }
+    }
+
+    /// The most specific (longest) matching rule wins, and Allow wins a tie.
+    fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+}
+
+/// Match a robots.txt path pattern, where `*` matches anything and a
+/// trailing `$` anchors it to the end of the path.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_links() {
+        let html = r#"<link rel="stylesheet" href="/style.css">
+            <a href='page?a=1&amp;b=2'>x</a> <img src="img.png" alt="">
+            <a href="">empty</a> <a data-href="nope">y</a>"#;
+        assert_eq!(
+            find_html_links(html),
+            ["/style.css", "page?a=1&b=2", "img.png"]
+        );
+    }
+
+    #[test]
+    fn robots_rules() {
+        let text = "
+            User-agent: *
+            Disallow: /private/
+            Crawl-delay: 2
+
+            # Just for us
+            User-agent: other
+            User-agent: XH
+            Disallow: /*.pdf$
+            Disallow: /admin
+            Allow: /admin/public
+            Disallow:
+        ";
+        let rules = Rules::parse(text, "xh");
+        assert_eq!(rules.crawl_delay, None);
+        assert!(rules.is_allowed("/private/"));
+        assert!(!rules.is_allowed("/docs/manual.pdf"));
+        assert!(rules.is_allowed("/docs/manual.pdf?download=1"));
+        assert!(!rules.is_allowed("/admin/users"));
+        assert!(rules.is_allowed("/admin/public/logo.png"));
+
+        let rules = Rules::parse(text, "curl");
+        assert_eq!(rules.crawl_delay, Some(Duration::from_secs(2)));
+        assert!(!rules.is_allowed("/private/x"));
+        assert!(rules.is_allowed("/admin"));
+
+        assert_eq!(Rules::parse("", "xh"), Rules::default());
+    }
+
+    #[test]
+    fn robots_patterns() {
+        assert!(pattern_matches("/", "/anything"));
+        assert!(pattern_matches("/a*c", "/abbbc/d"));
+        assert!(pattern_matches("/a*c$", "/abbbc"));
+        assert!(!pattern_matches("/a*c$", "/abbbc/d"));
+        assert!(pattern_matches("/a$", "/a"));
+        assert!(!pattern_matches("/a$", "/ab"));
+        assert!(pattern_matches("*.gif$", "/img/x.gif"));
+        assert!(!pattern_matches("/b", "/a/b"));
+    }
+
+    #[test]
+    fn sitemap_links() {
+        let xml = r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <url><loc>https://example.com/</loc></url>
+            <url><loc>
+                https://example.com/a?x=1&amp;y=2
+            </loc></url>
+        </urlset>"#;
+        assert_eq!(
+            find_sitemap_links(xml),
+            ["https://example.com/", "https://example.com/a?x=1&y=2"]
+        );
+    }
+}
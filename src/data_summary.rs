@@ -0,0 +1,529 @@
+//! Summaries of Avro and Parquet files, shown instead of the binary data
+//! suppressor.
+//!
+//! Only the container formats are parsed: the schema and the row counts
+//! are in the file header (Avro) or footer (Parquet), so there's no need to
+//! decode the rows themselves or to support any compression codecs.
+//!
+//! Both parsers work on a complete file and give up on anything unexpected,
+//! in which case the body is treated like any other binary body.
+
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use crate::utils::group_digits;
+
+pub struct Summary {
+    /// A one-line description, e.g. "Avro data file: 10 records in 1 block"
+    pub description: String,
+    pub schema: Schema,
+}
+
+pub enum Schema {
+    Json(String),
+    Text(String),
+}
+
+pub fn summarize(data: &[u8]) -> Option<Summary> {
+    if data.starts_with(AVRO_MAGIC) {
+        summarize_avro(data)
+    } else if data.len() >= 12 && data.starts_with(PARQUET_MAGIC) && data.ends_with(PARQUET_MAGIC) {
+        summarize_parquet(data)
+    } else {
+        None
+    }
+}
+
+fn plural(count: u64, singular: &str, plural: &str) -> String {
+    let noun = if count == 1 { singular } else { plural };
+    format!("{} {noun}", group_digits(count))
+}
+
+/// Read a zigzag-encoded variable-length integer, as used by both Avro and
+/// Thrift's compact protocol.
+fn read_varint(data: &mut &[u8]) -> Option<i64> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some((value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+    }
+    None
+}
+
+fn read_slice<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (slice, rest) = data.split_at(len);
+    *data = rest;
+    Some(slice)
+}
+
+const AVRO_MAGIC: &[u8] = b"Obj\x01";
+
+/// See https://avro.apache.org/docs/1.11.1/specification/#object-container-files
+fn summarize_avro(data: &[u8]) -> Option<Summary> {
+    fn read_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = read_varint(data)?;
+        read_slice(data, usize::try_from(len).ok()?)
+    }
+
+    let mut data = &data[AVRO_MAGIC.len()..];
+    let mut schema = None;
+    let mut codec = None;
+    loop {
+        let mut count = read_varint(&mut data)?;
+        if count == 0 {
+            break;
+        }
+        if count < 0 {
+            // A negative count is followed by the size of the block in bytes
+            count = -count;
+            read_varint(&mut data)?;
+        }
+        for _ in 0..count {
+            let key = read_bytes(&mut data)?;
+            let value = read_bytes(&mut data)?;
+            match key {
+                b"avro.schema" => schema = Some(value),
+                b"avro.codec" => codec = Some(value),
+                _ => {}
+            }
+        }
+    }
+    let sync = read_slice(&mut data, 16)?;
+
+    let (mut records, mut blocks) = (0u64, 0u64);
+    while !data.is_empty() {
+        records += u64::try_from(read_varint(&mut data)?).ok()?;
+        let size = read_varint(&mut data)?;
+        read_slice(&mut data, usize::try_from(size).ok()?)?;
+        if read_slice(&mut data, 16)? != sync {
+            return None;
+        }
+        blocks += 1;
+    }
+
+    let schema: Value = serde_json::from_slice(schema?).ok()?;
+    let codec = match codec {
+        Some(codec) => String::from_utf8_lossy(codec).into_owned(),
+        None => "null".to_string(),
+    };
+    Some(Summary {
+        description: format!(
+            "Avro data file: {} in {}, codec {codec}",
+            plural(records, "record", "records"),
+            plural(blocks, "block", "blocks"),
+        ),
+        schema: Schema::Json(schema.to_string()),
+    })
+}
+
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+/// See https://parquet.apache.org/docs/file-format/metadata/
+fn summarize_parquet(data: &[u8]) -> Option<Summary> {
+    let footer_end = data.len() - PARQUET_MAGIC.len();
+    let footer_len = u32::from_le_bytes(data[footer_end - 4..footer_end].try_into().ok()?);
+    let footer_start = (footer_end - 4).checked_sub(footer_len as usize)?;
+    if footer_start < PARQUET_MAGIC.len() {
+        return None;
+    }
+    let mut footer = &data[footer_start..footer_end - 4];
+    let metadata = thrift::read_struct(&mut footer, 0)?;
+
+    let rows = metadata.field(3)?.as_int()?;
+    let row_groups = match metadata.field(4) {
+        Some(thrift::Value::List(row_groups)) => row_groups.len(),
+        _ => 0,
+    };
+    let Some(thrift::Value::List(elements)) = metadata.field(2) else {
+        return None;
+    };
+    let mut schema = String::new();
+    let mut elements = elements.iter();
+    write_schema_element(&mut schema, elements.next()?, &mut elements, 0)?;
+
+    let mut description = format!(
+        "Parquet file: {} in {}",
+        plural(u64::try_from(rows).ok()?, "row", "rows"),
+        plural(row_groups as u64, "row group", "row groups"),
+    );
+    if let Some(created_by) = metadata.field(6).and_then(thrift::Value::as_str) {
+        write!(description, ", created by {created_by}").ok()?;
+    }
+    Some(Summary {
+        description,
+        schema: Schema::Text(schema),
+    })
+}
+
+/// Write a schema in the same format as the Parquet tools, e.g.
+/// `optional binary name (UTF8);`. The elements are a flattened tree, so
+/// the children of a group follow it directly.
+fn write_schema_element<'a, 'b: 'a>(
+    out: &mut String,
+    element: &thrift::Value,
+    rest: &mut impl Iterator<Item = &'a thrift::Value<'b>>,
+    depth: usize,
+) -> Option<()> {
+    const TYPES: &[&str] = &[
+        "boolean",
+        "int32",
+        "int64",
+        "int96",
+        "float",
+        "double",
+        "binary",
+        "fixed_len_byte_array",
+    ];
+    const REPETITIONS: &[&str] = &["required", "optional", "repeated"];
+    const CONVERTED_TYPES: &[&str] = &[
+        "UTF8",
+        "MAP",
+        "MAP_KEY_VALUE",
+        "LIST",
+        "ENUM",
+        "DECIMAL",
+        "DATE",
+        "TIME_MILLIS",
+        "TIME_MICROS",
+        "TIMESTAMP_MILLIS",
+        "TIMESTAMP_MICROS",
+        "UINT_8",
+        "UINT_16",
+        "UINT_32",
+        "UINT_64",
+        "INT_8",
+        "INT_16",
+        "INT_32",
+        "INT_64",
+        "JSON",
+        "BSON",
+        "INTERVAL",
+    ];
+    let lookup = |names: &[&'static str], id| -> Option<&'static str> {
+        names
+            .get(usize::try_from(element.field(id)?.as_int()?).ok()?)
+            .copied()
+    };
+
+    let indent = "  ".repeat(depth);
+    let name = element.field(4)?.as_str()?;
+    let children = element.field(5).and_then(thrift::Value::as_int);
+    if depth == 0 {
+        write!(out, "message {name}").ok()?;
+    } else {
+        let repetition = lookup(REPETITIONS, 3).unwrap_or("required");
+        let kind = match children {
+            Some(_) => "group",
+            None => lookup(TYPES, 1)?,
+        };
+        write!(out, "{indent}{repetition} {kind}").ok()?;
+        if let Some(length) = element.field(2).and_then(thrift::Value::as_int) {
+            write!(out, "({length})").ok()?;
+        }
+        write!(out, " {name}").ok()?;
+        if let Some(converted) = lookup(CONVERTED_TYPES, 6) {
+            write!(out, " ({converted})").ok()?;
+        }
+    }
+    match children {
+        Some(children) => {
+            out.push_str(" {\n");
+            for _ in 0..children {
+                write_schema_element(out, rest.next()?, rest, depth + 1)?;
+            }
+            writeln!(out, "{indent}}}").ok()?;
+        }
+        None => out.push_str(";\n"),
+    }
+    Some(())
+}
+
+/// Just enough of Thrift's compact protocol to read Parquet metadata.
+///
+/// See https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+mod thrift {
+    use super::{read_slice, read_varint};
+
+    /// Deeper nesting than this doesn't occur in Parquet metadata, and
+    /// malformed input shouldn't be able to overflow the stack.
+    const MAX_DEPTH: usize = 32;
+
+    pub enum Value<'a> {
+        Bool,
+        Int(i64),
+        Double,
+        Binary(&'a [u8]),
+        List(Vec<Value<'a>>),
+        Map,
+        Struct(Vec<(i16, Value<'a>)>),
+    }
+
+    impl<'a> Value<'a> {
+        pub fn field(&self, id: i16) -> Option<&Value<'a>> {
+            match self {
+                Value::Struct(fields) => fields
+                    .iter()
+                    .find(|(field_id, _)| *field_id == id)
+                    .map(|(_, value)| value),
+                _ => None,
+            }
+        }
+
+        pub fn as_int(&self) -> Option<i64> {
+            match self {
+                Value::Int(value) => Some(*value),
+                _ => None,
+            }
+        }
+
+        pub fn as_str(&self) -> Option<&'a str> {
+            match self {
+                Value::Binary(bytes) => std::str::from_utf8(bytes).ok(),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn read_struct<'a>(data: &mut &'a [u8], depth: usize) -> Option<Value<'a>> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let mut fields = Vec::new();
+        let mut last_id: i16 = 0;
+        loop {
+            let (&header, rest) = data.split_first()?;
+            *data = rest;
+            let kind = header & 0x0f;
+            if kind == 0 {
+                return Some(Value::Struct(fields));
+            }
+            let id = match header >> 4 {
+                0 => i16::try_from(read_varint(data)?).ok()?,
+                delta => last_id.checked_add(i16::from(delta))?,
+            };
+            last_id = id;
+            let value = match kind {
+                // Booleans are stored in the field header
+                1 | 2 => Value::Bool,
+                kind => read_value(data, kind, depth)?,
+            };
+            fields.push((id, value));
+        }
+    }
+
+    fn read_value<'a>(data: &mut &'a [u8], kind: u8, depth: usize) -> Option<Value<'a>> {
+        Some(match kind {
+            1 | 2 => {
+                // Inside collections booleans take a byte of their own
+                read_slice(data, 1)?;
+                Value::Bool
+            }
+            3 => {
+                let (&byte, rest) = data.split_first()?;
+                *data = rest;
+                Value::Int(i64::from(byte as i8))
+            }
+            4..=6 => Value::Int(read_varint(data)?),
+            7 => {
+                read_slice(data, 8)?;
+                Value::Double
+            }
+            8 => {
+                let len = usize::try_from(read_unsigned(data)?).ok()?;
+                Value::Binary(read_slice(data, len)?)
+            }
+            9 | 10 => {
+                let (&header, rest) = data.split_first()?;
+                *data = rest;
+                let len = match header >> 4 {
+                    15 => usize::try_from(read_unsigned(data)?).ok()?,
+                    len => usize::from(len),
+                };
+                // Every element takes at least a byte, so this bounds the
+                // allocation by the size of the input
+                if len > data.len() {
+                    return None;
+                }
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(read_value(data, header & 0x0f, depth + 1)?);
+                }
+                Value::List(items)
+            }
+            11 => {
+                let len = read_unsigned(data)?;
+                if len > 0 {
+                    let (&kinds, rest) = data.split_first()?;
+                    *data = rest;
+                    for _ in 0..len {
+                        read_value(data, kinds >> 4, depth + 1)?;
+                        read_value(data, kinds & 0x0f, depth + 1)?;
+                    }
+                }
+                Value::Map
+            }
+            12 => read_struct(data, depth + 1)?,
+            _ => return None,
+        })
+    }
+
+    /// Sizes aren't zigzag-encoded.
+    fn read_unsigned(data: &mut &[u8]) -> Option<u64> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = data.split_first()?;
+            *data = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zigzag(value: i64, out: &mut Vec<u8>) {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn avro_file() -> Vec<u8> {
+        let schema = br#"{"type":"record","name":"User","fields":[{"name":"id","type":"long"}]}"#;
+        let sync = [7; 16];
+        let mut file = AVRO_MAGIC.to_vec();
+        zigzag(1, &mut file);
+        zigzag(11, &mut file);
+        file.extend(b"avro.schema");
+        zigzag(schema.len() as i64, &mut file);
+        file.extend(schema);
+        zigzag(0, &mut file);
+        file.extend(sync);
+        for ids in [&[1, 2][..], &[3]] {
+            zigzag(ids.len() as i64, &mut file);
+            zigzag(ids.len() as i64, &mut file);
+            for &id in ids {
+                zigzag(id, &mut file);
+            }
+            file.extend(sync);
+        }
+        file
+    }
+
+    #[test]
+    fn avro_summary() {
+        let summary = summarize(&avro_file()).unwrap();
+        assert_eq!(
+            summary.description,
+            "Avro data file: 3 records in 2 blocks, codec null"
+        );
+        assert!(matches!(summary.schema, Schema::Json(schema) if schema.contains("\"User\"")));
+
+        let mut truncated = avro_file();
+        truncated.pop();
+        assert!(summarize(&truncated).is_none());
+    }
+
+    #[test]
+    fn parquet_summary() {
+        fn field(out: &mut Vec<u8>, delta: u8, kind: u8) {
+            out.push(delta << 4 | kind);
+        }
+        fn string(out: &mut Vec<u8>, text: &str) {
+            out.push(text.len() as u8);
+            out.extend(text.as_bytes());
+        }
+        fn element(
+            out: &mut Vec<u8>,
+            kind: Option<i64>,
+            repetition: i64,
+            name: &str,
+            children: Option<i64>,
+            converted: Option<i64>,
+        ) {
+            let mut last = 0;
+            if let Some(kind) = kind {
+                field(out, 1, 5);
+                zigzag(kind, out);
+                last = 1;
+            }
+            field(out, 3 - last, 5);
+            zigzag(repetition, out);
+            field(out, 1, 8);
+            string(out, name);
+            last = 4;
+            if let Some(children) = children {
+                field(out, 5 - last, 5);
+                zigzag(children, out);
+                last = 5;
+            }
+            if let Some(converted) = converted {
+                field(out, 6 - last, 5);
+                zigzag(converted, out);
+            }
+            out.push(0);
+        }
+
+        let mut metadata = Vec::new();
+        field(&mut metadata, 1, 5);
+        zigzag(1, &mut metadata);
+        field(&mut metadata, 1, 9);
+        metadata.push(4 << 4 | 12);
+        element(&mut metadata, None, 0, "schema", Some(2), None);
+        element(&mut metadata, Some(2), 0, "id", None, None);
+        element(&mut metadata, None, 1, "tags", Some(1), Some(3));
+        element(&mut metadata, Some(6), 2, "tag", None, Some(0));
+        field(&mut metadata, 1, 6);
+        zigzag(1500, &mut metadata);
+        field(&mut metadata, 1, 9);
+        metadata.push(1 << 4 | 12);
+        metadata.push(0);
+        field(&mut metadata, 2, 8);
+        string(&mut metadata, "xh tests");
+        metadata.push(0);
+
+        let mut file = PARQUET_MAGIC.to_vec();
+        file.extend([0; 10]);
+        file.extend(&metadata);
+        file.extend((metadata.len() as u32).to_le_bytes());
+        file.extend(PARQUET_MAGIC);
+
+        let summary = summarize(&file).unwrap();
+        assert_eq!(
+            summary.description,
+            "Parquet file: 1,500 rows in 1 row group, created by xh tests"
+        );
+        let Schema::Text(schema) = summary.schema else {
+            panic!("expected a text schema");
+        };
+        assert_eq!(
+            schema,
+            "message schema {\n  \
+               required int64 id;\n  \
+               optional group tags (LIST) {\n    \
+                 repeated binary tag (UTF8);\n  \
+               }\n\
+             }\n"
+        );
+
+        file.truncate(file.len() - 8);
+        file.extend(1000u32.to_le_bytes());
+        file.extend(PARQUET_MAGIC);
+        assert!(summarize(&file).is_none());
+    }
+}
@@ -3,9 +3,14 @@ use std::str::FromStr;
 
 use brotli::Decompressor as BrotliDecoder;
 use flate2::read::{GzDecoder, ZlibDecoder};
+use indicatif::HumanBytes;
 use reqwest::header::{HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING};
 use ruzstd::{FrameDecoder, StreamingDecoder as ZstdDecoder};
 
+/// How large a compressed response body may get once it's decoded, unless
+/// --max-decode-size says otherwise.
+pub const DEFAULT_MAX_DECODE_SIZE: u64 = 1 << 30;
+
 #[derive(Debug)]
 pub enum CompressionType {
     Gzip,
@@ -138,17 +143,49 @@ impl<R: Read> Read for Decoder<R> {
     }
 }
 
+/// Stops a tiny compressed body from expanding into more data than we're
+/// willing to hold in memory or write to disk.
+struct SizeLimit<R: Read> {
+    reader: R,
+    limit: u64,
+    remaining: u64,
+}
+
+impl<R: Read> Read for SizeLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        if len as u64 > self.remaining {
+            return Err(io::Error::other(format!(
+                "response body is larger than {} when decompressed, use --max-decode-size to raise the limit",
+                HumanBytes(self.limit)
+            )));
+        }
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+}
+
+/// Decode a response body. Decoding fails once more than `max_size` bytes
+/// come out of a compressed body.
 pub fn decompress(
     reader: &mut impl Read,
     compression_type: Option<CompressionType>,
+    max_size: u64,
 ) -> impl Read + '_ {
     let reader = InnerReader::new(reader);
-    match compression_type {
-        Some(CompressionType::Gzip) => Decoder::Gzip(GzDecoder::new(reader)),
-        Some(CompressionType::Deflate) => Decoder::Deflate(ZlibDecoder::new(reader)),
-        Some(CompressionType::Brotli) => Decoder::Brotli(BrotliDecoder::new(reader, 4096)),
-        Some(CompressionType::Zstd) => Decoder::Zstd(ZstdDecoder::new(reader).unwrap()),
-        None => Decoder::PlainText(reader),
+    let (decoder, limit) = match compression_type {
+        Some(CompressionType::Gzip) => (Decoder::Gzip(GzDecoder::new(reader)), max_size),
+        Some(CompressionType::Deflate) => (Decoder::Deflate(ZlibDecoder::new(reader)), max_size),
+        Some(CompressionType::Brotli) => {
+            (Decoder::Brotli(BrotliDecoder::new(reader, 4096)), max_size)
+        }
+        Some(CompressionType::Zstd) => (Decoder::Zstd(ZstdDecoder::new(reader).unwrap()), max_size),
+        None => (Decoder::PlainText(reader), u64::MAX),
+    };
+    SizeLimit {
+        reader: decoder,
+        limit,
+        remaining: limit,
     }
 }
 
@@ -160,7 +197,11 @@ mod tests {
     fn decode_errors_are_prepended_with_custom_message() {
         let uncompressed_data = String::from("Hello world");
         let mut uncompressed_data = uncompressed_data.as_bytes();
-        let mut reader = decompress(&mut uncompressed_data, Some(CompressionType::Gzip));
+        let mut reader = decompress(
+            &mut uncompressed_data,
+            Some(CompressionType::Gzip),
+            DEFAULT_MAX_DECODE_SIZE,
+        );
         let mut buffer = Vec::new();
         match reader.read_to_end(&mut buffer) {
             Ok(_) => unreachable!("gzip should fail to decompress an uncompressed data"),
@@ -177,12 +218,16 @@ mod tests {
         struct SadReader;
         impl Read for SadReader {
             fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
-                Err(io::Error::new(io::ErrorKind::Other, "oh no!"))
+                Err(io::Error::other("oh no!"))
             }
         }
 
         let mut sad_reader = SadReader;
-        let mut reader = decompress(&mut sad_reader, Some(CompressionType::Gzip));
+        let mut reader = decompress(
+            &mut sad_reader,
+            Some(CompressionType::Gzip),
+            DEFAULT_MAX_DECODE_SIZE,
+        );
         let mut buffer = Vec::new();
         match reader.read_to_end(&mut buffer) {
             Ok(_) => unreachable!("SadReader should never be read"),
@@ -209,9 +254,43 @@ mod tests {
         }
 
         let mut base_reader = InterruptedReader { init: false };
-        let mut reader = decompress(&mut base_reader, Some(CompressionType::Gzip));
+        let mut reader = decompress(
+            &mut base_reader,
+            Some(CompressionType::Gzip),
+            DEFAULT_MAX_DECODE_SIZE,
+        );
         let mut buffer = Vec::new();
         reader.read_to_end(&mut buffer).unwrap();
         assert_eq!(buffer, b"");
     }
+
+    #[test]
+    fn decoded_size_is_limited() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&[0; 1 << 20]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < 4096);
+
+        let decode = |max_size| {
+            let mut buffer = Vec::new();
+            decompress(&mut &compressed[..], Some(CompressionType::Gzip), max_size)
+                .read_to_end(&mut buffer)
+                .map(|_| buffer)
+        };
+        let err = decode(1000).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("response body is larger than 1000 B when decompressed"));
+        assert_eq!(decode(1 << 20).unwrap().len(), 1 << 20);
+
+        // Only decompression is limited
+        let mut buffer = Vec::new();
+        decompress(&mut &compressed[..], None, 10)
+            .read_to_end(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, compressed);
+    }
 }
@@ -0,0 +1,622 @@
+//! DNS lookups against specific servers, for --dns-servers, over HTTPS,
+//! for --dns-url, of `.local` names over mDNS (with the mdns feature), and
+//! spreading connections over the addresses that come back, for --lb.
+//!
+//! This is a minimal stub resolver: it asks for A and AAAA records and
+//! follows nothing itself, relying on the server to do the recursion.
+//! Building and parsing messages is kept apart from sending them over UDP
+//! so that DNS-over-HTTPS (RFC 8484) can reuse it.
+//!
+//! Answers from servers are cached for as long as their TTL allows, since
+//! redirects and retries often look up the same name again.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use url::Url;
+
+use crate::cli::LoadBalance;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// The answer to a lookup, and who gave it.
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub source: Source,
+    pub addrs: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Source {
+    Server(SocketAddr),
+    Doh(Url),
+    #[cfg_attr(not(feature = "mdns"), allow(dead_code))]
+    Mdns,
+    /// --resolve or --hosts-file
+    Override,
+    System,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Server(addr) => write!(f, "{addr}"),
+            Source::Doh(url) => write!(f, "{url}"),
+            Source::Mdns => f.write_str("mDNS"),
+            Source::Override => f.write_str("override"),
+            Source::System => f.write_str("system resolver"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Resolver {
+    /// If empty, names that aren't looked up with mDNS go to the system resolver.
+    servers: Vec<SocketAddr>,
+    /// Takes the place of `servers` if set.
+    doh: Option<(Url, reqwest::blocking::Client)>,
+    timeout: Duration,
+    overrides: Arc<HashMap<String, Vec<SocketAddr>>>,
+    load_balance: Option<LoadBalance>,
+    lookups: Arc<Mutex<HashMap<String, Lookup>>>,
+    /// Answers from servers, until they expire.
+    cache: Arc<Mutex<HashMap<String, (Instant, Lookup)>>>,
+    /// For round-robin, how many times each name has been looked up.
+    rotations: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Resolver {
+    pub fn new(servers: Vec<SocketAddr>, timeout: Option<Duration>) -> Self {
+        Resolver {
+            servers,
+            doh: None,
+            timeout: timeout.unwrap_or(DEFAULT_TIMEOUT),
+            overrides: Arc::default(),
+            load_balance: None,
+            lookups: Arc::default(),
+            cache: Arc::default(),
+            rotations: Arc::default(),
+        }
+    }
+
+    /// Send queries to a DNS-over-HTTPS server instead. Its own name is
+    /// looked up with the system resolver.
+    pub fn with_doh(mut self, url: Url) -> reqwest::Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()?;
+        self.doh = Some((url, client));
+        Ok(self)
+    }
+
+    /// Fixed addresses for some names, keyed by lowercase name.
+    pub fn with_overrides(mut self, overrides: HashMap<String, Vec<SocketAddr>>) -> Self {
+        self.overrides = Arc::new(overrides);
+        self
+    }
+
+    pub fn with_load_balance(mut self, load_balance: Option<LoadBalance>) -> Self {
+        self.load_balance = load_balance;
+        self
+    }
+
+    pub fn load_balance(&self) -> Option<LoadBalance> {
+        self.load_balance
+    }
+
+    /// Look up `name`, with the address to try first at the front.
+    pub fn lookup(&self, name: &str) -> io::Result<Vec<SocketAddr>> {
+        let name = name.to_lowercase();
+        let (source, mut addrs) = match self.overrides.get(&name) {
+            Some(addrs) => (Source::Override, addrs.clone()),
+            None => {
+                let (source, addrs) = self.query(&name)?;
+                let addrs = addrs.into_iter().map(|addr| SocketAddr::new(addr, 0));
+                (source, addrs.collect())
+            }
+        };
+        self.lookups
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                name.clone(),
+                Lookup {
+                    source,
+                    addrs: addrs.iter().map(SocketAddr::ip).collect(),
+                },
+            );
+
+        if !addrs.is_empty() {
+            let start = match self.load_balance {
+                None => 0,
+                Some(LoadBalance::RoundRobin) => {
+                    let mut rotations = self
+                        .rotations
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    let rotation = rotations.entry(name).or_default();
+                    *rotation += 1;
+                    *rotation - 1
+                }
+                Some(LoadBalance::Random) => RandomState::new().build_hasher().finish() as usize,
+            };
+            let len = addrs.len();
+            addrs.rotate_left(start % len);
+        }
+        Ok(addrs)
+    }
+
+    fn query(&self, name: &str) -> io::Result<(Source, Vec<IpAddr>)> {
+        #[cfg(feature = "mdns")]
+        if is_mdns_name(name) {
+            return self.query_mdns(name);
+        }
+        if self.servers.is_empty() && self.doh.is_none() {
+            return Ok((Source::System, system_lookup(name)?));
+        }
+
+        if let Some((expiry, lookup)) = self
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+        {
+            if *expiry > Instant::now() {
+                log::debug!("Using cached addresses for {name}: {:?}", lookup.addrs);
+                return Ok((lookup.source.clone(), lookup.addrs.clone()));
+            }
+        }
+
+        if let Some((url, client)) = &self.doh {
+            let source = Source::Doh(url.clone());
+            let answer = query_doh(client, url, name).map_err(|err| {
+                io::Error::new(err.kind(), format!("{url} failed to resolve {name}: {err}"))
+            })?;
+            return self.answer(name, source, answer);
+        }
+
+        let mut last_err = None;
+        for &server in &self.servers {
+            match self.query_server(server, name) {
+                Ok(answer) => return self.answer(name, Source::Server(server), answer),
+                Err(err) => {
+                    log::warn!("DNS server {server} failed to resolve {name}: {err}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| io::Error::other("no DNS servers configured")))
+    }
+
+    /// Check and cache the answer to a query.
+    fn answer(
+        &self,
+        name: &str,
+        source: Source,
+        answer: Answer,
+    ) -> io::Result<(Source, Vec<IpAddr>)> {
+        if answer.addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{source} has no address for {name}"),
+            ));
+        }
+        log::debug!("{source} resolved {name} to {:?}", answer.addrs);
+        if let Some(ttl) = answer.ttl.filter(|&ttl| ttl > 0) {
+            let lookup = Lookup {
+                source: source.clone(),
+                addrs: answer.addrs.clone(),
+            };
+            self.cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(
+                    name.to_string(),
+                    (Instant::now() + Duration::from_secs(ttl.into()), lookup),
+                );
+        }
+        Ok((source, answer.addrs))
+    }
+
+    /// `.local` names are reserved for mDNS, but some platforms can resolve
+    /// them already, so that gets the first try unless we're bypassing the
+    /// system resolver anyway.
+    #[cfg(feature = "mdns")]
+    fn query_mdns(&self, name: &str) -> io::Result<(Source, Vec<IpAddr>)> {
+        if self.servers.is_empty() {
+            match system_lookup(name) {
+                Ok(addrs) if !addrs.is_empty() => return Ok((Source::System, addrs)),
+                Ok(_) => {}
+                Err(err) => log::debug!("System resolver failed for {name} ({err}), trying mDNS"),
+            }
+        }
+        let addrs = mdns::query(name, self.timeout.min(mdns::TIMEOUT))?;
+        log::debug!("mDNS resolved {name} to {addrs:?}");
+        Ok((Source::Mdns, addrs))
+    }
+
+    /// The result of the most recent lookup of `name`, if any.
+    pub fn last_lookup(&self, name: &str) -> Option<Lookup> {
+        self.lookups
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&name.to_lowercase())
+            .cloned()
+    }
+
+    fn query_server(&self, server: SocketAddr, name: &str) -> io::Result<Answer> {
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+
+        let id = query_id();
+        let queries = [(id, TYPE_A), (id.wrapping_add(1), TYPE_AAAA)];
+        for (id, qtype) in queries {
+            socket.send(&build_query(id, name, qtype)?)?;
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let mut pending: Vec<u16> = queries.iter().map(|(id, _)| *id).collect();
+        let mut answer = Answer::default();
+        let mut buf = [0; 4096];
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let len = socket.recv(&mut buf)?;
+            // Stray or malformed datagrams are dropped, as a real resolver would
+            let Ok(response) = parse_response(&buf[..len]) else {
+                continue;
+            };
+            if let Some(pos) = pending.iter().position(|&id| id == response.id) {
+                pending.remove(pos);
+                if response.rcode == RCODE_NXDOMAIN {
+                    return Ok(Answer::default());
+                }
+                answer.add(response)?;
+            }
+        }
+        answer.addrs.sort_by_key(IpAddr::is_ipv6);
+        Ok(answer)
+    }
+}
+
+/// The combined answers to the A and AAAA queries for a name.
+#[derive(Debug, Default)]
+struct Answer {
+    addrs: Vec<IpAddr>,
+    /// The lowest TTL of the records.
+    ttl: Option<u32>,
+}
+
+impl Answer {
+    fn add(&mut self, response: Response) -> io::Result<()> {
+        if response.rcode != 0 {
+            return Err(io::Error::other(format!(
+                "server returned error code {}",
+                response.rcode
+            )));
+        }
+        self.addrs.extend(response.addrs);
+        self.ttl = match (self.ttl, response.ttl) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(())
+    }
+}
+
+/// Send both queries as POST requests, with the ID set to 0 so that the
+/// answers can be cached by HTTP caches.
+fn query_doh(client: &reqwest::blocking::Client, url: &Url, name: &str) -> io::Result<Answer> {
+    let mut answer = Answer::default();
+    for qtype in [TYPE_A, TYPE_AAAA] {
+        let response = client
+            .post(url.clone())
+            .header(CONTENT_TYPE, "application/dns-message")
+            .header(ACCEPT, "application/dns-message")
+            .body(build_query(0, name, qtype)?)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.bytes())
+            .map_err(io::Error::other)?;
+        let response = parse_response(&response)?;
+        if response.rcode == RCODE_NXDOMAIN {
+            return Ok(Answer::default());
+        }
+        answer.add(response)?;
+    }
+    Ok(answer)
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let name = name.as_str().to_string();
+            let addrs = tokio::task::spawn_blocking(move || resolver.lookup(&name)).await??;
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(feature = "mdns")]
+fn is_mdns_name(name: &str) -> bool {
+    matches!(
+        name.trim_end_matches('.').rsplit_once('.'),
+        Some((host, tld)) if !host.is_empty() && tld.eq_ignore_ascii_case("local")
+    )
+}
+
+fn system_lookup(name: &str) -> io::Result<Vec<IpAddr>> {
+    use std::net::ToSocketAddrs;
+    Ok((name, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect())
+}
+
+#[cfg(feature = "mdns")]
+mod mdns {
+    use super::*;
+
+    pub const TIMEOUT: Duration = Duration::from_secs(2);
+
+    const GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+    /// Once something answers, how long to wait for answers to the other query.
+    /// Responders don't have to reply if they have no records of that type.
+    const GRACE: Duration = Duration::from_millis(200);
+
+    /// Send a one-shot query (RFC 6762, section 5.1) from an ephemeral port,
+    /// which responders answer directly, like a regular DNS server would.
+    ///
+    /// Only IPv4 multicast is used, the records that come back may still
+    /// include IPv6 addresses.
+    pub fn query(name: &str, timeout: Duration) -> io::Result<Vec<IpAddr>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        let id = query_id();
+        let mut pending = vec![id, id.wrapping_add(1)];
+        socket.send_to(&build_query(id, name, TYPE_A)?, GROUP)?;
+        socket.send_to(&build_query(id.wrapping_add(1), name, TYPE_AAAA)?, GROUP)?;
+
+        let mut deadline = Instant::now() + timeout;
+        let mut addrs = Vec::new();
+        let mut buf = [0; 9000];
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let len = match socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    break
+                }
+                Err(err) => return Err(err),
+            };
+            let Ok(response) = parse_response(&buf[..len]) else {
+                continue;
+            };
+            if let Some(pos) = pending.iter().position(|&id| id == response.id) {
+                if !response.addrs.is_empty() {
+                    pending.remove(pos);
+                    addrs.extend(response.addrs);
+                    deadline = deadline.min(Instant::now() + GRACE);
+                }
+            }
+        }
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no mDNS responder answered for {name}"),
+            ));
+        }
+        // Same order as regular lookups
+        addrs.sort_by_key(IpAddr::is_ipv6);
+        Ok(addrs)
+    }
+}
+
+fn query_id() -> u16 {
+    // Only needs to be unpredictable enough to not match stale responses
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or(0);
+    (nanos ^ std::process::id()) as u16
+}
+
+const RCODE_NXDOMAIN: u8 = 3;
+
+pub fn build_query(id: u16, name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(18 + name.len());
+    msg.extend(id.to_be_bytes());
+    // Recursion desired
+    msg.extend(0x0100u16.to_be_bytes());
+    // One question, no other records
+    msg.extend([0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid domain name {name:?}"),
+            ));
+        }
+        msg.push(label.len() as u8);
+        msg.extend(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend(qtype.to_be_bytes());
+    msg.extend(CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub id: u16,
+    pub rcode: u8,
+    pub addrs: Vec<IpAddr>,
+    /// The lowest TTL of the address records, if there are any.
+    pub ttl: Option<u32>,
+}
+
+pub fn parse_response(msg: &[u8]) -> io::Result<Response> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response");
+    let header = msg.get(..12).ok_or_else(invalid)?;
+    let read_u16 = |at: usize| -> io::Result<u16> {
+        let bytes = msg.get(at..at + 2).ok_or_else(invalid)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let id = read_u16(0)?;
+    if header[2] & 0x80 == 0 {
+        // Not a response
+        return Err(invalid());
+    }
+    let rcode = header[3] & 0x0f;
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos).ok_or_else(invalid)? + 4;
+    }
+    let mut addrs = Vec::new();
+    let mut ttl: Option<u32> = None;
+    for _ in 0..answers {
+        pos = skip_name(msg, pos).ok_or_else(invalid)?;
+        let rtype = read_u16(pos)?;
+        let class = read_u16(pos + 2)?;
+        let record_ttl = u32::from(read_u16(pos + 4)?) << 16 | u32::from(read_u16(pos + 6)?);
+        let len = read_u16(pos + 8)? as usize;
+        pos += 10;
+        let data = msg.get(pos..pos + len).ok_or_else(invalid)?;
+        pos += len;
+        // The top bit is mDNS's cache-flush flag
+        if class & 0x7fff != CLASS_IN {
+            continue;
+        }
+        // CNAMEs are skipped, the server includes the records they lead to
+        match (rtype, data.len()) {
+            (TYPE_A, 4) => addrs.push(IpAddr::from(<[u8; 4]>::try_from(data).unwrap())),
+            (TYPE_AAAA, 16) => addrs.push(IpAddr::from(<[u8; 16]>::try_from(data).unwrap())),
+            _ => continue,
+        }
+        ttl = Some(ttl.map_or(record_ttl, |ttl| ttl.min(record_ttl)));
+    }
+    Ok(Response {
+        id,
+        rcode,
+        addrs,
+        ttl,
+    })
+}
+
+/// Return the position just past the (possibly compressed) name at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer ends the name
+            len if len & 0xc0 == 0xc0 => {
+                msg.get(pos + 1)?;
+                return Some(pos + 2);
+            }
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_encoding() {
+        let query = build_query(0x1234, "example.com", TYPE_A).unwrap();
+        assert_eq!(
+            query,
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x07example\x03com\x00\x00\x01\x00\x01"
+        );
+        assert!(build_query(1, "a..b", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn response_decoding() {
+        let mut msg = build_query(0x1234, "example.com", TYPE_A).unwrap();
+        // Turn the query into a response with two answers
+        msg[2] |= 0x80;
+        msg[7] = 2;
+        // CNAME pointing back at the question
+        msg.extend(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x02\xc0\x0c");
+        // A record, with a pointer for the name
+        msg.extend(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x5d\xb8\xd8\x22");
+
+        let response = parse_response(&msg).unwrap();
+        assert_eq!(response.id, 0x1234);
+        assert_eq!(response.rcode, 0);
+        assert_eq!(response.addrs, [IpAddr::from([93, 184, 216, 34])]);
+        assert_eq!(response.ttl, Some(60));
+
+        assert!(parse_response(&msg[..msg.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn load_balance_overrides() {
+        let addrs: Vec<SocketAddr> =
+            vec!["10.0.0.1:0".parse().unwrap(), "10.0.0.2:0".parse().unwrap()];
+        let overrides = HashMap::from([("example.com".to_string(), addrs.clone())]);
+
+        let resolver = Resolver::new(Vec::new(), None).with_overrides(overrides.clone());
+        assert_eq!(resolver.lookup("example.com").unwrap(), addrs);
+        assert_eq!(resolver.lookup("Example.com").unwrap(), addrs);
+
+        let resolver = Resolver::new(Vec::new(), None)
+            .with_overrides(overrides.clone())
+            .with_load_balance(Some(LoadBalance::RoundRobin));
+        let first = |resolver: &Resolver| resolver.lookup("example.com").unwrap()[0];
+        assert_eq!(first(&resolver), addrs[0]);
+        assert_eq!(first(&resolver), addrs[1]);
+        assert_eq!(first(&resolver), addrs[0]);
+        assert_eq!(resolver.last_lookup("example.com").unwrap().addrs.len(), 2);
+
+        let resolver = Resolver::new(Vec::new(), None)
+            .with_overrides(overrides)
+            .with_load_balance(Some(LoadBalance::Random));
+        assert!((0..100).any(|_| first(&resolver) == addrs[1]));
+    }
+
+    #[test]
+    #[cfg(feature = "mdns")]
+    fn mdns_names() {
+        assert!(is_mdns_name("printer.local"));
+        assert!(is_mdns_name("Printer.LOCAL."));
+        assert!(!is_mdns_name("local"));
+        assert!(!is_mdns_name(".local"));
+        assert!(!is_mdns_name("example.com"));
+        assert!(!is_mdns_name("notlocal"));
+    }
+}
@@ -0,0 +1,110 @@
+//! --edit, which lets the user compose the request body in an editor.
+//!
+//! The body from the command line (or an empty JSON object) is written to a
+//! temporary file, and whatever is saved there is sent instead. JSON is
+//! checked before sending: if it doesn't parse the editor is opened again
+//! with the error on top, like `kubectl edit` does.
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config;
+use crate::request_items::Body;
+
+const JSON_HELP: &str = "\
+// Edit the JSON body of the request. Lines starting with // are ignored,
+// and an empty body cancels the request.
+";
+
+pub fn edit_body(body: Body) -> Result<Body> {
+    let (template, is_json) = match body {
+        Body::Json(value) if value.is_null() => ("{\n    \n}\n".to_string(), true),
+        Body::Json(value) => (format!("{}\n", serde_json::to_string_pretty(&value)?), true),
+        Body::Form(items) => (serde_urlencoded::to_string(items)?, false),
+        Body::Raw(raw) => (
+            String::from_utf8(raw).context("Can't edit a body that isn't valid UTF-8")?,
+            false,
+        ),
+        Body::File { file_name, .. } => (
+            fs::read_to_string(&file_name)
+                .with_context(|| format!("Unable to read {}", file_name.display()))?,
+            false,
+        ),
+        Body::Multipart(..) => return Err(anyhow!("Can't edit a multipart body")),
+    };
+
+    let path = temp_path(if is_json { "json" } else { "txt" });
+    let content = if is_json {
+        format!("{JSON_HELP}{template}")
+    } else {
+        template
+    };
+    fs::write(&path, &content).with_context(|| format!("Unable to write {}", path.display()))?;
+
+    let result = edit_loop(&path, content, is_json);
+    if result.is_ok() {
+        fs::remove_file(&path).ok();
+    }
+    result
+}
+
+fn edit_loop(path: &Path, mut content: String, is_json: bool) -> Result<Body> {
+    loop {
+        if let Err(err) = config::open_editor(path) {
+            fs::remove_file(path).ok();
+            return Err(err.context("The request wasn't sent"));
+        }
+        let edited = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+
+        if !is_json {
+            if edited.trim().is_empty() {
+                return Err(anyhow!("The body is empty, the request wasn't sent"));
+            }
+            // Most editors add a final newline, which e.g. a form body can't have
+            let mut edited = edited;
+            if !content.ends_with('\n') && edited.ends_with('\n') {
+                edited.pop();
+                if edited.ends_with('\r') {
+                    edited.pop();
+                }
+            }
+            return Ok(Body::Raw(edited.into_bytes()));
+        }
+
+        let stripped = config::strip_comments(&edited);
+        if stripped.trim().is_empty() {
+            return Err(anyhow!("The body is empty, the request wasn't sent"));
+        }
+        match serde_json::from_str(&stripped) {
+            Ok(value) => return Ok(Body::Json(value)),
+            Err(err) if edited == content => {
+                // Saved without changes after we pointed out the error
+                return Err(anyhow!(err).context(format!(
+                    "Invalid JSON, the request wasn't sent. Your body is in {}",
+                    path.display()
+                )));
+            }
+            Err(err) => {
+                // Strip the error from the previous round, if any
+                let body = edited
+                    .strip_prefix("// Error: ")
+                    .and_then(|rest| rest.split_once('\n'))
+                    .map_or(edited.as_str(), |(_, rest)| rest);
+                content = format!("// Error: {err}\n{body}");
+                fs::write(path, &content)
+                    .with_context(|| format!("Unable to write {}", path.display()))?;
+            }
+        }
+    }
+}
+
+fn temp_path(extension: &str) -> PathBuf {
+    let random = RandomState::new().build_hasher().finish();
+    env::temp_dir().join(format!("xh-body-{random:016x}.{extension}"))
+}
@@ -0,0 +1,210 @@
+//! Mapping of errors to exit codes, so that scripts can tell different kinds
+//! of failures apart. The exit codes are documented in the man page.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+
+use crate::interrupt::Interrupted;
+
+/// The kinds of failures that have their own exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Other,
+    Timeout,
+    TooManyRedirects,
+    Dns,
+    Connect,
+    Tls,
+    Protocol,
+    DiskFull,
+    Interrupted,
+    /// An intermediary response failed --check-status=all
+    FailedHop(StatusCode),
+}
+
+impl ErrorKind {
+    pub fn classify(err: &anyhow::Error) -> ErrorKind {
+        if let Some(FailedHop(status)) = err.downcast_ref::<FailedHop>() {
+            return ErrorKind::FailedHop(*status);
+        }
+        if err.is::<DiskFull>() {
+            return ErrorKind::DiskFull;
+        }
+        if find_error::<Interrupted>(err).is_some() {
+            return ErrorKind::Interrupted;
+        }
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            if err.is_timeout() {
+                return ErrorKind::Timeout;
+            }
+        }
+        if err
+            .root_cause()
+            .to_string()
+            .starts_with("Too many redirects")
+        {
+            return ErrorKind::TooManyRedirects;
+        }
+        if is_tls_error(err) {
+            return ErrorKind::Tls;
+        }
+        // hyper-util doesn't expose a typed DNS error, but it's always labeled like this
+        // https://github.com/hyperium/hyper-util/blob/v0.1.10/src/client/legacy/connect/http.rs#L552
+        if err
+            .chain()
+            .any(|err| err.to_string().starts_with("dns error"))
+        {
+            return ErrorKind::Dns;
+        }
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            if err.is_connect() {
+                return ErrorKind::Connect;
+            }
+        }
+        if find_error::<hyper::Error>(err).is_some_and(|err| {
+            err.is_parse() || err.is_parse_status() || err.is_incomplete_message()
+        }) || find_error::<h2::Error>(err).is_some()
+        {
+            return ErrorKind::Protocol;
+        }
+        ErrorKind::Other
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Timeout => 2,
+            ErrorKind::TooManyRedirects => 6,
+            ErrorKind::Dns => 7,
+            ErrorKind::Connect => 8,
+            ErrorKind::Tls => 9,
+            ErrorKind::Protocol => 10,
+            ErrorKind::DiskFull => 12,
+            ErrorKind::Interrupted => 130,
+            ErrorKind::FailedHop(status) => status_exit_code(status, true),
+        }
+    }
+}
+
+/// The exit code for a response with --check-status. Redirects only count
+/// as failures if they're not being followed.
+pub fn status_exit_code(status: StatusCode, follow: bool) -> i32 {
+    match status.as_u16() {
+        300..=399 if !follow => 3,
+        400..=499 => 4,
+        500..=599 => 5,
+        _ => 0,
+    }
+}
+
+/// Returned for the first intermediary response that fails
+/// --check-status=all.
+#[derive(Debug)]
+pub struct FailedHop(pub StatusCode);
+
+impl fmt::Display for FailedHop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Intermediary response failed with status {}", self.0)
+    }
+}
+
+impl Error for FailedHop {}
+
+/// Returned when a download runs out of disk space.
+#[derive(Debug)]
+pub struct DiskFull {
+    /// How much of the download made it to disk.
+    pub written: u64,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for DiskFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Disk full, {} bytes written to {}",
+            self.written,
+            self.path.display()
+        )
+    }
+}
+
+impl Error for DiskFull {}
+
+/// Whether a write failed because the disk is full.
+#[cfg(unix)]
+pub fn is_disk_full(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENOSPC)
+}
+
+#[cfg(windows)]
+pub fn is_disk_full(err: &io::Error) -> bool {
+    // ERROR_HANDLE_DISK_FULL and ERROR_DISK_FULL
+    matches!(err.raw_os_error(), Some(39 | 112))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn is_disk_full(_err: &io::Error) -> bool {
+    false
+}
+
+/// Find an error of a particular type in the chain of causes.
+///
+/// Errors wrapped in an [`io::Error`] are found as well. Those are not part of
+/// the regular chain, because [`io::Error::source`] skips over them. They can
+/// be wrapped more than once, e.g. rustls errors during the handshake.
+fn find_error<E: Error + 'static>(err: &anyhow::Error) -> Option<&E> {
+    err.chain().find_map(|mut err| loop {
+        if let Some(err) = err.downcast_ref::<E>() {
+            return Some(err);
+        }
+        err = err.downcast_ref::<io::Error>()?.get_ref()?;
+    })
+}
+
+fn is_tls_error(err: &anyhow::Error) -> bool {
+    #[cfg(feature = "rustls")]
+    if find_error::<rustls::Error>(err).is_some() {
+        return true;
+    }
+    #[cfg(feature = "native-tls")]
+    if find_error::<native_tls::Error>(err).is_some() {
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        let err = anyhow::Error::new(io::Error::new(
+            io::ErrorKind::InvalidData,
+            h2::Error::from(h2::Reason::PROTOCOL_ERROR),
+        ));
+        assert_eq!(ErrorKind::classify(&err), ErrorKind::Protocol);
+
+        let err = anyhow::anyhow!("Too many redirects (--max-redirects=10)");
+        assert_eq!(ErrorKind::classify(&err).exit_code(), 6);
+
+        let err = anyhow::Error::new(FailedHop(StatusCode::BAD_GATEWAY));
+        assert_eq!(ErrorKind::classify(&err).exit_code(), 5);
+
+        let err = anyhow::Error::new(DiskFull {
+            written: 1024,
+            path: "file.part".into(),
+        });
+        assert_eq!(ErrorKind::classify(&err).exit_code(), 12);
+        #[cfg(unix)]
+        assert!(is_disk_full(&io::Error::from_raw_os_error(libc::ENOSPC)));
+
+        let err = anyhow::anyhow!("something else");
+        assert_eq!(ErrorKind::classify(&err).exit_code(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! Guessing whether the terminal has a light or a dark background, so that
+//! `--style=auto` can pick a palette that's readable on it.
+//!
+//! The terminal is asked for its background color with an OSC 11 query. That
+//! query is followed by a primary device attributes (DA1) query, which every
+//! terminal answers, so we don't have to wait out the timeout on terminals
+//! that ignore OSC 11.
+
+use std::env;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+static BACKGROUND: OnceLock<Background> = OnceLock::new();
+
+/// Settle on the background, preferring `config` over asking the terminal.
+///
+/// This has to happen before any output is written, since the terminal's
+/// answer comes in on the same tty.
+pub fn init(config: Option<Background>) {
+    BACKGROUND.get_or_init(|| config.or_else(detect).unwrap_or(Background::Dark));
+}
+
+/// The background found by [`init`], or dark if it never ran.
+pub fn get() -> Background {
+    BACKGROUND.get().copied().unwrap_or(Background::Dark)
+}
+
+fn detect() -> Option<Background> {
+    env::var("COLORFGBG")
+        .ok()
+        .and_then(|value| from_colorfgbg(&value))
+        .or_else(query_terminal)
+}
+
+/// Parse `$COLORFGBG`, set by some terminals as e.g. "15;0" (foreground;background).
+fn from_colorfgbg(value: &str) -> Option<Background> {
+    let background: u8 = value.rsplit(';').next()?.parse().ok()?;
+    if matches!(background, 7 | 9..=15) {
+        Some(Background::Light)
+    } else {
+        Some(Background::Dark)
+    }
+}
+
+/// Parse an OSC 11 reply like `\x1b]11;rgb:ffff/ffff/dddd\x1b\\`.
+fn from_osc11_reply(reply: &str) -> Option<Background> {
+    let start = reply.find("]11;rgb:")? + "]11;rgb:".len();
+    let rgb = &reply[start..];
+    let end = rgb
+        .find(|c: char| !c.is_ascii_hexdigit() && c != '/')
+        .unwrap_or(rgb.len());
+
+    let mut channels = rgb[..end].split('/').map(|channel| {
+        let max = 16f64.powi(channel.len() as i32) - 1.0;
+        let value = u16::from_str_radix(channel, 16).ok()?;
+        Some(f64::from(value) / max)
+    });
+    let (r, g, b) = (channels.next()??, channels.next()??, channels.next()??);
+
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luminance > 0.5 {
+        Some(Background::Light)
+    } else {
+        Some(Background::Dark)
+    }
+}
+
+#[cfg(unix)]
+fn query_terminal() -> Option<Background> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let fd = tty.as_raw_fd();
+
+    let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return None;
+    }
+    // Don't echo the reply, and give up on reads after 100ms of silence
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 0;
+    raw.c_cc[libc::VTIME] = 1;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+
+    let mut reply = Vec::new();
+    if tty.write_all(b"\x1b]11;?\x1b\\\x1b[c").is_ok() {
+        let mut buf = [0; 64];
+        while let Ok(n @ 1..) = tty.read(&mut buf) {
+            reply.extend(&buf[..n]);
+            if let Some(da1) = reply.windows(3).position(|w| w == b"\x1b[?") {
+                if reply[da1..].ends_with(b"c") {
+                    break;
+                }
+            }
+        }
+    }
+
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    from_osc11_reply(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+fn query_terminal() -> Option<Background> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_colorfgbg() {
+        assert_eq!(from_colorfgbg("15;0"), Some(Background::Dark));
+        assert_eq!(from_colorfgbg("0;15"), Some(Background::Light));
+        assert_eq!(from_colorfgbg("0;default;15"), Some(Background::Light));
+        assert_eq!(from_colorfgbg("default"), None);
+    }
+
+    #[test]
+    fn parse_osc11_reply() {
+        assert_eq!(
+            from_osc11_reply("\x1b]11;rgb:ffff/ffff/dddd\x1b\\\x1b[?62;22c"),
+            Some(Background::Light)
+        );
+        assert_eq!(
+            from_osc11_reply("\x1b]11;rgb:28/2c/34\x07"),
+            Some(Background::Dark)
+        );
+        assert_eq!(from_osc11_reply("\x1b[?62;22c"), None);
+    }
+}
@@ -1,13 +1,17 @@
+use std::borrow::Cow;
 use std::io::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use cookie_store::RawCookie;
 use reqwest::{
-    header::{HeaderMap, HeaderName, HeaderValue},
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, PROXY_AUTHORIZATION, SET_COOKIE},
     Method, StatusCode, Version,
 };
 use syntect::highlighting::Theme;
 use termcolor::WriteColor;
 use url::Url;
 
+use super::jwt::{find_jwts, format_timestamp, Jwt};
 use crate::utils::HeaderValueExt;
 
 super::palette::palette! {
@@ -41,6 +45,9 @@ pub(crate) struct HeaderFormatter<'a, W: WriteColor> {
     palette: Option<HeaderPalette>,
     is_terminal: bool,
     sort_headers: bool,
+    show_cookies: bool,
+    merge_headers: bool,
+    decode_jwt: bool,
 }
 
 impl<'a, W: WriteColor> HeaderFormatter<'a, W> {
@@ -55,9 +62,30 @@ impl<'a, W: WriteColor> HeaderFormatter<'a, W> {
             output,
             is_terminal,
             sort_headers,
+            show_cookies: false,
+            merge_headers: false,
+            decode_jwt: false,
         }
     }
 
+    /// Show `Set-Cookie` headers as a table instead of as regular headers.
+    pub(crate) fn with_show_cookies(mut self, show_cookies: bool) -> Self {
+        self.show_cookies = show_cookies;
+        self
+    }
+
+    /// Combine repeated headers into a single comma-separated line.
+    pub(crate) fn with_merge_headers(mut self, merge_headers: bool) -> Self {
+        self.merge_headers = merge_headers;
+        self
+    }
+
+    /// Show what the JWTs in `Authorization` and `Set-Cookie` headers contain.
+    pub(crate) fn with_decode_jwt(mut self, decode_jwt: bool) -> Self {
+        self.decode_jwt = decode_jwt;
+        self
+    }
+
     fn print(&mut self, text: &str) -> Result<()> {
         self.output.write_all(text.as_bytes())
     }
@@ -127,6 +155,21 @@ impl<'a, W: WriteColor> HeaderFormatter<'a, W> {
         Ok(())
     }
 
+    /// Print the headers of a part of a multipart body.
+    pub(crate) fn print_part_headers(&mut self, headers: &HeaderMap) -> Result<()> {
+        self.print_headers(headers, Version::HTTP_11)?;
+
+        if self.palette.is_some() {
+            self.output.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Print the trailers that came after a response body.
+    pub(crate) fn print_trailers(&mut self, trailers: &HeaderMap) -> Result<()> {
+        self.print_part_headers(trailers)
+    }
+
     fn print_http_version(&mut self, version: Version) -> Result<()> {
         let version = format!("{version:?}");
         let version = version.strip_prefix("HTTP/").unwrap_or(&version);
@@ -147,11 +190,37 @@ impl<'a, W: WriteColor> HeaderFormatter<'a, W> {
             Version::HTTP_2 | Version::HTTP_3 => false,
             _ => false,
         };
-        let mut headers: Vec<(&HeaderName, &HeaderValue)> = headers.iter().collect();
+        let mut headers: Vec<(&HeaderName, Cow<HeaderValue>)> = if self.merge_headers {
+            merge_duplicate_headers(headers)
+        } else {
+            headers
+                .iter()
+                .map(|(name, value)| (name, Cow::Borrowed(value)))
+                .collect()
+        };
         if self.sort_headers {
+            // This is a stable sort, so duplicate headers keep their order
             headers.sort_by_key(|(name, _)| name.as_str());
         }
 
+        let mut cookies = Vec::new();
+        if self.show_cookies {
+            // Cookies that fail to parse are still shown as regular headers
+            headers.retain(|(name, value)| {
+                let cookie = (*name == SET_COOKIE)
+                    .then(|| value.to_str().ok())
+                    .flatten()
+                    .and_then(|value| RawCookie::parse(value.to_string()).ok());
+                match cookie {
+                    Some(cookie) => {
+                        cookies.push(cookie);
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+
         let mut namebuf = String::with_capacity(64);
         for (name, value) in headers {
             let key = if as_titlecase {
@@ -207,10 +276,187 @@ impl<'a, W: WriteColor> HeaderFormatter<'a, W> {
                 }
             }
             self.print_plain("\n")?;
+
+            if self.decode_jwt && [AUTHORIZATION, PROXY_AUTHORIZATION, SET_COOKIE].contains(name) {
+                if let Ok(value) = value.to_str() {
+                    for jwt in find_jwts(value) {
+                        self.print_jwt(&jwt, "    ")?;
+                    }
+                }
+            }
+        }
+
+        if !cookies.is_empty() {
+            self.print_cookie_table(&cookies)?;
+        }
+
+        Ok(())
+    }
+
+    /// Print the JWTs found in a body, below the body itself.
+    pub(crate) fn print_body_jwts(&mut self, body: &str) -> Result<()> {
+        for jwt in find_jwts(body) {
+            let mut token = jwt.token.chars();
+            let start: String = token.by_ref().take(40).collect();
+            set_color!(self, header_name)?;
+            self.print("JWT")?;
+            set_color!(self, header_colon)?;
+            self.print(":")?;
+            self.print_plain(" ")?;
+            set_color!(self, header_value)?;
+            self.print(&start)?;
+            if token.next().is_some() {
+                self.print("…")?;
+            }
+            self.print_plain("\n")?;
+            self.print_jwt(&jwt, "    ")?;
+        }
+        if self.palette.is_some() {
+            self.output.reset()?;
+        }
+        Ok(())
+    }
+
+    fn print_jwt(&mut self, jwt: &Jwt, indent: &str) -> Result<()> {
+        for (label, value) in [("JWT header", &jwt.header), ("JWT claims", &jwt.claims)] {
+            self.print_plain(indent)?;
+            set_color!(self, header_name)?;
+            self.print(label)?;
+            set_color!(self, header_colon)?;
+            self.print(":")?;
+            self.print_plain(" ")?;
+            set_color!(self, header_value)?;
+            self.print(&value.to_string())?;
+            self.print_plain("\n")?;
+        }
+        if let Some(expiry) = jwt.expiry() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs() as i64);
+            let expired = expiry <= now;
+            self.print_plain(indent)?;
+            set_color!(self, header_name)?;
+            self.print("JWT expires")?;
+            set_color!(self, header_colon)?;
+            self.print(":")?;
+            self.print_plain(" ")?;
+            if expired {
+                set_color!(self, error)?;
+            } else {
+                set_color!(self, status_code)?;
+            }
+            self.print(&format_timestamp(expiry))?;
+            self.print(if expired { " (expired)" } else { " (valid)" })?;
+            self.print_plain("\n")?;
+        }
+        Ok(())
+    }
+
+    fn print_cookie_table(&mut self, cookies: &[RawCookie<'_>]) -> Result<()> {
+        const COLUMNS: [&str; 6] = ["Name", "Value", "Domain", "Path", "Expires", "Flags"];
+
+        let rows: Vec<[String; 6]> = cookies
+            .iter()
+            .map(|cookie| {
+                let expires = match (cookie.max_age(), cookie.expires_datetime()) {
+                    (Some(max_age), _) => format!("Max-Age={}", max_age.whole_seconds()),
+                    (None, Some(expires)) => httpdate::fmt_http_date(expires.into()),
+                    (None, None) => "Session".to_string(),
+                };
+                let mut flags = Vec::new();
+                if cookie.secure() == Some(true) {
+                    flags.push("Secure".to_string());
+                }
+                if cookie.http_only() == Some(true) {
+                    flags.push("HttpOnly".to_string());
+                }
+                if let Some(same_site) = cookie.same_site() {
+                    flags.push(format!("SameSite={same_site}"));
+                }
+                [
+                    cookie.name().to_string(),
+                    cookie.value().to_string(),
+                    cookie.domain().unwrap_or_default().to_string(),
+                    cookie.path().unwrap_or_default().to_string(),
+                    expires,
+                    flags.join(", "),
+                ]
+            })
+            .collect();
+
+        let mut widths = COLUMNS.map(|column| column.chars().count());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
         }
 
+        set_color!(self, header_name)?;
+        self.print("Set-Cookie")?;
+        set_color!(self, header_colon)?;
+        self.print(":")?;
+        self.print_plain("\n")?;
+        self.print_cookie_row(&COLUMNS, &widths, true)?;
+        for row in &rows {
+            self.print_cookie_row(row, &widths, false)?;
+        }
         Ok(())
     }
+
+    fn print_cookie_row(
+        &mut self,
+        cells: &[impl AsRef<str>],
+        widths: &[usize],
+        is_title: bool,
+    ) -> Result<()> {
+        // Don't pad the line with trailing whitespace for empty cells
+        let len = cells
+            .iter()
+            .rposition(|cell| !cell.as_ref().is_empty())
+            .map_or(0, |last| last + 1);
+        self.print_plain(" ")?;
+        for (index, (cell, width)) in cells[..len].iter().zip(widths).enumerate() {
+            let cell = cell.as_ref();
+            self.print_plain(" ")?;
+            match index {
+                _ if is_title => set_color!(self, default)?,
+                0 => set_color!(self, header_name)?,
+                _ => set_color!(self, header_value)?,
+            }
+            self.print(cell)?;
+            if index + 1 < len {
+                let padding = width - cell.chars().count();
+                self.print_plain(&" ".repeat(padding + 1))?;
+            }
+        }
+        self.print_plain("\n")
+    }
+}
+
+/// Join the values of headers that occur more than once with a comma, as
+/// allowed by RFC 9110 section 5.3.
+///
+/// Set-Cookie is the exception, because its values may contain commas.
+fn merge_duplicate_headers(headers: &HeaderMap) -> Vec<(&HeaderName, Cow<'_, HeaderValue>)> {
+    headers
+        .keys()
+        .flat_map(|name| {
+            let mut values = headers.get_all(name).iter();
+            if *name == SET_COOKIE || headers.get_all(name).iter().nth(1).is_none() {
+                return values
+                    .map(|value| (name, Cow::Borrowed(value)))
+                    .collect::<Vec<_>>();
+            }
+            let mut merged = values.next().unwrap().as_bytes().to_vec();
+            for value in values {
+                merged.extend_from_slice(b", ");
+                merged.extend_from_slice(value.as_bytes());
+            }
+            let merged = HeaderValue::from_bytes(&merged)
+                .expect("joined header values should be a valid header value");
+            vec![(name, Cow::Owned(merged))]
+        })
+        .collect()
 }
 
 fn titlecase_header<'b>(name: &HeaderName, buffer: &'b mut String) -> &'b str {
@@ -248,6 +494,85 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_merge_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append("vary", "accept".parse().unwrap());
+        headers.append("set-cookie", "a=1".parse().unwrap());
+        headers.append("vary", "origin".parse().unwrap());
+        headers.append("set-cookie", "b=2".parse().unwrap());
+        headers.append("allow", "GET".parse().unwrap());
+
+        let mut buf = termcolor::Ansi::new(Vec::new());
+        let mut formatter =
+            HeaderFormatter::new(&mut buf, None, false, true).with_merge_headers(true);
+        formatter.print_headers(&headers, Version::HTTP_11).unwrap();
+        let buf = buf.into_inner();
+        assert_eq!(
+            buf,
+            indoc! {b"
+                Allow: GET
+                Set-Cookie: a=1
+                Set-Cookie: b=2
+                Vary: accept, origin
+                "
+            }
+        );
+
+        let mut buf = termcolor::Ansi::new(Vec::new());
+        let mut formatter = HeaderFormatter::new(&mut buf, None, false, true);
+        formatter.print_headers(&headers, Version::HTTP_11).unwrap();
+        let buf = buf.into_inner();
+        assert_eq!(
+            buf,
+            indoc! {b"
+                Allow: GET
+                Set-Cookie: a=1
+                Set-Cookie: b=2
+                Vary: accept
+                Vary: origin
+                "
+            }
+        );
+    }
+
+    #[test]
+    fn test_cookie_table() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", "0".parse().unwrap());
+        headers.append(
+            "set-cookie",
+            "session=abc123; Domain=example.com; Path=/; Secure; HttpOnly; SameSite=Lax"
+                .parse()
+                .unwrap(),
+        );
+        headers.append(
+            "set-cookie",
+            "theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT"
+                .parse()
+                .unwrap(),
+        );
+        headers.append("set-cookie", "not a cookie".parse().unwrap());
+
+        let mut buf = termcolor::Ansi::new(Vec::new());
+        let mut formatter =
+            HeaderFormatter::new(&mut buf, None, false, false).with_show_cookies(true);
+        formatter.print_headers(&headers, Version::HTTP_11).unwrap();
+        let buf = buf.into_inner();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            indoc! {"
+                Content-Length: 0
+                Set-Cookie: not a cookie
+                Set-Cookie:
+                  Name     Value   Domain       Path  Expires                        Flags
+                  session  abc123  example.com  /     Session                        Secure, HttpOnly, SameSite=Lax
+                  theme    dark                       Wed, 21 Oct 2026 07:28:00 GMT
+                "
+            }
+        );
+    }
+
     #[test]
     fn test_header_casing() {
         let mut headers = HeaderMap::new();
@@ -12,7 +12,9 @@ use termcolor::WriteColor;
 
 use crate::{buffer::Buffer, cli::Theme};
 
+pub(crate) mod background;
 pub(crate) mod headers;
+pub(crate) mod jwt;
 pub(crate) mod palette;
 
 pub fn get_json_formatter(indent_level: usize) -> jsonxf::Formatter {
@@ -36,6 +38,61 @@ pub fn serde_json_format(indent_level: usize, text: &str, write: impl Write) ->
     Ok(())
 }
 
+/// Replace string values in a JSON document that look like base64 with their
+/// decoded content, for --decode-body-base64.
+///
+/// Returns `None` if the text isn't valid JSON.
+pub fn decode_base64_strings(text: &str) -> Option<String> {
+    fn walk(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => {
+                if let Some(decoded) = decode_base64_string(text) {
+                    *text = decoded;
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(walk),
+            serde_json::Value::Object(map) => map.values_mut().for_each(walk),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::from_str(text).ok()?;
+    walk(&mut value);
+    Some(value.to_string())
+}
+
+fn decode_base64_string(text: &str) -> Option<String> {
+    use base64::prelude::{Engine, BASE64_STANDARD};
+
+    // Short strings and plain words are too likely to decode by accident
+    if text.len() < 8 || text.len() % 4 != 0 {
+        return None;
+    }
+    let bytes = BASE64_STANDARD.decode(text).ok()?;
+    match String::from_utf8(bytes) {
+        Ok(decoded)
+            if !decoded
+                .chars()
+                .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')) =>
+        {
+            Some(decoded)
+        }
+        // Hex digests are valid base64 too
+        Err(err) if text.len() >= 32 && !text.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            let bytes = err.into_bytes();
+            let preview: Vec<_> = bytes.iter().take(16).map(|b| format!("{b:02x}")).collect();
+            let ellipsis = if bytes.len() > 16 { " ..." } else { "" };
+            Some(format!(
+                "<binary data, {} bytes: {}{}>",
+                bytes.len(),
+                preview.join(" "),
+                ellipsis
+            ))
+        }
+        _ => None,
+    }
+}
+
 pub(crate) static THEMES: once_cell::sync::Lazy<ThemeSet> = once_cell::sync::Lazy::new(|| {
     from_binary(include_bytes!(concat!(
         env!("OUT_DIR"),
@@ -80,7 +137,7 @@ impl<'a> Highlighter<'a> {
             for (style, component) in self
                 .highlighter
                 .highlight_line(line, self.syntax_set)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                .map_err(io::Error::other)?
             {
                 self.out.set_color(&convert_style(style))?;
                 write!(self.out, "{}", component)?;
@@ -0,0 +1,240 @@
+//! A small FTP client, for `--download` with ftp:// and ftps:// URLs.
+//!
+//! Only what downloading needs is here: logging in, passive mode, SIZE, REST
+//! for `--continue`, RETR and LIST. ftps:// means implicit TLS, on port 990
+//! by default, and the data connections are protected too. They don't reuse
+//! the TLS session of the control connection, so servers that insist on
+//! that (like vsftpd with `require_ssl_reuse`) won't work.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::cli::Verify;
+use crate::raw::{self, Stream};
+
+pub fn is_ftp_url(url: &Url) -> bool {
+    matches!(url.scheme(), "ftp" | "ftps")
+}
+
+/// Whether the URL points at a directory, which gets listed instead of
+/// downloaded.
+pub fn is_directory(url: &Url) -> bool {
+    url.path().ends_with('/')
+}
+
+pub struct Connection {
+    control: BufReader<Box<dyn Stream>>,
+    host: String,
+    tls: Option<Verify>,
+    timeout: Option<Duration>,
+}
+
+/// A reply from the server, like `226 Transfer complete`.
+pub struct Reply {
+    pub code: u16,
+    pub text: String,
+}
+
+impl Connection {
+    /// Connect and log in, with the credentials from the URL or anonymously.
+    pub fn open(url: &Url, verify: &Verify, timeout: Option<Duration>) -> Result<Self> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("Missing host in {url}"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let tls = (url.scheme() == "ftps").then(|| verify.clone());
+        let port = url.port().unwrap_or(if tls.is_some() { 990 } else { 21 });
+        let stream = raw::connect(&host, port, tls.as_ref(), timeout)
+            .with_context(|| format!("Failed to connect to {host}:{port}"))?;
+        let mut connection = Connection {
+            control: BufReader::new(stream),
+            host,
+            tls,
+            timeout,
+        };
+
+        connection.expect_reply(&[220])?;
+        let user = match url.username() {
+            "" => "anonymous".into(),
+            user => percent_decode_str(user).decode_utf8_lossy(),
+        };
+        let password = percent_decode_str(url.password().unwrap_or("anonymous@"))
+            .decode_utf8_lossy()
+            .into_owned();
+        if connection.command(&format!("USER {user}"))?.code == 331 {
+            connection.expect(&format!("PASS {password}"), &[230, 202])?;
+        }
+        if connection.tls.is_some() {
+            connection.expect("PBSZ 0", &[200])?;
+            connection.expect("PROT P", &[200])?;
+        }
+        connection.expect("TYPE I", &[200])?;
+        Ok(connection)
+    }
+
+    /// The size of a file, if the server supports SIZE.
+    pub fn size(&mut self, path: &str) -> Result<Option<u64>> {
+        let reply = self.command(&format!("SIZE {path}"))?;
+        Ok(match reply.code {
+            213 => reply.text.trim().parse().ok(),
+            _ => None,
+        })
+    }
+
+    /// Start downloading a file, from `offset` onwards if the server allows
+    /// it. Returns the data connection and whether the offset was accepted.
+    ///
+    /// [`Connection::finish`] has to be called once the data is read.
+    pub fn retrieve(&mut self, path: &str, offset: u64) -> Result<(Box<dyn Stream>, bool)> {
+        let data = self.passive()?;
+        let resumed = offset > 0 && self.command(&format!("REST {offset}"))?.code == 350;
+        self.expect(&format!("RETR {path}"), &[125, 150])?;
+        Ok((data, resumed))
+    }
+
+    /// List a directory.
+    pub fn list(&mut self, path: &str) -> Result<Vec<u8>> {
+        let mut data = self.passive()?;
+        let command = match path {
+            "" => "LIST".to_string(),
+            path => format!("LIST {path}"),
+        };
+        self.expect(&command, &[125, 150])?;
+        let mut listing = Vec::new();
+        data.read_to_end(&mut listing)
+            .context("Failed to read the directory listing")?;
+        self.finish()?;
+        Ok(listing)
+    }
+
+    /// Wait for the server to confirm that a transfer is complete.
+    pub fn finish(&mut self) -> Result<Reply> {
+        self.expect_reply(&[226, 250])
+    }
+
+    /// Open a data connection in passive mode, preferring EPSV.
+    ///
+    /// The address in a PASV reply is ignored in favor of the control
+    /// connection's host, since servers behind NAT often get it wrong.
+    fn passive(&mut self) -> Result<Box<dyn Stream>> {
+        let reply = self.command("EPSV")?;
+        let port = if reply.code == 229 {
+            parse_epsv(&reply.text)
+        } else {
+            let reply = self.expect("PASV", &[227])?;
+            parse_pasv(&reply.text)
+        }
+        .ok_or_else(|| anyhow!("Can't parse the server's passive mode reply"))?;
+        raw::connect(&self.host, port, self.tls.as_ref(), self.timeout)
+            .context("Failed to open the data connection")
+    }
+
+    fn command(&mut self, command: &str) -> Result<Reply> {
+        if command.starts_with("PASS ") {
+            log::debug!("FTP > PASS ****");
+        } else {
+            log::debug!("FTP > {command}");
+        }
+        let stream = self.control.get_mut();
+        stream.write_all(format!("{command}\r\n").as_bytes())?;
+        stream.flush()?;
+        self.reply()
+    }
+
+    fn expect(&mut self, command: &str, codes: &[u16]) -> Result<Reply> {
+        let reply = self.command(command)?;
+        check(reply, codes)
+    }
+
+    fn expect_reply(&mut self, codes: &[u16]) -> Result<Reply> {
+        let reply = self.reply()?;
+        check(reply, codes)
+    }
+
+    /// Read a reply, which may span several lines like `211-...` up to `211 ...`.
+    fn reply(&mut self) -> Result<Reply> {
+        let mut code = None;
+        loop {
+            let mut line = String::new();
+            if self.control.read_line(&mut line)? == 0 {
+                return Err(anyhow!("The FTP server closed the connection"));
+            }
+            log::debug!("FTP < {}", line.trim_end());
+            let line_code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+            if code.is_none() {
+                code = Some(
+                    line_code.ok_or_else(|| anyhow!("Bad reply from the FTP server: {line:?}"))?,
+                );
+            }
+            // The last line has the code followed by a space instead of a dash
+            if line_code == code && line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(Reply {
+                    code: line_code.unwrap_or_default(),
+                    text: line.get(4..).unwrap_or_default().trim_end().to_string(),
+                });
+            }
+        }
+    }
+}
+
+fn check(reply: Reply, codes: &[u16]) -> Result<Reply> {
+    if codes.contains(&reply.code) {
+        Ok(reply)
+    } else {
+        Err(anyhow!("FTP server replied: {} {}", reply.code, reply.text))
+    }
+}
+
+/// The path from the URL, relative to the login directory.
+pub fn path(url: &Url) -> String {
+    percent_decode_str(url.path().trim_start_matches('/'))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Parse `Entering Extended Passive Mode (|||6446|)`.
+fn parse_epsv(text: &str) -> Option<u16> {
+    let start = text.find('(')? + 1;
+    let end = text[start..].find(')')? + start;
+    let fields = &text[start..end];
+    let delimiter = fields.chars().next()?;
+    fields.split(delimiter).nth(3)?.parse().ok()
+}
+
+/// Parse `Entering Passive Mode (192,168,1,2,25,46)`.
+fn parse_pasv(text: &str) -> Option<u16> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let numbers: Vec<u16> = text[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .take(6)
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers[..] {
+        [_, _, _, _, high, low] if high < 256 && low < 256 => Some(high * 256 + low),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passive_replies() {
+        assert_eq!(
+            parse_epsv("Entering Extended Passive Mode (|||6446|)"),
+            Some(6446)
+        );
+        assert_eq!(
+            parse_pasv("Entering Passive Mode (192,168,1,2,25,46)."),
+            Some(25 * 256 + 46)
+        );
+        assert_eq!(parse_pasv("Entering Passive Mode"), None);
+    }
+}
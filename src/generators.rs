@@ -0,0 +1,280 @@
+//! Generated values for request items, e.g. `id:=#uuid` or `name=#name`.
+//!
+//! With `--generate`, a value that consists of `#` followed by a known
+//! generator is replaced before the request item is parsed. Other values that start with `#` are
+//! left alone, and `\#` can be used to send a generator name literally.
+//!
+//! The generators are:
+//!
+//! - `uuid`: a random (version 4) UUID
+//! - `int`, `int(MIN,MAX)`: a random integer, between 0 and 1000000 by default
+//! - `float`: a random number between 0 and 1
+//! - `bool`: true or false
+//! - `name`, `email` and `word`: made-up text from small built-in lists
+//! - `now`, `now(iso)`, `now(unix)`, `now(unix_ms)`: the current time, as an
+//!   RFC 3339 timestamp or as seconds or milliseconds since the Unix epoch
+//!
+//! The random generators are seeded from `--seed`, if given, so the same
+//! command line produces the same values.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+
+use crate::request_items::split_request_item;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Barbara", "Brian", "Claude", "Dennis", "Donald", "Edsger", "Frances", "Grace",
+    "Guido", "Hedy", "John", "Ken", "Linus", "Margaret", "Niklaus", "Radia", "Sophie", "Tim",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Allen",
+    "Backus",
+    "Cerf",
+    "Dijkstra",
+    "Hamilton",
+    "Hopper",
+    "Kernighan",
+    "Knuth",
+    "Lamarr",
+    "Liskov",
+    "Lovelace",
+    "Perlman",
+    "Ritchie",
+    "Shannon",
+    "Thompson",
+    "Torvalds",
+    "Turing",
+    "Wilson",
+    "Wirth",
+    "Wozniak",
+];
+
+const WORDS: &[&str] = &[
+    "amber", "breeze", "cactus", "dune", "ember", "fjord", "glacier", "harbor", "island", "jasper",
+    "kelp", "lagoon", "meadow", "nebula", "orchid", "pebble", "quartz", "river", "summit",
+    "tundra",
+];
+
+#[derive(Clone)]
+pub struct Generators {
+    state: u64,
+}
+
+impl Generators {
+    pub fn new(seed: Option<u64>) -> Generators {
+        let state = seed.unwrap_or_else(|| RandomState::new().build_hasher().finish());
+        Generators { state }
+    }
+
+    /// Replace a generator in the value of a `=`, `:=` or `==` request item.
+    pub fn expand(&mut self, request_item: String) -> Result<String> {
+        let Some((key, sep, value)) = split_request_item(&request_item) else {
+            return Ok(request_item);
+        };
+        if !matches!(sep, "=" | ":=" | "==") {
+            return Ok(request_item);
+        }
+        if let Some(expr) = value.strip_prefix("\\#") {
+            // Check on a copy, so that escaped generators don't change the
+            // values that follow
+            if self.clone().generate(expr).is_some() {
+                return Ok(format!("{key}{sep}#{expr}"));
+            }
+            return Ok(request_item);
+        }
+        let Some(expr) = value.strip_prefix('#') else {
+            return Ok(request_item);
+        };
+        let value = match self.generate(expr) {
+            Some(value) => value?,
+            None => return Ok(request_item),
+        };
+        let value = match (sep, value) {
+            (":=", value) => value.to_string(),
+            (_, Value::String(text)) => text.replace('\\', "\\\\"),
+            (_, value) => value.to_string(),
+        };
+        Ok(format!("{key}{sep}{value}"))
+    }
+
+    /// Generate a value for `expr`, the part after the `#`.
+    ///
+    /// Returns `None` if it doesn't name a generator.
+    pub fn generate(&mut self, expr: &str) -> Option<Result<Value>> {
+        let (name, args) = match expr.split_once('(') {
+            Some((name, args)) => (name, Some(args.strip_suffix(')')?)),
+            None => (expr, None),
+        };
+        let value = match (name, args) {
+            ("uuid", None) => Ok(Value::String(self.uuid())),
+            ("int", None) => Ok(Value::from(self.range(0, 1_000_000))),
+            ("int", Some(args)) => {
+                parse_range(args).map(|(min, max)| Value::from(self.range(min, max)))
+            }
+            ("float", None) => Ok(Value::from(self.float())),
+            ("bool", None) => Ok(Value::Bool(self.next() & 1 == 1)),
+            ("name", None) => Ok(Value::String(format!(
+                "{} {}",
+                self.pick(FIRST_NAMES),
+                self.pick(LAST_NAMES)
+            ))),
+            ("email", None) => Ok(Value::String(format!(
+                "{}.{}@example.com",
+                self.pick(FIRST_NAMES).to_lowercase(),
+                self.pick(LAST_NAMES).to_lowercase()
+            ))),
+            ("word", None) => Ok(Value::String(self.pick(WORDS).to_string())),
+            ("now", None | Some("iso")) => Ok(Value::String(now_iso())),
+            ("now", Some("unix")) => Ok(Value::from(since_epoch().as_secs())),
+            ("now", Some("unix_ms")) => Ok(Value::from(since_epoch().as_millis() as u64)),
+            ("now", Some(format)) => Err(anyhow!(
+                "Unknown time format '{format}' for #now, expected iso, unix or unix_ms"
+            )),
+            ("uuid" | "float" | "bool" | "name" | "email" | "word", Some(_)) => {
+                Err(anyhow!("#{name} doesn't take arguments"))
+            }
+            _ => return None,
+        };
+        Some(value.with_context(|| format!("Invalid generator #{expr}")))
+    }
+
+    /// SplitMix64, which is plenty for test data and keeps seeded output
+    /// stable across versions.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        let span = max.abs_diff(min).wrapping_add(1);
+        let offset = if span == 0 {
+            self.next()
+        } else {
+            self.next() % span
+        };
+        min.wrapping_add(offset as i64)
+    }
+
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn pick(&mut self, items: &[&'static str]) -> &'static str {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+
+    fn uuid(&mut self) -> String {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.next().to_be_bytes());
+        bytes[8..].copy_from_slice(&self.next().to_be_bytes());
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex = hex::encode(bytes);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+fn parse_range(args: &str) -> Result<(i64, i64)> {
+    let (min, max) = args
+        .split_once(',')
+        .ok_or_else(|| anyhow!("Expected #int(MIN,MAX)"))?;
+    let min: i64 = min.trim().parse()?;
+    let max: i64 = max.trim().parse()?;
+    if min > max {
+        return Err(anyhow!("MIN can't be larger than MAX"));
+    }
+    Ok((min, max))
+}
+
+fn since_epoch() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn now_iso() -> String {
+    let now = time::OffsetDateTime::from(SystemTime::now());
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_generators() {
+        let mut generators = Generators::new(Some(42));
+        let mut generate = |expr| generators.generate(expr).unwrap().unwrap();
+
+        let uuid = generate("uuid");
+        let uuid = uuid.as_str().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+
+        let int = generate("int(5,7)").as_i64().unwrap();
+        assert!((5..=7).contains(&int));
+        let float = generate("float").as_f64().unwrap();
+        assert!((0.0..1.0).contains(&float));
+        assert!(generate("email")
+            .as_str()
+            .unwrap()
+            .ends_with("@example.com"));
+        assert!(generate("now(unix)").is_u64());
+
+        let values = |seed| {
+            let mut generators = Generators::new(Some(seed));
+            ["uuid", "name", "int", "word"].map(|expr| generators.generate(expr).unwrap().unwrap())
+        };
+        assert_eq!(values(1), values(1));
+        assert_ne!(values(1), values(2));
+    }
+
+    #[test]
+    fn expand_request_items() {
+        let mut generators = Generators::new(Some(7));
+        let mut expand = |item: &str| generators.expand(item.to_string()).unwrap();
+
+        assert_eq!(expand("n:=#int(3,3)"), "n:=3");
+        assert_eq!(expand("n=#int(3,3)"), "n=3");
+        assert_eq!(expand("n==#int(3,3)"), "n==3");
+        assert!(expand("w:=#word").starts_with("w:=\""));
+        assert_eq!(expand(r"n=\#uuid"), "n=#uuid");
+        assert_eq!(expand("color=#fff"), "color=#fff");
+        assert_eq!(expand("X-Id:#uuid"), "X-Id:#uuid");
+        assert_eq!(expand("n=@#uuid"), "n=@#uuid");
+    }
+
+    #[test]
+    fn invalid_generators() {
+        let mut generators = Generators::new(None);
+        assert!(generators.generate("fff").is_none());
+        assert!(generators.generate("uuid(").is_none());
+        assert!(generators.generate("int(7,5)").unwrap().is_err());
+        assert!(generators.generate("int(a,b)").unwrap().is_err());
+        assert!(generators.generate("now(rfc2822)").unwrap().is_err());
+        assert!(generators.generate("name(1)").unwrap().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! --graphql, which sends a query in the JSON envelope that GraphQL servers
+//! expect:
+//!
+//! ```json
+//! {"query": "...", "operationName": "...", "variables": {...}}
+//! ```
+//!
+//! The query comes from the `query` request item (e.g. `query=@q.graphql`) or
+//! from stdin, and the other data items become the variables, so nested JSON
+//! syntax works for them as usual.
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use crate::request_items::Body;
+
+/// Wrap the body built from the request items into a GraphQL request.
+pub fn envelope(body: Body, query_from_stdin: Option<String>) -> Result<Body> {
+    let mut items = match body {
+        Body::Json(Value::Null) => Map::new(),
+        Body::Json(Value::Object(items)) => items,
+        Body::Json(_) => {
+            return Err(anyhow!(
+                "GraphQL variables must be an object, not a top-level array or value"
+            ))
+        }
+        _ => return Err(anyhow!("--graphql only works with JSON request items")),
+    };
+
+    let query = match (items.remove("query"), query_from_stdin) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow!(
+                "The query was given both on stdin and as a request item. \
+                 Pass --ignore-stdin to ignore standard input."
+            ))
+        }
+        (Some(Value::String(query)), None) | (None, Some(query)) => query,
+        (Some(_), None) => return Err(anyhow!("The GraphQL query must be a string")),
+        (None, None) => {
+            return Err(anyhow!(
+                "--graphql needs a query: query='{{ ... }}', query=@file.graphql or on stdin"
+            ))
+        }
+    };
+
+    let mut envelope = Map::new();
+    envelope.insert("query".into(), query.into());
+    if let Some(operation_name) = items.remove("operationName") {
+        envelope.insert("operationName".into(), operation_name);
+    }
+    if !items.is_empty() {
+        envelope.insert("variables".into(), items.into());
+    }
+    Ok(Body::Json(envelope.into()))
+}
+
+/// Describe the entries in the "errors" list of a GraphQL response, if any.
+pub fn response_errors(body: &[u8]) -> Vec<String> {
+    let Ok(response) = serde_json::from_slice::<Value>(body) else {
+        return Vec::new();
+    };
+    let Some(errors) = response.get("errors").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    errors
+        .iter()
+        .map(|error| {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .map_or_else(|| error.to_string(), str::to_string);
+            let path = error.get("path").and_then(Value::as_array).map(|path| {
+                path.iter()
+                    .map(|segment| match segment {
+                        Value::String(field) => field.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(".")
+            });
+            let location = error
+                .get("locations")
+                .and_then(|locations| locations.get(0))
+                .and_then(|location| {
+                    Some((
+                        location.get("line")?.as_u64()?,
+                        location.get("column")?.as_u64()?,
+                    ))
+                });
+            match (path, location) {
+                (Some(path), _) => format!("{message} (at {path})"),
+                (None, Some((line, column))) => format!("{message} (line {line}, column {column})"),
+                (None, None) => message,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn wraps_query_and_variables() {
+        let body = Body::Json(json!({
+            "query": "query Q($id: ID) { user(id: $id) { name } }",
+            "id": 5,
+            "operationName": "Q",
+        }));
+        let Body::Json(wrapped) = envelope(body, None).unwrap() else {
+            panic!("not JSON");
+        };
+        assert_eq!(
+            wrapped.to_string(),
+            r#"{"query":"query Q($id: ID) { user(id: $id) { name } }","operationName":"Q","variables":{"id":5}}"#
+        );
+
+        let Body::Json(wrapped) = envelope(Body::Json(Value::Null), Some("{ me }".into())).unwrap()
+        else {
+            panic!("not JSON");
+        };
+        assert_eq!(wrapped, json!({"query": "{ me }"}));
+
+        assert!(envelope(Body::Json(Value::Null), None).is_err());
+        assert!(envelope(Body::Json(json!({"query": "{ a }"})), Some("{ b }".into())).is_err());
+        assert!(envelope(Body::Json(json!(["{ a }"])), None).is_err());
+    }
+
+    #[test]
+    fn describes_errors() {
+        let body = json!({
+            "data": null,
+            "errors": [
+                {"message": "Not found", "path": ["user", 0, "name"]},
+                {"message": "Syntax error", "locations": [{"line": 2, "column": 3}]},
+                {"message": "Oops"},
+            ]
+        });
+        assert_eq!(
+            response_errors(body.to_string().as_bytes()),
+            [
+                "Not found (at user.0.name)",
+                "Syntax error (line 2, column 3)",
+                "Oops"
+            ]
+        );
+        assert!(response_errors(br#"{"data": {"me": null}}"#).is_empty());
+        assert!(response_errors(b"not json").is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! "xh init", which asks a few questions and writes a commented config file.
+
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::cli::{Print, Theme};
+use crate::config;
+
+#[derive(Debug, Default, PartialEq)]
+struct Answers {
+    https: bool,
+    style: Option<String>,
+    check_status: bool,
+    print: Option<String>,
+    editor: Option<String>,
+}
+
+pub fn run() -> Result<i32> {
+    let path = config::path()?;
+    let mut input = io::stdin().lock();
+    if path.exists() {
+        let question = format!("{} already exists. Replace it?", path.display());
+        if !ask_yes_no(&mut input, &question, false)? {
+            eprintln!("Nothing was changed.");
+            return Ok(0);
+        }
+    }
+    let answers = ask_all(&mut input)?;
+    config::save(&path, &render(&answers))?;
+    eprintln!(
+        "Wrote {}. Use \"xh config edit\" to change it.",
+        path.display()
+    );
+    Ok(0)
+}
+
+fn ask_all(input: &mut impl BufRead) -> Result<Answers> {
+    let https = ask(
+        input,
+        "Scheme for URLs that don't have one (http, https)",
+        "http",
+        |answer| match answer {
+            "http" | "https" => Ok(answer == "https"),
+            _ => Err(anyhow!("Please answer http or https")),
+        },
+    )?;
+    let themes: Vec<String> = Theme::value_variants()
+        .iter()
+        .filter_map(|theme| Some(theme.to_possible_value()?.get_name().to_string()))
+        .collect();
+    let style = ask(
+        input,
+        &format!("Color style ({})", themes.join(", ")),
+        "auto",
+        |answer| match Theme::from_str(answer, true) {
+            Ok(Theme::Auto) => Ok(None),
+            Ok(_) => Ok(Some(answer.to_lowercase())),
+            Err(_) => Err(anyhow!("Please answer one of {}", themes.join(", "))),
+        },
+    )?;
+    let check_status = ask_yes_no(
+        input,
+        "Exit with an error status when the response is 4xx or 5xx?",
+        true,
+    )?;
+    let print = ask(
+        input,
+        "What to print, out of H and B (request headers and body), h and b \
+         (response headers and body) and m (metadata). Empty means it depends \
+         on where the output goes",
+        "",
+        |answer| match answer {
+            "" => Ok(None),
+            _ => answer.parse::<Print>().map(|_| Some(answer.to_string())),
+        },
+    )?;
+    let editor = ask(
+        input,
+        "Editor for --edit and \"xh config edit\". Empty means $VISUAL or $EDITOR",
+        "",
+        |answer| Ok((!answer.is_empty()).then(|| answer.to_string())),
+    )?;
+    Ok(Answers {
+        https,
+        style,
+        check_status,
+        print,
+        editor,
+    })
+}
+
+/// Ask until `check` accepts the answer. An empty answer means `default`.
+fn ask<T>(
+    input: &mut impl BufRead,
+    question: &str,
+    default: &str,
+    check: impl Fn(&str) -> Result<T>,
+) -> Result<T> {
+    loop {
+        if default.is_empty() {
+            eprint!("{question}: ");
+        } else {
+            eprint!("{question} [{default}]: ");
+        }
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            eprintln!();
+            return Err(anyhow!("No answer, the config wasn't written"));
+        }
+        let answer = match answer.trim() {
+            "" => default,
+            answer => answer,
+        };
+        match check(answer) {
+            Ok(value) => return Ok(value),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+fn ask_yes_no(input: &mut impl BufRead, question: &str, default: bool) -> Result<bool> {
+    let question = format!("{question} ({})", if default { "Y/n" } else { "y/N" });
+    ask(input, &question, "", |answer| {
+        match answer.to_lowercase().as_str() {
+            "" => Ok(default),
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => Err(anyhow!("Please answer y or n")),
+        }
+    })
+}
+
+fn render(answers: &Answers) -> String {
+    let mut options = Vec::new();
+    if answers.https {
+        options.push("--https".to_string());
+    }
+    if let Some(style) = &answers.style {
+        options.push(format!("--style={style}"));
+    }
+    if !answers.check_status {
+        options.push("--no-check-status".to_string());
+    }
+    if let Some(print) = &answers.print {
+        options.push(format!("--print={print}"));
+    }
+
+    let mut content = String::from(
+        "// Written by \"xh init\". Change it with \"xh config\", see \"man xh\".\n{\n",
+    );
+    content.push_str(
+        "    // Added in front of the options on the command line, which can override them\n",
+    );
+    content.push_str(&format!(
+        "    \"default_options\": {}",
+        serde_json::Value::from(options)
+    ));
+    if let Some(editor) = &answers.editor {
+        content.push_str(
+            ",\n    // Used by --edit and \"xh config edit\" instead of $VISUAL or $EDITOR\n",
+        );
+        content.push_str(&format!(
+            "    \"editor\": {}",
+            serde_json::Value::from(editor.as_str())
+        ));
+    }
+    content.push_str("\n}\n");
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn questions() {
+        let mut input = "ftp\nhttps\n\nn\nx\nhb\nnano -w\n".as_bytes();
+        let answers = ask_all(&mut input).unwrap();
+        assert_eq!(
+            answers,
+            Answers {
+                https: true,
+                style: None,
+                check_status: false,
+                print: Some("hb".into()),
+                editor: Some("nano -w".into()),
+            }
+        );
+
+        let mut input = "\n".as_bytes();
+        assert!(ask_all(&mut input).is_err());
+    }
+
+    #[test]
+    fn rendering() {
+        let content = render(&Answers {
+            https: true,
+            style: Some("monokai".into()),
+            check_status: false,
+            print: Some("hb".into()),
+            editor: Some("nano".into()),
+        });
+        config::validate(&content).unwrap();
+        assert!(content.contains(
+            r#""default_options": ["--https","--style=monokai","--no-check-status","--print=hb"],"#
+        ));
+
+        let content = render(&Answers {
+            check_status: true,
+            ..Default::default()
+        });
+        config::validate(&content).unwrap();
+        assert!(content.contains("\"default_options\": []\n}"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Ctrl-C handling.
+//!
+//! Left to the default handler, an interrupt kills us in the middle of
+//! whatever we were writing, which can leave the terminal colored or
+//! without a cursor, and loses the progress of a download. So while a
+//! response body is being read, i.e. while a hook registered with
+//! [`on_interrupt`] is alive, Ctrl-C only sets a flag. The next read then
+//! runs the hooks and fails with [`Interrupted`], which unwinds through the
+//! usual error handling so that files and output get flushed, and `main`
+//! exits with code 130.
+//!
+//! If the connection has stalled there is no next read, so after a short
+//! wait the hooks are run from the signal thread instead, and we exit
+//! directly. A second Ctrl-C always exits immediately.
+//!
+//! This is only implemented on Unix. Elsewhere Ctrl-C keeps its default
+//! behavior.
+
+use std::fmt;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+static HOOKS: Mutex<Vec<(u64, Hook)>> = Mutex::new(Vec::new());
+static NEXT_HOOK_ID: AtomicU64 = AtomicU64::new(0);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Run `hook` if we're interrupted while the returned guard is alive.
+///
+/// Ctrl-C is only handled while there are hooks.
+#[must_use]
+pub fn on_interrupt(hook: impl FnOnce() + Send + 'static) -> HookGuard {
+    let id = NEXT_HOOK_ID.fetch_add(1, Ordering::Relaxed);
+    let mut hooks = HOOKS.lock().unwrap_or_else(PoisonError::into_inner);
+    if hooks.is_empty() {
+        if let Err(err) = imp::handle_sigint(true) {
+            log::debug!("Unable to handle Ctrl-C: {err}");
+        }
+    }
+    hooks.push((id, Box::new(hook)));
+    HookGuard(id)
+}
+
+pub struct HookGuard(u64);
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        let mut hooks = HOOKS.lock().unwrap_or_else(PoisonError::into_inner);
+        hooks.retain(|(id, _)| *id != self.0);
+        if hooks.is_empty() {
+            let _ = imp::handle_sigint(false);
+        }
+    }
+}
+
+/// The error for a read that was cut short by Ctrl-C.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Fail with [`Interrupted`] if Ctrl-C was pressed, after running the hooks.
+pub fn check() -> io::Result<()> {
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        run_hooks();
+        return Err(io::Error::other(Interrupted));
+    }
+    Ok(())
+}
+
+fn run_hooks() {
+    let hooks = std::mem::take(&mut *HOOKS.lock().unwrap_or_else(PoisonError::into_inner));
+    for (_, hook) in hooks.into_iter().rev() {
+        hook();
+    }
+}
+
+/// Output may have been cut off in the middle of a colored span, and
+/// progress bars hide the cursor.
+pub fn reset_terminal() {
+    if io::stdout().is_terminal() {
+        let _ = io::stdout().write_all(b"\x1b[0m");
+        let _ = io::stdout().flush();
+    }
+    if io::stderr().is_terminal() {
+        let _ = io::stderr().write_all(b"\x1b[0m\x1b[?25h");
+    }
+}
+
+/// Counts the bytes read through it, for reporting how far we got.
+pub struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        CountingReader {
+            inner,
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn count(&self) -> Arc<AtomicU64> {
+        self.count.clone()
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check()?;
+        let len = self.inner.read(buf)?;
+        self.count.fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+}
+
+/// The main thread didn't get to [`check`] in time, so clean up what we can
+/// from here.
+#[cfg(unix)]
+fn interrupted() -> ! {
+    run_hooks();
+    reset_terminal();
+    std::process::exit(130);
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::OnceLock;
+    use std::thread;
+    use std::time::Duration;
+
+    /// How long the main thread gets to notice an interrupt by itself.
+    const GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+    static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_sigint(_: libc::c_int) {
+        // Hardly anything is safe to do in a signal handler, but write() is,
+        // so pass it on to a thread that can do the actual work
+        let fd = SIGNAL_PIPE.load(Ordering::Relaxed);
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+
+    fn start_thread() -> io::Result<()> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let [read_fd, write_fd] = fds;
+        SIGNAL_PIPE.store(write_fd, Ordering::Relaxed);
+        let mut signals = unsafe { File::from_raw_fd(read_fd) };
+
+        thread::Builder::new()
+            .name("interrupt".to_string())
+            .spawn(move || loop {
+                match signals.read(&mut [0]) {
+                    Ok(1) => {
+                        // If cleaning up gets stuck a second Ctrl-C should still work
+                        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+                        super::INTERRUPTED.store(true, Ordering::Relaxed);
+                        thread::sleep(GRACE_PERIOD);
+                        super::interrupted();
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    _ => return,
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Start or stop handling Ctrl-C.
+    pub fn handle_sigint(enable: bool) -> io::Result<()> {
+        static THREAD: OnceLock<Result<(), io::ErrorKind>> = OnceLock::new();
+
+        if super::INTERRUPTED.load(Ordering::Relaxed) {
+            // Leave the second Ctrl-C to the default handler
+            return Ok(());
+        }
+        let handler = if enable {
+            if let Err(kind) = THREAD.get_or_init(|| start_thread().map_err(|err| err.kind())) {
+                return Err((*kind).into());
+            }
+            on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t
+        } else {
+            libc::SIG_DFL
+        };
+        if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub fn handle_sigint(_enable: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Minting JSON Web Tokens for `--auth-type=jwt` (RFC 7519).
+//!
+//! A PEM private key signs with RS256, ES256, ES384 or EdDSA depending on the
+//! kind of key, and anything else is taken as a shared secret for HS256. The
+//! token is made fresh for every run and is valid for five minutes, unless
+//! --jwt-claim sets `exp` itself.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use ring::rand::SystemRandom;
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, RsaKeyPair};
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+
+use crate::auth::AuthSource;
+use crate::utils::pkcs8_private_key;
+
+const LIFETIME: Duration = Duration::from_secs(300);
+
+enum Key {
+    Hmac(Vec<u8>),
+    Rsa(RsaKeyPair),
+    Ecdsa(EcdsaKeyPair, &'static str),
+    Ed25519(Ed25519KeyPair),
+}
+
+impl Key {
+    /// Load a PEM private key, or take anything else as a shared secret.
+    fn parse(key: &str) -> Result<Key> {
+        if !key.contains("-----BEGIN") {
+            return Ok(Key::Hmac(key.as_bytes().to_vec()));
+        }
+        let block = pem::parse_many(key)?
+            .into_iter()
+            .find(|block| block.tag().ends_with("PRIVATE KEY"))
+            .ok_or_else(|| anyhow!("No private key found"))?;
+        let der = pkcs8_private_key(&block)?;
+
+        let rng = SystemRandom::new();
+        if let Ok(key) = RsaKeyPair::from_pkcs8(&der) {
+            Ok(Key::Rsa(key))
+        } else if let Ok(key) =
+            EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &der, &rng)
+        {
+            Ok(Key::Ecdsa(key, "ES256"))
+        } else if let Ok(key) =
+            EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P384_SHA384_FIXED_SIGNING, &der, &rng)
+        {
+            Ok(Key::Ecdsa(key, "ES384"))
+        } else if let Ok(key) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der) {
+            Ok(Key::Ed25519(key))
+        } else {
+            Err(anyhow!(
+                "Unsupported private key, expected RSA, ECDSA (P-256 or P-384) or Ed25519"
+            ))
+        }
+    }
+
+    fn algorithm(&self) -> &'static str {
+        match self {
+            Key::Hmac(_) => "HS256",
+            Key::Rsa(_) => "RS256",
+            Key::Ecdsa(_, algorithm) => algorithm,
+            Key::Ed25519(_) => "EdDSA",
+        }
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+        match self {
+            Key::Hmac(secret) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("any key length");
+                mac.update(message);
+                Ok(mac.finalize().into_bytes().to_vec())
+            }
+            Key::Rsa(key) => {
+                let mut signature = vec![0; key.public().modulus_len()];
+                key.sign(&signature::RSA_PKCS1_SHA256, &rng, message, &mut signature)
+                    .map_err(|_| anyhow!("Failed to sign the token"))?;
+                Ok(signature)
+            }
+            Key::Ecdsa(key, _) => Ok(key
+                .sign(&rng, message)
+                .map_err(|_| anyhow!("Failed to sign the token"))?
+                .as_ref()
+                .to_vec()),
+            Key::Ed25519(key) => Ok(key.sign(message).as_ref().to_vec()),
+        }
+    }
+}
+
+/// Make a signed token from `--auth=[KEY_ID:]KEY` and the `--jwt-claim`s.
+///
+/// KEY goes through [`AuthSource`], so it can be "@FILE" or "env:VAR".
+pub fn mint(auth: &str, claims: &[(String, Value)], now: SystemTime) -> Result<String> {
+    let (key_id, key) = match auth.split_once(':') {
+        Some((key_id, key)) if !auth.starts_with('@') && !auth.starts_with("env:") => {
+            (Some(key_id), key)
+        }
+        _ => (None, auth),
+    };
+    let key = Key::parse(&AuthSource::parse(key).read()?).context("Invalid key for the JWT")?;
+
+    let mut header = Map::new();
+    header.insert("alg".into(), key.algorithm().into());
+    header.insert("typ".into(), "JWT".into());
+    if let Some(key_id) = key_id {
+        header.insert("kid".into(), key_id.into());
+    }
+
+    let iat = now.duration_since(UNIX_EPOCH)?.as_secs();
+    let mut payload = Map::new();
+    payload.insert("iat".into(), json!(iat));
+    payload.insert("exp".into(), json!(iat + LIFETIME.as_secs()));
+    for (name, value) in claims {
+        payload.insert(name.clone(), value.clone());
+    }
+
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload)?)
+    );
+    let signature = key.sign(message.as_bytes())?;
+    Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring::signature::{KeyPair, UnparsedPublicKey, ED25519};
+
+    fn decode(part: &str) -> Value {
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hs256() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let claims = [
+            ("aud".to_string(), json!("api.example.com")),
+            ("admin".to_string(), json!(true)),
+        ];
+        let token = mint("key-1:secret", &claims, now).unwrap();
+        assert_eq!(
+            token,
+            "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCIsImtpZCI6ImtleS0xIn0.\
+             eyJpYXQiOjE3MDAwMDAwMDAsImV4cCI6MTcwMDAwMDMwMCwiYXVkIjoiYXBpLmV4YW1wbGUuY29tIiwiYWRtaW4iOnRydWV9.\
+             kT1HYgHaK3_TQ4zRtXSDFQ8kolW_0d_LAhkNRlm8y6M"
+        );
+    }
+
+    #[test]
+    fn claims_override_defaults() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let token = mint("secret", &[("exp".to_string(), json!(1))], now).unwrap();
+        let parts: Vec<_> = token.split('.').collect();
+        assert_eq!(decode(parts[0]), json!({"alg": "HS256", "typ": "JWT"}));
+        assert_eq!(decode(parts[1]), json!({"iat": 1_700_000_000, "exp": 1}));
+    }
+
+    #[test]
+    fn eddsa() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let public_key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+            .unwrap()
+            .public_key()
+            .as_ref()
+            .to_vec();
+        let pem = pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref()));
+
+        let token = mint(&format!("kid:{pem}"), &[], SystemTime::now()).unwrap();
+        let (message, signature) = token.rsplit_once('.').unwrap();
+        assert_eq!(decode(message.split('.').next().unwrap())["alg"], "EdDSA");
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(
+                message.as_bytes(),
+                &URL_SAFE_NO_PAD.decode(signature).unwrap(),
+            )
+            .unwrap();
+    }
+}
@@ -1,52 +1,104 @@
 #![allow(clippy::bool_assert_comparison)]
 mod auth;
+mod browser;
 mod buffer;
 mod cli;
+mod clipboard;
+mod config;
+mod confirm;
+mod content_disposition;
+mod crawl;
+#[cfg(feature = "data-summaries")]
+mod data_summary;
 mod decoder;
+mod dns;
 mod download;
+mod edit;
+mod error_reporting;
 mod formatting;
+mod ftp;
+mod generators;
+mod graphql;
+mod init;
+mod interrupt;
+mod jwt;
+mod message_signature;
 mod middleware;
+mod mirror;
+mod multipart;
 mod nested_json;
 mod netrc;
+mod notify;
+mod ntlm;
+mod oauth;
+mod output_template;
+mod presets;
+mod presign;
+mod print_command;
 mod printer;
+mod protobuf;
+mod raw;
 mod redacted;
 mod redirect;
 mod request_items;
+mod retry;
 mod session;
+mod template;
 mod to_curl;
 mod utils;
 mod vendored;
+mod websocket;
+mod zone;
 
+use std::collections::HashMap;
 use std::env;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{self, IsTerminal, Read};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
 use std::path::PathBuf;
 use std::process;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{anyhow, Context, Result};
 use cookie_store::{CookieStore, RawCookie};
 use redirect::RedirectFollower;
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, Request, Response};
 use reqwest::header::{
-    HeaderValue, ACCEPT, ACCEPT_ENCODING, CONNECTION, CONTENT_TYPE, COOKIE, RANGE, USER_AGENT,
+    HeaderValue, ACCEPT, ACCEPT_ENCODING, AUTHORIZATION, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE,
+    COOKIE, DATE, EXPECT, IF_RANGE, RANGE, SET_COOKIE, TRAILER, TRANSFER_ENCODING, USER_AGENT,
 };
-use reqwest::tls;
-use url::Host;
+use reqwest::{tls, Method};
 use utils::reason_phrase;
 
-use crate::auth::{Auth, DigestAuthMiddleware};
+use crate::auth::{
+    prompt_secret, Auth, AuthSource, DigestAuthMiddleware, NtlmAuthMiddleware, ReauthMiddleware,
+};
 use crate::buffer::Buffer;
-use crate::cli::{Cli, FormatOptions, HttpVersion, Print, Proxy, Verify};
-use crate::download::{download_file, get_file_size};
-use crate::middleware::ClientWithMiddleware;
+use crate::cli::{
+    AuthType, BodySource, CertFormat, Cli, DateHeader, FormatOptions, HttpVersion, InputFormat,
+    Print, Proxy, Theme, Verify,
+};
+use crate::crawl::Crawler;
+use crate::decoder::{decompress, get_compression_type, DEFAULT_MAX_DECODE_SIZE};
+use crate::download::{
+    download_file, download_ftp, get_file_size, resume_path, saved_etag, split_parts,
+};
+use crate::error_reporting::{status_exit_code, ErrorKind, FailedHop};
+use crate::message_signature::PublicKey;
+use crate::middleware::{ClientWithMiddleware, ResponseExt, ResponseMeta};
+use crate::mirror::Mirror;
 use crate::printer::Printer;
 use crate::request_items::{Body, FORM_CONTENT_TYPE, JSON_ACCEPT, JSON_CONTENT_TYPE};
+use crate::retry::{HttpsFallback, RetryStaleConnection};
 use crate::session::Session;
-use crate::utils::{test_mode, test_pretend_term, url_with_query};
+use crate::utils::{
+    http2_error_hint, interface_index, is_local_url, read_ca_certificates, test_mode,
+    test_pretend_term, url_with_query,
+};
 use crate::vendored::reqwest_cookie_store;
+use crate::zone::ZonedHost;
 
 #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
 compile_error!("Either native-tls or rustls feature must be enabled!");
@@ -67,6 +119,8 @@ fn main() {
         setup_backtraces();
     }
     args.logger_config().init();
+    #[cfg(windows)]
+    buffer::setup_console();
     // HTTPie also prints the language version, library versions, and OS version.
     // But those are harder to access for us (and perhaps less likely to cause quirks).
     log::debug!("xh {} {}", env!("CARGO_PKG_VERSION"), env!("XH_FEATURES"));
@@ -74,6 +128,7 @@ fn main() {
 
     let native_tls = args.native_tls;
     let bin_name = args.bin_name.clone();
+    let porcelain_url = args.porcelain.then(|| args.url.clone());
 
     match run(args) {
         Ok(exit_code) => {
@@ -81,38 +136,143 @@ fn main() {
         }
         Err(err) => {
             log::debug!("{err:#?}");
-            eprintln!("{bin_name}: error: {err:?}");
-            let msg = err.root_cause().to_string();
-            if native_tls && msg == "invalid minimum TLS version for backend" {
-                eprintln!();
-                eprintln!("Try running without the --native-tls flag.");
-            }
-            if let Some(err) = err.downcast_ref::<reqwest::Error>() {
-                if err.is_timeout() {
-                    process::exit(2);
+            let kind = ErrorKind::classify(&err);
+            if kind == ErrorKind::Interrupted {
+                // The interrupt hooks have already said what happened
+                interrupt::reset_terminal();
+            } else {
+                eprintln!("{bin_name}: error: {err:?}");
+                let msg = err.root_cause().to_string();
+                if native_tls && msg == "invalid minimum TLS version for backend" {
+                    eprintln!();
+                    eprintln!("Try running without the --native-tls flag.");
+                }
+                if let Some(hint) = http2_error_hint(&err) {
+                    eprintln!();
+                    eprintln!("{hint}");
                 }
             }
-            if msg.starts_with("Too many redirects") {
-                process::exit(6);
+            let exit_code = kind.exit_code();
+            if let Some(url) = porcelain_url {
+                println!("ERR {exit_code} {url}");
             }
-            process::exit(1);
+            process::exit(exit_code);
         }
     }
 }
 
 fn run(args: Cli) -> Result<i32> {
+    if let Some(command) = &args.config_command {
+        return command.run();
+    }
+    if let Some(command) = &args.session_command {
+        return command.run();
+    }
+    if args.init {
+        return init::run();
+    }
+    if let Some(target) = &args.rawsend {
+        let verify = verify_setting(args.verify);
+        raw::rawsend(
+            target,
+            args.tls.then_some(&verify),
+            args.timeout.as_ref().and_then(|t| t.as_duration()),
+            io::stdout().is_terminal() || test_pretend_term(),
+        )?;
+        return Ok(0);
+    }
     if args.curl {
         to_curl::print_curl_translation(args)?;
         return Ok(0);
     }
+    if args.print_command {
+        print_command::print_command(args)?;
+        return Ok(0);
+    }
+
+    #[cfg(not(feature = "xattr"))]
+    if args.xattr {
+        return Err(anyhow!("This binary was built without xattr support"));
+    }
+
+    // The time for the Date header and for tokens, as moved by --clock-skew
+    let now = match args.clock_skew {
+        Some(skew) => skew
+            .apply(SystemTime::now())
+            .context("--clock-skew moves the date out of range")?,
+        None => SystemTime::now(),
+    };
 
     let (mut headers, headers_to_unset) = args.request_items.headers()?;
-    let url = url_with_query(args.url, &args.request_items.query()?);
+    let mut url = url_with_query(args.url, &args.request_items.query()?);
     log::debug!("Complete URL: {url}");
 
-    let use_stdin = !(args.ignore_stdin || io::stdin().is_terminal() || test_pretend_term());
+    // The handshake is a plain HTTP request, so sessions, netrc and the like
+    // can treat it as one
+    let is_websocket = websocket::is_websocket_url(&url);
+    if is_websocket {
+        let scheme = if url.scheme() == "wss" {
+            "https"
+        } else {
+            "http"
+        };
+        url.set_scheme(scheme)
+            .map_err(|_| anyhow!("Can't connect to {url} over WebSocket"))?;
+    }
+
+    if ftp::is_ftp_url(&url) {
+        if !args.download {
+            return Err(anyhow!(
+                "{}:// URLs can only be used with --download",
+                url.scheme()
+            ));
+        }
+        // The same as for HTTP downloads, which always print to stderr
+        let pretty = args
+            .pretty
+            .unwrap_or_else(|| Buffer::for_stderr(true).guess_pretty());
+        download_ftp(
+            &url,
+            args.output,
+            args.resume,
+            &verify_setting(args.verify),
+            args.timeout.as_ref().and_then(|t| t.as_duration()),
+            pretty.color().then_some(args.style.unwrap_or_default()),
+            args.quiet > 0,
+            args.porcelain,
+            args.sync,
+            args.xattr,
+        )?;
+        return Ok(0);
+    }
+
+    if let Some(presign) = &args.presign {
+        let method = args
+            .method
+            .clone()
+            .unwrap_or_else(|| args.request_items.pick_method());
+        let expiry = args.presign_expiry.unwrap_or(Duration::from_secs(15 * 60));
+        println!("{}", presign.sign(url, &method, now + expiry));
+        return Ok(0);
+    }
+
+    let use_stdin = match args.body_from {
+        Some(BodySource::Stdin) => true,
+        Some(BodySource::File) => false,
+        None => {
+            // A WebSocket reads stdin line by line later on, and --http-raw
+            // takes the whole request from a file
+            !(args.ignore_stdin
+                || args.edit
+                || is_websocket
+                || args.http_raw.is_some()
+                || io::stdin().is_terminal()
+                || test_pretend_term())
+        }
+    };
 
-    let body = if use_stdin {
+    // With --graphql stdin holds the query, and the items are the variables
+    let body = if use_stdin && !args.graphql {
         if !args.request_items.is_body_empty() {
             if args.multipart {
                 // Multipart bodies are never "empty", so we can get here without request items
@@ -132,25 +292,79 @@ fn run(args: Cli) -> Result<i32> {
         }
         let mut buffer = Vec::new();
         io::stdin().read_to_end(&mut buffer)?;
-        Body::Raw(buffer)
+        match args.input_format {
+            Some(InputFormat::Json) | None => Body::Raw(buffer),
+            Some(format) => Body::Json(request_items::parse_input(&buffer, format)?),
+        }
     } else if let Some(raw) = args.raw {
-        Body::Raw(raw.into_bytes())
+        if raw == clipboard::RAW_ARG {
+            Body::Raw(clipboard::paste()?)
+        } else {
+            Body::Raw(raw.into_bytes())
+        }
     } else {
         args.request_items.body()?
     };
+    let body = if args.graphql {
+        let query = if use_stdin {
+            let mut query = String::new();
+            io::stdin().read_to_string(&mut query)?;
+            Some(query).filter(|query| !query.trim().is_empty())
+        } else {
+            None
+        };
+        graphql::envelope(body, query)?
+    } else {
+        body
+    };
+    let body = if args.edit {
+        edit::edit_body(body)?
+    } else {
+        body
+    };
+    let proto = match &args.proto {
+        Some(path) => {
+            let content_type = headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok());
+            Some(protobuf::Call::load(path, &url, content_type)?)
+        }
+        None => None,
+    };
+    let body = match &proto {
+        Some(call) => Body::Raw(call.encode_request(body)?),
+        None => body,
+    };
 
-    let method = args.method.unwrap_or_else(|| body.pick_method());
+    let method = if is_websocket {
+        match args.method {
+            Some(method) if method != Method::GET => {
+                return Err(anyhow!("A WebSocket handshake can't be a {method} request"));
+            }
+            _ => Method::GET,
+        }
+    } else if proto.is_some() {
+        args.method.unwrap_or(Method::POST)
+    } else {
+        args.method.unwrap_or_else(|| body.pick_method())
+    };
     log::debug!("HTTP method: {method}");
 
     let mut client = Client::builder()
         .http1_title_case_headers()
         .http2_adaptive_window(true)
         .redirect(reqwest::redirect::Policy::none())
-        .timeout(args.timeout.and_then(|t| t.as_duration()))
+        .timeout(args.timeout.as_ref().and_then(|t| t.as_duration()))
+        .tcp_nodelay(args.tcp_nodelay.unwrap_or(true))
+        .tcp_keepalive(args.tcp_keepalive)
         .no_gzip()
         .no_deflate()
         .no_brotli();
 
+    // Whether we end up using native-tls, which needs to know its certificates
+    #[cfg_attr(not(feature = "native-tls"), allow(unused_mut))]
+    let mut native_tls = args.native_tls || cfg!(not(feature = "rustls"));
+
     #[cfg(feature = "rustls")]
     if !args.native_tls {
         client = client.use_rustls_tls();
@@ -165,6 +379,7 @@ fn run(args: Cli) -> Result<i32> {
         if !args.native_tls && tls_version < tls::Version::TLS_1_2 {
             log::warn!("rustls does not support older TLS versions. native-tls will be enabled. Use --native-tls to silence this warning.");
             client = client.use_native_tls();
+            native_tls = true;
         }
 
         #[cfg(not(feature = "native-tls"))]
@@ -187,39 +402,17 @@ fn run(args: Cli) -> Result<i32> {
     let mut resume: Option<u64> = None;
     let mut auth = None;
     let mut save_auth_in_session = true;
+    let mut auth_from_session = false;
 
-    let verify = args.verify.unwrap_or_else(|| {
-        // requests library which is used by HTTPie checks for both
-        // REQUESTS_CA_BUNDLE and CURL_CA_BUNDLE environment variables.
-        // See https://docs.python-requests.org/en/master/user/advanced/#ssl-cert-verification
-        if let Some(path) = env::var_os("REQUESTS_CA_BUNDLE") {
-            Verify::CustomCaBundle(PathBuf::from(path))
-        } else if let Some(path) = env::var_os("CURL_CA_BUNDLE") {
-            Verify::CustomCaBundle(PathBuf::from(path))
-        } else {
-            Verify::Yes
-        }
-    });
-    client = match verify {
+    let verify = verify_setting(args.verify);
+    client = match &verify {
         Verify::Yes => client,
         Verify::No => client.danger_accept_invalid_certs(true),
-        Verify::CustomCaBundle(path) => {
-            if args.native_tls {
-                // This is not a hard error in case it gets fixed upstream
-                // https://github.com/seanmonstar/reqwest/issues/1260
-                log::warn!("Custom CA bundles with native-tls are broken");
+        Verify::CustomCaBundle(path) | Verify::ExtraCaBundle(path) => {
+            if let Verify::CustomCaBundle(_) = verify {
+                client = client.tls_built_in_root_certs(false);
             }
-
-            let mut buffer = Vec::new();
-            let mut file = File::open(&path).with_context(|| {
-                format!("Failed to open the custom CA bundle: {}", path.display())
-            })?;
-            file.read_to_end(&mut buffer).with_context(|| {
-                format!("Failed to read the custom CA bundle: {}", path.display())
-            })?;
-
-            client = client.tls_built_in_root_certs(false);
-            for pem in pem::parse_many(buffer)? {
+            for pem in read_ca_certificates(path)? {
                 let certificate = reqwest::Certificate::from_pem(pem::encode(&pem).as_bytes())
                     .with_context(|| {
                         format!("Failed to load the custom CA bundle: {}", path.display())
@@ -230,21 +423,38 @@ fn run(args: Cli) -> Result<i32> {
         }
     };
 
-    #[cfg(feature = "rustls")]
     if let Some(cert) = args.cert {
-        if args.native_tls {
-            // Unlike the --verify case this is advertised to not work, so it's
-            // not an outright bug, but it's still imaginable that it'll start working
-            log::warn!("Client certificates are not supported for native-tls");
-        }
-
         let mut buffer = Vec::new();
         let mut file = File::open(&cert)
             .with_context(|| format!("Failed to open the cert file: {}", cert.display()))?;
         file.read_to_end(&mut buffer)
             .with_context(|| format!("Failed to read the cert file: {}", cert.display()))?;
 
-        if let Some(cert_key) = args.cert_key {
+        if args.cert_format == Some(CertFormat::P12) {
+            if args.cert_key.is_some() {
+                return Err(anyhow!(
+                    "--cert-key can't be used with --cert-format=p12, the key is in the bundle"
+                ));
+            }
+            let wrong_password = || anyhow!("Wrong password for {}", cert.display());
+            buffer = match AuthSource::from_args(
+                args.cert_password.as_deref().map(String::as_str),
+                args.cert_password_from.as_deref(),
+            ) {
+                Some(password) => {
+                    utils::pkcs12_to_pem(&buffer, &password.read()?)?.ok_or_else(wrong_password)?
+                }
+                // Bundles are often exported without a password
+                None => match utils::pkcs12_to_pem(&buffer, "")? {
+                    Some(pem) => pem,
+                    None => {
+                        let prompt = format!("xh: password for {}: ", cert.display());
+                        utils::pkcs12_to_pem(&buffer, &prompt_secret(&prompt)?)?
+                            .ok_or_else(wrong_password)?
+                    }
+                },
+            };
+        } else if let Some(cert_key) = args.cert_key {
             buffer.push(b'\n');
 
             let mut file = File::open(&cert_key).with_context(|| {
@@ -256,22 +466,16 @@ fn run(args: Cli) -> Result<i32> {
         }
 
         // We may fail here if we can't parse it but also if we don't have the key
-        let identity = reqwest::Identity::from_pem(&buffer)
+        let identity = client_identity(&buffer, native_tls)
             .context("Failed to load the cert/cert key files")?;
         client = client.identity(identity);
     }
-    #[cfg(not(feature = "rustls"))]
-    if args.cert.is_some() {
-        // Unlike the --verify case this is advertised to not work, so it's
-        // not an outright bug, but it's still imaginable that it'll start working
-        log::warn!("Client certificates are not supported for native-tls and this binary was built without rustls support");
-    }
 
-    for proxy in args.proxy.into_iter().rev() {
+    for proxy in args.proxy.iter().rev() {
         client = client.proxy(match proxy {
-            Proxy::Http(url) => reqwest::Proxy::http(url),
-            Proxy::Https(url) => reqwest::Proxy::https(url),
-            Proxy::All(url) => reqwest::Proxy::all(url),
+            Proxy::Http(url) => reqwest::Proxy::http(url.clone()),
+            Proxy::Https(url) => reqwest::Proxy::https(url.clone()),
+            Proxy::All(url) => reqwest::Proxy::all(url.clone()),
         }?);
     }
 
@@ -328,8 +532,84 @@ fn run(args: Cli) -> Result<i32> {
         };
     }
 
+    // reqwest's own overrides always return the addresses in the same order,
+    // so for --lb they go through our resolver instead
+    let mut overrides = HashMap::new();
     for resolve in args.resolve {
-        client = client.resolve(&resolve.domain, SocketAddr::new(resolve.addr, 0));
+        let mut addrs = Vec::with_capacity(resolve.addrs.len());
+        for resolve_addr in &resolve.addrs {
+            addrs.push(match (resolve_addr.addr, &resolve_addr.zone_id) {
+                (IpAddr::V6(addr), Some(zone_id)) => {
+                    SocketAddr::V6(SocketAddrV6::new(addr, 0, 0, interface_index(zone_id)?))
+                }
+                (addr, _) => SocketAddr::new(addr, 0),
+            });
+        }
+        if args.lb.is_some() {
+            overrides.insert(resolve.domain.to_lowercase(), addrs);
+        } else {
+            client = client.resolve_to_addrs(&resolve.domain, &addrs);
+        }
+    }
+
+    // The resolved addresses keep their port unless the URL has one of its own
+    if let Some(route_to) = &args.route_to {
+        let domain = url
+            .domain()
+            .context("--route-to needs a URL with a hostname, not an IP address")?;
+        if url.port().is_some_and(|port| port != route_to.port) {
+            return Err(anyhow!(
+                "--route-to can't change the port of a URL with an explicit port, \
+                 use --route-to={}:{} or remove the port from the URL",
+                route_to.host,
+                url.port().unwrap_or_default()
+            ));
+        }
+        let addrs: Vec<SocketAddr> = (route_to.host.as_str(), route_to.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Couldn't resolve {} for --route-to", route_to.host))?
+            .collect();
+        log::debug!("Routing {domain} to {addrs:?}");
+        if args.lb.is_some() {
+            overrides.insert(domain.to_lowercase(), addrs);
+        } else {
+            client = client.resolve_to_addrs(domain, &addrs);
+        }
+    }
+
+    let scoped_addr = match &args.zone_id {
+        Some(zone_id) => zone::scoped_addr(&url, zone_id)?,
+        None => None,
+    };
+    if let Some(addr) = &scoped_addr {
+        let stand_in = zone::stand_in_host(addr);
+        log::debug!("Routing {stand_in} to {addr}");
+        if args.lb.is_some() {
+            overrides.insert(stand_in, vec![SocketAddr::V6(*addr)]);
+        } else {
+            client = client.resolve_to_addrs(&stand_in, &[SocketAddr::V6(*addr)]);
+        }
+    }
+
+    let use_dns_resolver = !args.dns_servers.is_empty()
+        || args.dns_url.is_some()
+        || args.lb.is_some()
+        || cfg!(feature = "mdns");
+    let dns_resolver = if use_dns_resolver {
+        let servers = args.dns_servers.iter().map(|server| server.0).collect();
+        let mut resolver =
+            dns::Resolver::new(servers, args.timeout.as_ref().and_then(|t| t.as_duration()))
+                .with_overrides(overrides)
+                .with_load_balance(args.lb);
+        if let Some(url) = &args.dns_url {
+            resolver = resolver.with_doh(url.clone())?;
+        }
+        Some(resolver)
+    } else {
+        None
+    };
+    if let Some(dns_resolver) = &dns_resolver {
+        client = client.dns_resolver(Arc::new(dns_resolver.clone()));
     }
 
     log::trace!("Finalizing reqwest client");
@@ -348,6 +628,7 @@ fn run(args: Cli) -> Result<i32> {
 
     if let Some(ref mut s) = session {
         auth = s.auth()?;
+        auth_from_session = auth.is_some();
 
         headers = {
             let mut session_headers = s.headers()?;
@@ -367,6 +648,22 @@ fn run(args: Cli) -> Result<i32> {
         }
     }
 
+    // reqwest doesn't hand out the readers inside its bodies, so a request
+    // that's written to the connection by hand keeps one of its own
+    let writes_raw_request = args.raw_headers || args.expect_continue || !args.trailers.is_empty();
+    let mut body_reader = None;
+    let mut streamed_body = |reader: Box<dyn Read + Send>, len: Option<u64>| {
+        if writes_raw_request {
+            let reader = raw::BodyReader::new(reader, len);
+            body_reader = Some(reader.clone());
+            reader.to_body()
+        } else if let Some(len) = len {
+            reqwest::blocking::Body::sized(reader, len)
+        } else {
+            reqwest::blocking::Body::new(reader)
+        }
+    };
+
     let mut request = {
         let mut request_builder = client
             .request(method, url.clone())
@@ -395,7 +692,13 @@ fn run(args: Cli) -> Result<i32> {
 
         request_builder = match body {
             Body::Form(body) => request_builder.form(&body),
-            Body::Multipart(body) => request_builder.multipart(body),
+            Body::Multipart(body) => {
+                let content_type = body.content_type();
+                let (reader, len) = body.into_reader();
+                request_builder
+                    .header(CONTENT_TYPE, content_type)
+                    .body(streamed_body(reader, Some(len)))
+            }
             Body::Json(body) => {
                 // An empty JSON body would produce null instead of "", so
                 // this is the one kind of body that needs an is_null() check
@@ -417,6 +720,11 @@ fn run(args: Cli) -> Result<i32> {
                 if args.form {
                     request_builder
                         .header(CONTENT_TYPE, HeaderValue::from_static(FORM_CONTENT_TYPE))
+                } else if let Some(call) = &proto {
+                    let content_type = HeaderValue::from_str(call.content_type())?;
+                    request_builder
+                        .header(ACCEPT, content_type.clone())
+                        .header(CONTENT_TYPE, content_type)
                 } else {
                     request_builder
                         .header(ACCEPT, HeaderValue::from_static(JSON_ACCEPT))
@@ -437,10 +745,18 @@ fn run(args: Cli) -> Result<i32> {
                         "Ignoring ;filename= tag for single-file body. Consider --multipart."
                     );
                 }
-                request_builder.body(File::open(file_name)?).header(
-                    CONTENT_TYPE,
-                    file_type.unwrap_or_else(|| HeaderValue::from_static(JSON_CONTENT_TYPE)),
-                )
+                let file = File::open(file_name)?;
+                // Pipes, sockets and character devices (e.g. /dev/fd/3 from
+                // process substitution) report a size of zero, so only send a
+                // Content-Length for regular files and stream the rest chunked
+                let metadata = file.metadata()?;
+                let len = metadata.is_file().then_some(metadata.len());
+                request_builder
+                    .body(streamed_body(Box::new(file), len))
+                    .header(
+                        CONTENT_TYPE,
+                        file_type.unwrap_or_else(|| HeaderValue::from_static(JSON_CONTENT_TYPE)),
+                    )
             }
         };
 
@@ -464,25 +780,76 @@ fn run(args: Cli) -> Result<i32> {
                 log::warn!(
                     "--continue can't be used with a 'Range:' header. --continue will be disabled."
                 );
-            } else if let Some(file_size) = get_file_size(args.output.as_deref()) {
+            } else if let Some(file_size) =
+                get_file_size(args.output.as_deref().map(resume_path).as_deref())
+            {
                 request_builder = request_builder.header(RANGE, format!("bytes={}-", file_size));
                 resume = Some(file_size);
+                // Have the server send the whole file if it changed since
+                if args.xattr && !headers.contains_key(IF_RANGE) {
+                    let part = args.output.as_deref().map(resume_path);
+                    if let Some(etag) = part.and_then(|part| saved_etag(&part)) {
+                        request_builder = request_builder.header(IF_RANGE, etag);
+                    }
+                }
             }
         }
 
         let auth_type = args.auth_type.unwrap_or_default();
-        if let Some(auth_from_arg) = args.auth {
+        if auth_type == AuthType::Oauth2 {
+            // Reuse the session's token until it expires
+            auth = match auth.take() {
+                Some(Auth::OAuth2(token, expires)) if oauth::is_fresh(expires) => {
+                    Some(Auth::OAuth2(token, expires))
+                }
+                _ if args.offline => {
+                    return Err(anyhow!("Can't get an OAuth 2.0 token with --offline"));
+                }
+                _ => {
+                    let client_secret = match AuthSource::from_args(
+                        args.auth.as_deref().map(String::as_str),
+                        args.auth_from.as_deref(),
+                    ) {
+                        Some(source) => Some(source.read()?),
+                        None => None,
+                    };
+                    Some(oauth::fetch_token(
+                        &client,
+                        args.oauth_token_url.as_ref(),
+                        args.oauth_client_id.as_deref(),
+                        client_secret.as_deref(),
+                        args.oauth_scope.as_deref(),
+                        args.oauth_device_url.as_ref(),
+                    )?)
+                }
+            };
+            auth_from_session = false;
+        } else if auth_type == AuthType::Jwt {
+            let key = AuthSource::from_args(
+                args.auth.as_deref().map(String::as_str),
+                args.auth_from.as_deref(),
+            )
+            .ok_or_else(|| anyhow!("--auth-type=jwt requires --auth=[KEY_ID:]@KEY_FILE"))?
+            .read()?;
+            let token = jwt::mint(&key, &args.jwt_claims, now)?;
+            auth = Some(Auth::Bearer(token));
+            auth_from_session = false;
+            // A new token is made every time, so saving one is pointless
+            save_auth_in_session = false;
+        } else if let Some(auth_from_arg) = AuthSource::from_args(
+            args.auth.as_deref().map(String::as_str),
+            args.auth_from.as_deref(),
+        ) {
+            auth_from_session = false;
             auth = Some(Auth::from_str(
-                &auth_from_arg,
+                &auth_from_arg.read()?,
                 auth_type,
                 url.host_str().unwrap_or("<host>"),
             )?);
         } else if !args.ignore_netrc {
-            // I don't know if it's possible for host() to return None
-            // But if it does we still want to use the default entry, if there is one
-            let host = url.host().unwrap_or(Host::Domain(""));
-            if let Some(entry) = netrc::find_entry(host) {
+            if let Some(entry) = netrc::find_entry(&url) {
                 auth = Auth::from_netrc(auth_type, entry);
+                auth_from_session = false;
                 save_auth_in_session = false;
             }
         }
@@ -497,11 +864,24 @@ fn run(args: Cli) -> Result<i32> {
                 Auth::Basic(username, password) => {
                     request_builder.basic_auth(username, password.as_ref())
                 }
-                Auth::Bearer(token) => request_builder.bearer_auth(token),
-                Auth::Digest(..) => request_builder,
+                Auth::Bearer(token) | Auth::OAuth2(token, _) => request_builder.bearer_auth(token),
+                Auth::Digest(..) | Auth::Ntlm(..) | Auth::Negotiate(..) => request_builder,
             }
         }
 
+        let date = match (args.date_header, args.clock_skew) {
+            (Some(DateHeader::At(date)), Some(skew)) => Some(
+                skew.apply(date)
+                    .context("--clock-skew moves the date out of range")?,
+            ),
+            (Some(DateHeader::At(date)), None) => Some(date),
+            (Some(DateHeader::Now), _) | (None, Some(_)) => Some(now),
+            (None, None) => None,
+        };
+        if let Some(date) = date {
+            request_builder = request_builder.header(DATE, httpdate::fmt_http_date(date));
+        }
+
         let mut request = request_builder.headers(headers).build()?;
 
         for header in &headers_to_unset {
@@ -517,6 +897,20 @@ fn run(args: Cli) -> Result<i32> {
             .insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
     }
 
+    if !args.trailers.is_empty() {
+        let names: Vec<&str> = args.trailers.iter().map(|t| t.name.as_str()).collect();
+        let headers = request.headers_mut();
+        headers.remove(CONTENT_LENGTH);
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        headers.insert(TRAILER, HeaderValue::from_str(&names.join(", "))?);
+    }
+
+    if args.expect_continue && request.body().is_some() {
+        request
+            .headers_mut()
+            .insert(EXPECT, HeaderValue::from_static("100-continue"));
+    }
+
     log::trace!("Built reqwest request");
     // Note: Debug impl is incomplete?
     log::trace!("{request:#?}");
@@ -531,78 +925,347 @@ fn run(args: Cli) -> Result<i32> {
         Some(print) => print,
         None => Print::new(
             args.verbose,
-            args.headers,
+            args.headers || args.no_body_read,
             args.body,
             args.meta,
-            args.quiet > 0,
+            args.quiet > 0 || args.template.is_some(),
             args.offline,
             &buffer,
         ),
     };
     let theme = args.style.unwrap_or_default();
     let pretty = args.pretty.unwrap_or_else(|| buffer.guess_pretty());
+    if theme == Theme::Auto && pretty.color() {
+        formatting::background::init(config::background());
+    }
+    // The default limit is for bodies that end up in memory or on the
+    // terminal. Saved files may be as large as the disk allows.
+    let saves_body =
+        args.output.is_some() || args.download || args.split_parts.is_some() || args.mirror;
+    let max_decode_size = match args.max_decode_size {
+        Some(size) => size.0,
+        None if saves_body => u64::MAX,
+        None => DEFAULT_MAX_DECODE_SIZE,
+    };
+    let signature_key = match &args.verify_signature {
+        Some(key) => Some(PublicKey::load(key)?),
+        None => None,
+    };
     let format_options = args
         .format_options
         .iter()
         .fold(FormatOptions::default(), FormatOptions::merge);
-    let mut printer = Printer::new(pretty, theme, args.stream, buffer, format_options);
+    let mut printer = Printer::new(pretty, theme, args.stream, buffer, format_options)
+        .with_max_display_bytes(args.max_display_bytes.map(|size| size.0))
+        .with_summarize_bodies(args.summarize_bodies.map(|size| size.0))
+        .with_max_decode_size(max_decode_size)
+        .with_show_cookies(args.show_cookies)
+        .with_decode_base64(args.decode_body_base64)
+        .with_decode_jwt(args.decode_jwt)
+        .with_dns_resolver(dns_resolver)
+        .with_pipe(args.pipe.clone())
+        .with_protobuf(proto.clone())
+        .with_keep_body(args.copy || args.open || args.graphql || proto.is_some())
+        .with_print_to(args.print_to.map(|print_to| {
+            let stderr = Buffer::for_stderr(io::stderr().is_terminal() || test_pretend_term());
+            (print_to.stderr, stderr)
+        }))
+        .with_header_dump(
+            args.dump_header
+                .as_ref()
+                .map(|path| {
+                    File::create(path)
+                        .with_context(|| format!("couldn't create {}", path.display()))
+                })
+                .transpose()?,
+        )
+        .with_metrics_file(
+            args.metrics_file
+                .as_ref()
+                .map(|path| {
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .with_context(|| format!("couldn't open {}", path.display()))
+                })
+                .transpose()?,
+        );
 
     let response_charset = args.response_charset;
     let response_mime = args.response_mime.as_deref();
 
-    if print.request_headers {
-        printer.print_request_headers(&request, &*cookie_jar)?;
+    if !args.offline && !args.allow_insecure_auth {
+        warn_insecure_credentials(&request, &*cookie_jar);
+    }
+
+    let first_message = if is_websocket {
+        if let Some(cookie) = reqwest::cookie::CookieStore::cookies(&*cookie_jar, request.url()) {
+            request.headers_mut().insert(COOKIE, cookie);
+        }
+        websocket::prepare_handshake(&mut request)?
+    } else {
+        None
+    };
+
+    let http_raw = args
+        .http_raw
+        .as_deref()
+        .map(raw::read_request)
+        .transpose()?;
+    if let Some(data) = &http_raw {
+        if print.request_headers || print.request_body {
+            printer.print_raw_request(data)?;
+        }
+    } else {
+        if print.request_headers {
+            printer.print_request_headers(&request, &*cookie_jar)?;
+        }
+        if print.request_body {
+            printer.print_request_body(&mut request)?;
+        }
+    }
+
+    if !args.offline && confirm::is_needed(request.method(), args.confirm) {
+        // Show the request if it isn't on the terminal already
+        if !print.request_headers || is_output_redirected {
+            let stderr = Buffer::for_stderr(io::stderr().is_terminal() || test_pretend_term());
+            let pretty = stderr.guess_pretty();
+            let mut preview = Printer::new(pretty, theme, false, stderr, FormatOptions::default());
+            preview.print_request_headers(&request, &*cookie_jar)?;
+            preview.print_request_body(&mut request)?;
+        }
+        if !confirm::ask()? {
+            return Err(anyhow!("The request wasn't sent"));
+        }
     }
-    if print.request_body {
-        printer.print_request_body(&mut request)?;
+
+    // These write the request to the connection by hand instead of going
+    // through reqwest, but the response is handled the same way after that
+    let raw_transport = writes_raw_request || http_raw.is_some();
+
+    if scoped_addr.is_some() && (raw_transport || is_websocket || args.crawl) && !args.offline {
+        return Err(anyhow!(
+            "A zone ID in the URL doesn't work with WebSockets, --crawl, or requests \
+             that are written to the connection by hand"
+        ));
     }
 
-    if !args.offline {
-        let mut response = {
+    if is_websocket && !raw_transport && !args.offline {
+        let tls = (url.scheme() == "https").then_some(&verify);
+        websocket::run(
+            &mut request,
+            first_message,
+            tls,
+            args.timeout.as_ref().and_then(|t| t.as_duration()),
+            !(args.ignore_stdin || use_stdin),
+            &mut printer,
+            &print,
+        )?;
+    } else if args.crawl && !raw_transport && !args.offline {
+        let report = Crawler::new(&client, &request)
+            .with_max_depth(args.depth)
+            .with_same_host(args.same_host)
+            .with_max_redirects(args.max_redirects.unwrap_or(10))
+            .with_respect_robots(!args.ignore_robots)
+            .run()?;
+        report.write(
+            &mut io::stdout().lock(),
+            args.report_format.unwrap_or_default(),
+        )?;
+        if args.quiet == 0 {
+            eprint!(
+                "Checked {} links, {} broken",
+                report.checked,
+                report.broken.len()
+            );
+            if report.disallowed > 0 {
+                eprint!(", {} disallowed by robots.txt", report.disallowed);
+            }
+            eprintln!();
+        }
+        if !report.broken.is_empty() {
+            exit_code = 1;
+        }
+    } else if args.mirror && !raw_transport && !args.offline {
+        let mut client: ClientWithMiddleware<fn(&mut Response, &mut Request) -> Result<()>> =
+            ClientWithMiddleware::new(&client).with(RedirectFollower::new(
+                args.max_redirects.unwrap_or(10),
+                args.retry_all_methods,
+            ));
+        if let Some(Auth::Digest(username, password)) = &auth {
+            client = client.with(DigestAuthMiddleware::new(username, password));
+        }
+        if let Some(ntlm) = auth.as_ref().and_then(NtlmAuthMiddleware::for_auth) {
+            client = client.with(ntlm);
+        }
+        client = client.with(RetryStaleConnection::new(args.retry_all_methods));
+        if let Some(addr) = &scoped_addr {
+            client = client.with(ZonedHost::new(addr));
+        }
+        let failed = Mirror::new(
+            &mut |request| client.execute(request),
+            &request,
+            args.output,
+        )
+        .with_max_depth(args.mirror_depth)
+        .with_max_size(args.mirror_max_size.map(|size| size.0))
+        .with_respect_robots(!args.ignore_robots)
+        .with_output(
+            pretty.color().then_some(theme),
+            args.quiet > 0,
+            args.porcelain,
+        )
+        .with_max_decode_size(max_decode_size)
+        .with_sync(args.sync)
+        .with_xattr(args.xattr)
+        .run()?;
+        if failed > 0 {
+            exit_code = 1;
+        }
+    } else if !args.offline {
+        let mut renewed_auth = None;
+        let mut response = if raw_transport {
+            let host = url.host_str().unwrap_or_default();
+            let port = url.port_or_known_default().unwrap_or(80);
+            let tls = (url.scheme() == "https").then_some(&verify);
+            let timeout = args.timeout.as_ref().and_then(|t| t.as_duration());
+            let proxy = raw::select_proxy(&args.proxy, &url);
+            let starting_time = Instant::now();
+            let (mut stream, socket) = raw::connect_http(host, port, proxy, tls, timeout)?;
+            let (interim, data) = if let Some(data) = http_raw {
+                (Vec::new(), raw::exchange(&mut *stream, &data)?)
+            } else {
+                let (head, mut body) = raw::serialize_parts(&mut request, body_reader.take())?;
+                if !args.trailers.is_empty() {
+                    body = body.with_trailers(&args.trailers);
+                }
+                if args.expect_continue {
+                    raw::exchange_expect_continue(&mut *stream, &socket, &head, body)?
+                } else {
+                    (
+                        Vec::new(),
+                        raw::exchange_request(&mut *stream, &head, body)?,
+                    )
+                }
+            };
+            let request_duration = starting_time.elapsed();
+            let history_print = args.history_print.unwrap_or(print);
+            if args.all && history_print.response_headers {
+                for head in interim {
+                    printer.print_response_headers(&raw::parse_response(&head, &url)?)?;
+                }
+            }
+            let mut response = raw::parse_response(&data, &url)?;
+            reqwest::cookie::CookieStore::set_cookies(
+                &*cookie_jar,
+                &mut response.headers().get_all(SET_COOKIE).iter(),
+                &url,
+            );
+            response.extensions_mut().insert(ResponseMeta {
+                method: request.method().clone(),
+                request_body_size: None,
+                request_duration,
+                content_download_duration: None,
+                received_at: SystemTime::now(),
+                connection_reused: None,
+            });
+            response
+        } else {
             let history_print = args.history_print.unwrap_or(print);
             let mut client = ClientWithMiddleware::new(&client);
-            if args.all {
+            if args.all
+                || args.dump_header.is_some()
+                || args.metrics_file.is_some()
+                || args.check_status_all
+            {
                 client = client.with_printer(|prev_response, next_request| {
-                    if history_print.response_headers {
-                        printer.print_response_headers(prev_response)?;
-                    }
-                    if history_print.response_body {
-                        printer.print_response_body(
-                            prev_response,
-                            response_charset,
-                            response_mime,
-                        )?;
-                        printer.print_separator()?;
+                    printer.dump_response_headers(prev_response)?;
+                    printer.write_metrics(prev_response)?;
+                    if args.all {
+                        if history_print.response_headers {
+                            printer.print_response_headers(prev_response)?;
+                        }
+                        if history_print.response_body {
+                            printer.print_response_body(
+                                prev_response,
+                                response_charset,
+                                response_mime,
+                            )?;
+                            printer.print_separator()?;
+                        }
+                        if history_print.response_meta {
+                            printer.print_response_meta(prev_response)?;
+                        }
+                        if history_print.request_headers {
+                            printer.print_request_headers(next_request, &*cookie_jar)?;
+                        }
+                        if history_print.request_body {
+                            printer.print_request_body(next_request)?;
+                        }
                     }
-                    if history_print.response_meta {
-                        printer.print_response_meta(prev_response)?;
-                    }
-                    if history_print.request_headers {
-                        printer.print_request_headers(next_request, &*cookie_jar)?;
-                    }
-                    if history_print.request_body {
-                        printer.print_request_body(next_request)?;
+                    // A 401 only shows up here as the challenge that digest
+                    // or NTLM auth is answering, which isn't a failure
+                    let status = prev_response.status();
+                    if args.check_status_all
+                        && status != reqwest::StatusCode::UNAUTHORIZED
+                        && status_exit_code(status, true) != 0
+                    {
+                        return Err(FailedHop(status).into());
                     }
                     Ok(())
                 });
             }
             if args.follow {
-                client = client.with(RedirectFollower::new(args.max_redirects.unwrap_or(10)));
+                client = client.with(RedirectFollower::new(
+                    args.max_redirects.unwrap_or(10),
+                    args.retry_all_methods,
+                ));
             }
             if let Some(Auth::Digest(username, password)) = &auth {
                 client = client.with(DigestAuthMiddleware::new(username, password));
             }
+            if let Some(ntlm) = auth.as_ref().and_then(NtlmAuthMiddleware::for_auth) {
+                client = client.with(ntlm);
+            }
+            if let Some(auth) = auth.as_ref().filter(|_| args.reauth && auth_from_session) {
+                let host = url.host_str().unwrap_or("<host>");
+                client = client.with(ReauthMiddleware::new(auth, host, &mut renewed_auth));
+            }
+            if args.https_fallback {
+                let cookie_jar = &*cookie_jar;
+                let allow_insecure_auth = args.allow_insecure_auth;
+                client = client.with(HttpsFallback::new(request.url().clone(), move |request| {
+                    if !allow_insecure_auth {
+                        warn_insecure_credentials(request, cookie_jar);
+                    }
+                }));
+            }
+            client = client.with(RetryStaleConnection::new(args.retry_all_methods));
+            if let Some(addr) = &scoped_addr {
+                client = client.with(ZonedHost::new(addr));
+            }
             client.execute(request)?
         };
 
+        if let (Some(auth), Some(session)) = (&renewed_auth, &mut session) {
+            session.save_auth(auth);
+        }
+
+        // All the cookies are in by now. Save the session before reading
+        // the body, which may take long enough to get interrupted.
+        if let Some(session) = session.take() {
+            save_session(session, &cookie_jar)?;
+        }
+
+        let signature_verified = match &signature_key {
+            Some(key) => message_signature::check(&mut response, key),
+            None => true,
+        };
+
         let status = response.status();
         if args.check_status.unwrap_or(!args.httpie_compat_mode) {
-            exit_code = match status.as_u16() {
-                300..=399 if !args.follow => 3,
-                400..=499 => 4,
-                500..=599 => 5,
-                _ => 0,
-            };
+            exit_code = status_exit_code(status, args.follow);
             // Print this if the status code isn't otherwise ending up in the terminal.
             // HTTPie looks at --quiet, since --quiet always suppresses the response
             // headers even if you pass --print=h. But --print takes precedence for us.
@@ -611,43 +1274,207 @@ fn run(args: Cli) -> Result<i32> {
             }
         }
 
+        printer.dump_response_headers(&response)?;
         if print.response_headers {
             printer.print_response_headers(&response)?;
         }
-        if args.download {
+        // Downloads consume the response, so they're timed here instead
+        let mut meta = response.meta().clone();
+        let body_starting_time = Instant::now();
+        if args.no_body_read {
+            if print.response_meta {
+                printer.print_response_meta(&response)?;
+            }
+            printer.write_metrics(&response)?;
+            // Dropping the unread response closes the connection instead of
+            // returning it to the pool
+            drop(response);
+        } else if let Some(dir) = &args.split_parts {
+            printer.write_metrics(&response)?;
+            if exit_code == 0 {
+                split_parts(response, dir, args.quiet > 0, max_decode_size)?;
+            }
+            meta.content_download_duration = Some(body_starting_time.elapsed());
+        } else if args.download {
+            printer.write_metrics(&response)?;
             if exit_code == 0 {
+                let name_url = if args.trust_server_names {
+                    response.url().clone()
+                } else {
+                    url.clone()
+                };
                 download_file(
                     response,
                     args.output,
-                    &url,
+                    &name_url,
                     resume,
-                    pretty.color(),
+                    pretty.color().then_some(theme),
                     args.quiet > 0,
+                    args.porcelain,
+                    max_decode_size,
+                    args.sync,
+                    args.xattr,
                 )?;
+            } else if args.porcelain {
+                println!("ERR {exit_code} {url}");
             }
+            meta.content_download_duration = Some(body_starting_time.elapsed());
         } else {
-            if print.response_body {
-                printer.print_response_body(&mut response, response_charset, response_mime)?;
-                if print.response_meta {
-                    printer.print_separator()?;
+            if let Some(template) = &args.template {
+                printer.print_response_template(template, &mut response)?;
+            } else {
+                let print_trailers = print.response_headers
+                    && response.extensions().get::<raw::Trailers>().is_some();
+                if print.response_body {
+                    printer.print_response_body(&mut response, response_charset, response_mime)?;
+                    if print.response_meta || print_trailers {
+                        printer.print_separator()?;
+                    }
+                }
+                if print_trailers {
+                    printer.print_response_trailers(&response)?;
                 }
             }
             if print.response_meta {
                 printer.print_response_meta(&response)?;
             }
+            printer.write_metrics(&response)?;
+            if args.copy || args.open || args.graphql || proto.is_some() {
+                let body = match printer.take_response_body() {
+                    Some(body) if print.response_body => body,
+                    _ => {
+                        let mut body = Vec::new();
+                        let compression_type = get_compression_type(response.headers());
+                        decompress(&mut response, compression_type, max_decode_size)
+                            .read_to_end(&mut body)?;
+                        body
+                    }
+                };
+                if args.copy {
+                    if let Err(err) = clipboard::copy(&body) {
+                        log::warn!("{err:#}");
+                    }
+                }
+                if args.open {
+                    if let Err(err) = browser::open_response(&response, &body) {
+                        log::warn!("Not opening the response: {err:#}");
+                    }
+                }
+                if args.graphql {
+                    for error in graphql::response_errors(&body) {
+                        log::warn!("GraphQL error: {error}");
+                    }
+                }
+                if proto.is_some() {
+                    if let Some(error) = protobuf::response_error(response.headers(), &body) {
+                        log::warn!("gRPC error: {error}");
+                    }
+                }
+            }
+            meta = response.meta().clone();
+        }
+
+        if !signature_verified && exit_code == 0 {
+            exit_code = 13;
+        }
+
+        if let Some(max_time) = args.max_time_assert {
+            let elapsed = meta.total_duration();
+            if elapsed > max_time && exit_code == 0 {
+                log::warn!(
+                    "Response took {:.3}s, more than --max-time-assert={:.3}s",
+                    elapsed.as_secs_f64(),
+                    max_time.as_secs_f64()
+                );
+                exit_code = 11;
+            }
+        }
+
+        if args.notify {
+            let message = format!(
+                "{} {}\n{} in {:.2}s",
+                meta.method,
+                url,
+                status,
+                meta.total_duration().as_secs_f64()
+            );
+            if let Err(err) = notify::notify("xh", &message) {
+                log::warn!("Unable to show a notification: {err:#}");
+            }
         }
     }
 
-    if let Some(ref mut s) = session {
-        let cookie_jar = cookie_jar.lock().unwrap();
-        s.save_cookies(cookie_jar.iter_unexpired());
-        s.persist()
-            .with_context(|| format!("couldn't persist session {}", s.path.display()))?;
+    if let Some(session) = session {
+        save_session(session, &cookie_jar)?;
     }
 
     Ok(exit_code)
 }
 
+/// Warn if a request is about to send credentials or cookies over plain HTTP
+/// to a host that isn't on this machine.
+fn warn_insecure_credentials(request: &Request, cookie_jar: &impl reqwest::cookie::CookieStore) {
+    if request.url().scheme() != "http" || is_local_url(request.url()) {
+        return;
+    }
+    let credentials = if request.headers().contains_key(AUTHORIZATION) {
+        "credentials"
+    } else if request.headers().contains_key(COOKIE) || cookie_jar.cookies(request.url()).is_some()
+    {
+        "cookies"
+    } else {
+        return;
+    };
+    log::warn!(
+        "Sending {credentials} to {} over plain HTTP, anyone on the network can read them. \
+         Use https:// or pass --allow-insecure-auth to silence this warning.",
+        request.url().host_str().unwrap_or_default()
+    );
+}
+
+fn verify_setting(verify: Option<Verify>) -> Verify {
+    verify.unwrap_or_else(|| {
+        // requests library which is used by HTTPie checks for both
+        // REQUESTS_CA_BUNDLE and CURL_CA_BUNDLE environment variables.
+        // See https://docs.python-requests.org/en/master/user/advanced/#ssl-cert-verification
+        if let Some(path) = env::var_os("REQUESTS_CA_BUNDLE") {
+            Verify::CustomCaBundle(PathBuf::from(path))
+        } else if let Some(path) = env::var_os("CURL_CA_BUNDLE") {
+            Verify::CustomCaBundle(PathBuf::from(path))
+        } else {
+            Verify::Yes
+        }
+    })
+}
+
+/// Load a client certificate and its key in the form the TLS backend wants.
+#[cfg_attr(not(feature = "rustls"), allow(unused_variables))]
+fn client_identity(pem: &[u8], native_tls: bool) -> Result<reqwest::Identity> {
+    #[cfg(feature = "rustls")]
+    if !native_tls {
+        return Ok(reqwest::Identity::from_pem(pem)?);
+    }
+
+    #[cfg(feature = "native-tls")]
+    {
+        let (certificates, key) = utils::split_identity_pem(pem)?;
+        Ok(reqwest::Identity::from_pkcs8_pem(&certificates, &key)?)
+    }
+    #[cfg(not(feature = "native-tls"))]
+    unreachable!("this binary was built without native-tls support")
+}
+
+fn save_session(
+    mut session: Session,
+    cookie_jar: &reqwest_cookie_store::CookieStoreMutex,
+) -> Result<()> {
+    let cookie_jar = cookie_jar.lock().unwrap();
+    session.save_cookies(cookie_jar.iter_unexpired());
+    session
+        .persist()
+        .with_context(|| format!("couldn't persist session {}", session.path.display()))
+}
+
 /// Configure backtraces for standard panics and anyhow using `$RUST_BACKTRACE`.
 ///
 /// Note: they only check the environment variable once, so this won't take effect if
@@ -0,0 +1,390 @@
+//! Verifying HTTP message signatures on responses for `--verify-signature`
+//! (RFC 9421).
+//!
+//! The response's `Signature-Input` header lists the components that were
+//! signed, and `Signature` holds the signatures. A response passes if any of
+//! its signatures checks out against the public key. Supported algorithms are
+//! ed25519, ecdsa-p256-sha256, ecdsa-p384-sha384, rsa-pss-sha512 and
+//! rsa-v1_5-sha256. Components can be header fields and `@status`, and with
+//! `;req` the derived components of the request. A signed Content-Digest is
+//! compared as a header but not checked against the body.
+
+use std::fmt::Write;
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::blocking::Response;
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+
+use crate::middleware::ResponseExt;
+use crate::utils::expand_tilde;
+
+/// The outcome of the check, kept in the response's extensions so that it
+/// can be shown with --meta.
+#[derive(Clone)]
+pub struct Checked(pub String);
+
+/// An algorithm by its name in the HTTP Signature Algorithms registry.
+type Algorithm = (&'static str, &'static dyn VerificationAlgorithm);
+
+static RSA: [Algorithm; 2] = [
+    ("rsa-pss-sha512", &signature::RSA_PSS_2048_8192_SHA512),
+    ("rsa-v1_5-sha256", &signature::RSA_PKCS1_2048_8192_SHA256),
+];
+static ECDSA_P256: [Algorithm; 1] = [("ecdsa-p256-sha256", &signature::ECDSA_P256_SHA256_FIXED)];
+static ECDSA_P384: [Algorithm; 1] = [("ecdsa-p384-sha384", &signature::ECDSA_P384_SHA384_FIXED)];
+static ED25519: [Algorithm; 1] = [("ed25519", &signature::ED25519)];
+
+pub enum PublicKey {
+    Rsa(Vec<u8>),
+    EcdsaP256(Vec<u8>),
+    EcdsaP384(Vec<u8>),
+    Ed25519(Vec<u8>),
+}
+
+impl PublicKey {
+    /// Read a "BEGIN PUBLIC KEY" (SubjectPublicKeyInfo) or "BEGIN RSA PUBLIC
+    /// KEY" file.
+    pub fn load(key: &str) -> Result<PublicKey> {
+        let path = expand_tilde(key.strip_prefix('@').unwrap_or(key));
+        let buffer = fs::read(&path)
+            .with_context(|| format!("Failed to read the public key in {}", path.display()))?;
+        let block = pem::parse_many(buffer)?
+            .into_iter()
+            .find(|block| block.tag().ends_with("PUBLIC KEY"))
+            .ok_or_else(|| anyhow!("No public key found in {}", path.display()))?;
+        match block.tag() {
+            "RSA PUBLIC KEY" => Ok(PublicKey::Rsa(block.contents().to_vec())),
+            "PUBLIC KEY" => PublicKey::from_spki(block.contents())
+                .ok_or_else(|| anyhow!("Unsupported public key in {}", path.display())),
+            tag => Err(anyhow!("Unsupported {tag:?} in {}", path.display())),
+        }
+    }
+
+    fn from_spki(der: &[u8]) -> Option<PublicKey> {
+        // The object identifiers of the algorithms and curves
+        const RSA_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+        const EC_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+        const P256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+        const P384_OID: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+        const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+
+        let (0x30, info, _) = read_der(der)? else {
+            return None;
+        };
+        let (0x30, algorithm, rest) = read_der(info)? else {
+            return None;
+        };
+        let (0x03, bits, _) = read_der(rest)? else {
+            return None;
+        };
+        // The first byte of a BIT STRING counts the unused bits, always 0 here
+        let key = bits.strip_prefix(&[0])?.to_vec();
+        let (0x06, oid, parameters) = read_der(algorithm)? else {
+            return None;
+        };
+        match oid {
+            RSA_OID => Some(PublicKey::Rsa(key)),
+            EC_OID => match read_der(parameters)? {
+                (0x06, P256_OID, _) => Some(PublicKey::EcdsaP256(key)),
+                (0x06, P384_OID, _) => Some(PublicKey::EcdsaP384(key)),
+                _ => None,
+            },
+            ED25519_OID => Some(PublicKey::Ed25519(key)),
+            _ => None,
+        }
+    }
+
+    /// The algorithms this key could have signed with.
+    fn algorithms(&self) -> &'static [Algorithm] {
+        match self {
+            PublicKey::Rsa(_) => &RSA,
+            PublicKey::EcdsaP256(_) => &ECDSA_P256,
+            PublicKey::EcdsaP384(_) => &ECDSA_P384,
+            PublicKey::Ed25519(_) => &ED25519,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            PublicKey::Rsa(key)
+            | PublicKey::EcdsaP256(key)
+            | PublicKey::EcdsaP384(key)
+            | PublicKey::Ed25519(key) => key,
+        }
+    }
+
+    /// Check a signature, returning the algorithm that matched.
+    fn verify(&self, alg: Option<&str>, base: &[u8], sig: &[u8]) -> Result<&'static str> {
+        let mut candidates = self
+            .algorithms()
+            .iter()
+            .filter(|(name, _)| alg.map_or(true, |alg| alg == *name))
+            .peekable();
+        if candidates.peek().is_none() {
+            return Err(anyhow!(
+                "signed with {}, which the key doesn't fit",
+                alg.unwrap_or("")
+            ));
+        }
+        for (name, algorithm) in candidates {
+            if UnparsedPublicKey::new(*algorithm, self.bytes())
+                .verify(base, sig)
+                .is_ok()
+            {
+                return Ok(name);
+            }
+        }
+        Err(anyhow!("the signature doesn't match"))
+    }
+}
+
+/// Split a DER value into its tag, its contents and whatever follows it.
+fn read_der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count > 4 || input.len() < count {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(count);
+        input = rest;
+        bytes.iter().fold(0, |len, &byte| len << 8 | byte as usize)
+    };
+    (input.len() >= len).then(|| (tag, &input[..len], &input[len..]))
+}
+
+/// Split a structured field list or dictionary on the commas between its
+/// members, leaving commas in strings alone.
+fn split_members(value: &str) -> Vec<&str> {
+    let mut members = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                members.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    members.push(value[start..].trim());
+    members.retain(|member| !member.is_empty());
+    members
+}
+
+/// Get the value of a parameter like `;alg="ed25519"` or `;created=1618884473`.
+fn parameter<'a>(params: &'a str, name: &str) -> Option<&'a str> {
+    params.split(';').find_map(|param| {
+        let (key, value) = param.trim().split_once('=')?;
+        (key == name).then(|| value.trim_matches('"'))
+    })
+}
+
+/// The values of all the header lines with this name, joined by commas.
+fn combined_header_values(response: &Response, header: &str) -> Result<String> {
+    let values = response
+        .headers()
+        .get_all(header)
+        .iter()
+        .map(|value| Ok(value.to_str()?.trim()))
+        .collect::<Result<Vec<_>>>()?;
+    if values.is_empty() {
+        return Err(anyhow!("the signed {header:?} header is missing"));
+    }
+    Ok(values.join(", "))
+}
+
+/// Find the value of one of the components in the signature base.
+fn component_value(response: &Response, component: &str) -> Result<String> {
+    let (name, params) = component.split_once(';').unwrap_or((component, ""));
+    let name = name.trim_matches('"');
+    let from_request = params.split(';').any(|param| param == "req");
+    let url = response.url();
+    match name {
+        "@status" if !from_request => Ok(response.status().as_u16().to_string()),
+        "@method" if from_request => Ok(response.meta().method.to_string()),
+        "@target-uri" if from_request => Ok(url.to_string()),
+        "@authority" if from_request => {
+            let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+            Ok(match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host,
+            })
+        }
+        "@scheme" if from_request => Ok(url.scheme().to_string()),
+        "@path" if from_request => Ok(url.path().to_string()),
+        "@query" if from_request => Ok(format!("?{}", url.query().unwrap_or_default())),
+        "@request-target" if from_request => Ok(match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        }),
+        name if name.starts_with('@') => Err(anyhow!("unsupported component {component}")),
+        // Headers of the request aren't kept after it's sent
+        _ if !params.is_empty() => Err(anyhow!("unsupported component {component}")),
+        header => combined_header_values(response, header),
+    }
+}
+
+/// Build the signature base for a member of Signature-Input, like
+/// `("@status" "content-type");created=1618884473;keyid="key"`.
+fn signature_base(response: &Response, input: &str) -> Result<String> {
+    let (components, _) = input
+        .strip_prefix('(')
+        .and_then(|input| input.split_once(')'))
+        .ok_or_else(|| anyhow!("malformed Signature-Input"))?;
+    let mut base = String::new();
+    for component in components.split(' ').filter(|c| !c.is_empty()) {
+        let value = component_value(response, component)?;
+        writeln!(base, "{component}: {value}")?;
+    }
+    write!(base, "\"@signature-params\": {input}")?;
+    Ok(base)
+}
+
+/// Check one signature, returning its algorithm.
+fn check_signature(
+    response: &Response,
+    key: &PublicKey,
+    input: &str,
+    signature: &str,
+) -> Result<&'static str> {
+    let params = input.split_once(')').map_or("", |(_, params)| params);
+    if let Some(expires) = parameter(params, "expires").and_then(|e| e.parse::<u64>().ok()) {
+        if UNIX_EPOCH.elapsed()?.as_secs() > expires {
+            return Err(anyhow!("the signature has expired"));
+        }
+    }
+    let signature = signature
+        .strip_prefix(':')
+        .and_then(|signature| signature.strip_suffix(':'))
+        .ok_or_else(|| anyhow!("malformed Signature"))?;
+    let signature = BASE64.decode(signature).context("malformed Signature")?;
+    let base = signature_base(response, input)?;
+    log::debug!("Signature base:\n{base}");
+    key.verify(parameter(params, "alg"), base.as_bytes(), &signature)
+}
+
+/// Check the signatures on a response. On success, says which signature
+/// passed.
+fn verify(response: &Response, key: &PublicKey) -> Result<String> {
+    let (Some(inputs), Some(signatures)) = (
+        response.headers().get("signature-input"),
+        response.headers().get("signature"),
+    ) else {
+        return Err(anyhow!("The response isn't signed"));
+    };
+    let signatures: Vec<(&str, &str)> = split_members(signatures.to_str()?)
+        .into_iter()
+        .filter_map(|member| member.split_once('='))
+        .collect();
+
+    let mut failures = Vec::new();
+    for member in split_members(inputs.to_str()?) {
+        let Some((label, input)) = member.split_once('=') else {
+            continue;
+        };
+        let Some(&(_, signature)) = signatures.iter().find(|(other, _)| *other == label) else {
+            failures.push(format!("{label}: the signature is missing"));
+            continue;
+        };
+        match check_signature(response, key, input, signature) {
+            Ok(algorithm) => return Ok(format!("{label} verified ({algorithm})")),
+            Err(err) => failures.push(format!("{label}: {err}")),
+        }
+    }
+    if failures.is_empty() {
+        return Err(anyhow!("Signature-Input has no signatures"));
+    }
+    Err(anyhow!(
+        "Signature verification failed, {}",
+        failures.join("; ")
+    ))
+}
+
+/// Check the signatures on a response and keep the outcome for --meta.
+/// Returns whether the response passed, with a warning if it didn't.
+pub fn check(response: &mut Response, key: &PublicKey) -> bool {
+    let (passed, outcome) = match verify(response, key) {
+        Ok(outcome) => (true, outcome),
+        Err(err) => {
+            log::warn!("{err}");
+            (false, err.to_string())
+        }
+    };
+    response.extensions_mut().insert(Checked(outcome));
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair};
+
+    #[test]
+    fn dictionary_members() {
+        assert_eq!(
+            split_members(r#"sig1=("@status");nonce="a,b", sig2=("date")"#),
+            [r#"sig1=("@status");nonce="a,b""#, r#"sig2=("date")"#]
+        );
+        let params = r#";created=1618884473;keyid="test-key";alg="ed25519""#;
+        assert_eq!(parameter(params, "alg"), Some("ed25519"));
+        assert_eq!(parameter(params, "created"), Some("1618884473"));
+        assert_eq!(parameter(params, "expires"), None);
+    }
+
+    #[test]
+    fn ed25519_public_key() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let mut spki = vec![
+            0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+        ];
+        spki.extend(pair.public_key().as_ref());
+
+        let key = PublicKey::from_spki(&spki).unwrap();
+        let signature = pair.sign(b"base");
+        assert_eq!(
+            key.verify(None, b"base", signature.as_ref()).unwrap(),
+            "ed25519"
+        );
+        assert!(key.verify(None, b"other", signature.as_ref()).is_err());
+        assert!(key
+            .verify(Some("rsa-pss-sha512"), b"base", signature.as_ref())
+            .is_err());
+    }
+
+    #[test]
+    fn ecdsa_public_key() {
+        let rng = SystemRandom::new();
+        let algorithm = &signature::ECDSA_P256_SHA256_FIXED_SIGNING;
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(algorithm, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(algorithm, pkcs8.as_ref(), &rng).unwrap();
+        let mut spki = vec![
+            0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06,
+            0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+        ];
+        spki.extend(pair.public_key().as_ref());
+
+        let key = PublicKey::from_spki(&spki).unwrap();
+        let signature = pair.sign(&rng, b"base").unwrap();
+        assert_eq!(
+            key.verify(Some("ecdsa-p256-sha256"), b"base", signature.as_ref())
+                .unwrap(),
+            "ecdsa-p256-sha256"
+        );
+    }
+}
@@ -1,12 +1,30 @@
-use std::time::{Duration, Instant};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Result;
+use hyper_util::client::legacy::connect::HttpInfo;
 use reqwest::blocking::{Client, Request, Response};
+use reqwest::Method;
 
 #[derive(Clone)]
 pub struct ResponseMeta {
+    pub method: Method,
+    pub request_body_size: Option<u64>,
     pub request_duration: Duration,
     pub content_download_duration: Option<Duration>,
+    pub received_at: SystemTime,
+    /// Whether an earlier response came over the same connection, as told by
+    /// its local address. Unknown if the connection doesn't say.
+    pub connection_reused: Option<bool>,
+}
+
+impl ResponseMeta {
+    /// The time until the response headers arrived plus the time it took to
+    /// read the body, if it has been read.
+    pub fn total_duration(&self) -> Duration {
+        self.request_duration + self.content_download_duration.unwrap_or_default()
+    }
 }
 
 pub trait ResponseExt {
@@ -30,6 +48,8 @@ pub struct Context<'a, 'b> {
     client: &'a Client,
     printer: Option<Printer<'a, 'b>>,
     middlewares: &'a mut [Box<dyn Middleware + 'b>],
+    /// The local addresses of the connections used so far.
+    connections: &'a mut HashSet<SocketAddr>,
 }
 
 impl<'a, 'b> Context<'a, 'b> {
@@ -37,28 +57,49 @@ impl<'a, 'b> Context<'a, 'b> {
         client: &'a Client,
         printer: Option<Printer<'a, 'b>>,
         middlewares: &'a mut [Box<dyn Middleware + 'b>],
+        connections: &'a mut HashSet<SocketAddr>,
     ) -> Self {
         Context {
             client,
             printer,
             middlewares,
+            connections,
         }
     }
 
     fn execute(&mut self, request: Request) -> Result<Response> {
         match self.middlewares {
             [] => {
+                let method = request.method().clone();
+                let request_body_size = request
+                    .body()
+                    .and_then(|body| body.as_bytes())
+                    .map(|body| body.len() as u64);
                 let starting_time = Instant::now();
                 let mut response = self.client.execute(request)?;
+                let request_duration = starting_time.elapsed();
+                let connection_reused = response
+                    .extensions()
+                    .get::<HttpInfo>()
+                    .map(|info| !self.connections.insert(info.local_addr()));
                 response.extensions_mut().insert(ResponseMeta {
-                    request_duration: starting_time.elapsed(),
+                    method,
+                    request_body_size,
+                    request_duration,
                     content_download_duration: None,
+                    received_at: SystemTime::now(),
+                    connection_reused,
                 });
                 Ok(response)
             }
             [ref mut head, tail @ ..] => head.handle(
                 #[allow(clippy::needless_option_as_deref)]
-                Context::new(self.client, self.printer.as_deref_mut(), tail),
+                Context::new(
+                    self.client,
+                    self.printer.as_deref_mut(),
+                    tail,
+                    self.connections,
+                ),
                 request,
             ),
         }
@@ -93,6 +134,7 @@ where
     client: &'a Client,
     printer: Option<T>,
     middlewares: Vec<Box<dyn Middleware + 'a>>,
+    connections: HashSet<SocketAddr>,
 }
 
 impl<'a, T> ClientWithMiddleware<'a, T>
@@ -104,6 +146,7 @@ where
             client,
             printer: None,
             middlewares: vec![],
+            connections: HashSet::new(),
         }
     }
 
@@ -122,6 +165,7 @@ where
             self.client,
             self.printer.as_mut().map(|p| p as _),
             &mut self.middlewares[..],
+            &mut self.connections,
         );
         ctx.execute(request)
     }
@@ -0,0 +1,521 @@
+//! Mirroring of directory listings, for --download --mirror.
+//!
+//! Directories are read from the HTML index pages that nginx, Apache and
+//! similar servers generate, or with a WebDAV PROPFIND if there's no such
+//! page. Every link below the starting URL that ends in a slash is a
+//! directory to visit, anything else is a file to download.
+//!
+//! Files are downloaded to a `.part` file first and renamed once they're
+//! complete, so a later run can resume them. Finished files get the server's
+//! Last-Modified time and are only fetched again if they changed since.
+//!
+//! Like "xh crawl", robots.txt is followed unless --ignore-robots is used.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use indicatif::HumanBytes;
+use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
+use regex_lite::Regex;
+use reqwest::blocking::{Request, Response};
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_TYPE, IF_MODIFIED_SINCE, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+use crate::cli::Theme;
+use crate::crawl::Robots;
+use crate::decoder::DEFAULT_MAX_DECODE_SIZE;
+use crate::download::{download_file, get_file_size, part_path, saved_etag};
+use crate::error_reporting::DiskFull;
+
+const DEFAULT_MAX_DEPTH: usize = 5;
+
+pub struct Mirror<'a> {
+    send: &'a mut dyn FnMut(Request) -> Result<Response>,
+    /// The starting URL, with a trailing slash. Nothing outside of it is visited.
+    base: Url,
+    headers: HeaderMap,
+    dir: PathBuf,
+    max_depth: usize,
+    max_size: Option<u64>,
+    theme: Option<Theme>,
+    quiet: bool,
+    porcelain: bool,
+    max_decode_size: u64,
+    sync: bool,
+    xattr: bool,
+    robots: Option<Robots>,
+    downloaded: u64,
+    files: usize,
+    up_to_date: usize,
+    failed: usize,
+    disallowed: usize,
+}
+
+impl<'a> Mirror<'a> {
+    /// Mirror the listing at the URL of `start` into `dir`, or into a
+    /// directory named after the URL.
+    ///
+    /// The headers of `start` are sent along with every request.
+    pub fn new(
+        send: &'a mut dyn FnMut(Request) -> Result<Response>,
+        start: &Request,
+        dir: Option<PathBuf>,
+    ) -> Self {
+        let mut base = start.url().clone();
+        base.set_query(None);
+        base.set_fragment(None);
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let dir = dir.unwrap_or_else(|| {
+            let name = base
+                .path_segments()
+                .and_then(|segments| segments.rev().find(|segment| !segment.is_empty()))
+                .and_then(decode_segment)
+                .or_else(|| base.host_str().map(str::to_string))
+                .unwrap_or_else(|| "mirror".to_string());
+            PathBuf::from(name)
+        });
+        let mut headers = start.headers().clone();
+        headers.remove(RANGE);
+        let robots = Some(Robots::new(&headers));
+        Mirror {
+            send,
+            base,
+            headers,
+            dir,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_size: None,
+            theme: None,
+            quiet: false,
+            porcelain: false,
+            max_decode_size: DEFAULT_MAX_DECODE_SIZE,
+            sync: false,
+            xattr: false,
+            robots,
+            downloaded: 0,
+            files: 0,
+            up_to_date: 0,
+            failed: 0,
+            disallowed: 0,
+        }
+    }
+
+    /// How many levels of subdirectories to descend into.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        self
+    }
+
+    /// Stop after downloading this many bytes.
+    pub fn with_max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Fail on compressed files that decode to more than this many bytes.
+    pub fn with_max_decode_size(mut self, max_decode_size: u64) -> Self {
+        self.max_decode_size = max_decode_size;
+        self
+    }
+
+    /// Flush each file to disk once it's downloaded.
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Save the origin of each file in its extended attributes, and use it to
+    /// check that partial files are still current before resuming them.
+    pub fn with_xattr(mut self, xattr: bool) -> Self {
+        self.xattr = xattr;
+        self
+    }
+
+    /// Follow the rules in the site's robots.txt.
+    pub fn with_respect_robots(mut self, respect_robots: bool) -> Self {
+        if !respect_robots {
+            self.robots = None;
+        }
+        self
+    }
+
+    pub fn with_output(mut self, theme: Option<Theme>, quiet: bool, porcelain: bool) -> Self {
+        self.theme = theme;
+        self.quiet = quiet;
+        self.porcelain = porcelain;
+        self
+    }
+
+    /// Returns the number of files or directories that couldn't be fetched.
+    pub fn run(mut self) -> Result<usize> {
+        let mut robots = self.robots.take();
+        let mut queue = VecDeque::from([(self.base.clone(), 0)]);
+        let mut seen = HashSet::from([self.base.clone()]);
+        'crawl: while let Some((dir_url, depth)) = queue.pop_front() {
+            if !self.admit(&mut robots, &dir_url) {
+                continue;
+            }
+            let entries = match self.list(&dir_url) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    log::warn!("Couldn't list {dir_url}: {err:#}");
+                    self.failed += 1;
+                    continue;
+                }
+            };
+            for url in entries {
+                if !seen.insert(url.clone()) {
+                    continue;
+                }
+                if url.path().ends_with('/') {
+                    if depth < self.max_depth {
+                        queue.push_back((url, depth + 1));
+                    }
+                    continue;
+                }
+                if let Some(max_size) = self.max_size {
+                    if self.downloaded >= max_size {
+                        log::warn!(
+                            "Stopped mirroring after downloading {}",
+                            HumanBytes(self.downloaded)
+                        );
+                        break 'crawl;
+                    }
+                }
+                if !self.admit(&mut robots, &url) {
+                    continue;
+                }
+                let Some(path) = self.local_path(&url) else {
+                    log::warn!("Skipping {url}, it can't be saved under a safe name");
+                    continue;
+                };
+                if let Err(err) = self.fetch_file(&url, &path) {
+                    // The rest of the files won't fit either
+                    if err.is::<DiskFull>() {
+                        return Err(err);
+                    }
+                    log::warn!("Couldn't download {url}: {err:#}");
+                    self.failed += 1;
+                }
+            }
+        }
+
+        if !self.quiet {
+            eprint!(
+                "Mirrored {} into {:?}: {} downloaded ({}), {} up to date, {} failed",
+                self.base,
+                self.dir,
+                self.files,
+                HumanBytes(self.downloaded),
+                self.up_to_date,
+                self.failed
+            );
+            if self.disallowed > 0 {
+                eprint!(", {} disallowed by robots.txt", self.disallowed);
+            }
+            eprintln!();
+        }
+        Ok(self.failed)
+    }
+
+    /// Check robots.txt, if we're following it.
+    fn admit(&mut self, robots: &mut Option<Robots>, url: &Url) -> bool {
+        let Some(robots) = robots else {
+            return true;
+        };
+        let admitted = robots.admit(url, |robots_url| {
+            let mut request = Request::new(Method::GET, robots_url);
+            *request.headers_mut() = self.headers.clone();
+            (self.send)(request)
+        });
+        if !admitted {
+            log::debug!("Skipping {url}, robots.txt disallows it");
+            self.disallowed += 1;
+        }
+        admitted
+    }
+
+    fn request(&self, method: Method, url: &Url) -> Request {
+        let mut request = Request::new(method, url.clone());
+        *request.headers_mut() = self.headers.clone();
+        request
+    }
+
+    /// The files and subdirectories in a directory listing.
+    fn list(&mut self, url: &Url) -> Result<Vec<Url>> {
+        let request = self.request(Method::GET, url);
+        let response = (self.send)(request)?;
+        let is_html = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(true, |value| value.contains("html"));
+        let links = if response.status().is_success() && is_html {
+            parse_html_links(&response.text()?)
+        } else {
+            // Maybe the server does WebDAV instead
+            let mut request = self.request(Method::from_bytes(b"PROPFIND")?, url);
+            request
+                .headers_mut()
+                .insert("depth", HeaderValue::from_static("1"));
+            let response = (self.send)(request)?;
+            if response.status() != StatusCode::MULTI_STATUS {
+                return Err(anyhow!(
+                    "no directory listing (got {} for a WebDAV listing)",
+                    response.status()
+                ));
+            }
+            parse_webdav_links(&response.text()?)
+        };
+        Ok(links
+            .iter()
+            .filter_map(|link| self.child_url(url, link))
+            .collect())
+    }
+
+    /// Resolve a link and keep it if it points below the base URL.
+    fn child_url(&self, page: &Url, link: &str) -> Option<Url> {
+        let mut url = page.join(link).ok()?;
+        url.set_fragment(None);
+        let inside = url.query().is_none()
+            && url.origin() == self.base.origin()
+            && url.path().len() > self.base.path().len()
+            && url.path().starts_with(self.base.path());
+        (inside && url != *page).then_some(url)
+    }
+
+    /// Where to save a file, or None if its URL has a suspicious path.
+    fn local_path(&self, url: &Url) -> Option<PathBuf> {
+        let mut path = self.dir.clone();
+        for segment in url.path()[self.base.path().len()..].split('/') {
+            let segment = decode_segment(segment)?;
+            if segment.is_empty()
+                || segment == "."
+                || segment == ".."
+                || segment.contains(std::path::is_separator)
+                || segment.contains('\0')
+            {
+                return None;
+            }
+            path.push(segment);
+        }
+        Some(path)
+    }
+
+    fn fetch_file(&mut self, url: &Url, path: &Path) -> Result<()> {
+        let part_path = part_path(path);
+        let mut request = self.request(Method::GET, url);
+        let resume = get_file_size(Some(&part_path)).filter(|&size| size > 0);
+        if let Some(resume) = resume {
+            request
+                .headers_mut()
+                .insert(RANGE, format!("bytes={resume}-").parse()?);
+            if let Some(etag) = self.xattr.then(|| saved_etag(&part_path)).flatten() {
+                request.headers_mut().insert(IF_RANGE, etag.parse()?);
+            }
+        } else if let Ok(modified) = fs::metadata(path).and_then(|meta| meta.modified()) {
+            request.headers_mut().insert(
+                IF_MODIFIED_SINCE,
+                httpdate::fmt_http_date(modified).parse()?,
+            );
+        }
+
+        let response = (self.send)(request)?;
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            self.up_to_date += 1;
+            return Ok(());
+        }
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && resume.is_some() {
+            // The download finished, but we didn't get to rename it
+            fs::rename(&part_path, path)?;
+            self.files += 1;
+            return Ok(());
+        }
+        if !status.is_success() {
+            return Err(anyhow!("{status}"));
+        }
+
+        let last_modified = response
+            .headers()
+            .get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        download_file(
+            response,
+            Some(path.to_owned()),
+            url,
+            resume,
+            self.theme,
+            self.quiet,
+            false,
+            self.max_decode_size,
+            self.sync,
+            self.xattr,
+        )?;
+        let size = get_file_size(Some(path)).unwrap_or(0);
+        let resumed_from = match status {
+            StatusCode::PARTIAL_CONTENT => resume.unwrap_or(0),
+            _ => 0,
+        };
+        self.downloaded += size.saturating_sub(resumed_from);
+        if let Some(last_modified) = last_modified {
+            if let Err(err) = set_modified(path, last_modified) {
+                log::warn!("Couldn't set modification time of {path:?}: {err}");
+            }
+        }
+        self.files += 1;
+        if self.porcelain {
+            println!("OK {} {}", size, path.display());
+        }
+        Ok(())
+    }
+}
+
+fn decode_segment(segment: &str) -> Option<String> {
+    percent_decode_str(segment)
+        .decode_utf8()
+        .ok()
+        .map(|segment| segment.into_owned())
+}
+
+fn parse_html_links(html: &str) -> Vec<String> {
+    static HREF: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"(?i)<a\s[^>]*?href\s*=\s*["']([^"']*)["']"#).unwrap());
+    HREF.captures_iter(html)
+        .map(|caps| caps[1].replace("&amp;", "&"))
+        .collect()
+}
+
+/// Get the links out of a PROPFIND response, with a trailing slash for
+/// collections (directories).
+fn parse_webdav_links(xml: &str) -> Vec<String> {
+    static RESPONSE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?is)<(?:[a-z0-9]+:)?response\b.*?</(?:[a-z0-9]+:)?response>").unwrap()
+    });
+    static HREF: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"(?is)<(?:[a-z0-9]+:)?href>\s*([^<]*?)\s*</").unwrap());
+    static COLLECTION: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"(?i)<(?:[a-z0-9]+:)?collection\s*/?>").unwrap());
+
+    RESPONSE
+        .find_iter(xml)
+        .filter_map(|response| {
+            let response = response.as_str();
+            let href = HREF.captures(response)?[1].replace("&amp;", "&");
+            if COLLECTION.is_match(response) && !href.ends_with('/') {
+                Some(format!("{href}/"))
+            } else {
+                Some(href)
+            }
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn set_modified(path: &Path, time: SystemTime) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // File::set_modified() would do, but it needs a newer Rust than our MSRV
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(io::Error::other)?;
+    let time = libc::timespec {
+        tv_sec: since_epoch.as_secs() as libc::time_t,
+        tv_nsec: since_epoch.subsec_nanos() as _,
+    };
+    let file = fs::File::options().write(true).open(path)?;
+    if unsafe { libc::futimens(file.as_raw_fd(), [time, time].as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Without this If-Modified-Since falls back on the time of the download,
+/// which works as long as the clocks agree.
+#[cfg(not(unix))]
+fn set_modified(_path: &Path, _time: SystemTime) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_links() {
+        let html = r#"<html><body><h1>Index of /pub/</h1><hr><pre>
+            <a href="../">../</a>
+            <a href="?C=N;O=D">Name</a>
+            <A HREF='docs/'>docs/</A>
+            <a class="file" href="a%20b.txt">a b.txt</a>  12-Jan-2024 10:00  12
+        </pre></body></html>"#;
+        assert_eq!(
+            parse_html_links(html),
+            ["../", "?C=N;O=D", "docs/", "a%20b.txt"]
+        );
+    }
+
+    #[test]
+    fn webdav_links() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+            <D:multistatus xmlns:D="DAV:">
+              <D:response><D:href>/dav/</D:href>
+                <D:propstat><D:prop><D:resourcetype><D:collection/></D:resourcetype></D:prop></D:propstat>
+              </D:response>
+              <D:response><D:href>/dav/sub</D:href>
+                <D:propstat><D:prop><D:resourcetype><D:collection /></D:resourcetype></D:prop></D:propstat>
+              </D:response>
+              <D:response><D:href>/dav/file.txt</D:href>
+                <D:propstat><D:prop><D:resourcetype/></D:prop></D:propstat>
+              </D:response>
+            </D:multistatus>"#;
+        assert_eq!(
+            parse_webdav_links(xml),
+            ["/dav/", "/dav/sub/", "/dav/file.txt"]
+        );
+    }
+
+    #[test]
+    fn child_urls_and_paths() {
+        let mut send = |_| unreachable!();
+        let start = Request::new(Method::GET, "http://example.com/pub".parse().unwrap());
+        let mirror = Mirror::new(&mut send, &start, None);
+        assert_eq!(mirror.dir, Path::new("pub"));
+
+        let page = Url::parse("http://example.com/pub/docs/").unwrap();
+        let child = |link| mirror.child_url(&page, link).map(String::from);
+        assert_eq!(
+            child("a.txt").as_deref(),
+            Some("http://example.com/pub/docs/a.txt")
+        );
+        assert_eq!(
+            child("/pub/other/").as_deref(),
+            Some("http://example.com/pub/other/")
+        );
+        assert_eq!(child("../../"), None);
+        assert_eq!(child("?C=M"), None);
+        assert_eq!(child("#top"), None);
+        assert_eq!(child("http://example.org/pub/x"), None);
+
+        let url = Url::parse("http://example.com/pub/docs/a%20b.txt").unwrap();
+        assert_eq!(
+            mirror.local_path(&url),
+            Some(Path::new("pub").join("docs").join("a b.txt"))
+        );
+        let url = Url::parse("http://example.com/pub/docs/..%2f..%2fetc").unwrap();
+        assert_eq!(mirror.local_path(&url), None);
+    }
+}
@@ -0,0 +1,197 @@
+//! Parsing of multipart response bodies, such as `multipart/mixed` and
+//! `multipart/byteranges` (RFC 2046 and RFC 9110 section 14.6).
+//!
+//! The whole body is parsed in one go. That's fine for displaying it, and
+//! multipart responses are rarely large enough for streaming to matter.
+
+use anyhow::{anyhow, Result};
+use mime::Mime;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+
+#[derive(Debug)]
+pub struct Part {
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Get the boundary of a multipart content type.
+pub fn get_boundary(content_type: &str) -> Option<String> {
+    let mime: Mime = content_type.parse().ok()?;
+    if mime.type_() != mime::MULTIPART {
+        return None;
+    }
+    Some(mime.get_param(mime::BOUNDARY)?.as_str().to_string())
+}
+
+/// Get the boundary from the Content-Type header, if there is one.
+pub fn get_boundary_from_headers(headers: &HeaderMap) -> Option<String> {
+    get_boundary(headers.get(CONTENT_TYPE)?.to_str().ok()?)
+}
+
+/// Split a multipart body into its parts.
+///
+/// Both CRLF and bare LF line endings are accepted, since not every server
+/// gets this right. The preamble and epilogue are discarded.
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{boundary}").into_bytes();
+    let mut rest = if is_delimiter(body, &delimiter) {
+        body
+    } else {
+        let start = find_delimiter(body, &delimiter)
+            .ok_or_else(|| anyhow!("Multipart boundary {boundary:?} not found"))?;
+        &body[start..]
+    };
+
+    let mut parts = Vec::new();
+    loop {
+        rest = &rest[delimiter.len()..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        // Skip transport padding and the line ending after the delimiter
+        let line_end = memchr::memchr(b'\n', rest)
+            .ok_or_else(|| anyhow!("Unexpected end of multipart body"))?;
+        rest = &rest[line_end + 1..];
+
+        let end = find_delimiter(rest, &delimiter)
+            .ok_or_else(|| anyhow!("Missing closing multipart boundary {boundary:?}"))?;
+        // The line ending before a delimiter belongs to the delimiter
+        let content = strip_line_ending(&rest[..end]);
+        parts.push(parse_part(content)?);
+        rest = &rest[end..];
+    }
+}
+
+/// Find the start of the next delimiter that's at the start of a line.
+///
+/// The returned position points at the delimiter itself, so the line ending
+/// before it is still part of the preceding content.
+fn find_delimiter(haystack: &[u8], delimiter: &[u8]) -> Option<usize> {
+    let mut needle = Vec::with_capacity(delimiter.len() + 1);
+    needle.push(b'\n');
+    needle.extend_from_slice(delimiter);
+    memchr::memmem::find_iter(haystack, &needle)
+        .map(|pos| pos + 1)
+        .find(|&pos| is_delimiter(&haystack[pos..], delimiter))
+}
+
+/// Check whether `line` starts with a delimiter that takes up the whole line.
+///
+/// A line that merely starts with the delimiter, like `--abcdef` for the
+/// boundary `abc`, is content. The delimiter may be followed by `--` to close
+/// the body and by transport padding (RFC 2046 section 5.1.1).
+fn is_delimiter(line: &[u8], delimiter: &[u8]) -> bool {
+    let Some(rest) = line.strip_prefix(delimiter) else {
+        return false;
+    };
+    let rest = rest.strip_prefix(b"--").unwrap_or(rest);
+    let padding = rest
+        .iter()
+        .take_while(|&&b| b == b' ' || b == b'\t')
+        .count();
+    matches!(rest[padding..], [] | [b'\n', ..] | [b'\r', b'\n', ..])
+}
+
+fn strip_line_ending(content: &[u8]) -> &[u8] {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    content.strip_suffix(b"\r").unwrap_or(content)
+}
+
+fn trim_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |pos| pos + 1);
+    &bytes[start..end]
+}
+
+fn parse_part(content: &[u8]) -> Result<Part> {
+    let mut headers = HeaderMap::new();
+    let mut rest = content;
+    loop {
+        let (line, remainder) = match memchr::memchr(b'\n', rest) {
+            Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+            None => (rest, &b""[..]),
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        rest = remainder;
+        if line.is_empty() {
+            break;
+        }
+        let colon = memchr::memchr(b':', line)
+            .ok_or_else(|| anyhow!("Invalid header in multipart body"))?;
+        let name = HeaderName::from_bytes(&line[..colon])?;
+        let value = HeaderValue::from_bytes(trim_whitespace(&line[colon + 1..]))?;
+        headers.append(name, value);
+    }
+    Ok(Part {
+        headers,
+        body: rest.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_byteranges() {
+        let body = concat!(
+            "preamble\r\n",
+            "--3d6b6a416f9b5\r\n",
+            "Content-Type: text/html\r\n",
+            "Content-Range: bytes 0-50/1270\r\n",
+            "\r\n",
+            "<!doctype html>\r\n",
+            "--3d6b6a416f9b5\n",
+            "\n",
+            "no headers\n",
+            "--3d6b6a416f9b5--\r\n",
+            "epilogue",
+        );
+        let parts = parse(body.as_bytes(), "3d6b6a416f9b5").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].headers["content-type"], "text/html");
+        assert_eq!(parts[0].headers["content-range"], "bytes 0-50/1270");
+        assert_eq!(parts[0].body, b"<!doctype html>");
+        assert!(parts[1].headers.is_empty());
+        assert_eq!(parts[1].body, b"no headers");
+
+        parse(b"--abc\r\n\r\nunterminated", "abc").unwrap_err();
+        parse(b"no boundary", "abc").unwrap_err();
+    }
+
+    #[test]
+    fn delimiter_must_end_the_line() {
+        let body = concat!(
+            "--abc-not-a-delimiter\r\n",
+            "--abc \t\r\n",
+            "\r\n",
+            "--abcdef\r\n",
+            "--abc--x\r\n",
+            "--abc--\r\n",
+        );
+        let parts = parse(body.as_bytes(), "abc").unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].body, b"--abcdef\r\n--abc--x");
+
+        assert!(is_delimiter(b"--abc--", b"--abc"));
+        assert!(is_delimiter(b"--abc\n", b"--abc"));
+        assert!(!is_delimiter(b"--abc\rx", b"--abc"));
+        assert!(!is_delimiter(b"--abcd", b"--abc"));
+    }
+
+    #[test]
+    fn boundary_from_content_type() {
+        assert_eq!(
+            get_boundary("multipart/mixed; boundary=\"simple boundary\"").as_deref(),
+            Some("simple boundary")
+        );
+        assert_eq!(get_boundary("multipart/byteranges").as_deref(), None);
+        assert_eq!(get_boundary("text/plain; boundary=abc").as_deref(), None);
+    }
+}
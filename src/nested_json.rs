@@ -157,6 +157,28 @@ pub fn parse_path(json_path: &str) -> Result<Vec<PathAction>> {
     Ok(path)
 }
 
+/// Normalize a JSON path for use as a query parameter name.
+///
+/// Query strings have no types, so this only makes the bracket syntax
+/// predictable: `a[b][0][]` stays as it is, while escapes are resolved
+/// (`a\[b\]` becomes `a[b]`) and escaped numbers lose their backslash.
+pub fn query_key(json_path: &str) -> Result<String> {
+    let mut key = String::new();
+    for (i, action) in parse_path(json_path)?.into_iter().enumerate() {
+        match action {
+            PathAction::Key(name, _) if i == 0 => key.push_str(&name),
+            PathAction::Key(name, _) => {
+                key.push('[');
+                key.push_str(&name);
+                key.push(']');
+            }
+            PathAction::Index(index, _) => key.push_str(&format!("[{index}]")),
+            PathAction::Append(_) => key.push_str("[]"),
+        }
+    }
+    Ok(key)
+}
+
 pub fn insert(
     root: Option<Value>,
     path: &[PathAction],
@@ -452,4 +474,13 @@ mod tests {
         assert!(parse_path("[😀").is_err());
         assert!(parse_path("[][😀").is_err());
     }
+
+    #[test]
+    fn query_keys() {
+        assert_eq!(query_key("filter[tags][]").unwrap(), "filter[tags][]");
+        assert_eq!(query_key(r"a\[b\]").unwrap(), "a[b]");
+        assert_eq!(query_key(r"x[\0][1]").unwrap(), "x[0][1]");
+        assert_eq!(query_key("[][a]").unwrap(), "[][a]");
+        assert!(query_key("a[b").is_err());
+    }
 }
@@ -23,6 +23,13 @@
 //! This implementation additionally handles entries with just a password and no login,
 //! to support using .netrc for bearer auth.
 //!
+//! It also lets a machine name include a port (`machine localhost:8080`) or a
+//! scheme (`machine https://example.com`), so that services on one host can
+//! have their own credentials. When several entries match, the most specific
+//! one is used: a port counts for more than a scheme, and otherwise the first
+//! one wins. As in curl, the default entry is only used if no machine entry
+//! matches the host.
+//!
 //! This is too specialized for our use case to be a crate, but feel free to
 //! copy/paste into another project and modify.
 
@@ -33,6 +40,7 @@ use std::{
 
 use encoding_rs::UTF_8;
 use encoding_rs_io::DecodeReaderBytesBuilder;
+use url::{Host, Url};
 
 use crate::utils::get_home_dir;
 
@@ -42,7 +50,7 @@ pub struct Entry {
     pub password: String,
 }
 
-pub fn find_entry(host: url::Host<&str>) -> Option<Entry> {
+pub fn find_entry(url: &Url) -> Option<Entry> {
     let file = open_netrc()?;
     // UTF-16 is detected if it has a BOM.
     // Invalid UTF-8 is sanitized with replacement characters. That way it
@@ -52,7 +60,7 @@ pub fn find_entry(host: url::Host<&str>) -> Option<Entry> {
         .bom_override(true)
         .build(file);
     let file = BufReader::new(file);
-    let parser = Parser::new(file, host);
+    let parser = Parser::new(file, url);
     // Logging I/O errors would be nice.
     parser.parse().ok()?
 }
@@ -77,20 +85,60 @@ fn open_netrc() -> Option<File> {
 enum EntryState {
     /// We're outside any entry, or in one for the wrong host.
     Wrong,
-    /// We're inside the entry for the host we want.
-    Correct,
+    /// We're inside an entry for the host we want, this specific.
+    Correct(u8),
     /// We're inside the default entry.
     Default,
 }
 
+/// What a request is matched against.
+struct Target<'a> {
+    scheme: &'a str,
+    host: Host<&'a str>,
+    port: Option<u16>,
+}
+
+impl Target<'_> {
+    /// How specifically a machine name matches, if it does at all.
+    fn matches(&self, machine: &str) -> Option<u8> {
+        let (scheme, rest) = match machine.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme), rest),
+            None => (None, machine),
+        };
+        let rest = rest.trim_end_matches('/');
+        let (host, port) = split_port(rest);
+        let host = Host::parse(host).ok()?;
+        let port_matches = port.map_or(true, |port| Some(port) == self.port);
+        let scheme_matches = scheme.map_or(true, |scheme| scheme.eq_ignore_ascii_case(self.scheme));
+        if host != self.host || !port_matches || !scheme_matches {
+            return None;
+        }
+        Some(2 * u8::from(port.is_some()) + u8::from(scheme.is_some()))
+    }
+}
+
+/// Split off a port, if there is one.
+fn split_port(host: &str) -> (&str, Option<u16>) {
+    // Leave the colons in IPv6 addresses alone
+    let port_start = if host.starts_with('[') {
+        host.find("]:").map(|i| i + 1)
+    } else {
+        host.rfind(':')
+    };
+    match port_start.and_then(|i| Some((i, host[i + 1..].parse().ok()?))) {
+        Some((i, port)) => (&host[..i], Some(port)),
+        None => (host, None),
+    }
+}
+
 struct Parser<'a, R> {
     reader: R,
     /// The current line.
     buf: String,
     /// The index in `buf` to start looking for the next word.
     pos: usize,
-    /// The host we're looking for.
-    host: url::Host<&'a str>,
+    /// The request we're looking for.
+    target: Target<'a>,
     /// Info about the entry we're handling.
     state: EntryState,
     /// The data collected for the current entry.
@@ -101,17 +149,23 @@ struct Parser<'a, R> {
     suppress_default: bool,
     /// The default entry, to return if no other can be found.
     default: Option<Entry>,
-    /// A complete relevant entry, to be returned ASAP.
-    entry: Option<Entry>,
+    /// The most specific complete relevant entry so far.
+    entry: Option<(u8, Entry)>,
 }
 
 impl<'a, R: BufRead> Parser<'a, R> {
-    fn new(reader: R, host: url::Host<&'a str>) -> Self {
+    fn new(reader: R, url: &'a Url) -> Self {
         Parser {
             reader,
             buf: String::new(),
             pos: 0,
-            host,
+            target: Target {
+                scheme: url.scheme(),
+                // I don't know if it's possible for host() to return None
+                // But if it does we still want to use the default entry, if there is one
+                host: url.host().unwrap_or(Host::Domain("")),
+                port: url.port_or_known_default(),
+            },
             state: EntryState::Wrong,
             login: None,
             password: None,
@@ -136,13 +190,13 @@ impl<'a, R: BufRead> Parser<'a, R> {
                 }
                 "machine" => {
                     self.finish_entry();
-                    if let Some(new_host) = self.word()? {
-                        match url::Host::parse(new_host) {
-                            Ok(new_host) if self.host == new_host => {
-                                self.state = EntryState::Correct;
+                    if let Some(machine) = self.word()?.map(str::to_owned) {
+                        match self.target.matches(&machine) {
+                            Some(score) => {
+                                self.state = EntryState::Correct(score);
                                 self.suppress_default = true;
                             }
-                            _ => {
+                            None => {
                                 self.state = EntryState::Wrong;
                             }
                         }
@@ -197,12 +251,9 @@ impl<'a, R: BufRead> Parser<'a, R> {
                     self.finish_entry();
                 }
             }
-            if let Some(entry) = self.entry {
-                return Ok(Some(entry));
-            }
         }
         self.finish_entry();
-        if let Some(entry) = self.entry {
+        if let Some((_, entry)) = self.entry {
             Ok(Some(entry))
         } else if self.suppress_default {
             Ok(None)
@@ -224,7 +275,11 @@ impl<'a, R: BufRead> Parser<'a, R> {
             let entry = Entry { login, password };
             match state {
                 EntryState::Wrong => unreachable!("netrc: Should not have been storing info"),
-                EntryState::Correct => self.entry = Some(entry),
+                EntryState::Correct(score) => {
+                    if self.entry.as_ref().map_or(true, |(best, _)| score > *best) {
+                        self.entry = Some((score, entry));
+                    }
+                }
                 EntryState::Default => self.default = Some(entry),
             }
         }
@@ -236,7 +291,7 @@ impl<'a, R: BufRead> Parser<'a, R> {
         let word = self.word()?;
         match state {
             EntryState::Wrong => Ok(None),
-            EntryState::Correct | EntryState::Default => Ok(word.map(str::to_owned)),
+            EntryState::Correct(_) | EntryState::Default => Ok(word.map(str::to_owned)),
         }
     }
 
@@ -271,15 +326,13 @@ impl<'a, R: BufRead> Parser<'a, R> {
 mod tests {
     use super::*;
 
-    use std::net::Ipv4Addr;
-
     #[test]
     fn cases() {
-        const COM: url::Host<&str> = url::Host::Domain("example.com");
-        const ORG: url::Host<&str> = url::Host::Domain("example.org");
-        const UNI: url::Host<&str> = url::Host::Domain("xn--9ca.com");
-        const IP1: url::Host<&str> = url::Host::Ipv4(Ipv4Addr::new(1, 1, 1, 1));
-        const IP2: url::Host<&str> = url::Host::Ipv4(Ipv4Addr::new(2, 2, 2, 2));
+        const COM: &str = "http://example.com/";
+        const ORG: &str = "http://example.org/";
+        const UNI: &str = "http://xn--9ca.com/";
+        const IP1: &str = "http://1.1.1.1/";
+        const IP2: &str = "http://2.2.2.2/";
 
         const SIMPLE: &str = "
             machine example.com
@@ -448,22 +501,74 @@ mod tests {
         notfound(STRANGE_CHARACTERS, COM);
     }
 
+    #[test]
+    fn ports_and_schemes() {
+        const MIXED: &str = "
+            machine localhost login any password any
+            machine localhost:8080 login api password api
+            machine https://localhost login tls password tls
+            machine https://localhost:8443 login admin password admin
+            machine http://[::1]:3000 login six password six
+            default login def password ault
+        ";
+        found(MIXED, "http://localhost/", "any", "any");
+        found(MIXED, "http://localhost:8080/", "api", "api");
+        found(MIXED, "https://localhost:8080/", "api", "api");
+        found(MIXED, "https://localhost/", "tls", "tls");
+        found(MIXED, "https://localhost:9000/", "tls", "tls");
+        found(MIXED, "https://localhost:8443/", "admin", "admin");
+        found(MIXED, "http://localhost:8443/", "any", "any");
+        found(MIXED, "http://[::1]:3000/", "six", "six");
+        found(MIXED, "http://[::1]:3001/", "def", "ault");
+        found(MIXED, "http://example.com/", "def", "ault");
+
+        // Only more specific entries override earlier ones
+        const SAME_SPECIFICITY: &str = "
+            machine example.com:443 login first password first
+            machine https://example.com login second password second
+            machine example.com:443 login third password third
+        ";
+        found(SAME_SPECIFICITY, "https://example.com/", "first", "first");
+
+        // An entry for another port doesn't match, so the default is used
+        const OTHER_PORT_AND_DEFAULT: &str = "
+            machine localhost:8080 login api password api
+            default login def password ault
+        ";
+        found(
+            OTHER_PORT_AND_DEFAULT,
+            "http://localhost:9090/",
+            "def",
+            "ault",
+        );
+        notfound(
+            "machine localhost:8080 login api password api",
+            "http://localhost/",
+        );
+    }
+
+    #[test]
+    fn port_splitting() {
+        assert_eq!(split_port("localhost:8080"), ("localhost", Some(8080)));
+        assert_eq!(split_port("localhost"), ("localhost", None));
+        assert_eq!(split_port("[::1]:80"), ("[::1]", Some(80)));
+        assert_eq!(split_port("[::1]"), ("[::1]", None));
+        assert_eq!(split_port("host:http"), ("host:http", None));
+    }
+
     #[track_caller]
-    fn found(
-        netrc: &str,
-        host: url::Host<&str>,
-        login: impl Into<Option<&'static str>>,
-        password: &str,
-    ) {
-        let entry = Parser::new(netrc.as_bytes(), host).parse().unwrap();
+    fn found(netrc: &str, url: &str, login: impl Into<Option<&'static str>>, password: &str) {
+        let url = Url::parse(url).unwrap();
+        let entry = Parser::new(netrc.as_bytes(), &url).parse().unwrap();
         let entry = entry.expect("Didn't find entry");
         assert_eq!(entry.login.as_deref(), login.into());
         assert_eq!(entry.password, password);
     }
 
     #[track_caller]
-    fn notfound(netrc: &str, host: url::Host<&str>) {
-        let entry = Parser::new(netrc.as_bytes(), host).parse().unwrap();
+    fn notfound(netrc: &str, url: &str) {
+        let url = Url::parse(url).unwrap();
+        let entry = Parser::new(netrc.as_bytes(), &url).parse().unwrap();
         assert!(entry.is_none(), "Found entry");
     }
 }
@@ -0,0 +1,64 @@
+//! Desktop notifications for `--notify`.
+//!
+//! These are sent through the tools that come with each platform, so that
+//! no extra dependencies are needed: notify-send (libnotify) on Linux and
+//! the BSDs, osascript on macOS and PowerShell on Windows.
+
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+
+pub fn notify(title: &str, message: &str) -> Result<()> {
+    let mut command = notification_command(title, message);
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Unable to run {program}"))?;
+    if !status.success() {
+        return Err(anyhow!("{program} failed ({status})"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn notification_command(title: &str, message: &str) -> Command {
+    // Passing the strings as arguments avoids having to escape them
+    let mut command = Command::new("osascript");
+    command
+        .args(["-e", "on run argv"])
+        .args([
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+        ])
+        .args(["-e", "end run"])
+        .args([title, message]);
+    command
+}
+
+#[cfg(windows)]
+fn notification_command(title: &str, message: &str) -> Command {
+    const SCRIPT: &str = "\
+        [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+        $template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+        $text = $template.GetElementsByTagName('text'); \
+        $text.Item(0).AppendChild($template.CreateTextNode($env:XH_NOTIFY_TITLE)) > $null; \
+        $text.Item(1).AppendChild($template.CreateTextNode($env:XH_NOTIFY_MESSAGE)) > $null; \
+        $toast = [Windows.UI.Notifications.ToastNotification]::new($template); \
+        [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('xh').Show($toast)";
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("XH_NOTIFY_TITLE", title)
+        .env("XH_NOTIFY_MESSAGE", message);
+    command
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn notification_command(title: &str, message: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name=xh", "--", title, message]);
+    command
+}
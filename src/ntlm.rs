@@ -0,0 +1,243 @@
+//! The NTLM messages for `--auth-type=ntlm` and `--auth-type=negotiate`.
+//!
+//! Only NTLMv2 is supported, as described in [MS-NLMP]. There's no Kerberos:
+//! Negotiate carries the same NTLM messages, which servers accept when the
+//! client can't do Kerberos.
+//!
+//! [MS-NLMP]: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-nlmp/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+
+const FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NEGOTIATE_TARGET_INFO
+    | NEGOTIATE_128
+    | NEGOTIATE_56;
+
+/// The AV pair in the target info with the server's clock.
+const MSV_AV_TIMESTAMP: u16 = 7;
+
+/// The first message, which asks the server for a challenge.
+pub fn negotiate_message() -> Vec<u8> {
+    let mut message = SIGNATURE.to_vec();
+    message.extend(1u32.to_le_bytes());
+    message.extend(FLAGS.to_le_bytes());
+    // Empty domain and workstation
+    message.extend([0; 16]);
+    message
+}
+
+/// Answer the server's challenge message. `username` may start with a
+/// domain, as in `DOMAIN\user`.
+pub fn authenticate_message(challenge: &[u8], username: &str, password: &str) -> Result<Vec<u8>> {
+    let mut client_challenge = [0; 8];
+    getrandom::getrandom(&mut client_challenge)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    // In 100 nanosecond intervals since 1601
+    let timestamp =
+        (now.as_secs() + 11_644_473_600) * 10_000_000 + u64::from(now.subsec_nanos()) / 100;
+    build_authenticate(
+        &Challenge::parse(challenge)?,
+        username,
+        password,
+        client_challenge,
+        timestamp,
+    )
+}
+
+struct Challenge<'a> {
+    flags: u32,
+    server_challenge: [u8; 8],
+    target_info: &'a [u8],
+}
+
+impl<'a> Challenge<'a> {
+    fn parse(message: &'a [u8]) -> Result<Self> {
+        let bad = || anyhow!("Bad NTLM challenge from the server");
+        if message.len() < 32 || &message[..8] != SIGNATURE || read_u32(message, 8) != Some(2) {
+            return Err(bad());
+        }
+        let target_info = match read_buffer(message, 40) {
+            Some(target_info) => target_info.ok_or_else(bad)?,
+            None => &[],
+        };
+        Ok(Challenge {
+            flags: read_u32(message, 20).ok_or_else(bad)?,
+            server_challenge: message[24..32].try_into()?,
+            target_info,
+        })
+    }
+
+    /// The server's time from the target info, if it sent it.
+    fn timestamp(&self) -> Option<u64> {
+        let mut pairs = self.target_info;
+        while pairs.len() >= 4 {
+            let id = u16::from_le_bytes([pairs[0], pairs[1]]);
+            let len = usize::from(u16::from_le_bytes([pairs[2], pairs[3]]));
+            let value = pairs.get(4..4 + len)?;
+            if id == MSV_AV_TIMESTAMP {
+                return Some(u64::from_le_bytes(value.try_into().ok()?));
+            }
+            pairs = &pairs[4 + len..];
+        }
+        None
+    }
+}
+
+fn build_authenticate(
+    challenge: &Challenge,
+    username: &str,
+    password: &str,
+    client_challenge: [u8; 8],
+    timestamp: u64,
+) -> Result<Vec<u8>> {
+    let (domain, user) = username.split_once('\\').unwrap_or(("", username));
+    let key = nt_owf_v2(user, domain, password);
+
+    // The LMv2 response has to be left out if the server sent its time
+    let (timestamp, lm_response) = match challenge.timestamp() {
+        Some(timestamp) => (timestamp, vec![0; 24]),
+        None => {
+            let mut lm_response =
+                hmac_md5(&key, &[&challenge.server_challenge, &client_challenge]).to_vec();
+            lm_response.extend(client_challenge);
+            (timestamp, lm_response)
+        }
+    };
+
+    let mut blob = vec![1, 1, 0, 0, 0, 0, 0, 0];
+    blob.extend(timestamp.to_le_bytes());
+    blob.extend(client_challenge);
+    blob.extend([0; 4]);
+    blob.extend(challenge.target_info);
+    blob.extend([0; 4]);
+    let mut nt_response = hmac_md5(&key, &[&challenge.server_challenge, &blob]).to_vec();
+    nt_response.extend(blob);
+
+    let fields = [
+        lm_response,
+        nt_response,
+        utf16le(domain),
+        utf16le(user),
+        // Workstation and session key
+        Vec::new(),
+        Vec::new(),
+    ];
+    let mut message = SIGNATURE.to_vec();
+    message.extend(3u32.to_le_bytes());
+    let mut offset = message.len() + fields.len() * 8 + 4;
+    for field in &fields {
+        let len = u16::try_from(field.len())?;
+        message.extend(len.to_le_bytes());
+        message.extend(len.to_le_bytes());
+        message.extend(u32::try_from(offset)?.to_le_bytes());
+        offset += field.len();
+    }
+    message.extend((challenge.flags & FLAGS).to_le_bytes());
+    for field in fields {
+        message.extend(field);
+    }
+    Ok(message)
+}
+
+/// The NTLMv2 key, from the MD4 hash of the password.
+fn nt_owf_v2(user: &str, domain: &str, password: &str) -> [u8; 16] {
+    let nt_hash = Md4::digest(utf16le(password));
+    let identity = utf16le(&(user.to_uppercase() + domain));
+    hmac_md5(&nt_hash, &[&identity])
+}
+
+fn hmac_md5(key: &[u8], parts: &[&[u8]]) -> [u8; 16] {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("HMAC takes keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn utf16le(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn read_u32(message: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        message.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Read the field that the security buffer at `offset` points to. Returns
+/// `None` if the message is too short to have the buffer at all, and
+/// `Some(None)` if it points outside the message.
+fn read_buffer(message: &[u8], offset: usize) -> Option<Option<&[u8]>> {
+    let header = message.get(offset..offset + 8)?;
+    let len = usize::from(u16::from_le_bytes([header[0], header[1]]));
+    let start = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+    Some(message.get(start..start.checked_add(len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The NTLMv2 example in section 4.2.4 of MS-NLMP
+    #[test]
+    fn ntlm_v2_responses() {
+        let target_info = hex::decode(
+            "02000c0044006f006d00610069006e00\
+             01000c0053006500720076006500720000000000",
+        )
+        .unwrap();
+        let challenge = Challenge {
+            flags: FLAGS,
+            server_challenge: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
+            target_info: &target_info,
+        };
+        assert_eq!(
+            hex::encode(nt_owf_v2("User", "Domain", "Password")),
+            "0c868a403bfd7a93a3001ef22ef02e3f"
+        );
+
+        let message =
+            build_authenticate(&challenge, "Domain\\User", "Password", [0xaa; 8], 0).unwrap();
+        let lm_response = read_buffer(&message, 12).unwrap().unwrap();
+        assert_eq!(
+            hex::encode(lm_response),
+            "86c35097ac9cec102554764a57cccc19aaaaaaaaaaaaaaaa"
+        );
+        let nt_response = read_buffer(&message, 20).unwrap().unwrap();
+        assert_eq!(
+            hex::encode(&nt_response[..16]),
+            "68cd0ab851e51c96aabc927bebef6a1c"
+        );
+        assert_eq!(
+            read_buffer(&message, 28).unwrap().unwrap(),
+            utf16le("Domain")
+        );
+        assert_eq!(read_buffer(&message, 36).unwrap().unwrap(), utf16le("User"));
+    }
+
+    #[test]
+    fn bad_challenges() {
+        assert!(Challenge::parse(b"NTLMSSP\0").is_err());
+        assert!(Challenge::parse(&negotiate_message()).is_err());
+    }
+}
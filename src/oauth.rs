@@ -0,0 +1,216 @@
+//! Getting access tokens for `--auth-type=oauth2`.
+//!
+//! With a client secret in --auth, the token comes from the client credentials
+//! grant (RFC 6749, section 4.4). Without one, the device authorization grant
+//! (RFC 8628) has the user approve the request in a browser, possibly on
+//! another device, while we poll the token endpoint.
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::ACCEPT;
+use serde::Deserialize;
+use url::{form_urlencoded, Url};
+
+use crate::auth::Auth;
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Tokens that expire within this long are fetched again rather than reused.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error_description {
+            Some(description) => write!(f, "{} ({})", self.error, description),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    // Google spells it verification_url
+    #[serde(alias = "verification_url")]
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+struct OAuth<'a> {
+    client: &'a Client,
+    token_url: &'a Url,
+    client_id: &'a str,
+    client_secret: Option<&'a str>,
+    scope: Option<&'a str>,
+}
+
+/// Whether a token saved in the session can still be used.
+pub fn is_fresh(expires: Option<SystemTime>) -> bool {
+    match expires {
+        Some(expires) => expires > SystemTime::now() + EXPIRY_MARGIN,
+        None => true,
+    }
+}
+
+/// Get a new access token, with the device authorization grant if there's a
+/// `device_url` and with the client credentials grant otherwise.
+pub fn fetch_token(
+    client: &Client,
+    token_url: Option<&Url>,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+    scope: Option<&str>,
+    device_url: Option<&Url>,
+) -> Result<Auth> {
+    let oauth = OAuth {
+        client,
+        token_url: token_url
+            .ok_or_else(|| anyhow!("--auth-type=oauth2 requires --oauth-token-url"))?,
+        client_id: client_id
+            .ok_or_else(|| anyhow!("--auth-type=oauth2 requires --oauth-client-id"))?,
+        client_secret,
+        scope,
+    };
+    match (device_url, client_secret) {
+        (Some(device_url), _) => oauth.device_code(device_url),
+        (None, Some(_)) => oauth.client_credentials(),
+        (None, None) => Err(anyhow!(
+            "--auth-type=oauth2 needs a client secret in --auth, or --oauth-device-url to sign in"
+        )),
+    }
+}
+
+impl OAuth<'_> {
+    fn client_credentials(&self) -> Result<Auth> {
+        let mut params = vec![("grant_type", "client_credentials")];
+        params.extend(self.scope.map(|scope| ("scope", scope)));
+        let response = self.post(self.token_url, params)?;
+        read_token(response)?.map_err(|error| anyhow!("Couldn't get an access token: {error}"))
+    }
+
+    fn device_code(&self, device_url: &Url) -> Result<Auth> {
+        let params = self
+            .scope
+            .map(|scope| ("scope", scope))
+            .into_iter()
+            .collect();
+        let response = self.post(device_url, params)?;
+        let status = response.status();
+        let body = response.text()?;
+        if !status.is_success() {
+            return Err(match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(error) => anyhow!("Couldn't start the device sign-in: {error}"),
+                Err(_) => anyhow!("Couldn't start the device sign-in: {status}"),
+            });
+        }
+        let authorization: DeviceAuthorization = serde_json::from_str(&body)
+            .with_context(|| format!("Unexpected reply from {device_url}"))?;
+
+        match &authorization.verification_uri_complete {
+            Some(uri) => eprintln!(
+                "To sign in, visit {uri} and check that it shows the code {}",
+                authorization.user_code
+            ),
+            None => eprintln!(
+                "To sign in, visit {} and enter the code {}",
+                authorization.verification_uri, authorization.user_code
+            ),
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = Duration::from_secs(authorization.interval.unwrap_or(5));
+        loop {
+            thread::sleep(interval);
+            if Instant::now() > deadline {
+                return Err(anyhow!("The sign-in wasn't approved in time"));
+            }
+            let params = vec![
+                ("grant_type", DEVICE_CODE_GRANT),
+                ("device_code", &authorization.device_code),
+            ];
+            match read_token(self.post(self.token_url, params)?)? {
+                Ok(auth) => return Ok(auth),
+                Err(error) if error.error == "authorization_pending" => {}
+                Err(error) if error.error == "slow_down" => interval += Duration::from_secs(5),
+                Err(error) => return Err(anyhow!("Couldn't get an access token: {error}")),
+            }
+        }
+    }
+
+    /// Send a form to one of the endpoints, authenticating as the client.
+    fn post<'p>(&'p self, url: &Url, mut params: Vec<(&str, &'p str)>) -> Result<Response> {
+        let mut request = self
+            .client
+            .post(url.clone())
+            .header(ACCEPT, "application/json");
+        match self.client_secret {
+            // The credentials are form-encoded before they go in the header,
+            // see RFC 6749, section 2.3.1
+            Some(secret) => {
+                request = request.basic_auth(form_encode(self.client_id), Some(form_encode(secret)))
+            }
+            None => params.push(("client_id", self.client_id)),
+        }
+        log::debug!("Requesting {url} for an OAuth 2.0 token");
+        request
+            .form(&params)
+            .send()
+            .with_context(|| format!("Failed to reach {url}"))
+    }
+}
+
+/// Read a reply from the token endpoint, which has either a token or an error.
+fn read_token(response: Response) -> Result<Result<Auth, ErrorResponse>> {
+    let status = response.status();
+    let body = response.text()?;
+    if status.is_success() {
+        let token: TokenResponse =
+            serde_json::from_str(&body).context("Unexpected reply from the token endpoint")?;
+        let expires = token
+            .expires_in
+            .map(|expires_in| SystemTime::now() + Duration::from_secs(expires_in));
+        Ok(Ok(Auth::OAuth2(token.access_token, expires)))
+    } else {
+        match serde_json::from_str(&body) {
+            Ok(error) => Ok(Err(error)),
+            Err(_) => Err(anyhow!("The token endpoint replied with {status}")),
+        }
+    }
+}
+
+fn form_encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freshness() {
+        let now = SystemTime::now();
+        assert!(is_fresh(None));
+        assert!(is_fresh(Some(now + Duration::from_secs(3600))));
+        assert!(!is_fresh(Some(now + Duration::from_secs(10))));
+        assert!(!is_fresh(Some(now - Duration::from_secs(3600))));
+    }
+}
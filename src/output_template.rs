@@ -0,0 +1,189 @@
+//! Templates for `--template`, which render a single line from the response.
+//!
+//! This uses the same syntax as `--env` files (see [`crate::template`]):
+//! `{{field}}` is replaced by the value of a field and `\{{` produces a
+//! literal `{{`. The fields are:
+//!
+//! - `status`, `reason`, `version`, `method`, `url` and `elapsed` (in seconds)
+//! - `headers[NAME]`, with multiple values joined by commas
+//! - `body`
+//! - `json`, or `json.PATH` to take a value out of a JSON body, e.g.
+//!   `json.items.0.id`. Strings are inserted without quotes.
+//!
+//! Missing headers and JSON values render as an empty string.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::Response;
+use reqwest::header::HeaderName;
+use serde_json::Value;
+
+use crate::middleware::ResponseExt;
+use crate::template::{self, Part};
+use crate::utils::reason_phrase;
+
+#[derive(Debug, Clone)]
+pub struct OutputTemplate(Vec<Segment>);
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone)]
+enum Field {
+    Status,
+    Reason,
+    Version,
+    Method,
+    Url,
+    Elapsed,
+    Header(HeaderName),
+    Body,
+    Json(Vec<String>),
+}
+
+impl FromStr for OutputTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<OutputTemplate> {
+        let mut segments = Vec::new();
+        for part in template::parse(template)? {
+            match (part, segments.last_mut()) {
+                (Part::Text(text), Some(Segment::Literal(literal))) => literal.push_str(text),
+                (Part::Text(text), _) => segments.push(Segment::Literal(text.to_string())),
+                (Part::Placeholder(field), _) => segments.push(Segment::Field(parse_field(field)?)),
+            }
+        }
+        Ok(OutputTemplate(segments))
+    }
+}
+
+fn parse_field(field: &str) -> Result<Field> {
+    let field = match field {
+        "status" => Field::Status,
+        "reason" => Field::Reason,
+        "version" => Field::Version,
+        "method" => Field::Method,
+        "url" => Field::Url,
+        "elapsed" => Field::Elapsed,
+        "body" => Field::Body,
+        "json" => Field::Json(Vec::new()),
+        _ => {
+            if let Some(path) = field.strip_prefix("json.") {
+                Field::Json(path.split('.').map(String::from).collect())
+            } else if let Some(name) = field
+                .strip_prefix("headers[")
+                .and_then(|name| name.strip_suffix(']'))
+            {
+                let name = HeaderName::from_bytes(name.as_bytes()).with_context(|| {
+                    format!("Invalid header name in template field {{{{{field}}}}}")
+                })?;
+                Field::Header(name)
+            } else {
+                return Err(anyhow!("Unknown template field {{{{{field}}}}}"));
+            }
+        }
+    };
+    Ok(field)
+}
+
+impl OutputTemplate {
+    /// Render the template for a response whose body has already been read.
+    pub fn render(&self, response: &Response, body: &[u8]) -> Result<String> {
+        let mut json = None;
+        let mut out = String::new();
+        for segment in &self.0 {
+            let field = match segment {
+                Segment::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Segment::Field(field) => field,
+            };
+            match field {
+                Field::Status => out.push_str(response.status().as_str()),
+                Field::Reason => out.push_str(&reason_phrase(response)),
+                Field::Version => out.push_str(&format!("{:?}", response.version())),
+                Field::Method => out.push_str(response.meta().method.as_str()),
+                Field::Url => out.push_str(response.url().as_str()),
+                Field::Elapsed => out.push_str(&format!(
+                    "{:.3}",
+                    response.meta().total_duration().as_secs_f64()
+                )),
+                Field::Header(name) => {
+                    let values: Vec<_> = response
+                        .headers()
+                        .get_all(name)
+                        .iter()
+                        .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                        .collect();
+                    out.push_str(&values.join(", "));
+                }
+                Field::Body => out.push_str(&String::from_utf8_lossy(body)),
+                Field::Json(path) => {
+                    if json.is_none() {
+                        json = Some(
+                            serde_json::from_slice::<Value>(body)
+                                .context("Response body is not valid JSON")?,
+                        );
+                    }
+                    match lookup(json.as_ref().unwrap(), path) {
+                        Some(Value::String(text)) => out.push_str(text),
+                        Some(value) => out.push_str(&value.to_string()),
+                        None => {}
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_template() {
+        let template: OutputTemplate =
+            r"{{status}} {{ headers[content-type] }} \{{x}} {x} {{json.data.0.id}}"
+                .parse()
+                .unwrap();
+        assert!(matches!(
+            template.0.as_slice(),
+            [
+                Segment::Field(Field::Status),
+                Segment::Literal(a),
+                Segment::Field(Field::Header(name)),
+                Segment::Literal(b),
+                Segment::Field(Field::Json(path)),
+            ] if a == " " && name == "content-type" && b == " {{x}} {x} " && path == &["data", "0", "id"]
+        ));
+
+        "{{unknown}}".parse::<OutputTemplate>().unwrap_err();
+        "{{status".parse::<OutputTemplate>().unwrap_err();
+    }
+
+    #[test]
+    fn json_lookup() {
+        let value = serde_json::json!({"data": [{"id": 7}]});
+        let path = |path: &str| path.split('.').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            lookup(&value, &path("data.0.id")),
+            Some(&serde_json::json!(7))
+        );
+        assert_eq!(lookup(&value, &path("data.1.id")), None);
+        assert_eq!(lookup(&value, &path("data.id")), None);
+        assert_eq!(lookup(&value, &[]), Some(&value));
+    }
+}
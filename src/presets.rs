@@ -0,0 +1,175 @@
+//! Presets bundle the boilerplate needed to talk to a particular API: a base
+//! URL, a few headers, and a token taken from the environment.
+//!
+//! A couple of presets are built in. More can be defined (or the built-in ones
+//! overridden) in `presets.json` inside the config directory:
+//!
+//! ```json
+//! {
+//!     "internal": {
+//!         "base_url": "https://api.internal.example",
+//!         "headers": { "accept": "application/json" },
+//!         "auth_env": ["INTERNAL_API_TOKEN"],
+//!         "auth_type": "bearer"
+//!     }
+//! }
+//! ```
+//!
+//! Presets don't follow pagination links, and credentials only come from the
+//! environment. xh has no support for either of those to build on yet.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::cli::AuthType;
+use crate::utils::config_dir;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    /// Prepended to URLs that start with a `/`.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Headers to add unless the request sets or unsets them itself.
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// Environment variables to take the auth credentials from, in order of preference.
+    #[serde(default)]
+    pub auth_env: Vec<String>,
+    #[serde(default)]
+    pub auth_type: Option<String>,
+}
+
+impl Preset {
+    pub fn load(name: &str) -> Result<Preset> {
+        if let Some(preset) = user_presets()?.remove(name) {
+            return Ok(preset);
+        }
+        builtin(name).ok_or_else(|| anyhow!("Unknown preset '{name}'"))
+    }
+
+    /// Expand a URL like `/repos/ducaale/xh` using the preset's base URL.
+    pub fn expand_url(&self, url: &str) -> String {
+        match &self.base_url {
+            Some(base_url) if url.starts_with('/') => {
+                format!("{}{}", base_url.trim_end_matches('/'), url)
+            }
+            _ => url.to_string(),
+        }
+    }
+
+    pub fn auth(&self) -> Option<String> {
+        self.auth_env
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+    }
+
+    pub fn auth_type(&self) -> Result<Option<AuthType>> {
+        self.auth_type
+            .as_deref()
+            .map(|auth_type| {
+                AuthType::from_str(auth_type, true)
+                    .map_err(|_| anyhow!("Invalid auth_type '{auth_type}' in preset"))
+            })
+            .transpose()
+    }
+}
+
+fn builtin(name: &str) -> Option<Preset> {
+    let preset = match name {
+        "github" => Preset {
+            base_url: Some("https://api.github.com".into()),
+            headers: vec![
+                ("accept".into(), "application/vnd.github+json".into()),
+                ("x-github-api-version".into(), "2022-11-28".into()),
+            ],
+            auth_env: vec!["GITHUB_TOKEN".into(), "GH_TOKEN".into()],
+            auth_type: Some("bearer".into()),
+        },
+        "gitlab" => Preset {
+            base_url: Some("https://gitlab.com/api/v4".into()),
+            headers: vec![],
+            auth_env: vec!["GITLAB_TOKEN".into()],
+            auth_type: Some("bearer".into()),
+        },
+        _ => return None,
+    };
+    Some(preset)
+}
+
+fn user_presets() -> Result<HashMap<String, Preset>> {
+    let Some(path) = config_dir().map(|dir| dir.join("presets.json")) else {
+        return Ok(HashMap::new());
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err).with_context(|| format!("Unable to read {}", path.display())),
+    };
+    let presets: HashMap<String, serde_json::Value> = serde_json::from_str(&content)
+        .with_context(|| format!("Unable to parse {}", path.display()))?;
+    presets
+        .into_iter()
+        .map(|(name, value)| {
+            let preset = parse_preset(value)
+                .with_context(|| format!("Invalid preset '{name}' in {}", path.display()))?;
+            Ok((name, preset))
+        })
+        .collect()
+}
+
+/// Headers are written as an object in the presets file, but we want to keep
+/// their order, so they're converted into a list of pairs.
+fn parse_preset(mut value: serde_json::Value) -> Result<Preset> {
+    if let Some(headers) = value.get_mut("headers") {
+        if let serde_json::Value::Object(map) = headers.take() {
+            *headers = map
+                .into_iter()
+                .map(|(name, value)| serde_json::json!([name, value]))
+                .collect();
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_url() {
+        let preset = builtin("github").unwrap();
+        assert_eq!(
+            preset.expand_url("/repos/ducaale/xh"),
+            "https://api.github.com/repos/ducaale/xh"
+        );
+        assert_eq!(
+            preset.expand_url("https://example.com/"),
+            "https://example.com/"
+        );
+        assert_eq!(Preset::default().expand_url("/foo"), "/foo");
+    }
+
+    #[test]
+    fn parse_headers_in_order() {
+        let preset = parse_preset(serde_json::json!({
+            "base_url": "http://localhost",
+            "headers": { "b": "1", "a": "2" },
+            "auth_type": "basic"
+        }))
+        .unwrap();
+        assert_eq!(
+            preset.headers,
+            vec![("b".into(), "1".into()), ("a".into(), "2".into())]
+        );
+        assert_eq!(preset.auth_type().unwrap(), Some(AuthType::Basic));
+
+        parse_preset(serde_json::json!({ "pagination": true })).unwrap_err();
+    }
+}
@@ -1,28 +1,59 @@
 use std::borrow::Cow;
-use std::io::{self, BufRead, BufReader, Read, Write};
-use std::time::Instant;
-
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::net::IpAddr;
+use std::process;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{anyhow, Context};
 use encoding_rs::Encoding;
 use encoding_rs_io::DecodeReaderBytesBuilder;
+use indicatif::HumanBytes;
 use mime::Mime;
 use reqwest::blocking::{Body, Request, Response};
 use reqwest::cookie::CookieStore;
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT, ALT_SVC, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, DATE, HOST,
+};
+use reqwest::{StatusCode, Version};
+use termcolor::{NoColor, WriteColor};
 use url::Url;
 
+#[cfg(feature = "data-summaries")]
+use crate::data_summary::{self, Schema};
 use crate::formatting::headers::HeaderFormatter;
+use crate::formatting::jwt::find_jwts;
+use crate::formatting::palette::palette;
+use crate::message_signature::Checked;
+use crate::raw::Trailers;
 use crate::utils::reason_phrase;
 use crate::{
     buffer::Buffer,
     cli::FormatOptions,
-    cli::{Pretty, Theme},
-    decoder::{decompress, get_compression_type},
+    cli::{Pretty, Print, Theme},
+    decoder::{decompress, get_compression_type, DEFAULT_MAX_DECODE_SIZE},
+    dns,
     formatting::serde_json_format,
-    formatting::{get_json_formatter, Highlighter},
+    formatting::{decode_base64_strings, get_json_formatter, Highlighter},
+    interrupt::{self, CountingReader},
     middleware::ResponseExt,
-    utils::{copy_largebuf, test_mode, BUFFER_SIZE},
+    multipart,
+    output_template::OutputTemplate,
+    protobuf,
+    utils::{copy_largebuf, group_digits, test_mode, BUFFER_SIZE},
 };
 
+palette! {
+    struct MetaPalette {
+        label: ["source.http", "http.requestheaders", "support.variable.http"],
+        separator: ["source.http", "http.requestheaders", "punctuation.separator.http"],
+        value: ["source.http", "http.requestheaders", "string.other.http"],
+    }
+}
+
 const BINARY_SUPPRESSOR: &str = concat!(
     "+-----------------------------------------+\n",
     "| NOTE: binary data not shown in terminal |\n",
@@ -112,10 +143,29 @@ pub struct Printer {
     format_json: bool,
     json_indent_level: usize,
     sort_headers: bool,
+    merge_headers: bool,
     color: bool,
     theme: Theme,
     stream: Option<bool>,
+    max_display_bytes: Option<u64>,
+    summarize_bodies: Option<u64>,
+    max_decode_size: u64,
+    show_cookies: bool,
+    decode_base64: bool,
+    decode_jwt: bool,
+    protobuf: Option<protobuf::Call>,
+    dns_resolver: Option<dns::Resolver>,
+    pipe: Option<String>,
+    keep_body: bool,
+    kept_body: Option<Vec<u8>>,
+    header_dump: Option<BufWriter<File>>,
+    metrics_file: Option<BufWriter<File>>,
     buffer: Buffer,
+    /// With --print-to, the parts that go to stderr and the buffer that's
+    /// not currently selected
+    print_to: Option<(Print, Buffer)>,
+    on_stderr: bool,
+    separator_pending: bool,
 }
 
 impl Printer {
@@ -130,13 +180,108 @@ impl Printer {
             format_json: format_options.json_format.unwrap_or(pretty.format()),
             json_indent_level: format_options.json_indent.unwrap_or(4),
             sort_headers: format_options.headers_sort.unwrap_or(pretty.format()),
+            merge_headers: format_options.headers_merge.unwrap_or(false),
             color: pretty.color(),
             stream: stream.into(),
+            max_display_bytes: None,
+            summarize_bodies: None,
+            max_decode_size: DEFAULT_MAX_DECODE_SIZE,
+            show_cookies: false,
+            decode_base64: false,
+            decode_jwt: false,
+            protobuf: None,
+            dns_resolver: None,
+            pipe: None,
+            keep_body: false,
+            kept_body: None,
+            header_dump: None,
+            metrics_file: None,
             theme,
             buffer,
+            print_to: None,
+            on_stderr: false,
+            separator_pending: false,
         }
     }
 
+    /// Stop printing response bodies to the terminal after this many bytes.
+    pub fn with_max_display_bytes(mut self, max_display_bytes: Option<u64>) -> Self {
+        self.max_display_bytes = max_display_bytes;
+        self
+    }
+
+    /// Only print this many bytes of each body, followed by a count of the rest.
+    pub fn with_summarize_bodies(mut self, summarize_bodies: Option<u64>) -> Self {
+        self.summarize_bodies = summarize_bodies;
+        self
+    }
+
+    /// Fail once a compressed response body decodes to more than this many bytes.
+    pub fn with_max_decode_size(mut self, max_decode_size: u64) -> Self {
+        self.max_decode_size = max_decode_size;
+        self
+    }
+
+    /// Show Set-Cookie headers as a table.
+    pub fn with_show_cookies(mut self, show_cookies: bool) -> Self {
+        self.show_cookies = show_cookies;
+        self
+    }
+
+    /// Decode base64 strings in JSON response bodies.
+    pub fn with_decode_base64(mut self, decode_base64: bool) -> Self {
+        self.decode_base64 = decode_base64;
+        self
+    }
+
+    /// Show the contents of JWTs in bodies and headers.
+    pub fn with_decode_jwt(mut self, decode_jwt: bool) -> Self {
+        self.decode_jwt = decode_jwt;
+        self
+    }
+
+    /// Show protobuf bodies as JSON, using the message types of this call.
+    pub fn with_protobuf(mut self, protobuf: Option<protobuf::Call>) -> Self {
+        self.protobuf = protobuf;
+        self
+    }
+
+    /// Report the lookups done by this resolver in the response metadata.
+    pub fn with_dns_resolver(mut self, dns_resolver: Option<dns::Resolver>) -> Self {
+        self.dns_resolver = dns_resolver;
+        self
+    }
+
+    /// Pipe response bodies through this shell command.
+    pub fn with_pipe(mut self, pipe: Option<String>) -> Self {
+        self.pipe = pipe;
+        self
+    }
+
+    /// Keep a copy of each response body, for [`Printer::take_response_body`].
+    pub fn with_keep_body(mut self, keep_body: bool) -> Self {
+        self.keep_body = keep_body;
+        self
+    }
+
+    /// Send these parts of the output to `stderr` instead.
+    pub fn with_print_to(mut self, print_to: Option<(Print, Buffer)>) -> Self {
+        self.print_to = print_to;
+        self
+    }
+
+    /// Also write the headers of every response to this file.
+    pub fn with_header_dump(mut self, header_dump: Option<File>) -> Self {
+        self.header_dump = header_dump.map(BufWriter::new);
+        self
+    }
+
+    /// Append metrics about every response to this file.
+    pub fn with_metrics_file(mut self, metrics_file: Option<File>) -> Self {
+        self.metrics_file = metrics_file.map(BufWriter::new);
+        self
+    }
+
     fn get_highlighter(&mut self, syntax: &'static str) -> Highlighter<'_> {
         Highlighter::new(syntax, self.theme, &mut self.buffer)
     }
@@ -149,6 +294,9 @@ impl Printer {
             is_terminal,
             self.sort_headers,
         )
+        .with_show_cookies(self.show_cookies)
+        .with_merge_headers(self.merge_headers)
+        .with_decode_jwt(self.decode_jwt)
     }
 
     fn print_colorized_text(&mut self, text: &str, syntax: &'static str) -> io::Result<()> {
@@ -193,11 +341,25 @@ impl Printer {
     }
 
     fn print_body_text(&mut self, content_type: ContentType, body: &str) -> io::Result<()> {
+        let decoded;
+        let body = match content_type {
+            ContentType::Json if self.decode_base64 => {
+                decoded = decode_base64_strings(body);
+                decoded.as_deref().unwrap_or(body)
+            }
+            _ => body,
+        };
         match content_type {
             ContentType::Json => self.print_json_text(body, true),
             ContentType::Xml => self.print_syntax_text(body, "xml"),
             ContentType::Html => self.print_syntax_text(body, "html"),
             ContentType::Css => self.print_syntax_text(body, "css"),
+            ContentType::Yaml => self.print_syntax_text(body, "yaml"),
+            ContentType::GraphQl => self.print_syntax_text(body, "graphql"),
+            ContentType::Sql => self.print_syntax_text(body, "sql"),
+            ContentType::Shell => self.print_syntax_text(body, "sh"),
+            ContentType::Toml => self.print_syntax_text(body, "toml"),
+            ContentType::Diff => self.print_syntax_text(body, "diff"),
             // In HTTPie part of this behavior is gated behind the --json flag
             // But it does JSON formatting even without that flag, so doing
             // this check unconditionally is fine
@@ -205,13 +367,33 @@ impl Printer {
                 self.print_json_text(body, false)
             }
             ContentType::JavaScript => self.print_syntax_text(body, "js"),
+            ContentType::Text => match sniff_syntax(body) {
+                Some(syntax) => self.print_syntax_text(body, syntax),
+                None => self.buffer.print(body),
+            },
             _ => self.buffer.print(body),
+        }?;
+        if self.decode_jwt && find_jwts(body).next().is_some() {
+            if !body.ends_with('\n') {
+                self.buffer.print("\n")?;
+            }
+            self.get_header_formatter().print_body_jwts(body)?;
+        }
+        Ok(())
+    }
+
+    fn print_protobuf_messages(&mut self, messages: &[serde_json::Value]) -> io::Result<()> {
+        for message in messages {
+            self.print_body_text(ContentType::Json, &message.to_string())?;
+            self.buffer.print("\n")?;
         }
+        Ok(())
     }
 
     fn print_stream(&mut self, reader: &mut impl Read) -> io::Result<()> {
         if !self.buffer.is_terminal() {
-            return copy_largebuf(reader, &mut self.buffer, true);
+            copy_largebuf(reader, &mut self.buffer, true)?;
+            return Ok(());
         }
         let mut guard = BinaryGuard::new(reader, true);
         while let Some(lines) = guard.read_lines()? {
@@ -302,13 +484,108 @@ impl Printer {
             ContentType::Xml => self.print_syntax_stream(body, "xml"),
             ContentType::Html => self.print_syntax_stream(body, "html"),
             ContentType::Css => self.print_syntax_stream(body, "css"),
+            ContentType::Yaml => self.print_syntax_stream(body, "yaml"),
+            ContentType::GraphQl => self.print_syntax_stream(body, "graphql"),
+            ContentType::Sql => self.print_syntax_stream(body, "sql"),
+            ContentType::Shell => self.print_syntax_stream(body, "sh"),
+            ContentType::Toml => self.print_syntax_stream(body, "toml"),
+            ContentType::Diff => self.print_syntax_stream(body, "diff"),
             // print_body_text() has fancy JSON detection, but we can't do that here
             ContentType::JavaScript => self.print_syntax_stream(body, "js"),
             _ => self.print_stream(body),
         }
     }
 
+    /// Print each part of a multipart body with its own headers, formatting
+    /// the body of each part according to its own content type.
+    fn print_multipart_body(
+        &mut self,
+        parts: &[multipart::Part],
+        boundary: &str,
+        url: &Url,
+    ) -> io::Result<()> {
+        for part in parts {
+            self.buffer.print(&format!("--{boundary}\n"))?;
+            self.get_header_formatter()
+                .print_part_headers(&part.headers)?;
+            self.buffer.print("\n")?;
+            let content_type = get_content_type(&part.headers);
+            let encoding = get_charset(&part.headers);
+            match decode_blob(&part.body, encoding, url) {
+                None => self.buffer.print(BINARY_SUPPRESSOR)?,
+                Some(text) => {
+                    // Formatting may change how the text ends
+                    self.print_body_text(content_type, &text)?;
+                    if !self.buffer.at_line_start() {
+                        self.buffer.print("\n")?;
+                    }
+                }
+            }
+        }
+        self.buffer.print(&format!("--{boundary}--\n"))
+    }
+
+    /// Stand in for a binary body. Avro and Parquet files get a summary of
+    /// their contents instead, if the whole file was read.
+    #[cfg_attr(not(feature = "data-summaries"), allow(unused_variables))]
+    fn print_binary_body(&mut self, body: &[u8], complete: bool) -> io::Result<()> {
+        #[cfg(feature = "data-summaries")]
+        if let Some(summary) = complete.then(|| data_summary::summarize(body)).flatten() {
+            self.buffer.print(&summary.description)?;
+            self.buffer.print("\n\n")?;
+            match summary.schema {
+                Schema::Json(schema) => {
+                    self.print_json_text(&schema, false)?;
+                    if !self.format_json {
+                        self.buffer.print("\n\n")?;
+                    }
+                }
+                Schema::Text(schema) => {
+                    self.buffer.print(&schema)?;
+                    self.buffer.print("\n")?;
+                }
+            }
+            return Ok(());
+        }
+        self.buffer.print(BINARY_SUPPRESSOR)
+    }
+
+    /// Print a message in a box, in the style of [`BINARY_SUPPRESSOR`].
+    fn print_note(&mut self, note: &str) -> io::Result<()> {
+        let border = "-".repeat(note.chars().count() + 2);
+        self.buffer
+            .print(&format!("+{border}+\n| {note} |\n+{border}+\n\n"))
+    }
+
+    /// Switch to the output that --print-to routes a part to.
+    fn select(&mut self, part: fn(&Print) -> bool) -> io::Result<()> {
+        let Some((stderr_parts, other)) = &mut self.print_to else {
+            return Ok(());
+        };
+        let separator_pending = mem::take(&mut self.separator_pending);
+        let to_stderr = part(stderr_parts);
+        if to_stderr != self.on_stderr {
+            self.buffer.flush()?;
+            mem::swap(&mut self.buffer, other);
+            self.on_stderr = to_stderr;
+        } else if separator_pending {
+            self.buffer.print("\n")?;
+        }
+        Ok(())
+    }
+
+    /// The line that stands in for the rest of a body with --summarize-bodies.
+    fn print_summary(&mut self, remaining: u64) -> io::Result<()> {
+        self.buffer
+            .print(&format!("… ({} more bytes)\n", group_digits(remaining)))
+    }
+
     pub fn print_separator(&mut self) -> io::Result<()> {
+        if self.print_to.is_some() {
+            // Only print it if the next part ends up in the same place
+            self.separator_pending = true;
+            return Ok(());
+        }
         self.buffer.print("\n")?;
         self.buffer.flush()?;
         Ok(())
@@ -318,6 +595,7 @@ impl Printer {
     where
         T: CookieStore,
     {
+        self.select(|print| print.request_headers)?;
         let url = request.url();
         let version = request.version();
         let mut headers = request.headers().clone();
@@ -369,6 +647,7 @@ impl Printer {
     }
 
     pub fn print_response_headers(&mut self, response: &Response) -> io::Result<()> {
+        self.select(|print| print.response_headers)?;
         self.get_header_formatter().print_response_headers(
             response.version(),
             response.status(),
@@ -381,16 +660,163 @@ impl Printer {
         Ok(())
     }
 
+    /// Print the trailers of a chunked response, if it had any.
+    pub fn print_response_trailers(&mut self, response: &Response) -> io::Result<()> {
+        let Some(Trailers(trailers)) = response.extensions().get() else {
+            return Ok(());
+        };
+        self.select(|print| print.response_headers)?;
+        self.get_header_formatter().print_trailers(trailers)?;
+
+        self.buffer.print("\n")?;
+        self.buffer.flush()?;
+        Ok(())
+    }
+
+    /// Print the server's answer to a WebSocket handshake.
+    pub fn print_handshake_response(
+        &mut self,
+        status: StatusCode,
+        reason: &str,
+        headers: &HeaderMap,
+    ) -> io::Result<()> {
+        self.select(|print| print.response_headers)?;
+        self.get_header_formatter().print_response_headers(
+            Version::HTTP_11,
+            status,
+            reason,
+            headers,
+        )?;
+
+        self.buffer.print("\n")?;
+        self.buffer.flush()?;
+        Ok(())
+    }
+
+    /// Print a message received over a WebSocket, one per line (or per
+    /// block, for formatted JSON).
+    pub fn print_websocket_message(&mut self, message: &[u8], is_text: bool) -> io::Result<()> {
+        self.select(|print| print.response_body)?;
+        match std::str::from_utf8(message) {
+            Ok(text) if is_text => {
+                self.print_body_text(ContentType::Text, text)?;
+                let formatted = self.format_json && valid_json(text);
+                if !formatted && !text.ends_with('\n') {
+                    self.buffer.print("\n")?;
+                }
+            }
+            _ if !self.buffer.is_terminal() => self.buffer.write_all(message)?,
+            _ => self.print_note(&format!("Binary message of {} bytes", message.len()))?,
+        }
+        self.buffer.flush()?;
+        Ok(())
+    }
+
+    /// Print a response received by --raw-headers, as it came off the wire.
+    /// Print a request from --http-raw as it will be sent.
+    pub fn print_raw_request(&mut self, request: &[u8]) -> io::Result<()> {
+        self.select(|print| print.request_headers || print.request_body)?;
+        if self.buffer.is_terminal() && request.contains(&b'\0') {
+            self.buffer.print(BINARY_SUPPRESSOR)?;
+        } else {
+            self.buffer.write_all(request)?;
+            if !request.ends_with(b"\n") {
+                self.buffer.print("\n")?;
+            }
+            self.buffer.print("\n")?;
+        }
+        self.buffer.flush()?;
+        Ok(())
+    }
+
+    /// Write the response headers to the file given by --dump-header, if any.
+    pub fn dump_response_headers(&mut self, response: &Response) -> io::Result<()> {
+        if let Some(header_dump) = &mut self.header_dump {
+            let mut output = NoColor::new(&mut *header_dump);
+            HeaderFormatter::new(&mut output, None, false, false).print_response_headers(
+                response.version(),
+                response.status(),
+                &reason_phrase(response),
+                response.headers(),
+            )?;
+            header_dump.write_all(b"\n")?;
+            header_dump.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Append a line of JSON to the file given by --metrics-file, if any.
+    ///
+    /// The content download time is only known if the body has been read.
+    pub fn write_metrics(&mut self, response: &Response) -> io::Result<()> {
+        if let Some(metrics_file) = &mut self.metrics_file {
+            let meta = response.meta();
+            let content_download_duration = meta
+                .content_download_duration
+                .map(|duration| duration.as_secs_f64());
+            let timestamp = meta
+                .received_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0.0, |duration| duration.as_secs_f64());
+            // Response::content_length() counts down as the body is read
+            let response_body_size = response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+            let metrics = serde_json::json!({
+                "timestamp": timestamp,
+                "method": meta.method.as_str(),
+                "url": response.url().as_str(),
+                "status": response.status().as_u16(),
+                "http_version": format!("{:?}", response.version()),
+                "remote_addr": response.remote_addr().map(|addr| addr.to_string()),
+                "timings": {
+                    "response_headers": meta.request_duration.as_secs_f64(),
+                    "content_download": content_download_duration,
+                    "total": meta.total_duration().as_secs_f64(),
+                },
+                "sizes": {
+                    "request_body": meta.request_body_size,
+                    "response_body": response_body_size,
+                },
+            });
+            serde_json::to_writer(&mut *metrics_file, &metrics)?;
+            metrics_file.write_all(b"\n")?;
+            metrics_file.flush()?;
+        }
+        Ok(())
+    }
+
     pub fn print_request_body(&mut self, request: &mut Request) -> anyhow::Result<()> {
+        self.select(|print| print.request_body)?;
         let content_type = get_content_type(request.headers());
         if let Some(body) = request.body_mut() {
-            let body = body.buffer()?;
+            if let Some(call) = self.protobuf.clone() {
+                let messages = call.decode_request(body.buffer()?)?;
+                self.print_protobuf_messages(&messages)?;
+                // Breathing room between request and response
+                self.buffer.print("\n")?;
+                self.buffer.flush()?;
+                return Ok(());
+            }
+            let mut body = body.buffer()?;
+            let mut remaining = 0;
+            if let Some(limit) = self.summarize_bodies {
+                if body.len() as u64 > limit {
+                    let cut = utf8_boundary(body, limit as usize);
+                    remaining = (body.len() - cut) as u64;
+                    body = &body[..cut];
+                }
+            }
             if body.contains(&b'\0') {
                 self.buffer.print(BINARY_SUPPRESSOR)?;
             } else {
                 self.print_body_text(content_type, &String::from_utf8_lossy(body))?;
                 self.buffer.print("\n")?;
             }
+            if remaining > 0 {
+                self.print_summary(remaining)?;
+            }
             // Breathing room between request and response
             self.buffer.print("\n")?;
             self.buffer.flush()?;
@@ -404,19 +830,59 @@ impl Printer {
         encoding: Option<&'static Encoding>,
         mime: Option<&str>,
     ) -> anyhow::Result<()> {
+        self.select(|print| print.response_body)?;
         let starting_time = Instant::now();
         let url = response.url().clone();
         let content_type =
             mime.map_or_else(|| get_content_type(response.headers()), ContentType::from);
-        let encoding = encoding.or_else(|| get_charset(response));
+        let encoding = encoding.or_else(|| get_charset(response.headers()));
+        let boundary = match mime {
+            Some(mime) => multipart::get_boundary(mime),
+            None => multipart::get_boundary_from_headers(response.headers()),
+        };
         let compression_type = get_compression_type(response.headers());
-        let mut body = decompress(response, compression_type);
+        let raw_content_type = match mime {
+            Some(mime) => mime.to_string(),
+            None => response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+        };
+        let protobuf = (self.protobuf.clone()).filter(|_| protobuf::is_protobuf(&raw_content_type));
+        let mut counted = CountingReader::new(&mut *response);
+        let received = counted.count();
+        let _on_interrupt = interrupt::on_interrupt(move || {
+            eprintln!(
+                "\nInterrupted after receiving {} of the response body",
+                HumanBytes(received.load(Ordering::Relaxed))
+            );
+        });
+        let mut kept = Vec::new();
+        let mut body = CopyingReader {
+            inner: decompress(&mut counted, compression_type, self.max_decode_size),
+            copy: self.keep_body.then_some(&mut kept),
+        }
+        .take(self.summarize_bodies.unwrap_or(u64::MAX));
 
         // Automatically activate stream mode when it hasn't been set by the user and the content type is stream
         let stream = self.stream.unwrap_or(content_type.is_stream());
 
-        if !self.buffer.is_terminal() {
-            if (self.color || self.format_json) && content_type.is_text() {
+        if let Some(call) = protobuf {
+            let mut buf = Vec::new();
+            body.get_mut().read_to_end(&mut buf)?;
+            let messages = call.decode_response(&buf, &raw_content_type)?;
+            self.print_protobuf_messages(&messages)?;
+        } else if let Some(command) = &self.pipe {
+            let output = pipe_through(command, &mut body)?;
+            self.buffer.write_all(&output)?;
+            if self.buffer.is_terminal() && !output.ends_with(b"\n") {
+                self.buffer.print("\n")?;
+            }
+        } else if !self.buffer.is_terminal() {
+            let decode_base64 = self.decode_base64 && matches!(content_type, ContentType::Json);
+            if (self.color || self.format_json || decode_base64) && content_type.is_text() {
                 // The user explicitly asked for formatting even though this is
                 // going into a file, and the response is at least supposed to be
                 // text, so decode it
@@ -447,49 +913,212 @@ impl Printer {
                 body.read_to_end(&mut buf)?;
                 self.buffer.write_all(&buf)?;
             }
-        } else if stream {
-            match self
-                .print_body_stream(content_type, &mut decode_stream(&mut body, encoding, &url)?)
-            {
-                Ok(_) => {
-                    self.buffer.print("\n")?;
-                }
-                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
-                    self.buffer.print(BINARY_SUPPRESSOR)?;
-                }
-                Err(err) => return Err(err.into()),
-            }
         } else {
-            let mut buf = Vec::new();
-            body.read_to_end(&mut buf)?;
-            match decode_blob(&buf, encoding, &url) {
-                None => {
-                    self.buffer.print(BINARY_SUPPRESSOR)?;
+            // A huge body can keep the terminal busy for a very long time, so
+            // only read as much of it as the user is willing to look at
+            let max_display_bytes = self.max_display_bytes.unwrap_or(u64::MAX);
+            // Without a content type we only print the body if it decodes
+            // as text, so we might as well guess its format
+            let content_type = match content_type {
+                ContentType::Unknown if raw_content_type.is_empty() => ContentType::Text,
+                content_type => content_type,
+            };
+            let mut body = (&mut body).take(max_display_bytes);
+            let printed_text = if stream {
+                match self
+                    .print_body_stream(content_type, &mut decode_stream(&mut body, encoding, &url)?)
+                {
+                    Ok(_) => {
+                        self.buffer.print("\n")?;
+                        true
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                        self.buffer.print(BINARY_SUPPRESSOR)?;
+                        false
+                    }
+                    Err(err) => return Err(err.into()),
                 }
-                Some(text) => {
-                    self.print_body_text(content_type, &text)?;
+            } else {
+                let mut buf = Vec::new();
+                body.read_to_end(&mut buf)?;
+                let parts = boundary
+                    .as_deref()
+                    .and_then(|boundary| multipart::parse(&buf, boundary).ok());
+                if let (Some(parts), Some(boundary)) = (parts, &boundary) {
+                    self.print_multipart_body(&parts, boundary, &url)?;
                     self.buffer.print("\n")?;
+                    true
+                } else {
+                    match decode_blob(&buf, encoding, &url) {
+                        None => {
+                            self.print_binary_body(&buf, body.limit() > 0)?;
+                            false
+                        }
+                        Some(text) => {
+                            self.print_body_text(content_type, &text)?;
+                            self.buffer.print("\n")?;
+                            true
+                        }
+                    }
                 }
             };
+            if printed_text && body.limit() == 0 && body.get_mut().read(&mut [0])? > 0 {
+                self.print_note(&format!(
+                    "NOTE: body truncated after {} (see --max-display-bytes)",
+                    HumanBytes(max_display_bytes)
+                ))?;
+            }
+        }
+        if self.summarize_bodies.is_some() && body.limit() == 0 {
+            let remaining = io::copy(body.get_mut(), &mut io::sink())?;
+            if remaining > 0 {
+                if !self.buffer.is_terminal() {
+                    // The body was copied as is, so it may not end in a newline
+                    self.buffer.print("\n")?;
+                }
+                self.print_summary(remaining)?;
+            }
+        }
+        if self.keep_body {
+            // Keep the whole body, also what wasn't printed
+            io::copy(body.get_mut(), &mut io::sink())?;
         }
         self.buffer.flush()?;
         drop(body); // silence the borrow checker
         response.meta_mut().content_download_duration = Some(starting_time.elapsed());
+        if self.keep_body {
+            self.kept_body = Some(kept);
+        }
+        Ok(())
+    }
+
+    /// The last response body that was printed, after decompression, if
+    /// bodies are kept.
+    pub fn take_response_body(&mut self) -> Option<Vec<u8>> {
+        self.kept_body.take()
+    }
+
+    /// Print the line rendered from --template, which uses the whole body.
+    pub fn print_response_template(
+        &mut self,
+        template: &OutputTemplate,
+        response: &mut Response,
+    ) -> anyhow::Result<()> {
+        self.select(|print| print.response_body)?;
+        let starting_time = Instant::now();
+        let compression_type = get_compression_type(response.headers());
+        let mut body = Vec::new();
+        decompress(response, compression_type, self.max_decode_size).read_to_end(&mut body)?;
+        response.meta_mut().content_download_duration = Some(starting_time.elapsed());
+
+        self.buffer.print(&template.render(response, &body)?)?;
+        self.buffer.print("\n")?;
+        self.buffer.flush()?;
         Ok(())
     }
 
     pub fn print_response_meta(&mut self, response: &Response) -> anyhow::Result<()> {
+        self.select(|print| print.response_meta)?;
         let meta = response.meta();
-        let mut total_elapsed_time = meta.request_duration.as_secs_f64();
-        if let Some(content_download_duration) = meta.content_download_duration {
-            total_elapsed_time += content_download_duration.as_secs_f64();
-        }
-        self.buffer
-            .print(&format!("Elapsed time: {:.5}s\n", total_elapsed_time))?;
+        let mut lines = vec![(
+            "Elapsed time",
+            format!("{:.5}s", meta.total_duration().as_secs_f64()),
+        )];
 
         if let Some(remote_addr) = response.remote_addr() {
-            self.buffer
-                .print(&format!("Remote address: {:?}\n", remote_addr))?;
+            lines.push(("Remote address", format!("{:?}", remote_addr)));
+        }
+
+        if let Some(reused) = meta.connection_reused {
+            let connection = if reused { "reused" } else { "new" };
+            lines.push(("Connection", connection.to_string()));
+        }
+
+        lines.push(("Protocol", format!("{:?}", response.version())));
+
+        // xh can't speak HTTP/3 yet, but it's still useful to know when a
+        // server offers it
+        let h3_services: Vec<&str> = response
+            .headers()
+            .get_all(ALT_SVC)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|service| service.starts_with("h3=") || service.starts_with("h3-"))
+            .collect();
+        if !h3_services.is_empty() {
+            lines.push(("HTTP/3 offered", h3_services.join(", ")));
+        }
+
+        if let Some((resolver, lookup)) = self
+            .dns_resolver
+            .as_ref()
+            .zip(response.url().host_str())
+            .and_then(|(resolver, host)| Some((resolver, resolver.last_lookup(host)?)))
+        {
+            if matches!(
+                lookup.source,
+                dns::Source::Server(_) | dns::Source::Doh(_) | dns::Source::Mdns
+            ) {
+                let addrs: Vec<_> = lookup.addrs.iter().map(IpAddr::to_string).collect();
+                lines.push((
+                    "Resolved by",
+                    format!("{} ({})", lookup.source, addrs.join(", ")),
+                ));
+            }
+            if let Some((load_balance, position)) = resolver.load_balance().zip(
+                response
+                    .remote_addr()
+                    .and_then(|remote| lookup.addrs.iter().position(|&addr| addr == remote.ip())),
+            ) {
+                lines.push((
+                    "Load balancing",
+                    format!(
+                        "{load_balance}, address {} of {}",
+                        position + 1,
+                        lookup.addrs.len()
+                    ),
+                ));
+            }
+        }
+
+        if let Some(Checked(outcome)) = response.extensions().get() {
+            lines.push(("Signature", outcome.clone()));
+        }
+
+        if let Some(server_date) = response
+            .headers()
+            .get(DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| httpdate::parse_http_date(date).ok())
+        {
+            // HTTP dates only have a resolution of one second
+            let drift = match server_date.duration_since(meta.received_at) {
+                Ok(ahead) => ahead.as_secs() as i64,
+                Err(behind) => -(behind.duration().as_secs() as i64),
+            };
+            lines.push(("Clock drift", format!("{:+}s", drift)));
+        }
+
+        let palette = self
+            .color
+            .then(|| MetaPalette::from(self.theme.as_syntect_theme()));
+        for (label, value) in lines {
+            if let Some(palette) = &palette {
+                self.buffer.set_color(&palette.label)?;
+                self.buffer.print(label)?;
+                self.buffer.set_color(&palette.separator)?;
+                self.buffer.print(":")?;
+                self.buffer.reset()?;
+                self.buffer.print(" ")?;
+                self.buffer.set_color(&palette.value)?;
+                self.buffer.print(&value)?;
+                self.buffer.reset()?;
+                self.buffer.print("\n")?;
+            } else {
+                self.buffer.print(&format!("{label}: {value}\n"))?;
+            }
         }
 
         self.buffer.print("\n")?;
@@ -503,6 +1132,12 @@ enum ContentType {
     Xml,
     JavaScript,
     Css,
+    Yaml,
+    GraphQl,
+    Sql,
+    Shell,
+    Toml,
+    Diff,
     Text,
     UrlencodedForm,
     Multipart,
@@ -519,6 +1154,12 @@ impl ContentType {
             | ContentType::Xml
             | ContentType::JavaScript
             | ContentType::Css
+            | ContentType::Yaml
+            | ContentType::GraphQl
+            | ContentType::Sql
+            | ContentType::Shell
+            | ContentType::Toml
+            | ContentType::Diff
             | ContentType::Text
             | ContentType::EventStream => true,
         }
@@ -531,6 +1172,12 @@ impl ContentType {
             | ContentType::Xml
             | ContentType::JavaScript
             | ContentType::Css
+            | ContentType::Yaml
+            | ContentType::GraphQl
+            | ContentType::Sql
+            | ContentType::Shell
+            | ContentType::Toml
+            | ContentType::Diff
             | ContentType::Text
             | ContentType::UrlencodedForm
             | ContentType::Multipart
@@ -541,6 +1188,12 @@ impl ContentType {
 
 impl From<&str> for ContentType {
     fn from(content_type: &str) -> Self {
+        // Short names like "sh" and "sql" would match too much as substrings
+        let subtype = content_type
+            .split(';')
+            .next()
+            .and_then(|essence| essence.trim().split_once('/'))
+            .map_or("", |(_, subtype)| subtype);
         if content_type.contains("json") {
             ContentType::Json
         } else if content_type.contains("html") {
@@ -555,6 +1208,18 @@ impl From<&str> for ContentType {
             ContentType::JavaScript
         } else if content_type.contains("css") {
             ContentType::Css
+        } else if content_type.contains("yaml") {
+            ContentType::Yaml
+        } else if subtype == "graphql" {
+            ContentType::GraphQl
+        } else if matches!(subtype, "sql" | "x-sql") {
+            ContentType::Sql
+        } else if matches!(subtype, "x-sh" | "x-shellscript") {
+            ContentType::Shell
+        } else if matches!(subtype, "toml" | "x-toml") {
+            ContentType::Toml
+        } else if matches!(subtype, "x-diff" | "x-patch") {
+            ContentType::Diff
         } else if content_type.contains("event-stream") {
             ContentType::EventStream
         } else if content_type.contains("text") {
@@ -576,10 +1241,101 @@ fn get_content_type(headers: &HeaderMap) -> ContentType {
         .map_or(ContentType::Unknown, ContentType::from)
 }
 
+/// Run a shell command with `input` on its stdin and collect its stdout.
+/// A reader that keeps a copy of everything that's read through it.
+struct CopyingReader<'a, R> {
+    inner: R,
+    copy: Option<&'a mut Vec<u8>>,
+}
+
+impl<R: Read> Read for CopyingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if let Some(copy) = &mut self.copy {
+            copy.extend_from_slice(&buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+fn pipe_through(command: &str, input: &mut impl Read) -> anyhow::Result<Vec<u8>> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = process::Command::new(shell)
+        .args([flag, command])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Unable to run --pipe command {command:?}"))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    thread::scope(move |scope| {
+        let reader = scope.spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+        // The command may exit without reading all of its input (e.g. `head`)
+        match io::copy(input, &mut stdin) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => drop(stdin),
+        }
+        let output = reader.join().expect("reader thread panicked")?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(anyhow!("--pipe command {command:?} failed ({status})"));
+        }
+        Ok(output)
+    })
+}
+
+/// The largest index up to `limit` that doesn't split a UTF-8 sequence,
+/// unless the bytes aren't UTF-8 to begin with.
+fn utf8_boundary(bytes: &[u8], limit: usize) -> usize {
+    match std::str::from_utf8(&bytes[..limit]) {
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        _ => limit,
+    }
+}
+
 fn valid_json(text: &str) -> bool {
     serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
 }
 
+/// Guess the syntax of a plain text body.
+/// JSON is checked separately by [`valid_json`].
+fn sniff_syntax(text: &str) -> Option<&'static str> {
+    let text = text.trim_start_matches('\u{feff}').trim();
+    let starts_with = |prefix: &str| {
+        text.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    };
+
+    if starts_with("<!doctype html") || starts_with("<html") {
+        Some("html")
+    } else if starts_with("<?xml")
+        || (text.starts_with('<')
+            && text[1..].starts_with(|c: char| c.is_alphabetic() || c == '!')
+            && text.ends_with('>'))
+    {
+        Some("xml")
+    } else if starts_with("---") || text.contains('\n') {
+        // Most plain text is a valid YAML string, so only count it when
+        // it's a mapping or a list
+        let docs = yaml_rust::YamlLoader::load_from_str(text).ok()?;
+        let structured = !docs.is_empty()
+            && docs
+                .iter()
+                .all(|doc| matches!(doc, yaml_rust::Yaml::Hash(_) | yaml_rust::Yaml::Array(_)));
+        structured.then_some("yaml")
+    } else {
+        None
+    }
+}
+
 /// Decode a response, using BOM sniffing or chardet if the encoding is unknown.
 ///
 /// This is different from [`Response::text`], which assumes UTF-8 as a fallback.
@@ -651,8 +1407,8 @@ fn decode_stream<'a>(
     };
     // We could set .utf8_passthru(true) to not sanitize invalid UTF-8. It would
     // arrive more faithfully in the terminal.
-    // But that has questionable benefit and writing invalid UTF-8 to stdout
-    // causes an error on Windows (because the console is UTF-16).
+    // But that has questionable benefit, and on Windows it would only be
+    // sanitized again on its way to the console (which is UTF-16).
     let reader = DecodeReaderBytesBuilder::new()
         .encoding(Some(encoding))
         .build(reader);
@@ -703,8 +1459,8 @@ fn get_tld(domain: &str) -> Option<&str> {
 /// reqwest doesn't provide an API for this, and we don't want a fixed default.
 ///
 /// See https://github.com/seanmonstar/reqwest/blob/2940740493/src/async_impl/response.rs#L172
-fn get_charset(response: &Response) -> Option<&'static Encoding> {
-    let content_type = response.headers().get(CONTENT_TYPE)?.to_str().ok()?;
+fn get_charset(headers: &HeaderMap) -> Option<&'static Encoding> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
     let mime: Mime = content_type.parse().ok()?;
     let encoding_name = mime.get_param("charset")?.as_str();
     Encoding::for_label(encoding_name.as_bytes())
@@ -732,6 +1488,18 @@ mod tests {
         dir.to_str().unwrap().to_owned()
     }
 
+    #[test]
+    fn summary_helpers() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(12482), "12,482");
+        assert_eq!(group_digits(1234567), "1,234,567");
+
+        assert_eq!(utf8_boundary("abc".as_bytes(), 2), 2);
+        assert_eq!(utf8_boundary("aé".as_bytes(), 2), 1);
+        assert_eq!(utf8_boundary(b"\xff\xfe\xfd", 2), 2);
+    }
+
     #[test]
     fn terminal_mode() {
         let p = run_cmd(vec_of_strings!["xh", "httpbin.org/get"], true);
@@ -800,4 +1568,52 @@ mod tests {
         assert_eq!(p.color, true);
         assert!(p.buffer.is_stderr());
     }
+
+    #[test]
+    fn sniff_body_syntax() {
+        assert_eq!(sniff_syntax("<!DOCTYPE html>\n<html></html>"), Some("html"));
+        assert_eq!(sniff_syntax("<?xml version=\"1.0\"?><a/>"), Some("xml"));
+        assert_eq!(sniff_syntax("  <feed><entry/></feed>\n"), Some("xml"));
+        assert_eq!(sniff_syntax("---\nname: xh\n"), Some("yaml"));
+        assert_eq!(sniff_syntax("name: xh\ntags:\n  - http\n"), Some("yaml"));
+        assert_eq!(sniff_syntax("name: xh"), None);
+        assert_eq!(sniff_syntax("Hello!\nGoodbye!"), None);
+        assert_eq!(sniff_syntax("<3 xh"), None);
+    }
+
+    #[test]
+    fn content_type_subtypes() {
+        assert!(matches!(
+            ContentType::from("text/x-diff; charset=utf-8"),
+            ContentType::Diff
+        ));
+        assert!(matches!(
+            ContentType::from("application/graphql"),
+            ContentType::GraphQl
+        ));
+        assert!(matches!(
+            ContentType::from("application/graphql-response+json"),
+            ContentType::Json
+        ));
+        assert!(matches!(
+            ContentType::from("application/sql"),
+            ContentType::Sql
+        ));
+        assert!(matches!(
+            ContentType::from("application/x-sh"),
+            ContentType::Shell
+        ));
+        assert!(matches!(
+            ContentType::from("application/toml"),
+            ContentType::Toml
+        ));
+        assert!(matches!(
+            ContentType::from("application/x-sqlite3"),
+            ContentType::Unknown
+        ));
+        assert!(matches!(
+            ContentType::from("application/x-shockwave-flash"),
+            ContentType::Unknown
+        ));
+    }
 }
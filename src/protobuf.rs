@@ -0,0 +1,627 @@
+//! --proto, which encodes the request body as a protobuf message and decodes
+//! protobuf responses back to JSON, to talk to gRPC-web gateways and other
+//! protobuf APIs.
+//!
+//! The message types come from a `.proto` file, compiled with protox: the last
+//! two segments of the URL path name the service and the method, as in gRPC
+//! (`/package.Service/Method`). Imports are looked up next to the file. The
+//! well-known types and the HTTP annotations from `google/api` are built in.
+//! JSON follows the proto3 JSON mapping, so
+//! fields can be given by their original or lowerCamelCase names, 64-bit
+//! integers are printed as strings, and bytes are base64.
+//!
+//! Compiling `.proto` files needs the `protobuf` feature. Without it, --proto
+//! is rejected.
+
+#[cfg(feature = "protobuf")]
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use percent_encoding::percent_decode_str;
+#[cfg(feature = "protobuf")]
+use prost::Message;
+#[cfg(feature = "protobuf")]
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor};
+#[cfg(feature = "protobuf")]
+use protox::file::{
+    ChainFileResolver, File, FileResolver, GoogleFileResolver, IncludeFileResolver,
+};
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+#[cfg(feature = "protobuf")]
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::request_items::Body;
+
+#[cfg(feature = "protobuf")]
+pub const DEFAULT_CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+/// The flag on a gRPC-web frame that holds the trailers instead of a message.
+const TRAILERS_FLAG: u8 = 0x80;
+const COMPRESSED_FLAG: u8 = 0x01;
+
+const STATUS_CODES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+/// A method from a `.proto` file, ready to encode requests and decode
+/// responses.
+#[cfg(feature = "protobuf")]
+#[derive(Clone)]
+pub struct Call {
+    method: MethodDescriptor,
+    content_type: String,
+}
+
+#[cfg(feature = "protobuf")]
+impl Call {
+    /// Load `path` and pick the method that `url` points to. The request is
+    /// framed according to `content_type`.
+    pub fn load(path: &Path, url: &Url, content_type: Option<&str>) -> Result<Call> {
+        let pool = compile(path)?;
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        let methods: Vec<MethodDescriptor> = pool
+            .services()
+            .flat_map(|service| service.methods().collect::<Vec<_>>())
+            .collect();
+        let method = match segments[..] {
+            [.., service, method] => methods.iter().find(|candidate| {
+                let candidate_service = candidate.parent_service();
+                candidate.name() == method
+                    && (candidate_service.full_name() == service
+                        || !service.contains('.') && candidate_service.name() == service)
+            }),
+            _ => None,
+        };
+        let Some(method) = method else {
+            let available: Vec<String> = methods
+                .iter()
+                .map(|method| format!("/{}/{}", method.parent_service().full_name(), method.name()))
+                .collect();
+            return Err(anyhow!(
+                "The URL path {} doesn't name a method from {}. Available methods: {}",
+                url.path(),
+                path.display(),
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            ));
+        };
+        Ok(Call {
+            method: method.clone(),
+            content_type: content_type.unwrap_or(DEFAULT_CONTENT_TYPE).to_string(),
+        })
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// Encode a JSON body as the request message.
+    pub fn encode_request(&self, body: Body) -> Result<Vec<u8>> {
+        let value = match body {
+            Body::Json(Value::Null) => Value::Object(Map::new()),
+            Body::Json(value) => value,
+            Body::Raw(raw) => parse_json(&raw)?,
+            Body::File { file_name, .. } => parse_json(
+                &fs::read(&file_name)
+                    .with_context(|| format!("Couldn't read {}", file_name.display()))?,
+            )?,
+            _ => return Err(anyhow!("--proto only works with JSON request bodies")),
+        };
+        let input = self.method.input();
+        let message = DynamicMessage::deserialize(input.clone(), value)
+            .with_context(|| format!("Couldn't encode the request as {}", input.full_name()))?;
+        Ok(frame(
+            &message.encode_to_vec(),
+            Framing::from(self.content_type.as_str()),
+        ))
+    }
+
+    /// Decode a request body that [`Call::encode_request`] produced.
+    pub fn decode_request(&self, body: &[u8]) -> Result<Vec<Value>> {
+        let framing = Framing::from(self.content_type.as_str());
+        let (messages, _) = unframe(body, framing)?;
+        messages
+            .iter()
+            .map(|message| decode_message(self.method.input(), message))
+            .collect()
+    }
+
+    /// Decode the messages in a response body with this content type.
+    pub fn decode_response(&self, body: &[u8], content_type: &str) -> Result<Vec<Value>> {
+        let output = self.method.output();
+        let (messages, _) = unframe(body, Framing::from(content_type))?;
+        messages
+            .iter()
+            .map(|message| decode_message(output.clone(), message))
+            .collect::<Result<_>>()
+            .with_context(|| format!("Couldn't decode the response as {}", output.full_name()))
+    }
+}
+
+/// Without the `protobuf` feature there's no way to make a call.
+#[cfg(not(feature = "protobuf"))]
+#[derive(Clone)]
+pub enum Call {}
+
+#[cfg(not(feature = "protobuf"))]
+impl Call {
+    pub fn load(_path: &Path, _url: &Url, _content_type: Option<&str>) -> Result<Call> {
+        Err(anyhow!("This binary was built without protobuf support"))
+    }
+
+    pub fn content_type(&self) -> &str {
+        match *self {}
+    }
+
+    pub fn encode_request(&self, _body: Body) -> Result<Vec<u8>> {
+        match *self {}
+    }
+
+    pub fn decode_request(&self, _body: &[u8]) -> Result<Vec<Value>> {
+        match *self {}
+    }
+
+    pub fn decode_response(&self, _body: &[u8], _content_type: &str) -> Result<Vec<Value>> {
+        match *self {}
+    }
+}
+
+/// Compile a `.proto` file and everything it imports.
+#[cfg(feature = "protobuf")]
+fn compile(path: &Path) -> Result<DescriptorPool> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(IncludeFileResolver::new(dir.to_path_buf()));
+    resolver.add(GoogleFileResolver::new());
+    resolver.add(GoogleApiResolver);
+    let mut compiler = protox::Compiler::with_file_resolver(resolver);
+    compiler.include_imports(true);
+    compiler
+        .open_file(path)
+        .with_context(|| format!("Couldn't load {}", path.display()))?;
+    Ok(compiler.descriptor_pool())
+}
+
+/// Serves `google/api/annotations.proto`, which gRPC services often import
+/// for their HTTP mappings without shipping it. Only the parts needed to
+/// compile `option (google.api.http)` are included.
+#[cfg(feature = "protobuf")]
+struct GoogleApiResolver;
+
+#[cfg(feature = "protobuf")]
+const GOOGLE_API_ANNOTATIONS: &str = r#"
+    syntax = "proto3";
+    package google.api;
+
+    import "google/api/http.proto";
+    import "google/protobuf/descriptor.proto";
+
+    extend google.protobuf.MethodOptions {
+        HttpRule http = 72295728;
+    }
+"#;
+
+#[cfg(feature = "protobuf")]
+const GOOGLE_API_HTTP: &str = r#"
+    syntax = "proto3";
+    package google.api;
+
+    message Http {
+        repeated HttpRule rules = 1;
+        bool fully_decode_reserved_expansion = 2;
+    }
+
+    message HttpRule {
+        string selector = 1;
+        oneof pattern {
+            string get = 2;
+            string put = 3;
+            string post = 4;
+            string delete = 5;
+            string patch = 6;
+            CustomHttpPattern custom = 8;
+        }
+        string body = 7;
+        string response_body = 12;
+        repeated HttpRule additional_bindings = 11;
+    }
+
+    message CustomHttpPattern {
+        string kind = 1;
+        string path = 2;
+    }
+"#;
+
+#[cfg(feature = "protobuf")]
+impl FileResolver for GoogleApiResolver {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        match name {
+            "google/api/annotations.proto" => File::from_source(name, GOOGLE_API_ANNOTATIONS),
+            "google/api/http.proto" => File::from_source(name, GOOGLE_API_HTTP),
+            _ => Err(protox::Error::file_not_found(name)),
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+fn decode_message(descriptor: MessageDescriptor, bytes: &[u8]) -> Result<Value> {
+    let message = DynamicMessage::decode(descriptor, bytes)?;
+    // Going through text keeps floats short: a Value would widen them to f64,
+    // which turns 0.1 into 0.10000000149011612
+    Ok(serde_json::from_slice(&serde_json::to_vec(&message)?)?)
+}
+
+#[cfg(feature = "protobuf")]
+fn parse_json(body: &[u8]) -> Result<Value> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Value::Object(Map::new()));
+    }
+    serde_json::from_slice(body).context("The request body must be JSON to encode it with --proto")
+}
+
+/// Whether a body with this content type holds protobuf messages.
+pub fn is_protobuf(content_type: &str) -> bool {
+    content_type.contains("grpc") || content_type.contains("protobuf")
+}
+
+/// Describe the gRPC error in a response, if any. The status is in the
+/// trailers at the end of a gRPC-web body, or in the headers if the server
+/// had nothing else to send.
+pub fn response_error(headers: &HeaderMap, body: &[u8]) -> Option<String> {
+    let content_type = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let mut status = header_str(headers, "grpc-status").map(str::to_string);
+    let mut message = header_str(headers, "grpc-message").map(str::to_string);
+    if status.is_none() && content_type.contains("grpc") {
+        if let Ok((_, trailers)) = unframe(body, Framing::from(content_type)) {
+            for (name, value) in trailers {
+                match name.as_str() {
+                    "grpc-status" => status = Some(value),
+                    "grpc-message" => message = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+    let code: usize = status?.trim().parse().ok()?;
+    if code == 0 {
+        return None;
+    }
+    let name = STATUS_CODES.get(code).copied().unwrap_or("UNKNOWN");
+    let message = message.map(|message| {
+        percent_decode_str(&message)
+            .decode_utf8_lossy()
+            .into_owned()
+    });
+    Some(match message.filter(|message| !message.is_empty()) {
+        Some(message) => format!("{name} ({code}): {message}"),
+        None => format!("{name} ({code})"),
+    })
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// A bare message.
+    Plain,
+    /// Length-prefixed messages, followed by trailers in gRPC-web.
+    Grpc,
+    /// The same, base64 encoded.
+    GrpcText,
+}
+
+impl From<&str> for Framing {
+    fn from(content_type: &str) -> Self {
+        if content_type.contains("grpc-web-text") {
+            Framing::GrpcText
+        } else if content_type.contains("grpc") {
+            Framing::Grpc
+        } else {
+            Framing::Plain
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+fn frame(message: &[u8], framing: Framing) -> Vec<u8> {
+    if framing == Framing::Plain {
+        return message.to_vec();
+    }
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    match framing {
+        Framing::GrpcText => BASE64_STANDARD.encode(framed).into_bytes(),
+        _ => framed,
+    }
+}
+
+type Trailers = Vec<(String, String)>;
+
+/// Split a body into its messages and trailers.
+fn unframe(body: &[u8], framing: Framing) -> Result<(Vec<Vec<u8>>, Trailers)> {
+    let decoded;
+    let mut body = match framing {
+        Framing::Plain => return Ok((vec![body.to_vec()], Vec::new())),
+        Framing::Grpc => body,
+        Framing::GrpcText => {
+            // Each message may be encoded separately, padding and all
+            let text: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            let mut bytes = Vec::new();
+            for chunk in text.chunks(4) {
+                bytes.extend(
+                    BASE64_STANDARD
+                        .decode(chunk)
+                        .context("Invalid base64 in a grpc-web-text body")?,
+                );
+            }
+            decoded = bytes;
+            &decoded
+        }
+    };
+    let mut messages = Vec::new();
+    let mut trailers = Vec::new();
+    while !body.is_empty() {
+        if body.len() < 5 {
+            return Err(anyhow!("Truncated gRPC frame"));
+        }
+        let flags = body[0];
+        let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+        let payload = body.get(5..5 + len).context("Truncated gRPC frame")?;
+        body = &body[5 + len..];
+        if flags & TRAILERS_FLAG != 0 {
+            for line in String::from_utf8_lossy(payload).split("\r\n") {
+                if let Some((name, value)) = line.split_once(':') {
+                    trailers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                }
+            }
+        } else if flags & COMPRESSED_FLAG != 0 {
+            return Err(anyhow!("Compressed gRPC messages aren't supported"));
+        } else {
+            messages.push(payload.to_vec());
+        }
+    }
+    Ok((messages, trailers))
+}
+
+#[cfg(all(test, feature = "protobuf"))]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    const PROTO: &str = r#"
+        syntax = "proto3";
+        package shop.v1;
+
+        import "google/api/annotations.proto";
+        import "google/protobuf/empty.proto";
+        option go_package = "example.com/shop";
+
+        // Lookups by id
+        service Orders {
+            rpc GetOrder (GetOrderRequest) returns (Order);
+            rpc Echo (Order) returns (Order);
+            rpc Watch (stream GetOrderRequest) returns (stream Order) {
+                option (google.api.http) = { get: "/v1/orders/{id}" };
+            }
+        }
+
+        message GetOrderRequest {
+            int64 order_id = 1;
+        }
+
+        message Order {
+            enum Status {
+                STATUS_UNSPECIFIED = 0;
+                SHIPPED = 2;
+            }
+            message Line {
+                string sku = 1;
+                uint32 quantity = 2;
+            }
+            int64 order_id = 1;
+            Status status = 2;
+            repeated Line lines = 3;
+            repeated sint32 deltas = 4;
+            map<string, double> prices = 5;
+            bytes signature = 6 [json_name = "sig"];
+            oneof contact {
+                string email = 7;
+                float score = 8;
+            }
+            reserved 9, 10;
+        }
+    "#;
+
+    fn load(path: &str) -> Result<Call> {
+        load_as(path, None)
+    }
+
+    fn load_as(path: &str, content_type: Option<&str>) -> Result<Call> {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("shop.proto");
+        fs::write(&file, PROTO).unwrap();
+        Call::load(&file, &Url::parse(path).unwrap(), content_type)
+    }
+
+    #[test]
+    fn picks_method_from_path() {
+        let call = load("https://example.com/api/shop.v1.Orders/GetOrder").unwrap();
+        assert_eq!(call.method.input().full_name(), "shop.v1.GetOrderRequest");
+        assert_eq!(call.method.output().full_name(), "shop.v1.Order");
+        assert!(load("https://example.com/Orders/Watch").is_ok());
+        let err = load("https://example.com/shop.v1.Orders/Cancel")
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("/shop.v1.Orders/GetOrder, /shop.v1.Orders/Echo, /shop.v1.Orders/Watch"));
+    }
+
+    #[test]
+    fn encodes_requests() {
+        let call = load("https://example.com/shop.v1.Orders/GetOrder").unwrap();
+        let framed = call
+            .encode_request(Body::Json(json!({"order_id": 150})))
+            .unwrap();
+        assert_eq!(framed, [0, 0, 0, 0, 3, 0x08, 0x96, 0x01]);
+        let framed = call
+            .encode_request(Body::Raw(br#"{"orderId": "-1"}"#.to_vec()))
+            .unwrap();
+        assert_eq!(framed.len(), 5 + 11);
+        assert_eq!(
+            call.decode_request(&framed).unwrap(),
+            [json!({"orderId": "-1"})]
+        );
+
+        let err = call
+            .encode_request(Body::Json(json!({"id": 1})))
+            .err()
+            .unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Couldn't encode the request as shop.v1.GetOrderRequest: unrecognized field name 'id'"
+        );
+        let err = call
+            .encode_request(Body::Json(json!({"order_id": 1.5})))
+            .err()
+            .unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Couldn't encode the request as shop.v1.GetOrderRequest: expected integer value"
+        );
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let call = load_as(
+            "https://example.com/shop.v1.Orders/Echo",
+            Some("application/x-protobuf"),
+        )
+        .unwrap();
+        let order = json!({
+            "orderId": "9007199254740993",
+            "status": "SHIPPED",
+            "lines": [{"sku": "A-1", "quantity": 2}, {"sku": "B-2"}],
+            "deltas": [-1, 1, -300],
+            "prices": {"A-1": 9.5},
+            "sig": "3q2+7w==",
+            "score": 0.1,
+        });
+        let encoded = call.encode_request(Body::Json(order.clone())).unwrap();
+        let decoded = call
+            .decode_response(&encoded, "application/x-protobuf")
+            .unwrap();
+        assert_eq!(decoded, [order]);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let call = load_as(
+            "https://example.com/shop.v1.Orders/GetOrder",
+            Some("application/x-protobuf"),
+        )
+        .unwrap();
+        // A string field that claims more bytes than there are
+        let err = call
+            .decode_response(&[0x3a, 5, b'a'], "application/x-protobuf")
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Couldn't decode the response as shop.v1.Order"));
+        let err = call
+            .decode_response(&[0, 0, 0, 0, 9, 0x08], "application/grpc")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Truncated gRPC frame");
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("broken.proto");
+        fs::write(
+            &file,
+            "syntax = \"proto3\";\nmessage A { Missing field = 1; }",
+        )
+        .unwrap();
+        let url = Url::parse("https://example.com/A/B").unwrap();
+        let err = Call::load(&file, &url, None).err().unwrap();
+        assert!(format!("{err:#}").contains("Missing"), "{err:#}");
+    }
+
+    #[test]
+    fn decodes_grpc_web_responses() {
+        let call = load("https://example.com/shop.v1.Orders/Watch").unwrap();
+        let mut body = vec![0, 0, 0, 0, 4, 0x08, 0x01, 0x10, 0x02];
+        body.extend([0, 0, 0, 0, 2, 0x10, 0x07]);
+        let trailers = b"grpc-status:5\r\ngrpc-message:no%20such%20order\r\n";
+        body.push(TRAILERS_FLAG);
+        body.extend((trailers.len() as u32).to_be_bytes());
+        body.extend(trailers);
+
+        let decoded = call
+            .decode_response(&body, "application/grpc-web+proto")
+            .unwrap();
+        assert_eq!(
+            decoded,
+            [
+                json!({"orderId": "1", "status": "SHIPPED"}),
+                json!({"status": 7})
+            ]
+        );
+        let text = BASE64_STANDARD.encode(&body);
+        let decoded_text = call
+            .decode_response(text.as_bytes(), "application/grpc-web-text+proto")
+            .unwrap();
+        assert_eq!(decoded, decoded_text);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-type",
+            "application/grpc-web+proto".parse().unwrap(),
+        );
+        assert_eq!(
+            response_error(&headers, &body).as_deref(),
+            Some("NOT_FOUND (5): no such order")
+        );
+        headers.insert("grpc-status", "0".parse().unwrap());
+        assert_eq!(response_error(&headers, &body), None);
+    }
+}
@@ -0,0 +1,951 @@
+//! Sending bytes over a plain TCP or TLS connection, bypassing hyper.
+//!
+//! hyper refuses to send requests with conflicting framing headers (such as
+//! both Content-Length and Transfer-Encoding), which is exactly what you want
+//! to send when testing a server for request smuggling. `--raw-headers` writes
+//! the request out by hand instead.
+//!
+//! `--expect-continue` and `--trailer` go the same way, because hyper sends
+//! the body right after the headers instead of waiting for the server to ask
+//! for it, and has no way to send trailers from the blocking client.
+//!
+//! `--http-raw` sends a request that was written by hand, and `xh rawsend`
+//! goes one step further and sends arbitrary bytes. Except for `xh rawsend`,
+//! the response is parsed afterwards, so it's printed like any other.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use percent_encoding::percent_decode_str;
+use reqwest::blocking::{Request, Response};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, HOST, TRANSFER_ENCODING,
+};
+use reqwest::{ResponseBuilderExt, Version};
+use url::{Position, Url};
+
+use crate::cli::{Proxy, RawSend, RawSendData, Trailer, Verify};
+use crate::utils::{copy_largebuf, BUFFER_SIZE};
+
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Connect to `host`, optionally over TLS.
+///
+/// The timeout applies to connecting and to every read and write.
+pub fn connect(
+    host: &str,
+    port: u16,
+    tls: Option<&Verify>,
+    timeout: Option<Duration>,
+) -> Result<Box<dyn Stream>> {
+    let stream = open_tcp(host, port, timeout)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    secure(host, stream, tls)
+}
+
+/// Connect to `host` for a hand-written HTTP request, through a CONNECT
+/// tunnel on `proxy` if given, and optionally over TLS.
+///
+/// The socket underneath is returned as well, so its timeouts can still be
+/// changed once it's wrapped in TLS.
+pub fn connect_http(
+    host: &str,
+    port: u16,
+    proxy: Option<&Url>,
+    tls: Option<&Verify>,
+    timeout: Option<Duration>,
+) -> Result<(Box<dyn Stream>, TcpStream)> {
+    let socket = match proxy {
+        Some(proxy) => tunnel(proxy, host, port, timeout)?,
+        None => open_tcp(host, port, timeout)?,
+    };
+    socket.set_read_timeout(timeout)?;
+    socket.set_write_timeout(timeout)?;
+    let handle = socket.try_clone()?;
+    Ok((secure(host, socket, tls)?, handle))
+}
+
+/// Pick the proxy from `--proxy` that applies to `url`. The last one given wins,
+/// like for the regular client.
+pub fn select_proxy<'a>(proxies: &'a [Proxy], url: &Url) -> Option<&'a Url> {
+    proxies.iter().rev().find_map(|proxy| match proxy {
+        Proxy::All(proxy) => Some(proxy),
+        Proxy::Http(proxy) if url.scheme() == "http" => Some(proxy),
+        Proxy::Https(proxy) if url.scheme() == "https" => Some(proxy),
+        _ => None,
+    })
+}
+
+/// Open a CONNECT tunnel to `host` on an HTTP proxy.
+fn tunnel(proxy: &Url, host: &str, port: u16, timeout: Option<Duration>) -> Result<TcpStream> {
+    if proxy.scheme() != "http" {
+        return Err(anyhow!(
+            "Only http:// proxies can be used for raw requests, not {proxy}"
+        ));
+    }
+    let proxy_host = proxy.host_str().unwrap_or_default();
+    let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+    let mut stream = open_tcp(proxy_host, proxy_port, timeout)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if !proxy.username().is_empty() {
+        let credentials = format!(
+            "{}:{}",
+            percent_decode_str(proxy.username()).decode_utf8_lossy(),
+            percent_decode_str(proxy.password().unwrap_or_default()).decode_utf8_lossy()
+        );
+        request += &format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            BASE64_STANDARD.encode(credentials)
+        );
+    }
+    request += "\r\n";
+    stream
+        .write_all(request.as_bytes())
+        .context("Failed to send the CONNECT request to the proxy")?;
+
+    let head = read_head(&mut stream)
+        .context("Failed to read the proxy's answer to CONNECT")?
+        .ok_or_else(|| anyhow!("The proxy didn't answer the CONNECT request"))?;
+    if status_code(&head) != Some(200) {
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        return Err(anyhow!("The proxy refused to connect: {status_line}"));
+    }
+    Ok(stream)
+}
+
+/// Read the head of an HTTP response, byte by byte so that nothing past it
+/// is consumed.
+///
+/// Returns `None` if the read timed out before anything arrived.
+fn read_head(stream: &mut dyn Read) -> io::Result<Option<Vec<u8>>> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => head.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) && head.is_empty() =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Some(head))
+}
+
+/// The status code in the head of a response.
+fn status_code(head: &[u8]) -> Option<u16> {
+    let status_line = head.split(|&byte| byte == b'\r').next()?;
+    let code = status_line.split(|&byte| byte == b' ').nth(1)?;
+    std::str::from_utf8(code).ok()?.parse().ok()
+}
+
+/// Read a complete request for `--http-raw` from a file, or from stdin if
+/// `path` is "-".
+pub fn read_request(path: &Path) -> Result<Vec<u8>> {
+    let data = if path == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        data
+    } else {
+        fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?
+    };
+    if data.is_empty() {
+        return Err(anyhow!("The raw request is empty"));
+    }
+    Ok(data)
+}
+
+/// Open a TCP connection to `host`, with `timeout` applying to connecting only.
+pub fn open_tcp(host: &str, port: u16, timeout: Option<Duration>) -> Result<TcpStream> {
+    let host = unbracket(host);
+    let stream = tcp_connect(host, port, timeout)
+        .with_context(|| format!("Failed to connect to {host}:{port}"))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Wrap a connection to `host` in TLS if `tls` is given.
+pub fn secure(host: &str, stream: TcpStream, tls: Option<&Verify>) -> Result<Box<dyn Stream>> {
+    match tls {
+        Some(verify) => tls_connect(unbracket(host), stream, verify),
+        None => Ok(Box::new(stream)),
+    }
+}
+
+/// IPv6 addresses come bracketed out of URLs.
+fn unbracket(host: &str) -> &str {
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn tcp_connect(host: &str, port: u16, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let Some(timeout) = timeout else {
+        return TcpStream::connect((host, port));
+    };
+    let mut last_err = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::other("host did not resolve to any address")))
+}
+
+#[cfg(feature = "rustls")]
+fn tls_connect(host: &str, stream: TcpStream, verify: &Verify) -> Result<Box<dyn Stream>> {
+    use std::sync::Arc;
+
+    use rustls::pki_types::ServerName;
+
+    let config = tls::client_config(verify)?;
+    let server_name =
+        ServerName::try_from(host.to_string()).with_context(|| format!("Invalid host {host}"))?;
+    let connection = rustls::ClientConnection::new(Arc::new(config), server_name)?;
+    Ok(Box::new(rustls::StreamOwned::new(connection, stream)))
+}
+
+#[cfg(not(feature = "rustls"))]
+fn tls_connect(_host: &str, _stream: TcpStream, _verify: &Verify) -> Result<Box<dyn Stream>> {
+    Err(anyhow!(
+        "Raw TLS connections need rustls, and this binary was built without rustls support"
+    ))
+}
+
+#[cfg(feature = "rustls")]
+mod tls {
+    use std::sync::Arc;
+
+    use anyhow::{Context, Result};
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+    use crate::cli::Verify;
+    use crate::utils::read_ca_certificates;
+
+    pub fn client_config(verify: &Verify) -> Result<ClientConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let mut roots = RootCertStore::empty();
+        let config = match verify {
+            Verify::Yes => {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                builder.with_root_certificates(roots)
+            }
+            Verify::No => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider))),
+            Verify::CustomCaBundle(path) | Verify::ExtraCaBundle(path) => {
+                if let Verify::ExtraCaBundle(_) = verify {
+                    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                }
+                for pem in read_ca_certificates(path)? {
+                    roots
+                        .add(CertificateDer::from(pem.into_contents()))
+                        .with_context(|| {
+                            format!("Failed to load the custom CA bundle: {}", path.display())
+                        })?;
+                }
+                builder.with_root_certificates(roots)
+            }
+        };
+        Ok(config.with_no_client_auth())
+    }
+
+    /// `--verify=no`: check that the handshake is signed properly, but trust
+    /// any certificate.
+    #[derive(Debug)]
+    struct AcceptAnyCert(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for AcceptAnyCert {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+/// Send `data` and read everything that comes back.
+///
+/// Reading stops when the server closes the connection, or when it goes
+/// quiet for longer than the timeout after having sent something.
+pub fn exchange(stream: &mut dyn Stream, data: &[u8]) -> Result<Vec<u8>> {
+    stream
+        .write_all(data)
+        .context("Failed to send the request")?;
+    stream.flush()?;
+    read_response(stream, Vec::new())
+}
+
+/// Send a request and read everything that comes back, like [`exchange`].
+pub fn exchange_request(stream: &mut dyn Stream, head: &[u8], body: RawBody) -> Result<Vec<u8>> {
+    stream
+        .write_all(head)
+        .context("Failed to send the request")?;
+    body.write_to(stream)?;
+    stream.flush()?;
+    read_response(stream, Vec::new())
+}
+
+/// Send a request with `Expect: 100-continue`, and only send its body once
+/// the server asks for it.
+///
+/// Servers that don't know about Expect never answer, so the body is sent
+/// anyway if nothing arrives within a second, like curl does. If the server answers with a
+/// final response right away, the body isn't sent at all.
+///
+/// Returns the heads of the interim (1xx) responses, followed by the final
+/// response.
+pub fn exchange_expect_continue(
+    stream: &mut dyn Stream,
+    socket: &TcpStream,
+    head: &[u8],
+    body: RawBody,
+) -> Result<(Vec<Vec<u8>>, Vec<u8>)> {
+    const WAIT: Duration = Duration::from_secs(1);
+
+    stream
+        .write_all(head)
+        .context("Failed to send the request")?;
+    stream.flush()?;
+
+    let timeout = socket.read_timeout()?;
+    socket.set_read_timeout(Some(timeout.map_or(WAIT, |timeout| timeout.min(WAIT))))?;
+    let mut interim = Vec::new();
+    let final_head = loop {
+        let Some(response_head) = read_head(stream).context("Failed to read the response")? else {
+            break None;
+        };
+        match status_code(&response_head) {
+            Some(100) => {
+                interim.push(response_head);
+                break None;
+            }
+            Some(100..=199) => interim.push(response_head),
+            _ => break Some(response_head),
+        }
+    };
+    socket.set_read_timeout(timeout)?;
+
+    let response = match final_head {
+        Some(response_head) => read_response(stream, response_head)?,
+        None => {
+            body.write_to(stream)?;
+            stream.flush()?;
+            read_response(stream, Vec::new())?
+        }
+    };
+    Ok((interim, response))
+}
+
+/// The trailer fields that followed a chunked response body.
+#[derive(Clone, Debug)]
+pub struct Trailers(pub HeaderMap);
+
+/// Turn a response that was read by hand into a [`Response`], so that it's
+/// printed and handled the same as one from the regular client.
+///
+/// The body is taken out of its chunked encoding, if any, and its trailers
+/// are kept as a [`Trailers`] extension. Anything that follows the end of the
+/// response is ignored.
+pub fn parse_response(data: &[u8], url: &Url) -> Result<Response> {
+    if data.is_empty() {
+        return Err(anyhow!(
+            "The server closed the connection without a response"
+        ));
+    }
+    let mut headers = [httparse::EMPTY_HEADER; 128];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let head_len = match parsed.parse(data) {
+        Ok(httparse::Status::Complete(head_len)) => head_len,
+        Ok(httparse::Status::Partial) => {
+            return Err(anyhow!("The response ended in the middle of its headers"))
+        }
+        Err(err) => return Err(anyhow!("The response isn't valid HTTP/1.x: {err}")),
+    };
+
+    let mut response = http::Response::builder()
+        .status(parsed.code.unwrap_or_default())
+        .version(match parsed.version {
+            Some(0) => Version::HTTP_10,
+            _ => Version::HTTP_11,
+        })
+        .url(url.clone());
+    for header in parsed.headers.iter() {
+        response = response.header(header.name, header.value);
+    }
+    // Like hyper, only keep the reason phrase if it's not the usual one
+    let canonical_reason = parsed
+        .code
+        .and_then(|code| reqwest::StatusCode::from_u16(code).ok())
+        .and_then(|status| status.canonical_reason());
+    if let Some(reason) = parsed
+        .reason
+        .filter(|&reason| Some(reason) != canonical_reason)
+    {
+        if let Ok(reason) = hyper::ext::ReasonPhrase::try_from(reason.as_bytes()) {
+            response = response.extension(reason);
+        }
+    }
+    let response_headers = response.headers_ref().cloned().unwrap_or_default();
+
+    let data = &data[head_len..];
+    let (body, rest) = match parsed.code {
+        Some(100..=199 | 204 | 304) => (Vec::new(), data),
+        _ if is_chunked(&response_headers) => {
+            let (body, trailers, rest) = decode_chunked(data)?;
+            if !trailers.is_empty() {
+                response = response.extension(Trailers(trailers));
+            }
+            (body, rest)
+        }
+        _ => match content_length(&response_headers) {
+            Some(len) if len < data.len() as u64 => {
+                let (body, rest) = data.split_at(len as usize);
+                (body.to_vec(), rest)
+            }
+            _ => (data.to_vec(), &b""[..]),
+        },
+    };
+    if !rest.is_empty() {
+        log::warn!(
+            "Ignoring {} bytes that came after the end of the response",
+            rest.len()
+        );
+    }
+    Ok(response.body(body)?.into())
+}
+
+/// Whether the last transfer coding of a message is chunked.
+fn is_chunked(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(TRANSFER_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .last()
+        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Decode a chunked body. Returns the body, the trailer fields, and whatever
+/// comes after the body.
+fn decode_chunked(mut data: &[u8]) -> Result<(Vec<u8>, HeaderMap, &[u8])> {
+    let mut body = Vec::new();
+    loop {
+        let (line, rest) = split_line(data)?;
+        let size = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| anyhow!("Invalid chunk size in the response"))?;
+        data = rest;
+        if size == 0 {
+            break;
+        }
+        if (data.len() as u64) < size {
+            return Err(anyhow!("The response ended in the middle of a chunk"));
+        }
+        let (chunk, rest) = data.split_at(size as usize);
+        body.extend_from_slice(chunk);
+        data = rest
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| anyhow!("Missing line ending after a chunk in the response"))?;
+    }
+
+    let mut fields = [httparse::EMPTY_HEADER; 64];
+    let (len, fields) = match httparse::parse_headers(data, &mut fields) {
+        Ok(httparse::Status::Complete(parsed)) => parsed,
+        Ok(httparse::Status::Partial) => {
+            return Err(anyhow!("The response ended in the middle of its trailers"))
+        }
+        Err(err) => return Err(anyhow!("Invalid trailer fields in the response: {err}")),
+    };
+    let mut trailers = HeaderMap::new();
+    for field in fields {
+        trailers.append(
+            HeaderName::from_bytes(field.name.as_bytes())?,
+            HeaderValue::from_bytes(field.value)?,
+        );
+    }
+    let data = &data[len..];
+    Ok((body, trailers, data))
+}
+
+/// Split off a line ending in CRLF.
+fn split_line(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    let end = memchr::memmem::find(data, b"\r\n")
+        .ok_or_else(|| anyhow!("The response ended in the middle of its chunked body"))?;
+    Ok((&data[..end], &data[end + 2..]))
+}
+
+/// Read the rest of a response onto `response`.
+fn read_response(stream: &mut dyn Stream, mut response: Vec<u8>) -> Result<Vec<u8>> {
+    let mut buf = [0; 16 * 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => response.extend_from_slice(&buf[..len]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) && !response.is_empty() =>
+            {
+                break
+            }
+            // Lots of servers hang up without a TLS close_notify
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err).context("Failed to read the response"),
+        }
+    }
+    Ok(response)
+}
+
+/// Run `xh rawsend`.
+///
+/// The reply is written out as is if stdout is redirected. Otherwise it's
+/// shown as text if that can be done faithfully, and as a hex dump if not.
+pub fn rawsend(
+    target: &RawSend,
+    tls: Option<&Verify>,
+    timeout: Option<Duration>,
+    is_terminal: bool,
+) -> Result<()> {
+    let data = match &target.data {
+        RawSendData::Text(text) => text.as_bytes().to_vec(),
+        RawSendData::File(path) => {
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?
+        }
+        RawSendData::Stdin => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            data
+        }
+    };
+    let mut stream = connect(&target.host, target.port, tls, timeout)?;
+    let response = exchange(&mut *stream, &data)?;
+
+    let mut stdout = io::stdout().lock();
+    if is_terminal && !is_printable(&response) {
+        write_hex_dump(&mut stdout, &response)?;
+    } else {
+        stdout.write_all(&response)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn is_printable(data: &[u8]) -> bool {
+    match std::str::from_utf8(data) {
+        Ok(text) => text
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\r' | '\n')),
+        Err(_) => false,
+    }
+}
+
+/// Write a dump in the style of `hexdump -C`.
+fn write_hex_dump(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    for (index, line) in data.chunks(16).enumerate() {
+        write!(out, "{:08x} ", index * 16)?;
+        for column in 0..16 {
+            if column % 8 == 0 {
+                write!(out, " ")?;
+            }
+            match line.get(column) {
+                Some(byte) => write!(out, "{byte:02x} ")?,
+                None => write!(out, "   ")?,
+            }
+        }
+        let text: String = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(out, " |{text}|")?;
+    }
+    writeln!(out, "{:08x}", data.len())
+}
+
+/// A request body that's streamed from a reader, which can still be taken
+/// back after the body was given to reqwest.
+///
+/// reqwest doesn't hand out the readers inside its bodies, so a request that's
+/// written out by hand has to keep a handle of its own.
+#[derive(Clone)]
+pub struct BodyReader {
+    reader: Arc<Mutex<Box<dyn Read + Send>>>,
+    len: Option<u64>,
+}
+
+impl BodyReader {
+    pub fn new(reader: Box<dyn Read + Send>, len: Option<u64>) -> Self {
+        BodyReader {
+            reader: Arc::new(Mutex::new(reader)),
+            len,
+        }
+    }
+
+    pub fn to_body(&self) -> reqwest::blocking::Body {
+        match self.len {
+            Some(len) => reqwest::blocking::Body::sized(self.clone(), len),
+            None => reqwest::blocking::Body::new(self.clone()),
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.lock().unwrap().read(buf)
+    }
+}
+
+/// The body of a request that's written out by hand.
+pub struct RawBody {
+    reader: Box<dyn Read + Send>,
+    chunked: bool,
+    trailers: Vec<Trailer>,
+}
+
+impl RawBody {
+    /// Send the body in chunks, followed by `trailers`.
+    pub fn with_trailers(mut self, trailers: &[Trailer]) -> Self {
+        self.chunked = true;
+        self.trailers = trailers.to_vec();
+        self
+    }
+
+    /// Write the body without reading all of it into memory first.
+    fn write_to(mut self, out: &mut dyn Write) -> Result<()> {
+        if !self.chunked {
+            copy_largebuf(&mut self.reader, &mut &mut *out, false)
+                .context("Failed to send the request body")?;
+            return Ok(());
+        }
+        let mut buf = vec![0; BUFFER_SIZE];
+        loop {
+            crate::interrupt::check()?;
+            let len = match self.reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err).context("Failed to read the request body"),
+            };
+            write!(out, "{len:x}\r\n")?;
+            out.write_all(&buf[..len])?;
+            out.write_all(b"\r\n")?;
+        }
+        out.write_all(b"0\r\n")?;
+        for trailer in &self.trailers {
+            out.write_all(title_case(trailer.name.as_str()).as_bytes())?;
+            out.write_all(b": ")?;
+            out.write_all(trailer.value.as_bytes())?;
+            out.write_all(b"\r\n")?;
+        }
+        out.write_all(b"\r\n")?;
+        Ok(())
+    }
+}
+
+/// Write out an HTTP/1.1 request exactly as it was built.
+///
+/// Headers are sent in order and without any sanitization, so duplicate or
+/// conflicting framing headers reach the server as given. A Host header and
+/// a Content-Length for the body are only added if the request has neither
+/// of its own.
+pub fn serialize_request(request: &mut Request) -> Result<Vec<u8>> {
+    let (mut data, body) = serialize_parts(request, None)?;
+    body.write_to(&mut data)?;
+    Ok(data)
+}
+
+/// Like [`serialize_request`], but keep the head and the body apart, so the
+/// body can be streamed.
+///
+/// `reader` is where the body is read from if reqwest hasn't buffered it.
+/// A body of unknown length is sent chunked, unless the request has framing
+/// headers of its own.
+pub fn serialize_parts(
+    request: &mut Request,
+    reader: Option<BodyReader>,
+) -> Result<(Vec<u8>, RawBody)> {
+    let url = request.url().clone();
+    let mut data = Vec::new();
+    write!(
+        data,
+        "{} {} HTTP/1.1\r\n",
+        request.method(),
+        &url[Position::BeforePath..Position::AfterQuery]
+    )?;
+
+    let headers = request.headers();
+    if !headers.contains_key(HOST) {
+        write!(
+            data,
+            "Host: {}\r\n",
+            &url[Position::BeforeHost..Position::AfterPort]
+        )?;
+    }
+    let close = HeaderValue::from_static("close");
+    for (name, value) in headers {
+        // We only ever read one response, so don't make the server wait for
+        // another request
+        let value = if name == CONNECTION && value == "keep-alive" {
+            &close
+        } else {
+            value
+        };
+        data.extend(title_case(name.as_str()).bytes());
+        data.extend(b": ");
+        data.extend(value.as_bytes());
+        data.extend(b"\r\n");
+    }
+    let has_framing =
+        headers.contains_key(CONTENT_LENGTH) || headers.contains_key(TRANSFER_ENCODING);
+
+    let (body, len): (Box<dyn Read + Send>, _) = match (request.body_mut(), reader) {
+        (Some(body), Some(reader)) if body.as_bytes().is_none() => {
+            let len = reader.len;
+            (Box::new(reader), len)
+        }
+        (Some(body), _) => {
+            let body = body
+                .buffer()
+                .map_err(|err| anyhow!(err).context("Failed to read the request body"))?
+                .to_vec();
+            let len = body.len() as u64;
+            (Box::new(io::Cursor::new(body)), Some(len))
+        }
+        (None, _) => (Box::new(io::empty()), Some(0)),
+    };
+    let mut chunked = false;
+    if !has_framing {
+        match len {
+            Some(0) => {}
+            Some(len) => write!(data, "Content-Length: {len}\r\n")?,
+            None => {
+                data.extend(b"Transfer-Encoding: chunked\r\n");
+                chunked = true;
+            }
+        }
+    }
+    data.extend(b"\r\n");
+    let body = RawBody {
+        reader: body,
+        chunked,
+        trailers: Vec::new(),
+    };
+    Ok((data, body))
+}
+
+/// Match what the regular client sends, since it's been told to use
+/// title case headers.
+fn title_case(name: &str) -> String {
+    let mut upper = true;
+    name.chars()
+        .map(|c| {
+            let c = if upper { c.to_ascii_uppercase() } else { c };
+            upper = c == '-';
+            c
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_conflicting_framing() {
+        let client = reqwest::blocking::Client::new();
+        let mut request = client
+            .post("http://example.com:8080/a?b=c")
+            .header(CONNECTION, "keep-alive")
+            .header(CONTENT_LENGTH, "6")
+            .header(TRANSFER_ENCODING, "chunked")
+            .header(TRANSFER_ENCODING, "identity")
+            .body("0\r\n\r\nX")
+            .build()
+            .unwrap();
+        let data = serialize_request(&mut request).unwrap();
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "POST /a?b=c HTTP/1.1\r\n\
+             Host: example.com:8080\r\n\
+             Connection: close\r\n\
+             Content-Length: 6\r\n\
+             Transfer-Encoding: chunked\r\n\
+             Transfer-Encoding: identity\r\n\
+             \r\n\
+             0\r\n\r\nX"
+        );
+    }
+
+    #[test]
+    fn parse_raw_response() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let response = parse_response(
+            b"HTTP/1.1 404 Not Found\r\n\
+              Set-Cookie: a=1\r\n\
+              Transfer-Encoding: chunked\r\n\
+              \r\n\
+              3\r\nabc\r\n0\r\n\r\n",
+            &url,
+        )
+        .unwrap();
+        assert_eq!(response.status(), 404);
+        assert_eq!(response.headers()["set-cookie"], "a=1");
+        assert_eq!(response.url(), &url);
+        assert_eq!(response.text().unwrap(), "abc");
+
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhiHTTP/1.1 200 OK\r\n\r\n",
+            &url,
+        )
+        .unwrap();
+        assert_eq!(response.text().unwrap(), "hi");
+
+        assert!(parse_response(b"", &url).is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nServer: x", &url).is_err());
+        assert!(parse_response(b"SSH-2.0-OpenSSH_9.6\r\n", &url).is_err());
+    }
+
+    #[test]
+    fn hex_dump() {
+        let mut out = Vec::new();
+        write_hex_dump(&mut out, b"\x16\x03\x01 hello, world!\r\n").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "00000000  16 03 01 20 68 65 6c 6c  6f 2c 20 77 6f 72 6c 64  |... hello, world|\n\
+             00000010  21 0d 0a                                          |!..|\n\
+             00000013\n"
+        );
+        assert!(is_printable(b"HTTP/1.1 200 OK\r\n\r\n"));
+        assert!(!is_printable(b"\x16\x03\x01"));
+        assert!(!is_printable(b"\xff"));
+    }
+
+    #[test]
+    fn serialize_adds_content_length() {
+        let client = reqwest::blocking::Client::new();
+        let mut request = client
+            .put("http://[::1]/")
+            .header("x-custom-header", "1")
+            .body("hello")
+            .build()
+            .unwrap();
+        let data = serialize_request(&mut request).unwrap();
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "PUT / HTTP/1.1\r\nHost: [::1]\r\nX-Custom-Header: 1\r\nContent-Length: 5\r\n\r\nhello"
+        );
+    }
+
+    #[test]
+    fn stream_chunked_body_with_trailers() {
+        let client = reqwest::blocking::Client::new();
+        let reader = BodyReader::new(Box::new(&b"hello"[..]), None);
+        let mut request = client
+            .post("http://example.com/")
+            .body(reader.to_body())
+            .build()
+            .unwrap();
+        let (head, body) = serialize_parts(&mut request, Some(reader)).unwrap();
+        assert_eq!(
+            String::from_utf8(head).unwrap(),
+            "POST / HTTP/1.1\r\nHost: example.com\r\nTransfer-Encoding: chunked\r\n\r\n"
+        );
+        let trailers: Vec<Trailer> = vec!["x-checksum:abc".parse().unwrap()];
+        let mut data = Vec::new();
+        body.with_trailers(&trailers).write_to(&mut data).unwrap();
+        assert_eq!(data, b"5\r\nhello\r\n0\r\nX-Checksum: abc\r\n\r\n");
+    }
+
+    #[test]
+    fn decode_chunked_body() {
+        let (body, trailers, rest) =
+            decode_chunked(b"3;ext=1\r\nabc\r\n1\r\nd\r\n0\r\nX-Sum: 9\r\n\r\nextra").unwrap();
+        assert_eq!(body, b"abcd");
+        assert_eq!(trailers["x-sum"], "9");
+        assert_eq!(rest, b"extra");
+
+        assert!(decode_chunked(b"5\r\nabc").is_err());
+        assert!(decode_chunked(b"zz\r\n").is_err());
+    }
+}
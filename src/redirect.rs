@@ -1,3 +1,6 @@
+use std::thread;
+use std::time::Duration;
+
 use anyhow::{anyhow, Result};
 use reqwest::blocking::{Request, Response};
 use reqwest::header::{
@@ -7,15 +10,50 @@ use reqwest::header::{
 use reqwest::{Method, StatusCode, Url};
 
 use crate::middleware::{Context, Middleware};
+use crate::retry::{is_retry_safe, is_transient};
 use crate::utils::{clone_request, HeaderValueExt};
 
+/// How many times a failed hop is sent again before giving up on the chain.
+const RESUME_ATTEMPTS: u32 = 2;
+const RESUME_DELAY: Duration = Duration::from_millis(250);
+
 pub struct RedirectFollower {
     max_redirects: usize,
+    retry_all_methods: bool,
 }
 
 impl RedirectFollower {
-    pub fn new(max_redirects: usize) -> Self {
-        RedirectFollower { max_redirects }
+    pub fn new(max_redirects: usize, retry_all_methods: bool) -> Self {
+        RedirectFollower {
+            max_redirects,
+            retry_all_methods,
+        }
+    }
+
+    /// Send a redirected request again after a transient error, so the chain
+    /// continues from the hop that failed instead of failing as a whole. The
+    /// cookies from earlier hops are still in the cookie jar.
+    fn resume(
+        &self,
+        ctx: &mut Context,
+        request: &mut Request,
+        mut err: anyhow::Error,
+    ) -> Result<Response> {
+        for attempt in 1..=RESUME_ATTEMPTS {
+            if !is_transient(&err) || !is_retry_safe(request.method(), self.retry_all_methods) {
+                break;
+            }
+            log::warn!(
+                "Redirect to {} failed ({err:#}), resuming from there ({attempt}/{RESUME_ATTEMPTS})",
+                request.url()
+            );
+            thread::sleep(RESUME_DELAY * attempt);
+            match self.next(ctx, clone_request(request)?) {
+                Ok(response) => return Ok(response),
+                Err(next_err) => err = next_err,
+            }
+        }
+        Err(err)
     }
 }
 
@@ -41,7 +79,10 @@ impl Middleware for RedirectFollower {
             log::trace!("{next_request:#?}");
             self.print(&mut ctx, &mut response, &mut next_request)?;
             request = clone_request(&mut next_request)?;
-            response = self.next(&mut ctx, next_request)?;
+            response = match self.next(&mut ctx, next_request) {
+                Ok(response) => response,
+                Err(err) => self.resume(&mut ctx, &mut request, err)?,
+            };
         }
 
         Ok(response)
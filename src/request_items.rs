@@ -1,17 +1,22 @@
 use std::{
     borrow::Cow,
+    collections::hash_map::RandomState,
     collections::HashSet,
+    fmt,
     fs::{self, File},
-    io,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read},
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{blocking::multipart, Method};
+use reqwest::Method;
 
-use crate::cli::BodyType;
+use crate::cli::{BodyType, InputFormat};
 use crate::nested_json;
 use crate::utils::{expand_tilde, unescape};
 
@@ -19,6 +24,8 @@ pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
 pub const JSON_CONTENT_TYPE: &str = "application/json";
 pub const JSON_ACCEPT: &str = "application/json, */*;q=0.5";
 
+const SPECIAL_CHARS: &str = "=@:;\\";
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RequestItem {
     HttpHeader(String, String),
@@ -35,6 +42,8 @@ pub enum RequestItem {
         key: String,
         raw_key: String,
         value: String,
+        /// Send the file's content base64-encoded (`key=@file;base64`).
+        base64: bool,
     },
     JsonField(String, serde_json::Value),
     JsonFieldFromFile(String, String),
@@ -46,43 +55,74 @@ pub enum RequestItem {
     },
 }
 
-impl FromStr for RequestItem {
-    type Err = clap::Error;
-    fn from_str(request_item: &str) -> clap::error::Result<RequestItem> {
-        const SPECIAL_CHARS: &str = "=@:;\\";
-        const SEPS: &[&str] = &["==@", "=@", ":=@", ":@", "==", ":=", "=", "@", ":"];
-
-        fn split(request_item: &str) -> Option<(&str, &'static str, &str)> {
-            let mut char_inds = request_item.char_indices();
-            while let Some((ind, ch)) = char_inds.next() {
-                if ch == '\\' {
-                    // If the next character is special it's escaped and can't be
-                    // the start of the separator
-                    // And if it's normal it can't be the start either
-                    // Just skip it without looking
-                    char_inds.next();
-                    continue;
-                }
-                for sep in SEPS {
-                    if let Some(value) = request_item[ind..].strip_prefix(sep) {
-                        let key = &request_item[..ind];
-                        return Some((key, sep, value));
-                    }
-                }
+/// Split a request item into its key, separator and value.
+pub fn split_request_item(request_item: &str) -> Option<(&str, &'static str, &str)> {
+    const SEPS: &[&str] = &["==@", "=@", ":=@", ":@", "==", ":=", "=", "@", ":"];
+
+    let mut char_inds = request_item.char_indices();
+    while let Some((ind, ch)) = char_inds.next() {
+        if ch == '\\' {
+            // If the next character is special it's escaped and can't be
+            // the start of the separator
+            // And if it's normal it can't be the start either
+            // Just skip it without looking
+            char_inds.next();
+            continue;
+        }
+        for sep in SEPS {
+            if let Some(value) = request_item[ind..].strip_prefix(sep) {
+                let key = &request_item[..ind];
+                return Some((key, sep, value));
             }
-            None
         }
+    }
+    None
+}
+
+/// Strip a `;base64` or `;b64` modifier from the end of a raw value, unless
+/// its semicolon is escaped.
+fn strip_base64_modifier(raw_value: &str) -> Option<&str> {
+    let stripped = raw_value
+        .strip_suffix(";base64")
+        .or_else(|| raw_value.strip_suffix(";b64"))?;
+    let backslashes = stripped.len() - stripped.trim_end_matches('\\').len();
+    (backslashes % 2 == 0).then_some(stripped)
+}
 
-        if let Some((raw_key, sep, value)) = split(request_item) {
+impl FromStr for RequestItem {
+    type Err = clap::Error;
+    fn from_str(request_item: &str) -> clap::error::Result<RequestItem> {
+        if let Some((raw_key, sep, raw_value)) = split_request_item(request_item) {
             let raw_key = raw_key.to_string();
             let key = unescape(&raw_key, SPECIAL_CHARS);
-            let value = unescape(value, SPECIAL_CHARS);
+            let value = unescape(raw_value, SPECIAL_CHARS);
+            // Query parameter names support the same nested syntax as JSON
+            // paths, but the empty name is left alone
+            let query_key = || -> clap::error::Result<String> {
+                if raw_key.is_empty() {
+                    return Ok(key.clone());
+                }
+                nested_json::query_key(&raw_key).map_err(|err| {
+                    clap::Error::raw(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!(
+                            "Invalid value for '[REQUEST_ITEM]...': {:?} {}",
+                            request_item, err
+                        ),
+                    )
+                })
+            };
             match sep {
-                "==" => Ok(RequestItem::UrlParam(key, value)),
+                "==" => Ok(RequestItem::UrlParam(query_key()?, value)),
                 "=" => Ok(RequestItem::DataField {
                     key,
                     raw_key,
-                    value,
+                    value: match strip_base64_modifier(raw_value) {
+                        Some(raw_value) => {
+                            BASE64_STANDARD.encode(unescape(raw_value, SPECIAL_CHARS))
+                        }
+                        None => value,
+                    },
                 }),
                 ":=" => Ok(RequestItem::JsonField(
                     raw_key,
@@ -111,12 +151,19 @@ impl FromStr for RequestItem {
                 }
                 ":" if value.is_empty() => Ok(RequestItem::HttpHeaderToUnset(key)),
                 ":" => Ok(RequestItem::HttpHeader(key, value)),
-                "==@" => Ok(RequestItem::UrlParamFromFile(key, value)),
-                "=@" => Ok(RequestItem::DataFieldFromFile {
-                    key,
-                    raw_key,
-                    value,
-                }),
+                "==@" => Ok(RequestItem::UrlParamFromFile(query_key()?, value)),
+                "=@" => {
+                    let (value, base64) = match strip_base64_modifier(raw_value) {
+                        Some(raw_value) => (unescape(raw_value, SPECIAL_CHARS), true),
+                        None => (value, false),
+                    };
+                    Ok(RequestItem::DataFieldFromFile {
+                        key,
+                        raw_key,
+                        value,
+                        base64,
+                    })
+                }
                 ":=@" => Ok(RequestItem::JsonFieldFromFile(raw_key, value)),
                 ":@" => Ok(RequestItem::HttpHeaderFromFile(key, value)),
                 _ => unreachable!(),
@@ -136,6 +183,78 @@ impl FromStr for RequestItem {
     }
 }
 
+/// Format the item so that parsing it produces the same item again.
+impl fmt::Display for RequestItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn escape_key(key: &str) -> String {
+            let mut out = String::with_capacity(key.len());
+            for ch in key.chars() {
+                if SPECIAL_CHARS.contains(ch) {
+                    out.push('\\');
+                }
+                out.push(ch);
+            }
+            out
+        }
+        fn escape_value(value: &str) -> String {
+            value.replace('\\', "\\\\")
+        }
+
+        match self {
+            RequestItem::HttpHeader(key, value) if value.is_empty() => write!(f, "{key};"),
+            RequestItem::HttpHeader(key, value) => {
+                write!(f, "{}:{}", escape_key(key), escape_value(value))
+            }
+            RequestItem::HttpHeaderFromFile(key, value) => {
+                write!(f, "{}:@{}", escape_key(key), escape_value(value))
+            }
+            RequestItem::HttpHeaderToUnset(key) => write!(f, "{}:", escape_key(key)),
+            RequestItem::UrlParam(key, value) => {
+                write!(f, "{}=={}", escape_key(key), escape_value(value))
+            }
+            RequestItem::UrlParamFromFile(key, value) => {
+                write!(f, "{}==@{}", escape_key(key), escape_value(value))
+            }
+            RequestItem::DataField { raw_key, value, .. } => {
+                write!(f, "{}={}", raw_key, escape_value(value))
+            }
+            RequestItem::DataFieldFromFile {
+                raw_key,
+                value,
+                base64,
+                ..
+            } => {
+                write!(f, "{}=@{}", raw_key, escape_value(value))?;
+                if *base64 {
+                    write!(f, ";base64")?;
+                }
+                Ok(())
+            }
+            RequestItem::JsonField(raw_key, value) => {
+                write!(f, "{}:={}", raw_key, escape_value(&value.to_string()))
+            }
+            RequestItem::JsonFieldFromFile(raw_key, value) => {
+                write!(f, "{}:=@{}", raw_key, escape_value(value))
+            }
+            RequestItem::FormFile {
+                key,
+                file_name,
+                file_type,
+                file_name_header,
+            } => {
+                write!(f, "{}@{}", escape_key(key), escape_value(file_name))?;
+                if let Some(file_type) = file_type {
+                    write!(f, ";type={file_type}")?;
+                }
+                if let Some(file_name_header) = file_name_header {
+                    write!(f, ";filename={file_name_header}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 struct PartWithParams {
     value: String,
@@ -210,12 +329,16 @@ fn rsplit_once_any<'a>(
 pub struct RequestItems {
     pub items: Vec<RequestItem>,
     pub body_type: BodyType,
+    pub multipart_boundary: Option<String>,
+    pub input_format: InputFormat,
+    /// Whether to guess the Content-Type of a `@file` body.
+    pub content_type_guess: bool,
 }
 
 pub enum Body {
     Json(serde_json::Value),
     Form(Vec<(String, String)>),
-    Multipart(multipart::Form),
+    Multipart(MultipartForm),
     Raw(Vec<u8>),
     File {
         file_name: PathBuf,
@@ -257,6 +380,15 @@ impl RequestItems {
             .any(|item| matches!(item, RequestItem::FormFile { .. }))
     }
 
+    /// Whether there's a `@file` item to use as the entire request body.
+    pub fn has_body_file(&self) -> bool {
+        self.body_type == BodyType::Json
+            && self
+                .items
+                .iter()
+                .any(|item| matches!(item, RequestItem::FormFile { key, .. } if key.is_empty()))
+    }
+
     pub fn headers(&self) -> Result<(HeaderMap<HeaderValue>, HashSet<HeaderName>)> {
         let mut headers = HeaderMap::new();
         #[allow(clippy::mutable_key_type)]
@@ -293,7 +425,7 @@ impl RequestItems {
         Ok((headers, headers_to_unset))
     }
 
-    pub fn query(&self) -> Result<Vec<(&str, Cow<str>)>> {
+    pub fn query(&self) -> Result<Vec<(&str, Cow<'_, str>)>> {
         let mut query: Vec<(&str, Cow<str>)> = vec![];
         for item in &self.items {
             if let RequestItem::UrlParam(key, value) = item {
@@ -306,9 +438,11 @@ impl RequestItems {
         Ok(query)
     }
 
-    fn body_as_json(self) -> Result<Body> {
+    /// Build a JSON body from the data items, inserting them into `base` if
+    /// there is one.
+    fn body_as_json(self, base: Option<serde_json::Value>) -> Result<Body> {
         use serde_json::Value;
-        let mut body = None;
+        let mut body = base;
         for item in self.items {
             let (raw_key, value) = match item {
                 RequestItem::JsonField(raw_key, value) => (raw_key, value),
@@ -317,11 +451,14 @@ impl RequestItems {
                     (raw_key, value)
                 }
                 RequestItem::DataField { raw_key, value, .. } => (raw_key, Value::String(value)),
-                RequestItem::DataFieldFromFile { raw_key, value, .. } => {
-                    let value = fs::read_to_string(expand_tilde(value))?;
-                    (raw_key, Value::String(value))
-                }
-                RequestItem::FormFile { .. } => unreachable!(),
+                RequestItem::DataFieldFromFile {
+                    raw_key,
+                    value,
+                    base64,
+                    ..
+                } => (raw_key, Value::String(read_data_file(&value, base64)?)),
+                // The file that `base` was read from
+                RequestItem::FormFile { .. } => continue,
                 RequestItem::HttpHeader(..)
                 | RequestItem::HttpHeaderFromFile(..)
                 | RequestItem::HttpHeaderToUnset(..)
@@ -344,9 +481,10 @@ impl RequestItems {
                     return Err(anyhow!("JSON values are not supported in Form fields"));
                 }
                 RequestItem::DataField { key, value, .. } => text_fields.push((key, value)),
-                RequestItem::DataFieldFromFile { key, value, .. } => {
-                    let path = expand_tilde(value);
-                    text_fields.push((key, fs::read_to_string(path)?));
+                RequestItem::DataFieldFromFile {
+                    key, value, base64, ..
+                } => {
+                    text_fields.push((key, read_data_file(&value, base64)?));
                 }
                 RequestItem::FormFile { .. } => unreachable!(),
                 RequestItem::HttpHeader(..) => {}
@@ -360,18 +498,19 @@ impl RequestItems {
     }
 
     fn body_as_multipart(self) -> Result<Body> {
-        let mut form = multipart::Form::new();
+        let mut form = MultipartForm::new(self.multipart_boundary);
         for item in self.items {
             match item {
                 RequestItem::JsonField(..) | RequestItem::JsonFieldFromFile(..) => {
                     return Err(anyhow!("JSON values are not supported in multipart fields"));
                 }
                 RequestItem::DataField { key, value, .. } => {
-                    form = form.text(key, value);
+                    form.text(key, value);
                 }
-                RequestItem::DataFieldFromFile { key, value, .. } => {
-                    let path = expand_tilde(value);
-                    form = form.text(key, fs::read_to_string(path)?);
+                RequestItem::DataFieldFromFile {
+                    key, value, base64, ..
+                } => {
+                    form.text(key, read_data_file(&value, base64)?);
                 }
                 RequestItem::FormFile {
                     key,
@@ -381,12 +520,15 @@ impl RequestItems {
                 } => {
                     let mut part = file_to_part(expand_tilde(file_name))?;
                     if let Some(file_type) = file_type {
-                        part = part.mime_str(&file_type)?;
+                        file_type
+                            .parse::<mime::Mime>()
+                            .with_context(|| format!("Invalid MIME type {file_type:?}"))?;
+                        part.mime = Some(file_type);
                     }
                     if let Some(file_name_header) = file_name_header {
-                        part = part.file_name(file_name_header);
+                        part.file_name = Some(file_name_header);
                     }
-                    form = form.part(key, part);
+                    form.part(key, part);
                 }
                 RequestItem::HttpHeader(..) => {}
                 RequestItem::HttpHeaderFromFile(..) => {}
@@ -409,16 +551,23 @@ impl RequestItems {
                 "Can't use file fields in JSON mode (perhaps you meant --form?)"
             ));
         }
+        if self.items.iter().any(|item| {
+            matches!(
+                item,
+                RequestItem::DataField { .. }
+                    | RequestItem::JsonField(..)
+                    | RequestItem::DataFieldFromFile { .. }
+                    | RequestItem::JsonFieldFromFile(..)
+            )
+        }) {
+            return self.body_merged_into_file();
+        }
         for item in self.items {
             match item {
                 RequestItem::DataField { .. }
                 | RequestItem::JsonField(..)
                 | RequestItem::DataFieldFromFile { .. }
-                | RequestItem::JsonFieldFromFile(..) => {
-                    return Err(anyhow!(
-                        "Request body (from a file) and request data (key=value) cannot be mixed."
-                    ));
-                }
+                | RequestItem::JsonFieldFromFile(..) => unreachable!(),
                 RequestItem::FormFile {
                     key,
                     file_name,
@@ -429,13 +578,25 @@ impl RequestItems {
                     if body.is_some() {
                         return Err(anyhow!("Can't read request from multiple files"));
                     }
+                    if self.input_format != InputFormat::Json {
+                        let path = expand_tilde(file_name);
+                        let content = fs::read(&path)
+                            .with_context(|| format!("Failed to read {}", path.display()))?;
+                        body = Some(Body::Json(parse_input(&content, self.input_format)?));
+                        continue;
+                    }
+                    let file_name = expand_tilde(file_name);
+                    let file_type = match file_type {
+                        Some(file_type) => Some(HeaderValue::from_str(&file_type)?),
+                        None if self.content_type_guess => mime_guess::from_path(&file_name)
+                            .first_raw()
+                            .or_else(|| sniff_mime_type(&file_name))
+                            .map(HeaderValue::from_static),
+                        None => None,
+                    };
                     body = Some(Body::File {
-                        file_type: file_type
-                            .as_deref()
-                            .or_else(|| mime_guess::from_path(&file_name).first_raw())
-                            .map(HeaderValue::from_str)
-                            .transpose()?,
-                        file_name: expand_tilde(file_name),
+                        file_type,
+                        file_name,
                         file_name_header,
                     });
                 }
@@ -450,13 +611,32 @@ impl RequestItems {
         Ok(body)
     }
 
+    /// Use a JSON file as the base of the body and merge the data items into
+    /// it, e.g. `@base.json nested[override]=x`.
+    fn body_merged_into_file(self) -> Result<Body> {
+        let mut files = self.items.iter().filter_map(|item| match item {
+            RequestItem::FormFile { file_name, .. } => Some(file_name),
+            _ => None,
+        });
+        let file_name = files.next().expect("Should have had a file field");
+        if files.next().is_some() {
+            return Err(anyhow!("Can't read request from multiple files"));
+        }
+        let path = expand_tilde(file_name);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let base = parse_input(content.as_bytes(), self.input_format)
+            .with_context(|| format!("Can't merge request data into {}", path.display()))?;
+        self.body_as_json(Some(base))
+    }
+
     pub fn body(self) -> Result<Body> {
         match self.body_type {
             BodyType::Multipart => self.body_as_multipart(),
             BodyType::Form if self.has_form_files() => self.body_as_multipart(),
             BodyType::Form => self.body_as_form(),
             BodyType::Json if self.has_form_files() => self.body_from_file(),
-            BodyType::Json => self.body_as_json(),
+            BodyType::Json => self.body_as_json(None),
         }
     }
 
@@ -506,26 +686,354 @@ impl RequestItems {
     }
 }
 
-pub fn file_to_part(path: impl AsRef<Path>) -> io::Result<multipart::Part> {
+pub fn file_to_part(path: impl AsRef<Path>) -> io::Result<Part> {
     let path = path.as_ref();
     let file_name = path
         .file_name()
         .map(|file_name| file_name.to_string_lossy().to_string());
     let file = File::open(path)?;
     let file_length = file.metadata()?.len();
-    let mut part = multipart::Part::reader_with_length(file, file_length);
-    if let Some(file_name) = file_name {
-        part = part.file_name(file_name);
+    Ok(Part {
+        content: PartContent::File(file, file_length),
+        file_name,
+        mime: None,
+    })
+}
+
+/// Read the file of a `key=@file` item as text, or base64-encode it.
+fn read_data_file(path: &str, base64: bool) -> Result<String> {
+    let path = expand_tilde(path);
+    let content = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if base64 {
+        return Ok(BASE64_STANDARD.encode(content));
+    }
+    String::from_utf8(content).map_err(|_| {
+        anyhow!(
+            "{} is not valid UTF-8 text. Use KEY=@{};base64 to send it base64-encoded",
+            path.display(),
+            path.display()
+        )
+    })
+}
+
+/// Magic bytes at the start of common binary formats, and their offset.
+const MAGIC_BYTES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (0, b"II*\0", "image/tiff"),
+    (0, b"MM\0*", "image/tiff"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\0", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\0asm", "application/wasm"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"ID3", "audio/mpeg"),
+    (8, b"WAVE", "audio/wav"),
+    (4, b"ftyp", "video/mp4"),
+];
+
+/// Guess the MIME type of a file without an informative extension from its
+/// first few bytes.
+///
+/// Only regular files are read, since even opening a pipe would consume the
+/// writer that's supposed to send the body.
+fn sniff_mime_type(path: &Path) -> Option<&'static str> {
+    if !fs::metadata(path).ok()?.is_file() {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    let mut start = Vec::with_capacity(16);
+    file.take(16).read_to_end(&mut start).ok()?;
+    sniff_bytes(&start)
+}
+
+fn sniff_bytes(start: &[u8]) -> Option<&'static str> {
+    MAGIC_BYTES.iter().find_map(|&(offset, magic, mime)| {
+        let riff = offset == 8 && !start.starts_with(b"RIFF");
+        let matches = start.get(offset..offset + magic.len()) == Some(magic);
+        (matches && !riff).then_some(mime)
+    })
+}
+
+/// Parse a request body in the given format into JSON.
+pub fn parse_input(input: &[u8], format: InputFormat) -> Result<serde_json::Value> {
+    match format {
+        InputFormat::Json => serde_json::from_slice(input).context("Body is not valid JSON"),
+        InputFormat::Yaml => {
+            let input = std::str::from_utf8(input).context("YAML body is not valid UTF-8")?;
+            let mut docs = yaml_rust::YamlLoader::load_from_str(input)
+                .map_err(|err| anyhow!("Body is not valid YAML: {err}"))?;
+            if docs.len() > 1 {
+                return Err(anyhow!("Body contains multiple YAML documents"));
+            }
+            yaml_to_json(docs.pop().unwrap_or(yaml_rust::Yaml::Null))
+        }
+    }
+}
+
+fn yaml_to_json(yaml: yaml_rust::Yaml) -> Result<serde_json::Value> {
+    use serde_json::Value;
+    use yaml_rust::Yaml;
+
+    Ok(match yaml {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(value) => Value::Bool(value),
+        Yaml::Integer(value) => Value::from(value),
+        Yaml::Real(ref text) => yaml
+            .as_f64()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("Can't represent {text} in JSON"))?,
+        Yaml::String(value) => Value::String(value),
+        Yaml::Array(items) => items.into_iter().map(yaml_to_json).collect::<Result<_>>()?,
+        Yaml::Hash(hash) => {
+            let mut map = serde_json::Map::new();
+            for (key, value) in hash {
+                // JSON only has string keys
+                let key = match key {
+                    Yaml::String(key) | Yaml::Real(key) => key,
+                    Yaml::Integer(key) => key.to_string(),
+                    Yaml::Boolean(key) => key.to_string(),
+                    Yaml::Null => "null".to_string(),
+                    key => return Err(anyhow!("Unsupported YAML key {key:?}")),
+                };
+                map.insert(key, yaml_to_json(value)?);
+            }
+            Value::Object(map)
+        }
+        Yaml::Alias(..) | Yaml::BadValue => return Err(anyhow!("Unsupported YAML value")),
+    })
+}
+
+/// Characters that are percent-encoded in field names, the same set that
+/// reqwest uses.
+const NAME_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'`')
+    .add(b'?')
+    .add(b'{')
+    .add(b'}')
+    .add(b'/')
+    .add(b'%');
+
+/// A multipart/form-data body.
+///
+/// reqwest's own multipart support always picks a random boundary, so this
+/// is built by hand to let `--multipart-boundary` make the body reproducible.
+/// Parts are written in the order they were added.
+pub struct MultipartForm {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+pub struct Part {
+    content: PartContent,
+    file_name: Option<String>,
+    mime: Option<String>,
+}
+
+enum PartContent {
+    Text(String),
+    File(File, u64),
+}
+
+impl MultipartForm {
+    pub fn new(boundary: Option<String>) -> MultipartForm {
+        MultipartForm {
+            boundary: boundary.unwrap_or_else(random_boundary),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn text(&mut self, name: String, value: String) {
+        let part = Part {
+            content: PartContent::Text(value),
+            file_name: None,
+            mime: None,
+        };
+        self.parts.push((name, part));
+    }
+
+    pub fn part(&mut self, name: String, part: Part) {
+        self.parts.push((name, part));
+    }
+
+    pub fn content_type(&self) -> String {
+        // Some characters that are allowed in boundaries have to be quoted
+        if self
+            .boundary
+            .contains(['(', ')', ',', '/', ':', '=', '?', ' '])
+        {
+            format!("multipart/form-data; boundary=\"{}\"", self.boundary)
+        } else {
+            format!("multipart/form-data; boundary={}", self.boundary)
+        }
+    }
+
+    /// The encoded form and its length.
+    pub fn into_reader(self) -> (Box<dyn Read + Send>, u64) {
+        let mut length = 0;
+        let mut reader: Box<dyn Read + Send> = Box::new(io::empty());
+        for (name, part) in self.parts {
+            let head = format!(
+                "--{}\r\n{}\r\n\r\n",
+                self.boundary,
+                part_headers(&name, &part)
+            );
+            length += head.len() as u64 + 2;
+            let content: Box<dyn Read + Send> = match part.content {
+                PartContent::Text(text) => {
+                    length += text.len() as u64;
+                    Box::new(io::Cursor::new(text))
+                }
+                PartContent::File(file, file_length) => {
+                    length += file_length;
+                    Box::new(file.take(file_length))
+                }
+            };
+            reader = Box::new(
+                reader
+                    .chain(io::Cursor::new(head))
+                    .chain(content)
+                    .chain(&b"\r\n"[..]),
+            );
+        }
+        if length > 0 {
+            let tail = format!("--{}--\r\n", self.boundary);
+            length += tail.len() as u64;
+            reader = Box::new(reader.chain(io::Cursor::new(tail)));
+        }
+        (reader, length)
+    }
+}
+
+fn part_headers(name: &str, part: &Part) -> String {
+    let encoded = percent_encode(name.as_bytes(), NAME_ENCODE_SET).to_string();
+    let mut headers = if encoded == name {
+        format!("Content-Disposition: form-data; name=\"{name}\"")
+    } else {
+        format!("Content-Disposition: form-data; name*=utf-8''{encoded}")
+    };
+    if let Some(file_name) = &part.file_name {
+        let file_name = file_name
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\r', "\\\r")
+            .replace('\n', "\\\n");
+        headers.push_str(&format!("; filename=\"{file_name}\""));
+    }
+    if let Some(mime) = &part.mime {
+        headers.push_str(&format!("\r\nContent-Type: {mime}"));
     }
-    Ok(part)
+    headers
+}
+
+fn random_boundary() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    format!(
+        "{:016x}-{:016x}-{:016x}-{:016x}",
+        random(),
+        random(),
+        random(),
+        random()
+    )
+}
+
+/// Read headers from a file with a `Name: value` pair on each line.
+pub fn read_headers_file(path: &Path) -> Result<Vec<RequestItem>> {
+    let path = expand_tilde(path);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read headers file {}", path.display()))?;
+    parse_headers_file(&content).with_context(|| format!("Invalid headers file {}", path.display()))
+}
+
+/// Blank lines and lines starting with `#` are skipped. So are HTTP/2
+/// pseudo-headers like `:authority:`, which show up when copying request
+/// headers from browser devtools.
+///
+/// As on the command line, `Name:` unsets a header and `Name;` sends it with
+/// an empty value.
+fn parse_headers_file(content: &str) -> Result<Vec<RequestItem>> {
+    let mut headers = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(':') {
+            continue;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => match line.strip_suffix(';') {
+                Some(name) => (name.trim(), None),
+                None => return Err(anyhow!("Missing ':' on line {}", line_number + 1)),
+            },
+        };
+        HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name on line {}", line_number + 1))?;
+        headers.push(match value {
+            Some("") => RequestItem::HttpHeaderToUnset(name.to_string()),
+            Some(value) => {
+                HeaderValue::from_str(value)
+                    .with_context(|| format!("Invalid header value on line {}", line_number + 1))?;
+                RequestItem::HttpHeader(name.to_string(), value.to_string())
+            }
+            None => RequestItem::HttpHeader(name.to_string(), String::new()),
+        });
+    }
+    Ok(headers)
+}
+
+/// Read querystring parameters from a file with a `name=value` pair on each line,
+/// like a `.env` file.
+pub fn read_query_file(path: &Path) -> Result<Vec<RequestItem>> {
+    let path = expand_tilde(path);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read query file {}", path.display()))?;
+    parse_query_file(&content).with_context(|| format!("Invalid query file {}", path.display()))
+}
+
+/// Blank lines and lines starting with `#` are skipped, as is an `export`
+/// prefix. Values may be wrapped in single or double quotes, which are removed.
+/// Nothing else is unescaped, so a value can't span multiple lines.
+fn parse_query_file(content: &str) -> Result<Vec<RequestItem>> {
+    let mut params = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Missing '=' on line {}", line_number + 1))?;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+        params.push(RequestItem::UrlParam(
+            key.trim().to_string(),
+            value.to_string(),
+        ));
+    }
+    Ok(params)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use serde_json::json;
-
     #[test]
     fn request_item_parsing() {
         use serde_json::json;
@@ -551,7 +1059,33 @@ mod tests {
             DataFieldFromFile {
                 key: "foo".into(),
                 raw_key: "foo".into(),
-                value: "data.json".into()
+                value: "data.json".into(),
+                base64: false,
+            }
+        );
+        assert_eq!(
+            parse(r"foo=bar;b64"),
+            DataField {
+                key: "foo".into(),
+                raw_key: "foo".into(),
+                value: "YmFy".into()
+            }
+        );
+        assert_eq!(
+            parse(r"foo=bar\;b64"),
+            DataField {
+                key: "foo".into(),
+                raw_key: "foo".into(),
+                value: "bar;b64".into()
+            }
+        );
+        assert_eq!(
+            parse("foo=@image.png;base64"),
+            DataFieldFromFile {
+                key: "foo".into(),
+                raw_key: "foo".into(),
+                value: "image.png".into(),
+                base64: true,
             }
         );
         // URL param
@@ -741,4 +1275,153 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn query_file_parsing() {
+        let params = parse_query_file(
+            "# signed request\n\
+             \n\
+             X-Amz-Algorithm=AWS4-HMAC-SHA256\n\
+             export X-Amz-Date = \"20240101T000000Z\"\n\
+             empty=\n\
+             sig='a=b'\n",
+        )
+        .unwrap();
+        assert_eq!(
+            params,
+            vec![
+                RequestItem::UrlParam("X-Amz-Algorithm".into(), "AWS4-HMAC-SHA256".into()),
+                RequestItem::UrlParam("X-Amz-Date".into(), "20240101T000000Z".into()),
+                RequestItem::UrlParam("empty".into(), "".into()),
+                RequestItem::UrlParam("sig".into(), "a=b".into()),
+            ]
+        );
+
+        parse_query_file("no-equals-sign").unwrap_err();
+    }
+
+    #[test]
+    fn headers_file_parsing() {
+        let headers = parse_headers_file(
+            ":authority: example.com\n\
+             # comment\n\
+             \n\
+             Accept: text/html, */*\n\
+             User-Agent:\n\
+             X-Empty;\n\
+             Cookie: a=b; c=d\n",
+        )
+        .unwrap();
+        assert_eq!(
+            headers,
+            vec![
+                RequestItem::HttpHeader("Accept".into(), "text/html, */*".into()),
+                RequestItem::HttpHeaderToUnset("User-Agent".into()),
+                RequestItem::HttpHeader("X-Empty".into(), "".into()),
+                RequestItem::HttpHeader("Cookie".into(), "a=b; c=d".into()),
+            ]
+        );
+
+        parse_headers_file("no colon").unwrap_err();
+        parse_headers_file("bad name: value").unwrap_err();
+    }
+
+    #[test]
+    fn request_item_display_roundtrip() {
+        for text in [
+            "X-Foo:bar",
+            "X-Empty;",
+            "X-Unset:",
+            "X-File:@header.txt",
+            r"q\=x==a=b",
+            "q==@query.txt",
+            r"a[b]=c:\\d",
+            "a=@data.txt",
+            "a=@image.png;base64",
+            r#"a:={"b":"c"}"#,
+            "a:=@data.json",
+            "f@file.txt;type=text/plain;filename=other.txt",
+            "@body.json",
+        ] {
+            let item: RequestItem = text.parse().unwrap();
+            let formatted = item.to_string();
+            assert_eq!(formatted.parse::<RequestItem>().unwrap(), item, "{text}");
+        }
+        assert_eq!(
+            RequestItem::UrlParam("a=b".into(), r"c\d".into()).to_string(),
+            r"a\=b==c\\d"
+        );
+    }
+
+    #[test]
+    fn multipart_form_body() {
+        let mut form = MultipartForm::new(Some("xyz".into()));
+        form.text("a".into(), "1".into());
+        form.text("b c".into(), "2".into());
+        form.part(
+            "f".into(),
+            Part {
+                content: PartContent::Text("hi".into()),
+                file_name: Some("a\"b.txt".into()),
+                mime: Some("text/plain".into()),
+            },
+        );
+        assert_eq!(form.content_type(), "multipart/form-data; boundary=xyz");
+        let (mut reader, length) = form.into_reader();
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        assert_eq!(body.len() as u64, length);
+        assert_eq!(
+            body,
+            concat!(
+                "--xyz\r\n",
+                "Content-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n",
+                "--xyz\r\n",
+                "Content-Disposition: form-data; name*=utf-8''b%20c\r\n\r\n2\r\n",
+                "--xyz\r\n",
+                "Content-Disposition: form-data; name=\"f\"; filename=\"a\\\"b.txt\"\r\n",
+                "Content-Type: text/plain\r\n\r\nhi\r\n",
+                "--xyz--\r\n",
+            )
+        );
+
+        let form = MultipartForm::new(Some("a b".into()));
+        assert_eq!(form.content_type(), "multipart/form-data; boundary=\"a b\"");
+        let (mut reader, length) = form.into_reader();
+        assert_eq!(io::copy(&mut reader, &mut io::sink()).unwrap(), 0);
+        assert_eq!(length, 0);
+    }
+
+    #[test]
+    fn yaml_input() {
+        let value = parse_input(
+            b"name: a\nitems: [1, 2.5, true, ~]\n3: three\nnested:\n  key: value\n",
+            InputFormat::Yaml,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "a",
+                "items": [1, 2.5, true, null],
+                "3": "three",
+                "nested": {"key": "value"},
+            })
+        );
+
+        parse_input(b"a: [", InputFormat::Yaml).unwrap_err();
+        parse_input(b"a: 1\n---\nb: 2\n", InputFormat::Yaml).unwrap_err();
+        parse_input(b"a: .inf", InputFormat::Yaml).unwrap_err();
+    }
+
+    #[test]
+    fn mime_type_sniffing() {
+        assert_eq!(sniff_bytes(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff_bytes(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_bytes(b"RIFX\0\0\0\0WEBPVP8 "), None);
+        assert_eq!(sniff_bytes(b"\0\0\0\x20ftypisom"), Some("video/mp4"));
+        assert_eq!(sniff_bytes(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff_bytes(b"{\"a\": 1}"), None);
+        assert_eq!(sniff_bytes(b""), None);
+    }
 }
@@ -0,0 +1,168 @@
+use std::io;
+
+use anyhow::Result;
+use reqwest::blocking::{Request, Response};
+use reqwest::Method;
+use url::Url;
+
+use crate::middleware::{Context, Middleware};
+
+/// Retry a request once if it failed because the server closed the
+/// connection, e.g. because a keep-alive connection went stale.
+///
+/// Only idempotent requests are retried, unless `all_methods` is set, as the
+/// server may have processed the request before closing the connection.
+pub struct RetryStaleConnection {
+    all_methods: bool,
+}
+
+impl RetryStaleConnection {
+    pub fn new(all_methods: bool) -> Self {
+        RetryStaleConnection { all_methods }
+    }
+}
+
+impl Middleware for RetryStaleConnection {
+    fn handle(&mut self, mut ctx: Context, request: Request) -> Result<Response> {
+        if !is_retry_safe(request.method(), self.all_methods) {
+            return self.next(&mut ctx, request);
+        }
+        // Streaming bodies (e.g. from a file) can't be cloned. We don't buffer
+        // them just for this, so such requests aren't retried.
+        let Some(retry_request) = request.try_clone() else {
+            return self.next(&mut ctx, request);
+        };
+        match self.next(&mut ctx, request) {
+            Err(err) if is_closed_connection(&err) => {
+                log::info!("Connection was closed by the server ({err:#}), retrying once");
+                self.next(&mut ctx, retry_request)
+            }
+            result => result,
+        }
+    }
+}
+
+/// For --https-first: if the request to `url` can't connect over HTTPS, send
+/// it again over plain HTTP. Redirects and other requests are left alone.
+///
+/// Only a refused or unreachable TCP connection counts. A failed TLS handshake
+/// could be an attacker in the middle, so it never leads to a downgrade.
+pub struct HttpsFallback<'a> {
+    url: Url,
+    tried: bool,
+    /// Called with the plain HTTP request before it's sent.
+    on_fallback: Box<dyn Fn(&Request) + 'a>,
+}
+
+impl<'a> HttpsFallback<'a> {
+    pub fn new(url: Url, on_fallback: impl Fn(&Request) + 'a) -> Self {
+        HttpsFallback {
+            url,
+            tried: false,
+            on_fallback: Box::new(on_fallback),
+        }
+    }
+}
+
+impl Middleware for HttpsFallback<'_> {
+    fn handle(&mut self, mut ctx: Context, request: Request) -> Result<Response> {
+        if self.tried || *request.url() != self.url {
+            return self.next(&mut ctx, request);
+        }
+        self.tried = true;
+        let Some(mut fallback) = request.try_clone() else {
+            return self.next(&mut ctx, request);
+        };
+        match self.next(&mut ctx, request) {
+            Err(err) if is_unreachable(&err) => {
+                log::warn!(
+                    "Couldn't connect to {} over HTTPS ({}), falling back to HTTP",
+                    self.url.host_str().unwrap_or_default(),
+                    err.root_cause()
+                );
+                fallback
+                    .url_mut()
+                    .set_scheme("http")
+                    .expect("https URLs can become http URLs");
+                (self.on_fallback)(&fallback);
+                self.next(&mut ctx, fallback)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Whether a request may be sent again after it failed midway.
+pub fn is_retry_safe(method: &Method, all_methods: bool) -> bool {
+    all_methods
+        || matches!(
+            *method,
+            Method::GET
+                | Method::HEAD
+                | Method::OPTIONS
+                | Method::TRACE
+                | Method::PUT
+                | Method::DELETE
+        )
+}
+
+/// Whether an error might go away by itself: the connection couldn't be
+/// made, timed out, or was closed before the response arrived.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    is_closed_connection(err)
+        || err.chain().any(|err| {
+            err.downcast_ref::<reqwest::Error>()
+                .is_some_and(|err| err.is_connect() || err.is_timeout())
+        })
+}
+
+/// Whether the TCP connection was refused or the host couldn't be reached,
+/// before any TLS happened.
+fn is_unreachable(err: &anyhow::Error) -> bool {
+    let is_connect = err.chain().any(|err| {
+        err.downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_connect)
+    });
+    is_connect
+        && err.chain().any(|err| {
+            err.downcast_ref::<io::Error>().is_some_and(|err| {
+                err.kind() == io::ErrorKind::ConnectionRefused || is_unreachable_os_error(err)
+            })
+        })
+}
+
+#[cfg(unix)]
+fn is_unreachable_os_error(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::ENETUNREACH | libc::EHOSTUNREACH)
+    )
+}
+
+#[cfg(windows)]
+fn is_unreachable_os_error(err: &io::Error) -> bool {
+    // WSAENETUNREACH and WSAEHOSTUNREACH
+    matches!(err.raw_os_error(), Some(10051 | 10065))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_unreachable_os_error(_err: &io::Error) -> bool {
+    false
+}
+
+fn is_closed_connection(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| {
+        if let Some(err) = err.downcast_ref::<hyper::Error>() {
+            err.is_incomplete_message()
+        } else if let Some(err) = err.downcast_ref::<io::Error>() {
+            matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+            )
+        } else {
+            false
+        }
+    })
+}
@@ -3,14 +3,22 @@ use std::convert::TryInto;
 use std::ffi::OsString;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 use url::Url;
 
 use crate::auth;
+use crate::cli::SessionFormat;
 use crate::utils::{config_dir, test_mode};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -42,6 +50,9 @@ struct Auth {
     #[serde(rename = "type")]
     auth_type: Option<String>,
     raw_auth: Option<String>,
+    /// When an OAuth 2.0 token expires, in seconds since the epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<u64>,
 }
 
 // Unlike xh, HTTPie serializes path, secure and expires with defaults of "/", false, and null respectively.
@@ -168,10 +179,7 @@ impl Session {
         let path = if is_path(&name_or_path) {
             PathBuf::from(name_or_path)
         } else {
-            let mut path = config_dir()
-                .context("couldn't get config directory")?
-                .join("sessions")
-                .join(path_from_url(&url)?);
+            let mut path = sessions_dir()?.join(path_from_url(&url)?);
             name_or_path.push(".json");
             path.push(name_or_path);
             path
@@ -230,6 +238,7 @@ impl Session {
         if let Auth {
             auth_type: Some(auth_type),
             raw_auth: Some(raw_auth),
+            expires,
         } = &self.content.auth
         {
             match auth_type.as_str() {
@@ -244,7 +253,19 @@ impl Session {
                         password.unwrap_or_else(|| "".into()),
                     )))
                 }
+                "ntlm" | "negotiate" => {
+                    let (username, password) = auth::parse_auth(raw_auth, "")?;
+                    let password = password.unwrap_or_default();
+                    Ok(Some(match auth_type.as_str() {
+                        "ntlm" => auth::Auth::Ntlm(username, password),
+                        _ => auth::Auth::Negotiate(username, password),
+                    }))
+                }
                 "bearer" => Ok(Some(auth::Auth::Bearer(raw_auth.into()))),
+                "oauth2" => Ok(Some(auth::Auth::OAuth2(
+                    raw_auth.into(),
+                    expires.map(|expires| UNIX_EPOCH + Duration::from_secs(expires)),
+                ))),
                 _ => Err(anyhow!("Unknown auth type {}", raw_auth)),
             }
         } else {
@@ -259,18 +280,41 @@ impl Session {
                 self.content.auth = Auth {
                     auth_type: Some("basic".into()),
                     raw_auth: Some(format!("{}:{}", username, password)),
+                    expires: None,
                 }
             }
             auth::Auth::Digest(username, password) => {
                 self.content.auth = Auth {
                     auth_type: Some("digest".into()),
                     raw_auth: Some(format!("{}:{}", username, password)),
+                    expires: None,
+                }
+            }
+            auth::Auth::Ntlm(username, password) | auth::Auth::Negotiate(username, password) => {
+                let auth_type = match auth {
+                    auth::Auth::Ntlm(..) => "ntlm",
+                    _ => "negotiate",
+                };
+                self.content.auth = Auth {
+                    auth_type: Some(auth_type.into()),
+                    raw_auth: Some(format!("{}:{}", username, password)),
+                    expires: None,
                 }
             }
             auth::Auth::Bearer(token) => {
                 self.content.auth = Auth {
                     auth_type: Some("bearer".into()),
                     raw_auth: Some(token.into()),
+                    expires: None,
+                }
+            }
+            auth::Auth::OAuth2(token, expires) => {
+                self.content.auth = Auth {
+                    auth_type: Some("oauth2".into()),
+                    raw_auth: Some(token.into()),
+                    expires: expires
+                        .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+                        .map(|expires| expires.as_secs()),
                 }
             }
         }
@@ -348,17 +392,563 @@ impl Session {
             if let Some(parent_path) = self.path.parent() {
                 fs::create_dir_all(parent_path)?;
             }
-            let mut session_file = fs::File::create(&self.path)?;
+            let session_file = fs::File::create(&self.path)?;
             log::debug!("Persisting session to {:?}", self.path);
-            let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
-            let mut ser = serde_json::Serializer::with_formatter(&mut session_file, formatter);
-            self.content.serialize(&mut ser)?;
-            session_file.write_all(b"\n")?;
+            write_json(session_file, &self.content)?;
         }
         Ok(())
     }
 }
 
+/// What to do for "xh session".
+#[derive(Debug, PartialEq)]
+pub enum SessionCommand {
+    List,
+    Prune(Duration),
+    Export(String, SessionFormat),
+    Import(PathBuf, Option<String>),
+}
+
+impl SessionCommand {
+    pub fn from_args(
+        args: Vec<String>,
+        format: Option<SessionFormat>,
+        older_than: Option<Duration>,
+    ) -> Result<Self> {
+        const USAGE: &str = "Usage: xh session <list | prune --session-older-than=DURATION | export SESSION [--session-format=httpie|har|netscape] | import FILE [SESSION]>";
+        let mut args = args.into_iter();
+        let command = match args.next().as_deref() {
+            Some("list") => SessionCommand::List,
+            Some("prune") => SessionCommand::Prune(
+                older_than.context("\"xh session prune\" requires --session-older-than")?,
+            ),
+            Some("export") => {
+                SessionCommand::Export(args.next().context(USAGE)?, format.unwrap_or_default())
+            }
+            Some("import") => {
+                SessionCommand::Import(args.next().context(USAGE)?.into(), args.next())
+            }
+            _ => return Err(anyhow!(USAGE)),
+        };
+        if format.is_some() && !matches!(command, SessionCommand::Export(..)) {
+            return Err(anyhow!(
+                "--session-format can only be used with \"xh session export\""
+            ));
+        }
+        if older_than.is_some() && !matches!(command, SessionCommand::Prune(_)) {
+            return Err(anyhow!(
+                "--session-older-than can only be used with \"xh session prune\""
+            ));
+        }
+        if let Some(extra) = args.next() {
+            return Err(anyhow!("Unexpected argument {extra:?}"));
+        }
+        Ok(command)
+    }
+
+    pub fn run(&self) -> Result<i32> {
+        match self {
+            SessionCommand::List => {
+                for (name, path) in all_sessions()? {
+                    let modified = OffsetDateTime::from(fs::metadata(&path)?.modified()?);
+                    println!("{}  {name}{}", modified.date(), describe(&path));
+                }
+            }
+            SessionCommand::Prune(older_than) => {
+                let cutoff = SystemTime::now()
+                    .checked_sub(*older_than)
+                    .unwrap_or(UNIX_EPOCH);
+                for (name, path) in all_sessions()? {
+                    if fs::metadata(&path)?.modified()? < cutoff {
+                        let description = describe(&path);
+                        fs::remove_file(&path)?;
+                        println!("Removed {name}{description}");
+                        // Fails unless that was the host's last session
+                        if let Some(dir) = path.parent() {
+                            let _ = fs::remove_dir(dir);
+                        }
+                    }
+                }
+            }
+            SessionCommand::Export(name, format) => {
+                let path = find_session(name)?;
+                let content = read_content(&path)?;
+                let url = session_url(&path);
+                let mut stdout = io::stdout().lock();
+                match format {
+                    SessionFormat::Httpie => write_json(&mut stdout, &content.into_httpie())?,
+                    SessionFormat::Har => write_json(&mut stdout, &content.to_har(url.as_ref())?)?,
+                    SessionFormat::Netscape => {
+                        stdout.write_all(content.to_netscape(url.as_ref())?.as_bytes())?
+                    }
+                }
+            }
+            SessionCommand::Import(file, name) => {
+                let text = fs::read_to_string(file)
+                    .with_context(|| format!("Couldn't read {}", file.display()))?;
+                let (content, url) = Content::import(&text)
+                    .with_context(|| format!("Couldn't import {}", file.display()))?;
+                let name = match name {
+                    Some(name) => name.clone(),
+                    None => file
+                        .file_stem()
+                        .context("Missing session name")?
+                        .to_string_lossy()
+                        .into_owned(),
+                };
+                let path = if name.ends_with(".json") {
+                    PathBuf::from(name)
+                } else if name.contains('/') {
+                    sessions_dir()?.join(format!("{name}.json"))
+                } else {
+                    let url = url.context(
+                        "Can't tell which host the session is for, give it as HOST/NAME",
+                    )?;
+                    sessions_dir()?
+                        .join(path_from_url(&url)?)
+                        .join(format!("{name}.json"))
+                };
+                if path.exists() {
+                    return Err(anyhow!("{} already exists", path.display()));
+                }
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_json(fs::File::create(&path)?, &content)?;
+                eprintln!("Saved the session to {}", path.display());
+            }
+        }
+        Ok(0)
+    }
+}
+
+impl Content {
+    fn cookie_list(&self) -> &[Cookie] {
+        match &self.cookies {
+            Cookies::Map(_) => unreachable!("cookies should have been migrated to Cookies::List"),
+            Cookies::List(cookies) => cookies,
+        }
+    }
+
+    fn header_list(&self) -> &[Header] {
+        match &self.headers {
+            Headers::Map(_) => unreachable!("headers should have been migrated to Headers::List"),
+            Headers::List(headers) => headers,
+        }
+    }
+
+    /// Whether the session holds a password or a token. Cookies aren't
+    /// counted, though they often keep a login too.
+    fn has_credentials(&self) -> bool {
+        self.auth.raw_auth.is_some()
+            || self.header_list().iter().any(|header| {
+                header.name.eq_ignore_ascii_case("authorization")
+                    || header.name.eq_ignore_ascii_case("proxy-authorization")
+            })
+    }
+
+    /// Turn into a session that HTTPie understands, which has no OAuth 2.0.
+    fn into_httpie(mut self) -> Self {
+        self.meta = Meta::Httpie {
+            about: "HTTPie session file".into(),
+            help: "https://httpie.io/docs#sessions".into(),
+            httpie: "3.2.4".into(),
+        };
+        if self.auth.auth_type.as_deref() == Some("oauth2") {
+            self.auth.auth_type = Some("bearer".into());
+        }
+        self.auth.expires = None;
+        self
+    }
+
+    /// A HAR file with one request that carries the session.
+    fn to_har(&self, url: Option<&Url>) -> Result<Value> {
+        let url = url.context("Can't tell which host the session is for")?;
+        let mut headers: Vec<Value> = self
+            .header_list()
+            .iter()
+            .map(|header| json!({"name": header.name, "value": header.value}))
+            .collect();
+        if let Auth {
+            auth_type: Some(auth_type),
+            raw_auth: Some(raw_auth),
+            ..
+        } = &self.auth
+        {
+            match auth_type.as_str() {
+                "basic" => headers.push(json!({
+                    "name": "Authorization",
+                    "value": format!("Basic {}", BASE64.encode(raw_auth)),
+                })),
+                "bearer" | "oauth2" => headers.push(json!({
+                    "name": "Authorization",
+                    "value": format!("Bearer {raw_auth}"),
+                })),
+                auth_type => log::warn!("{auth_type} auth can't be exported to HAR"),
+            }
+        }
+        let cookies: Vec<Value> = self
+            .cookie_list()
+            .iter()
+            .map(|cookie| {
+                let mut har_cookie = json!({
+                    "name": cookie.name,
+                    "value": cookie.value,
+                    "path": cookie.path.as_deref().unwrap_or("/"),
+                    "domain": cookie.domain.as_deref().or(url.host_str()),
+                    "secure": cookie.secure.unwrap_or(false),
+                });
+                if let Some(expires) = cookie
+                    .expires
+                    .and_then(|expires| OffsetDateTime::from_unix_timestamp(expires).ok())
+                {
+                    har_cookie["expires"] = expires.format(&Rfc3339)?.into();
+                }
+                Ok(har_cookie)
+            })
+            .collect::<Result<_>>()?;
+        let started = if test_mode() {
+            UNIX_EPOCH
+        } else {
+            SystemTime::now()
+        };
+
+        Ok(json!({
+            "log": {
+                "version": "1.2",
+                "creator": {"name": "xh", "version": xh_version()},
+                "entries": [{
+                    "startedDateTime": OffsetDateTime::from(started).format(&Rfc3339)?,
+                    "time": 0,
+                    "request": {
+                        "method": "GET",
+                        "url": url.as_str(),
+                        "httpVersion": "HTTP/1.1",
+                        "cookies": cookies,
+                        "headers": headers,
+                        "queryString": [],
+                        "headersSize": -1,
+                        "bodySize": 0,
+                    },
+                    "response": {
+                        "status": 0,
+                        "statusText": "",
+                        "httpVersion": "",
+                        "cookies": [],
+                        "headers": [],
+                        "content": {"size": 0, "mimeType": ""},
+                        "redirectURL": "",
+                        "headersSize": -1,
+                        "bodySize": -1,
+                    },
+                    "cache": {},
+                    "timings": {"send": 0, "wait": 0, "receive": 0},
+                }],
+            }
+        }))
+    }
+
+    /// A cookies.txt file, in the format curl reads with --cookie.
+    fn to_netscape(&self, url: Option<&Url>) -> Result<String> {
+        let mut text = "# Netscape HTTP Cookie File\n".to_string();
+        for cookie in self.cookie_list() {
+            let domain = cookie
+                .domain
+                .as_deref()
+                .or_else(|| url?.host_str())
+                .with_context(|| format!("Can't tell which host cookie {} is for", cookie.name))?;
+            // Sessions always give cookies to subdomains, except for IP addresses
+            let domain = match domain.parse::<IpAddr>() {
+                Ok(_) => format!("{domain}\tFALSE"),
+                Err(_) => format!(".{}\tTRUE", domain.trim_start_matches('.')),
+            };
+            text += &format!(
+                "{domain}\t{}\t{}\t{}\t{}\t{}\n",
+                cookie.path.as_deref().unwrap_or("/"),
+                if cookie.secure == Some(true) {
+                    "TRUE"
+                } else {
+                    "FALSE"
+                },
+                cookie.expires.unwrap_or(0),
+                cookie.name,
+                cookie.value,
+            );
+        }
+        Ok(text)
+    }
+
+    /// Read a session, HAR or cookies.txt file. Also returns the URL of the
+    /// host the session seems to be for.
+    fn import(text: &str) -> Result<(Content, Option<Url>)> {
+        let (content, url) = if text.trim_start().starts_with('{') {
+            let value: Value = serde_json::from_str(text)?;
+            if value.get("log").is_some() {
+                Content::from_har(&value)?
+            } else {
+                (serde_json::from_value::<Content>(value)?.migrate(), None)
+            }
+        } else {
+            (Content::from_netscape(text)?, None)
+        };
+        let url = url.or_else(|| {
+            let domain = content.cookie_list().first()?.domain.as_deref()?;
+            Url::parse(&format!("http://{}/", domain.trim_start_matches('.'))).ok()
+        });
+        Ok((content, url))
+    }
+
+    /// Take the headers and auth of the last request in a HAR file, and the
+    /// cookies of all of them.
+    fn from_har(har: &Value) -> Result<(Content, Option<Url>)> {
+        #[derive(Deserialize)]
+        struct Entry {
+            request: Message,
+            response: Option<Message>,
+        }
+        #[derive(Deserialize)]
+        struct Message {
+            url: Option<String>,
+            #[serde(default)]
+            headers: Vec<Header>,
+            #[serde(default)]
+            cookies: Vec<HarCookie>,
+        }
+        #[derive(Deserialize)]
+        struct HarCookie {
+            name: String,
+            value: String,
+            path: Option<String>,
+            domain: Option<String>,
+            expires: Option<String>,
+            secure: Option<bool>,
+        }
+
+        let entries: Vec<Entry> =
+            serde_json::from_value(har["log"]["entries"].clone()).context("Invalid HAR file")?;
+        let mut content = Content::default();
+        let mut cookies: Vec<Cookie> = Vec::new();
+        let mut url = None;
+        for entry in &entries {
+            url = entry
+                .request
+                .url
+                .as_deref()
+                .and_then(|url| Url::parse(url).ok());
+            let host = url.as_ref().and_then(|url| url.host_str());
+            let har_cookies = entry
+                .request
+                .cookies
+                .iter()
+                .chain(entry.response.iter().flat_map(|response| &response.cookies));
+            for cookie in har_cookies {
+                let cookie = Cookie {
+                    name: cookie.name.clone(),
+                    value: cookie.value.clone(),
+                    expires: cookie
+                        .expires
+                        .as_deref()
+                        .and_then(|expires| OffsetDateTime::parse(expires, &Rfc3339).ok())
+                        .map(|expires| expires.unix_timestamp()),
+                    path: cookie.path.clone(),
+                    secure: cookie.secure,
+                    domain: cookie
+                        .domain
+                        .as_deref()
+                        .or(host)
+                        .map(|domain| domain.trim_start_matches('.').to_string()),
+                };
+                cookies.retain(|other| {
+                    (&other.name, &other.domain, &other.path)
+                        != (&cookie.name, &cookie.domain, &cookie.path)
+                });
+                cookies.push(cookie);
+            }
+        }
+        content.cookies = Cookies::List(cookies);
+
+        let mut headers = Vec::new();
+        for header in entries
+            .into_iter()
+            .last()
+            .map(|entry| entry.request.headers)
+            .unwrap_or_default()
+        {
+            let name = header.name.to_ascii_lowercase();
+            // Like save_headers(), plus HTTP/2 pseudo-headers and Host
+            if name == "cookie"
+                || name == "host"
+                || name.starts_with(':')
+                || name.starts_with("content-")
+                || name.starts_with("if-")
+            {
+                continue;
+            }
+            if name == "authorization" {
+                if let Some((scheme, credentials)) = header.value.split_once(' ') {
+                    if scheme.eq_ignore_ascii_case("basic") {
+                        if let Some(raw_auth) = BASE64
+                            .decode(credentials)
+                            .ok()
+                            .and_then(|raw_auth| String::from_utf8(raw_auth).ok())
+                        {
+                            content.auth.auth_type = Some("basic".into());
+                            content.auth.raw_auth = Some(raw_auth);
+                            continue;
+                        }
+                    } else if scheme.eq_ignore_ascii_case("bearer") {
+                        content.auth.auth_type = Some("bearer".into());
+                        content.auth.raw_auth = Some(credentials.into());
+                        continue;
+                    }
+                }
+            }
+            headers.push(header);
+        }
+        content.headers = Headers::List(headers);
+        Ok((content, url))
+    }
+
+    fn from_netscape(text: &str) -> Result<Content> {
+        let mut cookies = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            // curl marks HttpOnly cookies with a prefix that looks like a comment
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, _, path, secure, expires, name, value] = fields[..] else {
+                return Err(anyhow!(
+                    "Expected 7 tab-separated fields on line {}",
+                    number + 1
+                ));
+            };
+            cookies.push(Cookie {
+                name: name.into(),
+                value: value.into(),
+                expires: match expires.parse()? {
+                    0 => None,
+                    expires => Some(expires),
+                },
+                path: Some(path.into()),
+                secure: Some(secure.eq_ignore_ascii_case("TRUE")),
+                domain: Some(domain.trim_start_matches('.').into()),
+            });
+        }
+        if cookies.is_empty() {
+            return Err(anyhow!("No cookies found"));
+        }
+        Ok(Content {
+            cookies: Cookies::List(cookies),
+            ..Content::default()
+        })
+    }
+}
+
+fn sessions_dir() -> Result<PathBuf> {
+    Ok(config_dir()
+        .context("couldn't get config directory")?
+        .join("sessions"))
+}
+
+/// Find a session from its name, "HOST/NAME" or a path ending in .json.
+fn find_session(name: &str) -> Result<PathBuf> {
+    if name.ends_with(".json") {
+        return Ok(PathBuf::from(name));
+    }
+    let sessions_dir = sessions_dir()?;
+    if name.contains('/') {
+        return Ok(sessions_dir.join(format!("{name}.json")));
+    }
+    let mut found: Vec<PathBuf> = all_sessions()?
+        .into_iter()
+        .filter(|(id, _)| id.rsplit_once('/').map(|(_, session)| session) == Some(name))
+        .map(|(_, path)| path)
+        .collect();
+    match found.len() {
+        0 => Err(anyhow!("No session named {name:?} was found")),
+        1 => Ok(found.remove(0)),
+        _ => {
+            let hosts: Vec<_> = found
+                .iter()
+                .filter_map(|path| path.parent()?.file_name()?.to_str())
+                .collect();
+            Err(anyhow!(
+                "Several hosts have a session named {name:?}, use HOST/NAME with one of: {}",
+                hosts.join(", ")
+            ))
+        }
+    }
+}
+
+/// All the sessions in the sessions directory as "HOST/NAME", with their paths.
+fn all_sessions() -> Result<Vec<(String, PathBuf)>> {
+    let mut sessions = Vec::new();
+    let host_dirs = match fs::read_dir(sessions_dir()?) {
+        Ok(host_dirs) => host_dirs,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(sessions),
+        Err(err) => return Err(err.into()),
+    };
+    for host_dir in host_dirs {
+        let host_dir = host_dir?;
+        if !host_dir.file_type()?.is_dir() {
+            continue;
+        }
+        let host = host_dir.file_name();
+        for entry in fs::read_dir(host_dir.path())? {
+            let path = entry?.path();
+            if path.extension() != Some("json".as_ref()) {
+                continue;
+            }
+            if let Some(name) = path.file_stem() {
+                let id = format!("{}/{}", host.to_string_lossy(), name.to_string_lossy());
+                sessions.push((id, path));
+            }
+        }
+    }
+    sessions.sort();
+    Ok(sessions)
+}
+
+/// A note for "xh session list" and "xh session prune" on what's in a session.
+fn describe(path: &Path) -> &'static str {
+    match read_content(path) {
+        Ok(content) if content.has_credentials() => " (has credentials)",
+        Ok(_) => "",
+        Err(_) => " (unreadable)",
+    }
+}
+
+fn read_content(path: &Path) -> Result<Content> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read the session in {}", path.display()))?;
+    Ok(serde_json::from_str::<Content>(&content)?.migrate())
+}
+
+/// The URL of the host that a session in the sessions directory is for.
+fn session_url(path: &Path) -> Option<Url> {
+    let dir = path.parent()?;
+    if dir.parent()? != sessions_dir().ok()? {
+        return None;
+    }
+    // The reverse of path_from_url()
+    let dir = dir.file_name()?.to_str()?;
+    let authority = match dir.rsplit_once('_') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => format!("{host}:{port}"),
+        _ => dir.to_string(),
+    };
+    Url::parse(&format!("http://{authority}/")).ok()
+}
+
+fn write_json(mut writer: impl Write, value: &impl Serialize) -> Result<()> {
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut ser = serde_json::Serializer::with_formatter(&mut writer, formatter);
+    value.serialize(&mut ser)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
 fn xh_version() -> String {
     if test_mode() {
         "0.0.0".into()
@@ -456,7 +1046,8 @@ mod tests {
             session.content.auth,
             Auth {
                 auth_type: Some("bearer".into()),
-                raw_auth: Some("secret-token".into())
+                raw_auth: Some("secret-token".into()),
+                expires: None,
             },
         );
 
@@ -569,4 +1160,99 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn oauth2_tokens_keep_their_expiry() -> Result<()> {
+        let mut session = load_session_from_str(indoc::indoc! {r#"
+            {
+                "__meta__": {
+                    "about": "xh session file",
+                    "xh": "0.0.0"
+                },
+                "auth": {},
+                "cookies": [],
+                "headers": []
+            }
+        "#})?;
+        let expires = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        session.save_auth(&auth::Auth::OAuth2("token".into(), Some(expires)));
+        assert_eq!(session.content.auth.expires, Some(1_700_000_000));
+        assert_eq!(
+            session.auth()?,
+            Some(auth::Auth::OAuth2("token".into(), Some(expires)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn netscape_cookies_round_trip() -> Result<()> {
+        let text = indoc::indoc! {"
+            # Netscape HTTP Cookie File
+            .example.com\tTRUE\t/\tTRUE\t1700000000\tid\t123
+            #HttpOnly_127.0.0.1\tFALSE\t/api\tFALSE\t0\ttheme\tdark
+        "};
+        let (content, url) = Content::import(text)?;
+        assert_eq!(url, Some(Url::parse("http://example.com")?));
+        assert_eq!(
+            content.cookie_list()[0].domain.as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(content.cookie_list()[1].expires, None);
+        assert_eq!(content.to_netscape(None)?, text.replace("#HttpOnly_", ""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_har() -> Result<()> {
+        let (content, url) = Content::import(indoc::indoc! {r#"
+            {
+                "log": {
+                    "entries": [
+                        {
+                            "request": {
+                                "url": "https://example.com/login",
+                                "headers": [],
+                                "cookies": []
+                            },
+                            "response": {
+                                "cookies": [
+                                    { "name": "id", "value": "1" }
+                                ]
+                            }
+                        },
+                        {
+                            "request": {
+                                "url": "https://example.com/api",
+                                "headers": [
+                                    { "name": ":authority", "value": "example.com" },
+                                    { "name": "Authorization", "value": "Basic dXNlcjpwYXNz" },
+                                    { "name": "X-Api-Version", "value": "2" }
+                                ],
+                                "cookies": [{ "name": "id", "value": "2" }]
+                            }
+                        }
+                    ]
+                }
+            }
+        "#})?;
+        assert_eq!(url, Some(Url::parse("https://example.com/api")?));
+        assert_eq!(content.auth.raw_auth.as_deref(), Some("user:pass"));
+        let headers = content.header_list();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].name, "X-Api-Version");
+        let cookies = content.cookie_list();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].value, "2");
+        assert_eq!(cookies[0].domain.as_deref(), Some("example.com"));
+
+        // The cookie from the response had an expiry
+        let (content, _) = Content::import(
+            r#"{"log": {"entries": [{"request": {"url": "http://a.test/"}, "response": {"cookies": [{"name": "id", "value": "1", "expires": "2023-11-14T22:13:20Z"}]}}]}}"#,
+        )?;
+        assert_eq!(content.cookie_list()[0].expires, Some(1_700_000_000));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,139 @@
+//! Substitution of `{{name}}` placeholders, as used by `--env` files and
+//! `--template`.
+//!
+//! The syntax is deliberately tiny: a placeholder is a name between double
+//! braces, optionally padded with whitespace. There are no filters or
+//! expressions. `\{{` produces a literal `{{`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::utils::expand_tilde;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Variables(HashMap<String, String>);
+
+impl Variables {
+    /// Load variables from a JSON file containing a single object.
+    ///
+    /// Non-string values are inserted using their JSON representation, so
+    /// `{"page": 2}` and `{"page": "2"}` behave the same.
+    pub fn load(path: &Path) -> Result<Variables> {
+        let path = expand_tilde(path);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read env file {}", path.display()))?;
+        let values: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&content)
+            .with_context(|| format!("Unable to parse env file {}", path.display()))?;
+        Ok(Variables(
+            values
+                .into_iter()
+                .map(|(name, value)| match value {
+                    serde_json::Value::String(value) => (name, value),
+                    value => (name, value.to_string()),
+                })
+                .collect(),
+        ))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+impl<const N: usize> From<[(&str, &str); N]> for Variables {
+    fn from(pairs: [(&str, &str); N]) -> Self {
+        Variables(
+            pairs
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+}
+
+/// A piece of a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part<'a> {
+    Text(&'a str),
+    /// The trimmed name inside a `{{name}}` placeholder.
+    Placeholder(&'a str),
+}
+
+/// Split `text` into literal text and placeholders.
+pub fn parse(text: &str) -> Result<Vec<Part<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            parts.push(Part::Text(&rest[..start - 1]));
+            parts.push(Part::Text("{{"));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        parts.push(Part::Text(&rest[..start]));
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unterminated '{{{{' in {text:?}"))?;
+        parts.push(Part::Placeholder(after[..end].trim()));
+        rest = &after[end + 2..];
+    }
+    parts.push(Part::Text(rest));
+    parts.retain(|part| *part != Part::Text(""));
+    Ok(parts)
+}
+
+/// Replace every `{{name}}` in `text` by looking up `name`.
+pub fn render<'a>(text: &str, lookup: impl Fn(&str) -> Option<&'a str>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    for part in parse(text)? {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Placeholder(name) => {
+                out.push_str(lookup(name).ok_or_else(|| anyhow!("Undefined variable '{name}'"))?)
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitution() {
+        let vars = Variables::from([("base_url", "https://example.com"), ("token", "t0k")]);
+        let render = |text| render(text, |name| vars.get(name));
+
+        assert_eq!(
+            render("{{base_url}}/items").unwrap(),
+            "https://example.com/items"
+        );
+        assert_eq!(
+            render("Authorization:Bearer {{ token }}").unwrap(),
+            "Authorization:Bearer t0k"
+        );
+        assert_eq!(render("no placeholders").unwrap(), "no placeholders");
+        assert_eq!(render(r"\{{token}}").unwrap(), "{{token}}");
+        render("{{missing}}").unwrap_err();
+        render("{{token").unwrap_err();
+    }
+
+    #[test]
+    fn parts() {
+        assert_eq!(
+            parse(r"a {{ b }}\{{c}}").unwrap(),
+            vec![
+                Part::Text("a "),
+                Part::Placeholder("b"),
+                Part::Text("{{"),
+                Part::Text("c}}"),
+            ]
+        );
+        assert_eq!(parse("").unwrap(), vec![]);
+    }
+}
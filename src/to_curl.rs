@@ -4,10 +4,15 @@ use anyhow::{anyhow, Context, Result};
 use os_display::Quotable;
 use reqwest::{tls, Method};
 use std::ffi::OsString;
+use std::net::IpAddr;
 
-use crate::cli::{AuthType, Cli, HttpVersion, Verify};
+use crate::auth::AuthSource;
+use crate::cli::{AuthType, BodySource, CertFormat, Cli, HttpVersion, Verify};
+use crate::clipboard;
+use crate::graphql;
 use crate::request_items::{Body, RequestItem, FORM_CONTENT_TYPE, JSON_ACCEPT, JSON_CONTENT_TYPE};
 use crate::utils::{url_with_query, HeaderValueExt};
+use crate::zone;
 
 pub fn print_curl_translation(args: Cli) -> Result<()> {
     let cmd = translate(args)?;
@@ -25,6 +30,8 @@ pub fn print_curl_translation(args: Cli) -> Result<()> {
 pub struct Command {
     pub long: bool,
     pub args: Vec<OsString>,
+    /// Indices of args that are written as-is, for the shell to expand
+    pub shell_args: Vec<usize>,
     pub env: Vec<(&'static str, String)>,
     pub warnings: Vec<String>,
 }
@@ -34,6 +41,7 @@ impl Command {
         Command {
             long,
             args: Vec::new(),
+            shell_args: Vec::new(),
             env: Vec::new(),
             warnings: Vec::new(),
         }
@@ -51,6 +59,21 @@ impl Command {
         self.args.push(arg.into());
     }
 
+    fn shell_arg(&mut self, arg: String) {
+        self.shell_args.push(self.args.len());
+        self.args.push(arg.into());
+    }
+
+    /// An argument that may have to be read when the command runs.
+    fn auth_arg(&mut self, auth: AuthSource) {
+        match auth {
+            AuthSource::Inline(auth) => self.arg(auth),
+            // This is wrong for Windows, like the environment variables
+            AuthSource::Env(var) => self.shell_arg(format!("\"${var}\"")),
+            AuthSource::File(path) => self.shell_arg(format!("\"$(cat {})\"", path.maybe_quote())),
+        }
+    }
+
     fn header(&mut self, name: &str, value: &str) {
         self.opt("-H", "--header");
         self.arg(format!("{}: {}", name, value));
@@ -73,8 +96,12 @@ impl std::fmt::Display for Command {
             write!(f, "{}={} ", key, value.maybe_quote())?;
         }
         write!(f, "curl")?;
-        for arg in &self.args {
-            write!(f, " {}", arg.maybe_quote().external(true))?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if self.shell_args.contains(&i) {
+                write!(f, " {}", arg.to_string_lossy())?;
+            } else {
+                write!(f, " {}", arg.maybe_quote().external(true))?;
+            }
         }
         Ok(())
     }
@@ -93,13 +120,71 @@ pub fn translate(args: Cli) -> Result<Command> {
         // No straightforward equivalent
         (args.print.is_some(), "-p/--print"),
         // No equivalent
+        (args.print_to.is_some(), "--print-to"),
+        // No equivalent
         (args.pretty.is_some(), "--pretty"),
+        // curl always reads the body
+        (args.no_body_read, "--no-body-read"),
         // No equivalent
         (args.style.is_some(), "-s/--style"),
         // No equivalent
         (args.response_charset.is_some(), "--response-charset"),
         // No equivalent
         (args.response_mime.is_some(), "--response-mime"),
+        // No equivalent
+        (args.max_display_bytes.is_some(), "--max-display-bytes"),
+        // No equivalent
+        (args.summarize_bodies.is_some(), "--summarize-bodies"),
+        // No equivalent
+        (args.max_decode_size.is_some(), "--max-decode-size"),
+        // A fixed Date header can be passed with -H, but "now" can't
+        (args.date_header.is_some(), "--date-header"),
+        // No equivalent
+        (args.clock_skew.is_some(), "--clock-skew"),
+        // No equivalent
+        (args.split_parts.is_some(), "--split-parts"),
+        // No equivalent
+        (args.show_cookies, "--show-cookies"),
+        // No equivalent
+        (args.retry_all_methods, "--retry-all-methods"),
+        // curl's --write-out is similar, but not quite the same
+        (args.metrics_file.is_some(), "--metrics-file"),
+        // No equivalent
+        (args.max_time_assert.is_some(), "--max-time-assert"),
+        // curl's --write-out is similar, but has different variables
+        (args.template.is_some(), "--template"),
+        // Can be done by piping curl's output instead
+        (args.pipe.is_some(), "--pipe"),
+        // No equivalent
+        (args.porcelain, "--porcelain"),
+        // No equivalent
+        (args.trust_server_names, "--trust-server-names"),
+        // No equivalent
+        (args.notify, "--notify"),
+        // No equivalent
+        (args.presign.is_some(), "--presign"),
+        // No equivalent
+        (args.decode_body_base64, "--decode-body-base64"),
+        // No equivalent
+        (args.decode_jwt, "--decode-jwt"),
+        // No equivalent
+        (args.lb.is_some(), "--lb"),
+        // No equivalent
+        (args.mirror, "--mirror"),
+        // No equivalent
+        (args.copy, "--copy"),
+        // No equivalent
+        (args.open, "--open"),
+        // No equivalent
+        (args.reauth, "--reauth"),
+        // No equivalent
+        (args.confirm, "--confirm"),
+        // curl gets the https:// URL and won't fall back to http://
+        (args.https_fallback, "--https-first"),
+        // The command gets the body from before editing
+        (args.edit, "--edit"),
+        // curl picks its own boundary
+        (args.multipart_boundary.is_some(), "--multipart-boundary"),
         // Already the default
         (args.all, "--all"),
         // No (straightforward?) equivalent
@@ -125,6 +210,14 @@ pub fn translate(args: Cli) -> Result<Command> {
     // - .ignore_stdin: assumed by default
     //   (to send stdin, --data-binary @- -H 'Content-Type: application/octet-stream')
     // - .curl and .curl_long: you are here
+    // - .print_command: --curl takes precedence
+    // - .raw_headers and .yes_i_know: curl already sends -H headers as given
+    // - .tls and .rawsend: rawsend isn't an HTTP request, so it never gets here
+    // - .config_command and .init: "xh config" and "xh init" aren't requests either
+    // - .mirror_depth and .mirror_max_size: only used with --mirror
+    // - .crawl, .depth, .same_host, .report_format and .ignore_robots: "xh crawl" isn't a single request
+    // - .allow_insecure_auth: curl doesn't warn about plain HTTP to begin with
+    // - .graphql: the body gets the same envelope below, but errors aren't listed
 
     // Output options
     if args.verbose > 0 {
@@ -169,14 +262,40 @@ pub fn translate(args: Cli) -> Result<Command> {
     } else if args.download {
         cmd.opt("-O", "--remote-name");
     }
+    if args.tcp_nodelay == Some(false) {
+        // curl enables TCP_NODELAY by default, like us
+        cmd.arg("--no-tcp-nodelay");
+    }
+    if let Some(keepalive) = args.tcp_keepalive {
+        cmd.arg("--keepalive-time");
+        cmd.arg(keepalive.as_secs().max(1).to_string());
+    }
+    if let Some(filename) = args.dump_header {
+        let filename = filename.to_str().ok_or_else(|| anyhow!("Invalid UTF-8"))?;
+        cmd.opt("-D", "--dump-header");
+        cmd.arg(filename);
+    }
     if args.resume {
         cmd.opt("-C", "--continue-at");
         cmd.arg("-"); // Tell curl to guess, like we do
     }
     match args.verify.unwrap_or(Verify::Yes) {
-        Verify::CustomCaBundle(filename) => {
-            cmd.arg("--cacert");
-            cmd.arg(filename);
+        Verify::CustomCaBundle(path) => {
+            cmd.arg(if path.is_dir() {
+                "--capath"
+            } else {
+                "--cacert"
+            });
+            cmd.arg(path);
+        }
+        Verify::ExtraCaBundle(path) => {
+            cmd.warn("curl can't add to the built-in root certificates, they're replaced instead");
+            cmd.arg(if path.is_dir() {
+                "--capath"
+            } else {
+                "--cacert"
+            });
+            cmd.arg(path);
         }
         Verify::No => {
             cmd.opt("-k", "--insecure");
@@ -187,6 +306,17 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.opt("-E", "--cert");
         cmd.arg(cert);
     }
+    if args.cert_format == Some(CertFormat::P12) {
+        cmd.arg("--cert-type");
+        cmd.arg("P12");
+    }
+    if let Some(password) = AuthSource::from_args(
+        args.cert_password.as_deref().map(String::as_str),
+        args.cert_password_from.as_deref(),
+    ) {
+        cmd.arg("--pass");
+        cmd.auth_arg(password);
+    }
     if let Some(keyfile) = args.cert_key {
         cmd.arg("--key");
         cmd.arg(keyfile);
@@ -243,6 +373,10 @@ pub fn translate(args: Cli) -> Result<Command> {
             HttpVersion::Http2PriorKnowledge => cmd.arg("--http2-prior-knowledge"),
         }
     }
+    if args.expect_continue {
+        // curl only does this by itself for bodies over 1 MiB
+        cmd.header("expect", "100-continue");
+    }
 
     if args.method == Some(Method::HEAD) {
         cmd.opt("-I", "--head");
@@ -285,7 +419,7 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.opt("-g", "--globoff")
     }
 
-    cmd.arg(url.to_string());
+    cmd.arg(zone::with_zone_id(&url, args.zone_id.as_deref()));
 
     // Force ipv4/ipv6 options
     match (args.ipv4, args.ipv6) {
@@ -307,10 +441,47 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.warn("Inferred port number in --resolve from request URL.");
         for resolve in args.resolve {
             cmd.arg("--resolve");
-            cmd.arg(format!("{}:{}:{}", resolve.domain, port, resolve.addr));
+            let addrs: Vec<_> = resolve
+                .addrs
+                .iter()
+                .map(|resolve_addr| match resolve_addr.addr {
+                    IpAddr::V6(addr) => format!("[{addr}]"),
+                    addr => addr.to_string(),
+                })
+                .collect();
+            cmd.arg(format!("{}:{}:{}", resolve.domain, port, addrs.join(",")));
         }
     }
 
+    if let Some(route_to) = args.route_to {
+        let port = url
+            .port_or_known_default()
+            .with_context(|| format!("Unsupported URL scheme: '{}'", url.scheme()))?;
+        cmd.arg("--connect-to");
+        cmd.arg(format!(
+            "{}:{}:{}",
+            url.host_str().unwrap_or_default(),
+            port,
+            route_to
+        ));
+    }
+
+    if !args.dns_servers.is_empty() {
+        // Only supported if curl was built with c-ares
+        cmd.arg("--dns-servers");
+        cmd.arg(
+            args.dns_servers
+                .iter()
+                .map(|server| server.0.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+    if let Some(url) = &args.dns_url {
+        cmd.arg("--doh-url");
+        cmd.arg(url.as_str());
+    }
+
     // Payload
     for (header, value) in headers.iter() {
         cmd.opt("-H", "--header");
@@ -328,28 +499,73 @@ pub fn translate(args: Cli) -> Result<Command> {
         // Already the default, so a bit questionable
         cmd.arg("--no-netrc");
     }
-    if let Some(auth) = args.auth {
+    if args.auth_type == Some(AuthType::Oauth2) {
+        return Err(anyhow!(
+            "Can't convert --auth-type=oauth2 to curl, it doesn't fetch tokens"
+        ));
+    }
+    if args.auth_type == Some(AuthType::Jwt) {
+        return Err(anyhow!(
+            "Can't convert --auth-type=jwt to curl, it doesn't sign tokens"
+        ));
+    }
+    if let Some(auth) = AuthSource::from_args(
+        args.auth.as_deref().map(String::as_str),
+        args.auth_from.as_deref(),
+    ) {
         match args.auth_type.unwrap_or_default() {
             AuthType::Basic => {
                 cmd.arg("--basic");
                 // curl implements this flag the same way, including password prompt
                 cmd.opt("-u", "--user");
-                cmd.arg(auth);
+                cmd.auth_arg(auth);
             }
             AuthType::Digest => {
                 cmd.arg("--digest");
                 // curl implements this flag the same way, including password prompt
                 cmd.opt("-u", "--user");
-                cmd.arg(auth);
+                cmd.auth_arg(auth);
+            }
+            AuthType::Ntlm => {
+                cmd.arg("--ntlm");
+                cmd.opt("-u", "--user");
+                cmd.auth_arg(auth);
+            }
+            AuthType::Negotiate => {
+                cmd.arg("--negotiate");
+                cmd.opt("-u", "--user");
+                cmd.auth_arg(auth);
             }
             AuthType::Bearer => {
                 cmd.arg("--oauth2-bearer");
-                cmd.arg(auth);
+                cmd.auth_arg(auth);
             }
+            AuthType::Oauth2 | AuthType::Jwt => unreachable!("rejected above"),
         }
     }
 
-    if let Some(raw) = args.raw {
+    if args.http_raw.is_some() {
+        return Err(anyhow!(
+            "Can't convert a raw HTTP request (--http-raw) to curl"
+        ));
+    }
+    if !args.trailers.is_empty() {
+        return Err(anyhow!(
+            "Can't convert request trailers (--trailer) to curl"
+        ));
+    }
+
+    if args.proto.is_some() {
+        return Err(anyhow!(
+            "Can't convert a protobuf request (--proto) to curl"
+        ));
+    }
+
+    if args.graphql && args.body_from == Some(BodySource::Stdin) {
+        return Err(anyhow!("Can't convert a GraphQL query from stdin to curl"));
+    }
+
+    if args.raw.is_some() || args.body_from == Some(BodySource::Stdin) {
         if args.form {
             cmd.header("content-type", FORM_CONTENT_TYPE);
         } else {
@@ -357,8 +573,20 @@ pub fn translate(args: Cli) -> Result<Command> {
             cmd.header("accept", JSON_ACCEPT);
         }
 
-        cmd.opt("-d", "--data");
-        cmd.arg(raw);
+        match args.raw {
+            Some(raw) if raw == clipboard::RAW_ARG => {
+                cmd.opt("-d", "--data");
+                cmd.arg(String::from_utf8(clipboard::paste()?)?);
+            }
+            Some(raw) => {
+                cmd.opt("-d", "--data");
+                cmd.arg(raw);
+            }
+            None => {
+                cmd.arg("--data-binary");
+                cmd.arg("@-");
+            }
+        }
     } else if args.request_items.is_multipart() {
         // We can't use .body() here because we can't look inside the multipart
         // form after construction and we don't want to actually read the files
@@ -371,10 +599,20 @@ pub fn translate(args: Cli) -> Result<Command> {
                     cmd.opt("-F", "--form");
                     cmd.arg(format!("{}={}", key, value));
                 }
-                RequestItem::DataFieldFromFile { key, value, .. } => {
+                RequestItem::DataFieldFromFile {
+                    key,
+                    value,
+                    base64: false,
+                    ..
+                } => {
                     cmd.opt("-F", "--form");
                     cmd.arg(format!("{}=<{}", key, value));
                 }
+                RequestItem::DataFieldFromFile { key, .. } => {
+                    return Err(anyhow!(
+                        "Can't convert base64-encoded file field {key:?} to curl"
+                    ));
+                }
                 RequestItem::FormFile {
                     key,
                     file_name,
@@ -401,7 +639,13 @@ pub fn translate(args: Cli) -> Result<Command> {
             }
         }
     } else {
-        match args.request_items.body()? {
+        let body = args.request_items.body()?;
+        let body = if args.graphql {
+            graphql::envelope(body, None)?
+        } else {
+            body
+        };
+        match body {
             Body::Form(items) => {
                 if items.is_empty() {
                     // Force the header
@@ -464,6 +708,10 @@ mod tests {
         let expected = vec![
             ("xh httpbin.org/get", "curl http://httpbin.org/get"),
             ("xh httpbin.org/get -4", "curl http://httpbin.org/get -4"),
+            (
+                "xh https://example.com --route-to=staging.internal:8443",
+                "curl https://example.com/ --connect-to example.com:443:staging.internal:8443",
+            ),
             ("xh httpbin.org/get -6", "curl http://httpbin.org/get -6"),
             (
                 "xh httpbin.org/post x=3",
@@ -511,10 +759,21 @@ mod tests {
                 #[cfg(windows)]
                 r#"curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{\"x\":[3]}'"#,
             ),
+            (
+                "xh --graphql httpbin.org/post query={me} id:=1",
+                #[cfg(not(windows))]
+                r#"curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{"query":"{me}","variables":{"id":1}}'"#,
+                #[cfg(windows)]
+                r#"curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{\"query\":\"{me}\",\"variables\":{\"id\":1}}'"#,
+            ),
             (
                 "xh --json httpbin.org/post",
                 "curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5'",
             ),
+            (
+                "xh --body-from=stdin httpbin.org/post",
+                "curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' --data-binary @-",
+            ),
             (
                 "xh --form httpbin.org/post x@/dev/null",
                 "curl http://httpbin.org/post -F 'x=@/dev/null'",
@@ -527,6 +786,14 @@ mod tests {
                 "xh --bearer foobar post httpbin.org/post",
                 "curl -X POST http://httpbin.org/post --oauth2-bearer foobar",
             ),
+            (
+                "xh -A bearer --auth-from env:API_TOKEN httpbin.org/get",
+                r#"curl http://httpbin.org/get --oauth2-bearer "$API_TOKEN""#,
+            ),
+            (
+                "xh --auth-from @secrets/user.txt httpbin.org/get",
+                r#"curl http://httpbin.org/get --basic -u "$(cat secrets/user.txt)""#,
+            ),
             (
                 "xh httpbin.org/get foo:Bar baz; user-agent:",
                 "curl http://httpbin.org/get -H 'foo: Bar' -H 'baz;' -H user-agent:",
@@ -543,6 +810,10 @@ mod tests {
                 "xh --curl-long -d -o foobar --continue httpbin.org/get",
                 "curl --fail --location --output foobar --continue-at - http://httpbin.org/get",
             ),
+            (
+                "xh -D headers.txt httpbin.org/get",
+                "curl -D headers.txt http://httpbin.org/get",
+            ),
             (
                 "xh httpbin.org/post @foo.txt",
                 #[cfg(not(windows))]
@@ -1,14 +1,17 @@
 use std::borrow::Cow;
 use std::env::var_os;
+use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::str::Utf8Error;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use reqwest::blocking::{Request, Response};
 use reqwest::header::HeaderValue;
 use url::Url;
 
+use crate::interrupt;
+
 pub fn unescape(text: &str, special_chars: &'static str) -> String {
     let mut out = String::new();
     let mut chars = text.chars();
@@ -108,6 +111,20 @@ pub fn get_home_dir() -> Option<PathBuf> {
     dirs::home_dir()
 }
 
+/// Whether a URL points to the local machine, so that traffic to it
+/// never crosses the network.
+pub fn is_local_url(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => true,
+    }
+}
+
 /// Perform simple tilde expansion if `dirs::home_dir()` is `Some(path)`.
 ///
 /// Note that prefixed tilde e.g `~foo` is ignored.
@@ -124,6 +141,182 @@ pub fn expand_tilde(path: impl AsRef<Path>) -> PathBuf {
     }
 }
 
+/// Read the certificates in a PEM file, or in every .pem, .crt and .cer file
+/// in a directory.
+pub fn read_ca_certificates(path: &Path) -> Result<Vec<pem::Pem>> {
+    let files = if path.is_dir() {
+        let mut files = Vec::new();
+        let entries = fs::read_dir(path)
+            .with_context(|| format!("Failed to read the CA directory: {}", path.display()))?;
+        for entry in entries {
+            let file = entry?.path();
+            let is_certificate = file
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    ["pem", "crt", "cer"]
+                        .iter()
+                        .any(|known| extension.eq_ignore_ascii_case(known))
+                });
+            if is_certificate && file.is_file() {
+                files.push(file);
+            }
+        }
+        if files.is_empty() {
+            return Err(anyhow!(
+                "No .pem, .crt or .cer files in the CA directory: {}",
+                path.display()
+            ));
+        }
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut certificates = Vec::new();
+    for file in files {
+        let buffer = fs::read(&file)
+            .with_context(|| format!("Failed to read the custom CA bundle: {}", file.display()))?;
+        let pems = pem::parse_many(buffer)
+            .with_context(|| format!("Failed to load the custom CA bundle: {}", file.display()))?;
+        certificates.extend(pems);
+    }
+    Ok(certificates)
+}
+
+/// Split a client certificate file (possibly with its key appended) into the
+/// certificate chain and a PKCS#8 private key, which is what native-tls takes.
+#[cfg(any(feature = "native-tls", test))]
+pub fn split_identity_pem(buffer: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut certificates = Vec::new();
+    let mut key = None;
+    for block in pem::parse_many(buffer)? {
+        match block.tag() {
+            "CERTIFICATE" => certificates.push(block),
+            tag if tag.ends_with("PRIVATE KEY") => {
+                let info = pkcs8_private_key(&block)?;
+                key = Some(pem::Pem::new("PRIVATE KEY", info));
+            }
+            _ => {}
+        }
+    }
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificate found"));
+    }
+    let key = key.ok_or_else(|| anyhow!("No private key found, try --cert-key"))?;
+    Ok((
+        pem::encode_many(&certificates).into_bytes(),
+        pem::encode(&key).into_bytes(),
+    ))
+}
+
+/// Unpack a PKCS#12 bundle into a PEM certificate chain followed by its
+/// PKCS#8 private key, the same layout as a --cert file with the key appended.
+///
+/// Returns `None` if the password is wrong.
+pub fn pkcs12_to_pem(bundle: &[u8], password: &str) -> Result<Option<Vec<u8>>> {
+    use p12_keystore::error::Error;
+
+    let keystore = match p12_keystore::KeyStore::from_pkcs12(bundle, password) {
+        Ok(keystore) => keystore,
+        // Without a MAC a wrong password only shows as garbled padding
+        Err(Error::MacError(_) | Error::UnpadError) => return Ok(None),
+        Err(err) => return Err(anyhow!("Invalid PKCS#12 bundle: {err}")),
+    };
+    let (_, key_chain) = keystore
+        .private_key_chain()
+        .ok_or_else(|| anyhow!("No private key with a certificate found in the PKCS#12 bundle"))?;
+    let mut blocks: Vec<_> = key_chain
+        .chain()
+        .iter()
+        .map(|certificate| pem::Pem::new("CERTIFICATE", certificate.as_der()))
+        .collect();
+    blocks.push(pem::Pem::new("PRIVATE KEY", key_chain.key()));
+    Ok(Some(pem::encode_many(&blocks).into_bytes()))
+}
+
+/// Get a private key in PKCS#8 form, the format ring and native-tls take.
+///
+/// Traditional "BEGIN RSA PRIVATE KEY" keys are converted. Other formats have
+/// to be converted by the user.
+pub fn pkcs8_private_key(block: &pem::Pem) -> Result<Vec<u8>> {
+    match block.tag() {
+        "PRIVATE KEY" => Ok(block.contents().to_vec()),
+        "RSA PRIVATE KEY" => {
+            // PrivateKeyInfo with the rsaEncryption algorithm, see RFC 5208
+            const VERSION_AND_ALGORITHM: &[u8] = &[
+                0x02, 0x01, 0x00, 0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01,
+                0x01, 0x01, 0x05, 0x00,
+            ];
+            let mut private_key = vec![0x04];
+            private_key.extend(der_length(block.contents().len()));
+            private_key.extend(block.contents());
+            let mut info = vec![0x30];
+            info.extend(der_length(VERSION_AND_ALGORITHM.len() + private_key.len()));
+            info.extend(VERSION_AND_ALGORITHM);
+            info.extend(private_key);
+            Ok(info)
+        }
+        tag => Err(anyhow!(
+            "Expected a PKCS#8 private key, not {tag:?}. \
+             It can be converted with: openssl pkcs8 -topk8 -nocrypt"
+        )),
+    }
+}
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+    let bytes: Vec<u8> = len
+        .to_be_bytes()
+        .into_iter()
+        .skip_while(|&byte| byte == 0)
+        .collect();
+    let mut encoded = vec![0x80 | bytes.len() as u8];
+    encoded.extend(bytes);
+    encoded
+}
+
+/// Look up the index of a network interface, for use as the scope ID of a
+/// link-local IPv6 address.
+pub fn interface_index(name: &str) -> Result<u32> {
+    if let Ok(index) = name.parse() {
+        return Ok(index);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    {
+        let path = format!("/sys/class/net/{name}/ifindex");
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|index| index.trim().parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("Unknown network interface {:?}", name))
+    }
+
+    #[cfg(all(
+        not(any(target_os = "android", target_os = "linux")),
+        feature = "network-interface"
+    ))]
+    {
+        use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+        NetworkInterface::show()?
+            .iter()
+            .find(|interface| interface.name == name)
+            .map(|interface| interface.index)
+            .ok_or_else(|| anyhow::anyhow!("Unknown network interface {:?}", name))
+    }
+
+    #[cfg(all(
+        not(any(target_os = "android", target_os = "linux")),
+        not(feature = "network-interface")
+    ))]
+    Err(anyhow::anyhow!(
+        "This binary was built without support for interface names in zone IDs. Use the interface index instead."
+    ))
+}
+
 pub fn url_with_query(mut url: Url, query: &[(&str, Cow<str>)]) -> Url {
     if !query.is_empty() {
         // If we run this even without adding pairs it adds a `?`, hence
@@ -151,11 +344,26 @@ macro_rules! vec_of_strings {
 /// the largest read size. But this doesn't translate to a shorter runtime.
 pub const BUFFER_SIZE: usize = 128 * 1024;
 
+/// Format a number with commas between groups of three digits.
+pub fn group_digits(number: u64) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
 /// io::copy, but with a larger buffer size.
 ///
 /// io::copy's buffer is just 8 KiB. This noticeably slows down fast
 /// large downloads, especially with a progress bar.
 ///
+/// Returns the number of bytes copied.
+///
 /// If `flush` is true, the writer will be flushed after each write. This is
 /// appropriate for streaming output, where you don't want a delay between data
 /// arriving and being shown.
@@ -163,13 +371,16 @@ pub fn copy_largebuf(
     reader: &mut impl io::Read,
     writer: &mut impl Write,
     flush: bool,
-) -> io::Result<()> {
+) -> io::Result<u64> {
     let mut buf = vec![0; BUFFER_SIZE];
+    let mut copied = 0;
     loop {
+        interrupt::check()?;
         match reader.read(&mut buf) {
-            Ok(0) => return Ok(()),
+            Ok(0) => return Ok(copied),
             Ok(len) => {
                 writer.write_all(&buf[..len])?;
+                copied += len as u64;
                 if flush {
                     writer.flush()?;
                 }
@@ -249,10 +460,64 @@ pub(crate) fn reason_phrase(response: &Response) -> Cow<'_, str> {
     }
 }
 
+/// Explain an HTTP/2 stream reset or GOAWAY somewhere in the error chain.
+///
+/// The underlying errors only state the error code, which on its own doesn't
+/// tell the user whether it's worth retrying or what to change.
+pub(crate) fn http2_error_hint(err: &anyhow::Error) -> Option<String> {
+    let err = err
+        .chain()
+        .find_map(|err| err.downcast_ref::<h2::Error>())?;
+    let reason = err.reason()?;
+    let (peer, scope) = match (err.is_remote(), err.is_go_away()) {
+        (true, true) => ("The server", "closed the HTTP/2 connection"),
+        (true, false) => ("The server", "reset the HTTP/2 stream"),
+        (false, true) => ("xh", "closed the HTTP/2 connection"),
+        (false, false) => ("xh", "reset the HTTP/2 stream"),
+    };
+    let advice = match reason {
+        h2::Reason::NO_ERROR if err.is_go_away() => {
+            "This is a graceful shutdown, e.g. by a gateway recycling connections. Retrying should work."
+        }
+        h2::Reason::REFUSED_STREAM => {
+            "The request was not processed, so it is safe to retry."
+        }
+        h2::Reason::ENHANCE_YOUR_CALM => "The server is rate limiting this client.",
+        h2::Reason::HTTP_1_1_REQUIRED => "Try again with --http-version=1.1.",
+        h2::Reason::INADEQUATE_SECURITY => {
+            "The TLS configuration is not acceptable for HTTP/2. Try --http-version=1.1."
+        }
+        h2::Reason::PROTOCOL_ERROR | h2::Reason::COMPRESSION_ERROR => {
+            "This points to a bug in the server or in a proxy in between."
+        }
+        _ => "",
+    };
+    let hint = format!("{peer} {scope} with error code {reason:?} ({reason}).");
+    if advice.is_empty() {
+        Some(hint)
+    } else {
+        Some(format!("{hint} {advice}"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_http2_error_hint() {
+        let err = anyhow::Error::new(h2::Error::from(h2::Reason::REFUSED_STREAM))
+            .context("error sending request");
+        let hint = http2_error_hint(&err).unwrap();
+        assert!(hint.contains("REFUSED_STREAM"));
+        assert!(hint.ends_with("it is safe to retry."));
+
+        assert_eq!(
+            http2_error_hint(&anyhow::anyhow!("connection closed")),
+            None
+        );
+    }
+
     #[test]
     fn test_latin1() {
         let good = HeaderValue::from_static("Rhodes");
@@ -272,4 +537,77 @@ mod tests {
         assert_eq!(worse.latin1(), "Ródos");
         assert_eq!(worse.utf8(), None);
     }
+
+    #[test]
+    fn ca_certificates_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        read_ca_certificates(dir.path()).unwrap_err();
+
+        fs::copy(
+            "tests/fixtures/certs/wildcard-self-signed.pem",
+            dir.path().join("a.pem"),
+        )
+        .unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a certificate").unwrap();
+        assert_eq!(read_ca_certificates(dir.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn identity_for_native_tls() {
+        let mut buffer = fs::read("tests/fixtures/certs/client.badssl.com.crt").unwrap();
+        buffer.push(b'\n');
+        buffer.extend(fs::read("tests/fixtures/certs/client.badssl.com.key").unwrap());
+
+        let (certificates, key) = split_identity_pem(&buffer).unwrap();
+        let certificates = pem::parse_many(certificates).unwrap();
+        assert!(certificates
+            .iter()
+            .all(|block| block.tag() == "CERTIFICATE"));
+        let key = pem::parse(key).unwrap();
+        assert_eq!(key.tag(), "PRIVATE KEY");
+        // A SEQUENCE with a two byte length, then the version
+        assert_eq!(key.contents()[..2], [0x30, 0x82]);
+        assert_eq!(key.contents()[4..7], [0x02, 0x01, 0x00]);
+        assert_eq!(
+            u16::from_be_bytes([key.contents()[2], key.contents()[3]]) as usize,
+            key.contents().len() - 4
+        );
+
+        split_identity_pem(&fs::read("tests/fixtures/certs/client.badssl.com.crt").unwrap())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn local_urls() {
+        let is_local = |url: &str| is_local_url(&Url::parse(url).unwrap());
+        assert!(is_local("http://localhost:8000/"));
+        assert!(is_local("http://api.localhost/"));
+        assert!(is_local("http://127.0.0.2/"));
+        assert!(is_local("http://[::1]/"));
+        assert!(!is_local("http://example.com/"));
+        assert!(!is_local("http://10.0.0.1/"));
+        assert!(!is_local("http://localhost.example.com/"));
+    }
+
+    #[test]
+    fn der_lengths() {
+        assert_eq!(der_length(5), [0x05]);
+        assert_eq!(der_length(0x7f), [0x7f]);
+        assert_eq!(der_length(0x80), [0x81, 0x80]);
+        assert_eq!(der_length(0x4a3), [0x82, 0x04, 0xa3]);
+    }
+
+    #[test]
+    fn pkcs12_bundles() {
+        let bundle = std::fs::read("tests/fixtures/certs/client.badssl.com.p12").unwrap();
+        assert!(pkcs12_to_pem(&bundle, "wrong").unwrap().is_none());
+
+        let pem = pkcs12_to_pem(&bundle, "badssl.com").unwrap().unwrap();
+        let (certificates, _) = split_identity_pem(&pem).unwrap();
+        let expected = std::fs::read("tests/fixtures/certs/client.badssl.com.crt").unwrap();
+        assert_eq!(
+            pem::parse(certificates).unwrap().contents(),
+            pem::parse(expected).unwrap().contents()
+        );
+    }
 }
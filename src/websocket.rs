@@ -0,0 +1,504 @@
+//! WebSocket client mode, for ws:// and wss:// URLs.
+//!
+//! The handshake is an ordinary HTTP/1.1 request that we write out by hand
+//! like --raw-headers does, since reqwest can't hand over the connection
+//! afterwards. From then on the connection carries frames (RFC 6455): lines
+//! from stdin are sent as text messages, and incoming messages go through the
+//! Printer so JSON gets formatted like a response body would.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::blocking::Request;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE,
+    SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::cli::{Print, Verify};
+use crate::printer::Printer;
+use crate::raw::{self, Stream};
+use crate::utils::test_mode;
+
+/// Appended to our key by the server to prove it speaks WebSocket.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// How often to check for lines to send while waiting for messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long to wait for the server to answer our close frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+pub fn is_websocket_url(url: &Url) -> bool {
+    matches!(url.scheme(), "ws" | "wss")
+}
+
+/// Turn a request into a handshake.
+///
+/// A body can't go along with the handshake, so it's taken out to be sent as
+/// the first message instead.
+pub fn prepare_handshake(request: &mut Request) -> Result<Option<Vec<u8>>> {
+    let first_message = match request.body_mut().take() {
+        Some(mut body) => Some(
+            body.buffer()
+                .map_err(|err| anyhow!(err).context("Failed to read the request body"))?
+                .to_vec(),
+        ),
+        None => None,
+    };
+
+    let key = if test_mode() {
+        // The example from RFC 6455, so that test servers can use a fixed answer
+        "dGhlIHNhbXBsZSBub25jZQ==".to_string()
+    } else {
+        let mut random = [0; 16];
+        random[..8].copy_from_slice(&random_u64().to_ne_bytes());
+        random[8..].copy_from_slice(&random_u64().to_ne_bytes());
+        BASE64_STANDARD.encode(random)
+    };
+
+    let headers = request.headers_mut();
+    if first_message.is_some() {
+        headers.remove(CONTENT_TYPE);
+        headers.remove(CONTENT_LENGTH);
+    }
+    headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+    headers.insert(SEC_WEBSOCKET_KEY, HeaderValue::from_str(&key)?);
+    Ok(first_message)
+}
+
+/// Do the handshake, then exchange messages until either side closes the
+/// connection.
+///
+/// Lines are read from stdin if `read_stdin` is set, and the connection is
+/// closed when it runs out. Otherwise we only listen.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    request: &mut Request,
+    first_message: Option<Vec<u8>>,
+    tls: Option<&Verify>,
+    timeout: Option<Duration>,
+    read_stdin: bool,
+    printer: &mut Printer,
+    print: &Print,
+) -> Result<()> {
+    let url = request.url().clone();
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let key = request
+        .headers()
+        .get(SEC_WEBSOCKET_KEY)
+        .context("The request has no Sec-WebSocket-Key")?
+        .as_bytes()
+        .to_vec();
+    let handshake = raw::serialize_request(request)?;
+
+    let socket = raw::open_tcp(host, port, timeout)?;
+    socket.set_read_timeout(timeout)?;
+    socket.set_write_timeout(timeout)?;
+    // Shares the socket, so we can change the timeout after the handshake
+    let control = socket.try_clone()?;
+    let mut stream = raw::secure(host, socket, tls)?;
+    stream
+        .write_all(&handshake)
+        .context("Failed to send the handshake")?;
+    stream.flush()?;
+
+    let (status, reason, headers) = read_response_head(&mut *stream)?;
+    if print.response_headers {
+        printer.print_handshake_response(status, &reason, &headers)?;
+    }
+    if status != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(anyhow!(
+            "The server refused to switch to WebSocket ({status})"
+        ));
+    }
+    let accept = headers.get(SEC_WEBSOCKET_ACCEPT).map(HeaderValue::as_bytes);
+    if accept != Some(accept_key(&key).as_bytes()) {
+        return Err(anyhow!("The server sent a bad Sec-WebSocket-Accept header"));
+    }
+
+    control.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut connection = Connection {
+        stream,
+        incoming: Vec::new(),
+        message: Vec::new(),
+        message_opcode: TEXT,
+    };
+    if let Some(message) = first_message {
+        connection.send(TEXT, &message)?;
+    }
+    let lines = read_stdin.then(read_lines);
+    let mut closing_since = None;
+
+    loop {
+        if let (Some(lines), None) = (&lines, closing_since) {
+            loop {
+                match lines.try_recv() {
+                    Ok(line) => connection.send(TEXT, line.as_bytes())?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        connection.send(CLOSE, &1000u16.to_be_bytes())?;
+                        closing_since = Some(Instant::now());
+                        break;
+                    }
+                }
+            }
+        }
+
+        let frame = match connection.receive() {
+            Ok(Some(frame)) => frame,
+            // The server went away without a close frame
+            Ok(None) => break,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if closing_since.is_some_and(|since| since.elapsed() > CLOSE_TIMEOUT) {
+                    break;
+                }
+                continue;
+            }
+            Err(err) => return Err(err).context("Failed to read from the WebSocket"),
+        };
+
+        match frame.opcode {
+            TEXT | BINARY | CONTINUATION => {
+                if frame.opcode != CONTINUATION {
+                    connection.message_opcode = frame.opcode;
+                }
+                connection.message.extend(frame.payload);
+                if frame.fin {
+                    let message = std::mem::take(&mut connection.message);
+                    if print.response_body {
+                        printer
+                            .print_websocket_message(&message, connection.message_opcode == TEXT)?;
+                    }
+                }
+            }
+            PING => connection.send(PONG, &frame.payload)?,
+            PONG => {}
+            CLOSE => {
+                if closing_since.is_none() {
+                    // Echo the status code back, as the protocol asks
+                    connection.send(CLOSE, frame.payload.get(..2).unwrap_or_default())?;
+                }
+                if let Some(code) = frame.payload.get(..2) {
+                    let code = u16::from_be_bytes([code[0], code[1]]);
+                    if code != 1000 {
+                        let reason = String::from_utf8_lossy(&frame.payload[2..]);
+                        log::warn!("The server closed the connection: {code} {reason}");
+                    }
+                }
+                break;
+            }
+            opcode => return Err(anyhow!("Received a frame with unknown opcode {opcode}")),
+        }
+    }
+    Ok(())
+}
+
+struct Connection {
+    stream: Box<dyn Stream>,
+    /// Bytes received that don't make up a whole frame yet.
+    incoming: Vec<u8>,
+    /// The fragments of a message received so far.
+    message: Vec<u8>,
+    message_opcode: u8,
+}
+
+impl Connection {
+    fn send(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mask = random_u64().to_ne_bytes();
+        let frame = encode_frame(opcode, payload, [mask[0], mask[1], mask[2], mask[3]]);
+        self.stream
+            .write_all(&frame)
+            .context("Failed to write to the WebSocket")?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Read the next frame, or `None` if the connection was closed. Times out
+    /// after [`POLL_INTERVAL`] if nothing comes in.
+    fn receive(&mut self) -> io::Result<Option<Frame>> {
+        let mut buf = [0; 16 * 1024];
+        loop {
+            if let Some((frame, len)) = parse_frame(&self.incoming) {
+                self.incoming.drain(..len);
+                return Ok(Some(frame));
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Ok(None),
+                Ok(len) => self.incoming.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // Lots of servers hang up without a TLS close_notify
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Read the status line and headers, leaving the frames that may follow
+/// them unread.
+fn read_response_head(stream: &mut dyn Stream) -> Result<(StatusCode, String, HeaderMap)> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 64 * 1024 {
+            return Err(anyhow!("The handshake response is too large"));
+        }
+        match stream.read(&mut byte) {
+            Ok(0) => {
+                return Err(anyhow!(
+                    "The server closed the connection during the handshake"
+                ))
+            }
+            Ok(_) => head.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err).context("Failed to read the handshake response"),
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let status = match (parts.next(), parts.next()) {
+        (Some(version), Some(status)) if version.starts_with("HTTP/") => {
+            StatusCode::from_bytes(status.as_bytes())?
+        }
+        _ => return Err(anyhow!("Invalid handshake response: {status_line:?}")),
+    };
+    let reason = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HeaderMap::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        let (name, value) = line
+            .split_once(':')
+            .with_context(|| format!("Invalid header in the handshake response: {line:?}"))?;
+        headers.append(
+            HeaderName::from_bytes(name.trim().as_bytes())?,
+            HeaderValue::from_str(value.trim())?,
+        );
+    }
+    Ok((status, reason, headers))
+}
+
+fn read_lines() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[derive(Debug, PartialEq)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Parse the frame at the start of `buf`, and return it with its length.
+/// Returns `None` if `buf` doesn't hold all of it yet.
+fn parse_frame(buf: &[u8]) -> Option<(Frame, usize)> {
+    let [first, second, ..] = *buf else {
+        return None;
+    };
+    let (len, mut pos) = match second & 0x7f {
+        126 => (
+            u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as u64,
+            4,
+        ),
+        127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?), 10),
+        len => (len as u64, 2),
+    };
+    let mask = if second & 0x80 != 0 {
+        let mask: [u8; 4] = buf.get(pos..pos + 4)?.try_into().ok()?;
+        pos += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    let end = pos.checked_add(usize::try_from(len).ok()?)?;
+    let mut payload = buf.get(pos..end)?.to_vec();
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+    let frame = Frame {
+        fin: first & 0x80 != 0,
+        opcode: first & 0x0f,
+        payload,
+    };
+    Some((frame, end))
+}
+
+/// Encode a single, final frame. Frames from a client are always masked.
+fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xffff => {
+            frame.push(0x80 | 126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(mask);
+    let start = frame.len();
+    frame.extend(payload);
+    apply_mask(&mut frame[start..], mask);
+    frame
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (byte, mask) in data.iter_mut().zip(mask.iter().cycle()) {
+        *byte ^= mask;
+    }
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// The Sec-WebSocket-Accept value that proves the server read our key.
+fn accept_key(key: &[u8]) -> String {
+    let mut data = key.to_vec();
+    data.extend(GUID.as_bytes());
+    BASE64_STANDARD.encode(sha1(&data))
+}
+
+/// SHA-1, which the handshake needs and nothing else does. It's not used
+/// for anything security-sensitive.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, new) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(new);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_accept_key() {
+        assert_eq!(
+            hex::encode(sha1(b"")),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        assert_eq!(
+            hex::encode(sha1(&[b'a'; 100])),
+            "7f9000257a4918d7072655ea468540cdcbd42e0c"
+        );
+        // From RFC 6455
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn frames() {
+        let frame = encode_frame(TEXT, b"Hello", [0x37, 0xfa, 0x21, 0x3d]);
+        // The masked example from RFC 6455
+        assert_eq!(
+            frame,
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+        let (parsed, len) = parse_frame(&frame).unwrap();
+        assert_eq!(len, frame.len());
+        assert_eq!(
+            parsed,
+            Frame {
+                fin: true,
+                opcode: TEXT,
+                payload: b"Hello".to_vec()
+            }
+        );
+
+        // An unmasked first fragment, and an incomplete frame after it
+        let (parsed, len) = parse_frame(&[0x01, 0x03, b'H', b'e', b'l', 0x80, 0x02]).unwrap();
+        assert_eq!(len, 5);
+        assert!(!parsed.fin);
+        assert_eq!(parsed.payload, b"Hel");
+        assert_eq!(parse_frame(&[0x80, 0x02, b'l']), None);
+
+        let long = vec![b'x'; 300];
+        let frame = encode_frame(BINARY, &long, [1, 2, 3, 4]);
+        assert_eq!(frame[1..4], [0x80 | 126, 0x01, 0x2c]);
+        assert_eq!(parse_frame(&frame[..100]), None);
+        assert_eq!(parse_frame(&frame).unwrap().0.payload, long);
+    }
+}
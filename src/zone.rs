@@ -0,0 +1,102 @@
+//! Link-local IPv6 addresses with a zone ID in the URL, like
+//! `http://[fe80::1%eth0]/`.
+//!
+//! hyper connects to an IP address in the URL directly, without a way to give
+//! it a scope ID. So the request goes out to a stand-in hostname instead, which
+//! resolves to the scoped address the same way as with --resolve, while the
+//! Host header keeps the address from the URL.
+
+use std::net::{Ipv6Addr, SocketAddrV6};
+
+use anyhow::Result;
+use reqwest::blocking::{Request, Response};
+use reqwest::header::{HeaderValue, HOST};
+use url::{Host, Position, Url};
+
+use crate::middleware::{Context, Middleware};
+use crate::utils::interface_index;
+
+/// Put a zone ID back into the host of a URL, percent-encoded as in RFC 6874.
+pub fn with_zone_id(url: &Url, zone_id: Option<&str>) -> String {
+    match (zone_id, url.host()) {
+        (Some(zone_id), Some(Host::Ipv6(addr))) => {
+            url.as_str()
+                .replacen(&format!("[{addr}]"), &format!("[{addr}%25{zone_id}]"), 1)
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// The address in `url` with the scope ID of the interface `zone_id` names.
+pub fn scoped_addr(url: &Url, zone_id: &str) -> Result<Option<SocketAddrV6>> {
+    match url.host() {
+        Some(Host::Ipv6(addr)) => Ok(Some(SocketAddrV6::new(
+            addr,
+            0,
+            0,
+            interface_index(zone_id)?,
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// The hostname that stands in for a scoped address. It's under `.invalid`,
+/// so it can't accidentally resolve to anything else.
+pub fn stand_in_host(addr: &SocketAddrV6) -> String {
+    let ip = addr.ip().to_string().replace(':', "-");
+    format!("{ip}s{}.ipv6-zone.invalid", addr.scope_id())
+}
+
+/// Send requests for the scoped address to its stand-in hostname.
+pub struct ZonedHost {
+    addr: Ipv6Addr,
+    stand_in: String,
+}
+
+impl ZonedHost {
+    pub fn new(addr: &SocketAddrV6) -> Self {
+        ZonedHost {
+            addr: *addr.ip(),
+            stand_in: stand_in_host(addr),
+        }
+    }
+}
+
+impl Middleware for ZonedHost {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        if request.url().host() == Some(Host::Ipv6(self.addr)) {
+            if !request.headers().contains_key(HOST) {
+                let host = &request.url()[Position::BeforeHost..Position::BeforePath];
+                let host = HeaderValue::from_str(host)?;
+                request.headers_mut().insert(HOST, host);
+            }
+            request.url_mut().set_host(Some(&self.stand_in))?;
+        }
+        self.next(&mut ctx, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puts_zone_id_back() {
+        let url = Url::parse("http://[fe80::1]:8080/a").unwrap();
+        assert_eq!(
+            with_zone_id(&url, Some("eth0")),
+            "http://[fe80::1%25eth0]:8080/a"
+        );
+        assert_eq!(with_zone_id(&url, None), "http://[fe80::1]:8080/a");
+    }
+
+    #[test]
+    fn stand_in_is_a_hostname() {
+        let addr = SocketAddrV6::new("fe80::1".parse().unwrap(), 0, 0, 2);
+        let host = stand_in_host(&addr);
+        assert_eq!(host, "fe80--1s2.ipv6-zone.invalid");
+        let mut url = Url::parse("http://[fe80::1]/").unwrap();
+        url.set_host(Some(&host)).unwrap();
+        assert_eq!(url.host(), Some(Host::Domain(host.as_str())));
+    }
+}